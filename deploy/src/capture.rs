@@ -0,0 +1,75 @@
+use n64_types::{CaptureMessageBuffer, CAPTURE_CHUNK_SIZE};
+use std::{error::Error, fs, fs::File, io::BufWriter, path::PathBuf};
+
+const CAPTURE_DIR: &str = "captures";
+
+/// Puts the chunks of frames streamed from the N64 back together and writes them as PNGs
+#[derive(Default)]
+pub struct FrameCapture {
+    frame: u16,
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+    received: usize,
+}
+
+impl FrameCapture {
+    pub fn submit_chunk(&mut self, msg: &CaptureMessageBuffer) -> Result<(), Box<dyn Error>> {
+        let frame = u16::from_be(msg.frame);
+        let index = u16::from_be(msg.index) as usize;
+        let count = u16::from_be(msg.count) as usize;
+
+        if index == 0 {
+            self.frame = frame;
+            self.width = u16::from_be(msg.width) as u32;
+            self.height = u16::from_be(msg.height) as u32;
+            self.rgba.clear();
+            self.rgba.resize(count * CAPTURE_CHUNK_SIZE, 0);
+            self.received = 0;
+        }
+
+        if frame != self.frame || index >= count || self.rgba.len() != count * CAPTURE_CHUNK_SIZE {
+            return Ok(());
+        }
+
+        self.rgba[index * CAPTURE_CHUNK_SIZE..(index + 1) * CAPTURE_CHUNK_SIZE]
+            .copy_from_slice(&msg.data);
+        self.received += 1;
+
+        if index == count - 1 {
+            if self.received == count {
+                self.write_png()?;
+            } else {
+                println!(
+                    "Dropped capture of frame {}, got {} of {} chunks",
+                    self.frame, self.received, count
+                );
+            }
+
+            self.rgba.clear();
+        }
+
+        Ok(())
+    }
+
+    fn write_png(&mut self) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(CAPTURE_DIR)?;
+
+        let path = PathBuf::from(CAPTURE_DIR).join(format!("frame_{:05}.png", self.frame));
+
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(File::create(&path)?),
+            self.width,
+            self.height,
+        );
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        self.rgba.truncate((4 * self.width * self.height) as usize);
+        encoder.write_header()?.write_image_data(&self.rgba)?;
+
+        println!("Captured {}", path.display());
+
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
-use crate::profiler::N64Profiler;
-use n64_types::{ProfilerMessageBuffer, MESSAGE_MAGIC_PRINT, MESSAGE_MAGIC_PROFILER};
+use crate::{capture::FrameCapture, profiler::N64Profiler};
+use n64_types::{
+    CaptureMessageBuffer, ProfilerMessageBuffer, MESSAGE_MAGIC_CAPTURE, MESSAGE_MAGIC_PRINT,
+    MESSAGE_MAGIC_PROFILER, ROM_FS_ALIGNMENT, ROM_FS_CART_HEADER_OFFSET,
+};
 use serialport::SerialPort;
 use std::{
     collections::HashMap,
@@ -13,6 +16,7 @@ use std::{
 };
 use zerocopy::LayoutVerified;
 
+mod capture;
 mod profiler;
 
 fn write_cmd(port: &mut dyn SerialPort, cmd: u8, addr: u32, len: u32, arg: u32) {
@@ -81,6 +85,18 @@ fn fetch_scope_names() -> HashMap<i16, String> {
     res
 }
 
+// Appends the rom filesystem written by the pipeline and records where it starts in the
+// cartridge header. The header is outside the IPL3 checksum so the rom stays bootable.
+fn append_rom_fs(rom: &mut Vec<u8>, rom_fs: &[u8]) {
+    let offset = (rom.len() + ROM_FS_ALIGNMENT - 1) & !(ROM_FS_ALIGNMENT - 1);
+
+    rom.resize(offset, 0);
+    rom.extend_from_slice(rom_fs);
+
+    rom[ROM_FS_CART_HEADER_OFFSET..ROM_FS_CART_HEADER_OFFSET + 4]
+        .copy_from_slice(&(offset as u32).to_be_bytes());
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let _puffin_server =
         puffin_http::Server::new(&format!("0.0.0.0:{}", puffin_http::DEFAULT_PORT)).ok();
@@ -110,7 +126,10 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     println!("Found EverDrive");
 
-    let rom = fs::read("target/mips-nintendo64-none/release/game.n64").unwrap();
+    let mut rom = fs::read("target/mips-nintendo64-none/release/game.n64").unwrap();
+    let rom_fs = fs::read("target/mips-nintendo64-none/release/rom_fs.bin").unwrap();
+
+    append_rom_fs(&mut rom, &rom_fs);
 
     {
         write_cmd(&mut *ed, b'W', 0x10000000, rom.len() as u32, 0);
@@ -119,6 +138,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 
     let mut profiler = N64Profiler::default();
+    let mut capture = FrameCapture::default();
 
     loop {
         let mut buf = [0; 32];
@@ -159,5 +179,15 @@ fn main() -> Result<(), Box<dyn Error>> {
                 profiler.flush_frame();
             }
         }
+        if buf[0] == MESSAGE_MAGIC_CAPTURE {
+            let mut msg = [0; size_of::<CaptureMessageBuffer>()];
+            msg[0] = buf[0];
+            ed.read_exact(&mut msg[1..])?;
+
+            let capture_message =
+                LayoutVerified::<&[u8], CaptureMessageBuffer>::new_unaligned(&msg[..]).unwrap();
+
+            capture.submit_chunk(capture_message.into_ref())?;
+        }
     }
 }
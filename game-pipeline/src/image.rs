@@ -9,10 +9,32 @@ pub(crate) struct Image {
     pub(crate) data: Vec<u8>,
 }
 
+// Loads the image as RGBA 5551, big endian
 pub(crate) fn load_png(
     path: impl AsRef<Path>,
     rotate_180: bool,
     size: Option<(i32, i32)>,
+) -> Result<Image, Box<dyn Error>> {
+    let image = load_png_rgba8(path, rotate_180, size)?;
+
+    let mut data = Vec::with_capacity((2 * image.width * image.height) as usize);
+
+    for pixel in image.data.chunks_exact(4) {
+        let color = Color::from_bytes(pixel.assert_into());
+        data.extend(color.value().to_be_bytes());
+    }
+
+    Ok(Image {
+        width: image.width,
+        height: image.height,
+        data,
+    })
+}
+
+pub(crate) fn load_png_rgba8(
+    path: impl AsRef<Path>,
+    rotate_180: bool,
+    size: Option<(i32, i32)>,
 ) -> Result<Image, Box<dyn Error>> {
     println!("rerun-if-changed={}", path.as_ref().to_string_lossy());
 
@@ -88,18 +110,10 @@ pub(crate) fn load_png(
 
     let image_width = image.width().assert_into();
     let image_height = image.height().assert_into();
-    let buf = image.into_raw();
-
-    let mut data = Vec::with_capacity((2 * image_width * image_height) as usize);
-
-    for pixel in buf.chunks_exact(4) {
-        let color = Color::from_bytes(pixel.assert_into());
-        data.extend(color.value().to_be_bytes());
-    }
 
     Ok(Image {
         width: image_width,
         height: image_height,
-        data,
+        data: image.into_raw(),
     })
 }
@@ -1,15 +1,24 @@
+use rom_fs::RomFs;
 use std::path::Path;
 
 pub mod image;
 pub mod maps;
 pub mod models;
+pub mod rom_fs;
 pub mod sounds;
 pub mod textures;
 pub mod utils;
 
 pub fn run(out_dir: &Path) {
-    textures::parse();
-    maps::parse(out_dir);
-    sounds::parse();
-    models::parse();
+    let mut rom_fs = RomFs::new();
+
+    textures::parse(&mut rom_fs);
+    maps::parse(&mut rom_fs);
+    sounds::parse(&mut rom_fs);
+    models::parse(&mut rom_fs);
+
+    // Written next to the game executable, out_dir is target/<triple>/<profile>/build/<crate>/out
+    rom_fs
+        .write(out_dir.ancestors().nth(3).unwrap().join("rom_fs.bin"))
+        .unwrap();
 }
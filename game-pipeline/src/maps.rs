@@ -1,7 +1,4 @@
-use crate::{
-    image::load_png, image::Image, utils::write_binary_file_if_changed,
-    utils::write_file_if_changed,
-};
+use crate::{image::load_png, image::Image, rom_fs::RomFs, utils::write_file_if_changed};
 use assert_into::AssertInto;
 use std::{
     collections::HashMap,
@@ -18,7 +15,7 @@ use tiled::{LayerData, Map, ObjectTemplate, PropertyValue, Tileset};
 
 #[rustfmt::skip]
 macro_rules! TILE_TEMPLATE { () => {
-r##"static {tile_ident}: StaticTexture = StaticTexture::from_static({width}, {height}, include_bytes_align_as!(Color, {tile_path:?}));
+r##"static {tile_ident}: StaticTexture = StaticTexture::from_rom({width}, {height}, RomAsset::new({tile_asset}));
 "##
 }; }
 
@@ -133,9 +130,8 @@ fn load_tile_image(
 }
 
 fn parse_map_tiles(
-    out_dir: &Path,
+    rom_fs: &mut RomFs,
     map_path: &Path,
-    name: &str,
     uppercase_name: &str,
     map: &Map,
     used_tile_ids: &[u32],
@@ -152,11 +148,6 @@ fn parse_map_tiles(
         let width: i32 = map.tile_width.assert_into();
         let height: i32 = map.tile_height.assert_into();
 
-        let tile_path = out_dir
-            .join(format!("{}_tile_{}", name, *id))
-            .with_extension("ntex");
-        let tile_path = tile_path.to_str().ok_or("Bad Path")?;
-
         let tileset = find_tileset_with_gid(*id, &map.tilesets)?;
         let tile_image = load_tile_image(
             *id,
@@ -168,8 +159,6 @@ fn parse_map_tiles(
             false,
         )?;
 
-        write_binary_file_if_changed(tile_path, &tile_image)?;
-
         let tile_ident = format!("{}_TILE_{}", uppercase_name, id);
 
         let tile = format!(
@@ -177,7 +166,7 @@ fn parse_map_tiles(
             tile_ident = tile_ident,
             width = width,
             height = height,
-            tile_path = tile_path,
+            tile_asset = rom_fs.add(&tile_image),
         );
 
         let tile_ref = format!(TILE_IDENT_TEMPLATE!(), tile_ident = tile_ident);
@@ -191,7 +180,7 @@ fn parse_map_tiles(
 
 #[rustfmt::skip]
 macro_rules! OBJECT_TEXTURE_TEMPLATE { () => {
-r##"static {object_texture_ident}: StaticTexture = StaticTexture::from_static({width}, {height}, include_bytes_align_as!(Color, {object_texture_path:?}));
+r##"static {object_texture_ident}: StaticTexture = StaticTexture::from_rom({width}, {height}, RomAsset::new({object_texture_asset}));
 "##
 }; }
 
@@ -223,7 +212,7 @@ r##"crate::components::spawner::SpawnerData::SpawnerWithTexture {{
 
 fn parse_map_objects(
     map: &Map,
-    out_dir: &Path,
+    rom_fs: &mut RomFs,
     map_path: &Path,
     tileset_image_cache: &mut HashMap<PathBuf, Image>,
     emitted_object_texture: &mut HashSet<String>,
@@ -265,10 +254,6 @@ fn parse_map_objects(
                     ));
 
                     if !emitted_object_texture.contains(&object_texture_ident) {
-                        let object_texture_path = out_dir
-                            .join(format!("object_{}_{}", tileset.name, template_object.gid))
-                            .with_extension("ntex");
-
                         let texture_image = load_tile_image(
                             template_object.gid,
                             map_path,
@@ -285,14 +270,12 @@ fn parse_map_objects(
                                     * template_object.height as usize
                         );
 
-                        write_binary_file_if_changed(&object_texture_path, &texture_image)?;
-
                         object_textures.push(format!(
                             OBJECT_TEXTURE_TEMPLATE!(),
                             object_texture_ident = object_texture_ident,
                             width = template_object.width as i32,
                             height = template_object.height as i32,
-                            object_texture_path = object_texture_path,
+                            object_texture_asset = rom_fs.add(&texture_image),
                         ));
 
                         emitted_object_texture.insert(object_texture_ident);
@@ -348,7 +331,7 @@ pub static {map_name_ident}: &StaticMapData = &StaticMapData {{
     tile_width: {tile_width},
     tile_height: {tile_height},
     tiles: {tiles_name_ident},
-    layers: include_bytes!({map_data_path:?}),
+    layers: RomAsset::new({map_data_asset}),
     objects: {objects_name_ident},
 }};"##
 }; }
@@ -360,16 +343,15 @@ r##"// This file is generated
 #![cfg_attr(rustfmt, rustfmt::skip)]
 
 use crate::map::{{StaticMapData, StaticObject}};
-use n64_math::Color;
 use n64::gfx::StaticTexture;
-use n64::include_bytes_align_as;
+use n64::rom::RomAsset;
 
 {tiles}
 {maps}
 "##
 }; }
 
-pub fn parse(out_dir: &Path) {
+pub fn parse(rom_fs: &mut RomFs) {
     let mut maps = Vec::new();
     let mut tiles = Vec::new();
 
@@ -423,9 +405,8 @@ pub fn parse(out_dir: &Path) {
         }
 
         let (map_tiles, map_tile_refs) = parse_map_tiles(
-            out_dir,
+            rom_fs,
             &path,
-            name,
             &uppercase_name,
             &map,
             &used_tile_ids,
@@ -435,14 +416,9 @@ pub fn parse(out_dir: &Path) {
 
         tiles.extend_from_slice(&map_tiles);
 
-        let map_data_path = out_dir.join(name).with_extension("nmap");
-        let map_data_path = map_data_path.to_str().unwrap();
-
-        write_binary_file_if_changed(map_data_path, &layers).unwrap();
-
         let (objects, object_textures) = parse_map_objects(
             &map,
-            out_dir,
+            rom_fs,
             &path,
             &mut tileset_image_cache,
             &mut emitted_object_texture,
@@ -466,7 +442,7 @@ pub fn parse(out_dir: &Path) {
             map_height = map_height,
             tile_width = tile_width,
             tile_height = tile_height,
-            map_data_path = map_data_path,
+            map_data_asset = rom_fs.add(&layers),
             object_textures = object_textures.join(""),
             objects = objects.join(""),
             objects_name_ident = objects_name_ident,
@@ -1,18 +1,18 @@
-use crate::utils::{write_binary_file_if_changed, write_file_if_changed};
+use crate::{rom_fs::RomFs, utils::write_file_if_changed};
 use assert_into::AssertInto;
 use blend::{Blend, Instance};
 use meshopt::{generate_vertex_remap, remap_index_buffer, remap_vertex_buffer};
 use n64_math::{vec2, Vec2, Vec3};
-use std::{env, ffi::OsStr, fs, path::Path};
+use std::{env, ffi::OsStr, fs};
 use zerocopy::AsBytes;
 
 #[rustfmt::skip]
 macro_rules! MODEL_TEMPLATE { () => {
 r##"pub static {name}: StaticModelData = StaticModelData {{
-    verts: include_bytes_align_as!(Vec3, {verts_path:?}),
-    uvs: include_bytes_align_as!(Vec2, {uvs_path:?}),
-    colors: include_bytes_align_as!(u32, {colors_path:?}),
-    indices: include_bytes_align_as!(u8, {indices_path:?}),
+    verts: RomAsset::new({verts}),
+    uvs: RomAsset::new({uvs}),
+    colors: RomAsset::new({colors}),
+    indices: RomAsset::new({indices}),
     size: const_vec2!([{model_width}_f32, {model_height}_f32]),
 }};
 "##
@@ -25,8 +25,8 @@ r##"// This file is generated
 #![cfg_attr(rustfmt, rustfmt::skip)]
 
 use crate::model::StaticModelData;
-use n64::include_bytes_align_as;
-use n64_math::{{Vec2, Vec3, const_vec2}};
+use n64::rom::RomAsset;
+use n64_math::const_vec2;
 
 {models}"##
 }; }
@@ -217,7 +217,7 @@ fn parse_gltf_model(mesh: &gltf::Mesh, buffers: &[gltf::buffer::Data]) -> Option
     None
 }
 
-pub(crate) fn parse() {
+pub(crate) fn parse(rom_fs: &mut RomFs) {
     let mut models = String::new();
 
     for path in fs::read_dir("models")
@@ -236,10 +236,9 @@ pub(crate) fn parse() {
                     let data = obj.get("data");
 
                     let name = format!("{}", file_name);
-                    let out_base_path = path.canonicalize().unwrap().with_file_name(&name);
 
                     if let Some(model) = parse_model(data) {
-                        output_model(&mut models, &name, &out_base_path, &model);
+                        output_model(&mut models, rom_fs, &name, &model);
                     }
                     break;
                 }
@@ -257,13 +256,12 @@ pub(crate) fn parse() {
 
         if let Some(file_name) = path.file_stem().map(|n| n.to_string_lossy()) {
             let name = format!("{}", file_name);
-            let out_base_path = path.canonicalize().unwrap().with_file_name(&name);
 
             let (gltf, buffers, _) = gltf::import(&path).unwrap();
 
             for mesh in gltf.meshes() {
                 if let Some(model) = parse_gltf_model(&mesh, &buffers) {
-                    output_model(&mut models, &name, &out_base_path, &model);
+                    output_model(&mut models, rom_fs, &name, &model);
                     break;
                 }
             }
@@ -279,25 +277,14 @@ pub(crate) fn parse() {
     .unwrap();
 }
 
-fn output_model(models: &mut String, name: &str, out_base_path: &Path, model: &Model) {
-    let verts_path = out_base_path.with_extension("nvert");
-    let uvs_path = out_base_path.with_extension("nuv");
-    let colors_path = out_base_path.with_extension("ncol");
-    let indices_path = out_base_path.with_extension("nind");
-
-    write_binary_file_if_changed(&verts_path, byteswap_u32_slice(model.verts.as_bytes())).unwrap();
-    write_binary_file_if_changed(&uvs_path, byteswap_u32_slice(model.uvs.as_bytes())).unwrap();
-    write_binary_file_if_changed(&colors_path, byteswap_u32_slice(model.colors.as_bytes()))
-        .unwrap();
-    write_binary_file_if_changed(&indices_path, model.indices.as_bytes()).unwrap();
-
+fn output_model(models: &mut String, rom_fs: &mut RomFs, name: &str, model: &Model) {
     models.push_str(&format!(
         MODEL_TEMPLATE!(),
         name = name.to_uppercase(),
-        verts_path = verts_path,
-        uvs_path = uvs_path,
-        colors_path = colors_path,
-        indices_path = indices_path,
+        verts = rom_fs.add(byteswap_u32_slice(model.verts.as_bytes())),
+        uvs = rom_fs.add(byteswap_u32_slice(model.uvs.as_bytes())),
+        colors = rom_fs.add(byteswap_u32_slice(model.colors.as_bytes())),
+        indices = rom_fs.add(model.indices.as_bytes()),
         model_width = model.size.x,
         model_height = model.size.y,
     ));
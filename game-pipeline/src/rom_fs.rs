@@ -0,0 +1,66 @@
+use crate::utils::write_binary_file_if_changed;
+use assert_into::AssertInto;
+use n64_types::{ROM_FS_ALIGNMENT, ROM_FS_ENTRY_SIZE, ROM_FS_HEADER_SIZE, ROM_FS_MAGIC};
+use std::{collections::HashMap, error::Error, path::Path};
+
+#[derive(Default)]
+pub struct RomFs {
+    assets: Vec<Vec<u8>>,
+    indices: HashMap<Vec<u8>, u32>,
+}
+
+impl RomFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns the index the generated code uses to refer to the asset, identical assets are
+    // only stored once
+    pub fn add(&mut self, data: impl AsRef<[u8]>) -> u32 {
+        let data = data.as_ref();
+
+        if let Some(index) = self.indices.get(data) {
+            return *index;
+        }
+
+        let index = self.assets.len().assert_into();
+
+        self.assets.push(data.to_vec());
+        self.indices.insert(data.to_vec(), index);
+
+        index
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let table_size = ROM_FS_HEADER_SIZE + ROM_FS_ENTRY_SIZE * self.assets.len();
+
+        let mut table = Vec::with_capacity(table_size);
+        let mut data = Vec::new();
+
+        table.extend_from_slice(&ROM_FS_MAGIC);
+        table.extend_from_slice(&(self.assets.len() as u32).to_be_bytes());
+
+        for asset in &self.assets {
+            let offset = align(table_size) + data.len();
+
+            table.extend_from_slice(&(offset as u32).to_be_bytes());
+            table.extend_from_slice(&(asset.len() as u32).to_be_bytes());
+
+            data.extend_from_slice(asset);
+            data.resize(align(data.len()), 0);
+        }
+
+        table.resize(align(table.len()), 0);
+        table.extend_from_slice(&data);
+
+        table
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        write_binary_file_if_changed(path, self.to_bytes())
+    }
+}
+
+fn align(offset: usize) -> usize {
+    (offset + ROM_FS_ALIGNMENT - 1) & !(ROM_FS_ALIGNMENT - 1)
+}
@@ -1,4 +1,4 @@
-use crate::utils::{write_binary_file_if_changed, write_file_if_changed};
+use crate::{rom_fs::RomFs, utils::write_file_if_changed};
 use itertools::Itertools;
 use std::{env, ffi::OsStr, fs, path::Path};
 use zerocopy::AsBytes;
@@ -38,7 +38,7 @@ fn load_wav(path: impl AsRef<Path>) -> Vec<i16> {
 
 #[rustfmt::skip]
 macro_rules! SOUND_TEMPLATE { () => {
-r##"pub static {name}: StaticSoundData = StaticSoundData {{ data: include_bytes_align_as!(i16, {path:?}) }};
+r##"pub static {name}: StaticSoundData = StaticSoundData {{ data: RomAsset::new({asset}) }};
 "##
 }; }
 
@@ -49,12 +49,12 @@ r##"// This file is generated
 #![cfg_attr(rustfmt, rustfmt::skip)]
 
 use crate::sound::StaticSoundData;
-use n64::include_bytes_align_as;
+use n64::rom::RomAsset;
 
 {sounds}"##
 }; }
 
-pub(crate) fn parse() {
+pub(crate) fn parse(rom_fs: &mut RomFs) {
     let mut sounds = String::new();
 
     for path in fs::read_dir("sounds")
@@ -64,15 +64,12 @@ pub(crate) fn parse() {
         .filter(|path| path.extension() == Some(OsStr::new("wav")))
     {
         if let Some(name) = path.file_stem().map(|n| n.to_string_lossy()) {
-            let out_path = path.canonicalize().unwrap().with_extension("nsnd");
             let wav = load_wav(&path);
 
-            write_binary_file_if_changed(&out_path, wav.as_bytes()).unwrap();
-
             sounds.push_str(&format!(
                 SOUND_TEMPLATE!(),
                 name = name.to_uppercase(),
-                path = out_path,
+                asset = rom_fs.add(wav.as_bytes()),
             ));
         }
     }
@@ -1,9 +1,16 @@
-use crate::{image::load_png, utils::write_binary_file_if_changed, utils::write_file_if_changed};
-use std::{env, ffi::OsStr, fs};
+use crate::{
+    image::{load_png_rgba8, Image},
+    rom_fs::RomFs,
+    utils::write_file_if_changed,
+};
+use assert_into::AssertInto;
+use n64_math::Color;
+use n64_types::TextureFormat;
+use std::{env, error::Error, ffi::OsStr, fs, path::Path};
 
 #[rustfmt::skip]
 macro_rules! TEXTURE_TEMPLATE { () => {
-r##"pub static {name}: StaticTexture = StaticTexture::from_static({width}, {height}, include_bytes_align_as!(TextureAlignment, {path:?}));
+r##"pub static {name}: StaticTexture = StaticTexture::from_rom({width}, {height}, RomAsset::new({asset})){format}{palette}{mip_levels};
 "##
 }; }
 
@@ -13,13 +20,209 @@ r##"// This file is generated
 
 #![cfg_attr(rustfmt, rustfmt::skip)]
 
-use n64::gfx::{{StaticTexture, TextureAlignment}};
-use n64::include_bytes_align_as;
+use n64::gfx::StaticTexture;
+use n64::rom::RomAsset;
 
 {textures}"##
 }; }
 
-pub(crate) fn parse() {
+// Matches the render tiles the RDP has for mip levels
+const MAX_MIP_LEVELS: usize = 7;
+
+// The format is picked from a second extension, `name.ia4.png`, and defaults to RGBA16
+fn format_from_extension(extension: &str) -> Option<TextureFormat> {
+    match extension {
+        "rgba16" => Some(TextureFormat::Rgba16),
+        "rgba32" => Some(TextureFormat::Rgba32),
+        "ci4" => Some(TextureFormat::Ci4),
+        "ci8" => Some(TextureFormat::Ci8),
+        "i4" => Some(TextureFormat::I4),
+        "i8" => Some(TextureFormat::I8),
+        "ia4" => Some(TextureFormat::Ia4),
+        "ia8" => Some(TextureFormat::Ia8),
+        "ia16" => Some(TextureFormat::Ia16),
+        _ => None,
+    }
+}
+
+fn intensity(pixel: &[u8]) -> u8 {
+    ((pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) / 3) as u8
+}
+
+// Packs texels of at most 8 bits, two 4 bit texels go in a byte with the first in the high nibble
+fn pack(bits: usize, texels: impl Iterator<Item = u8>) -> Vec<u8> {
+    if bits == 8 {
+        return texels.collect();
+    }
+
+    let texels = texels.collect::<Vec<_>>();
+
+    texels
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair.get(1).map_or(0, |texel| texel & 0xf))
+        .collect()
+}
+
+// Halves the image, color indexed textures are point sampled so no colors are added to the palette
+fn downsample(image: &Image, point_sample: bool) -> Image {
+    let width = (image.width / 2).max(1);
+    let height = (image.height / 2).max(1);
+
+    let mut data = Vec::with_capacity((4 * width * height) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            for channel in 0..4 {
+                let texel = |dx: i32, dy: i32| {
+                    let sx = (2 * x + dx).min(image.width - 1);
+                    let sy = (2 * y + dy).min(image.height - 1);
+                    image.data[(4 * (sy * image.width + sx) + channel) as usize] as u32
+                };
+
+                data.push(if point_sample {
+                    texel(0, 0) as u8
+                } else {
+                    ((texel(0, 0) + texel(1, 0) + texel(0, 1) + texel(1, 1) + 2) / 4) as u8
+                });
+            }
+        }
+    }
+
+    Image {
+        width,
+        height,
+        data,
+    }
+}
+
+// Levels halve down to a single texel, 4 bit textures stop at 2 texels wide as the RDP loads
+// them as pairs
+fn mip_chain(
+    image: Image,
+    format: TextureFormat,
+    path: &Path,
+) -> Result<Vec<Image>, Box<dyn Error>> {
+    if !(image.width as u32).is_power_of_two() || !(image.height as u32).is_power_of_two() {
+        return Err(format!(
+            "{} is {}x{}, mipmapped textures need power of two sizes",
+            path.to_string_lossy(),
+            image.width,
+            image.height
+        )
+        .into());
+    }
+
+    let min_width = if format.bits_per_texel() == 4 { 2 } else { 1 };
+
+    let mut levels = vec![image];
+
+    while levels.len() < MAX_MIP_LEVELS {
+        let last = levels.last().unwrap();
+
+        if (last.width / 2 < min_width) || (last.width == 1 && last.height == 1) {
+            break;
+        }
+
+        let level = downsample(last, format.palette_size() > 0);
+        levels.push(level);
+    }
+
+    Ok(levels)
+}
+
+struct EncodedTexture {
+    data: Vec<u8>,
+    // Big endian RGBA16 entries, only for color indexed formats
+    palette: Option<Vec<u8>>,
+}
+
+// Mip levels follow each other, each starting 8 byte aligned like n64::gfx::Texture expects.
+// Color indexed levels share one palette.
+fn encode(
+    levels: &[Image],
+    format: TextureFormat,
+    path: &Path,
+) -> Result<EncodedTexture, Box<dyn Error>> {
+    let mut data = Vec::new();
+    let mut palette = Vec::new();
+
+    for image in levels {
+        data.resize((data.len() + 7) & !7, 0);
+
+        let pixels = image.data.chunks_exact(4);
+
+        match format {
+            TextureFormat::Rgba16 => data
+                .extend(pixels.flat_map(|pixel| {
+                    Color::from_bytes(pixel.assert_into()).value().to_be_bytes()
+                })),
+            TextureFormat::Rgba32 => data.extend_from_slice(&image.data),
+            TextureFormat::Ci4 | TextureFormat::Ci8 => {
+                let mut indices = Vec::new();
+
+                for pixel in pixels {
+                    let color = Color::from_bytes(pixel.assert_into()).value();
+
+                    let index = match palette.iter().position(|entry| *entry == color) {
+                        Some(index) => index,
+                        None => {
+                            palette.push(color);
+                            palette.len() - 1
+                        }
+                    };
+
+                    indices.push(index as u8);
+                }
+
+                data.extend(pack(format.bits_per_texel(), indices.into_iter()));
+            }
+            TextureFormat::I4 => data.extend(pack(4, pixels.map(|pixel| intensity(pixel) >> 4))),
+            TextureFormat::I8 => data.extend(pack(8, pixels.map(intensity))),
+            TextureFormat::Ia4 => data.extend(pack(
+                4,
+                pixels.map(|pixel| ((intensity(pixel) >> 5) << 1) | (pixel[3] >= 0x80) as u8),
+            )),
+            TextureFormat::Ia8 => data.extend(pack(
+                8,
+                pixels.map(|pixel| (intensity(pixel) & 0xf0) | (pixel[3] >> 4)),
+            )),
+            TextureFormat::Ia16 => {
+                data.extend(pixels.flat_map(|pixel| [intensity(pixel), pixel[3]]))
+            }
+        }
+    }
+
+    if format.palette_size() == 0 {
+        return Ok(EncodedTexture {
+            data,
+            palette: None,
+        });
+    }
+
+    if palette.len() > format.palette_size() {
+        return Err(format!(
+            "{} has {} colors, {} fit in its palette",
+            path.to_string_lossy(),
+            palette.len(),
+            format.palette_size()
+        )
+        .into());
+    }
+
+    palette.resize(format.palette_size(), 0);
+
+    Ok(EncodedTexture {
+        data,
+        palette: Some(
+            palette
+                .iter()
+                .flat_map(|entry| entry.to_be_bytes())
+                .collect(),
+        ),
+    })
+}
+
+pub(crate) fn parse(rom_fs: &mut RomFs) {
     let mut textures = String::new();
 
     for path in fs::read_dir("textures")
@@ -28,18 +231,57 @@ pub(crate) fn parse() {
         .map(|e| e.path())
         .filter(|path| path.extension() == Some(OsStr::new("png")))
     {
-        if let Some(name) = path.file_stem().map(|n| n.to_string_lossy()) {
-            let out_path = path.canonicalize().unwrap().with_extension("ntex");
-            let image = load_png(path.as_path(), false, None).unwrap();
+        if let Some(stem) = path.file_stem().map(|n| n.to_string_lossy()) {
+            // `name.ci4.mip.png` picks the format and asks for mipmaps
+            let mut extensions = stem.split('.');
+            let name = extensions.next().unwrap();
+            let mut format = TextureFormat::Rgba16;
+            let mut mipmapped = false;
+
+            for extension in extensions {
+                if extension == "mip" {
+                    mipmapped = true;
+                } else {
+                    format = format_from_extension(extension).unwrap_or_else(|| {
+                        panic!("Unknown texture format {} for {}", extension, name)
+                    });
+                }
+            }
+
+            let image = load_png_rgba8(path.as_path(), false, None).unwrap();
+            let (width, height) = (image.width, image.height);
+
+            let levels = if mipmapped {
+                mip_chain(image, format, &path).unwrap()
+            } else {
+                vec![image]
+            };
 
-            write_binary_file_if_changed(&out_path, &image.data).unwrap();
+            let texture = encode(&levels, format, &path).unwrap();
 
             textures.push_str(&format!(
                 TEXTURE_TEMPLATE!(),
                 name = name.to_uppercase(),
-                width = image.width,
-                height = image.height,
-                path = out_path
+                width = width,
+                height = height,
+                asset = rom_fs.add(&texture.data),
+                format = if format == TextureFormat::Rgba16 {
+                    String::new()
+                } else {
+                    format!(".with_format(n64::gfx::TextureFormat::{:?})", format)
+                },
+                palette = texture
+                    .palette
+                    .map(|palette| format!(
+                        ".with_palette(RomAsset::new({}))",
+                        rom_fs.add(&palette)
+                    ))
+                    .unwrap_or_default(),
+                mip_levels = if levels.len() > 1 {
+                    format!(".with_mip_levels({})", levels.len())
+                } else {
+                    String::new()
+                },
             ));
         }
     }
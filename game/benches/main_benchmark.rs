@@ -8,16 +8,18 @@ use game::{
         print_position, projectile, remove_when_below, spawner, trap, waypoint_ai,
     },
     ecs::world::World,
+    game_loop::GameLoop,
     map::Map,
     maps::MAP_1,
     sound_mixer::SoundMixer,
 };
-use n64::{Controllers, VideoMode};
+use n64::{ControllerInput, Controllers, InputScript, VideoFormat, VideoMode, N64};
 use n64_math::vec2;
 
 const VIDEO_MODE: VideoMode = VideoMode::Pal {
     width: 320,
     height: 240,
+    format: VideoFormat::default(),
 };
 
 fn criterion_benchmark(c: &mut Criterion) {
@@ -60,5 +62,23 @@ fn criterion_benchmark(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, criterion_benchmark);
+fn headless_benchmark(c: &mut Criterion) {
+    let mut input = InputScript::new();
+    input.hold(
+        0,
+        ControllerInput::default().with_buttons(ControllerInput::Z),
+    );
+
+    let Some(mut n64) = N64::new_headless(VIDEO_MODE, input) else {
+        eprintln!("Skipping game_loop_headless, there is no graphics adapter");
+        return;
+    };
+    let mut game_loop = GameLoop::new(&n64);
+
+    c.bench_function("game_loop_headless", |b| {
+        b.iter(|| game_loop.frame(&mut n64))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark, headless_benchmark);
 criterion_main!(benches);
@@ -21,7 +21,7 @@ use crate::{
 };
 use core::f32::consts::PI;
 use game_derive::SparseComponent;
-use n64::gfx::Texture;
+use n64::gfx::{CullMode, Texture};
 use n64_math::{Quat, Vec2};
 
 #[derive(SparseComponent)]
@@ -116,6 +116,7 @@ pub fn spawn_boss(
         .add(MeshDrawable {
             model,
             rot: Quat::IDENTITY,
+            cull_mode: CullMode::None,
         })
         .add(Shadow)
         .add(Health {
@@ -11,7 +11,7 @@ use n64::{
         color_combiner_mode::{
             AAlphaSrc, ASrc, BAlphaSrc, BSrc, CAlphaSrc, CSrc, ColorCombinerMode, DAlphaSrc, DSrc,
         },
-        CommandBuffer, Pipeline,
+        CommandBuffer, CullMode, Pipeline,
     },
     VideoMode,
 };
@@ -21,6 +21,8 @@ use n64_math::{vec3, Mat4, Quat};
 pub struct MeshDrawable {
     pub model: ModelData<'static>,
     pub rot: Quat,
+    // Back for closed models, open ones like flat projectiles are seen from both sides
+    pub cull_mode: CullMode,
 }
 
 static MESH_PIPELINE: Pipeline = Pipeline {
@@ -57,7 +59,7 @@ pub fn draw(world: &mut World, cb: &mut CommandBuffer, video_mode: VideoMode, ca
     for (_e, mesh_drawable, movable, health) in
         query::<(MeshDrawable, Movable, Option<Health>)>(&mut world.components)
     {
-        let mut pipeline = MESH_PIPELINE;
+        let mut pipeline = MESH_PIPELINE.with_cull_mode(mesh_drawable.cull_mode);
 
         if let Some(health) = health {
             if health.damaged_this_frame {
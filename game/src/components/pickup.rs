@@ -18,6 +18,7 @@ use crate::{
     sounds::PICKUP_1,
 };
 use game_derive::SparseComponent;
+use n64::gfx::CullMode;
 use n64_math::{random_u32, vec2, Aabb2, Quat, Vec2};
 use strum::{EnumCount, IntoEnumIterator};
 
@@ -37,6 +38,7 @@ pub fn spawn_pickup(entities: &mut EntitySystem, start_pos: Vec2) -> Entity {
         .add(MeshDrawable {
             model: WEAPON_PICKUP.as_model_data(),
             rot: Quat::IDENTITY,
+            cull_mode: CullMode::None,
         })
         .add(Pickup)
         .add(RemoveWhenBelow)
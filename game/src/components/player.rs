@@ -23,7 +23,10 @@ use crate::{
 };
 use core::f32::consts::PI;
 use game_derive::SparseComponent;
-use n64::{gfx::CommandBuffer, Controllers, VideoMode};
+use n64::{
+    gfx::{CommandBuffer, CullMode},
+    Controllers, VideoMode,
+};
 use n64_math::{const_vec2, vec2, Quat, Vec2, Vec3};
 
 const PLAYER_START_POS: Vec2 = const_vec2!([0.5, 0.8]);
@@ -45,6 +48,7 @@ pub fn spawn_player(entities: &mut EntitySystem, start_pos: Vec2) -> Entity {
         .add(MeshDrawable {
             model: SHIP_3.as_model_data(),
             rot: Quat::IDENTITY,
+            cull_mode: CullMode::Back,
         })
         .add(Shadow)
         .add(Health {
@@ -9,14 +9,24 @@ use n64::{
         color_combiner_mode::{
             AAlphaSrc, ASrc, BAlphaSrc, BSrc, CAlphaSrc, CSrc, ColorCombinerMode, DAlphaSrc, DSrc,
         },
-        CommandBuffer, Pipeline, Texture,
+        CommandBuffer, Pipeline, Sprite, SpriteBatch, Texture,
     },
     VideoMode,
 };
 use n64_math::Vec2;
 
+// The tint is added to the texel, it flashes sprites that take damage
 static SPRITE_PIPELINE: Pipeline = Pipeline {
-    color_combiner_mode: ColorCombinerMode::single(DSrc::Texel),
+    color_combiner_mode: ColorCombinerMode::one(
+        ASrc::One,
+        BSrc::Zero,
+        CSrc::Texel,
+        DSrc::Primitive,
+        AAlphaSrc::Zero,
+        BAlphaSrc::Zero,
+        CAlphaSrc::Zero,
+        DAlphaSrc::TexelAlpha,
+    ),
     blend: true,
     ..Pipeline::default()
 };
@@ -29,39 +39,26 @@ pub struct SpriteDrawable {
 pub fn draw(world: &mut World, cb: &mut CommandBuffer, video_mode: VideoMode, camera: &Camera) {
     n64::scope!("sprite_drawable::draw");
 
+    let screen_size = Vec2::new(video_mode.width() as f32, video_mode.height() as f32);
+    let mut batch = SpriteBatch::new(SPRITE_PIPELINE);
+
     for (_e, sprite_drawable, movable, size, health) in
         query::<(SpriteDrawable, Movable, Size, Option<Health>)>(&mut world.components)
     {
-        let half_size = size.size / 2.0;
-
-        let upper_left = movable.pos - half_size;
-        let lower_right = movable.pos + half_size;
-
-        let screen_size = Vec2::new(video_mode.width() as f32, video_mode.height() as f32);
-
-        let mut pipeline = SPRITE_PIPELINE.with_texture(Some(sprite_drawable.texture));
+        let tint = match health {
+            Some(health) if health.damaged_this_frame => 0xa0a0a0ff,
+            _ => 0,
+        };
 
-        if let Some(health) = health {
-            if health.damaged_this_frame {
-                pipeline.color_combiner_mode = ColorCombinerMode::one(
-                    ASrc::One,
-                    BSrc::Zero,
-                    CSrc::Texel,
-                    DSrc::Primitive,
-                    AAlphaSrc::Zero,
-                    BAlphaSrc::Zero,
-                    CAlphaSrc::Zero,
-                    DAlphaSrc::TexelAlpha,
-                );
-                pipeline.prim_color = Some(0xa0a0a0ff);
-            }
-        }
-
-        cb.set_pipeline(&pipeline);
-
-        cb.add_textured_rect(
-            (upper_left - camera.pos) * screen_size,
-            (lower_right - camera.pos) * screen_size,
+        batch.add(
+            Sprite::new(
+                sprite_drawable.texture,
+                (movable.pos - camera.pos) * screen_size,
+                size.size * screen_size,
+            )
+            .with_tint(tint),
         );
     }
+
+    cb.add_sprite_batch(&mut batch);
 }
@@ -19,6 +19,7 @@ use crate::{
 };
 use core::f32::consts::PI;
 use game_derive::SparseComponent;
+use n64::gfx::CullMode;
 use n64_math::{vec2, Mat2, Quat, Vec2, Vec3};
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -73,6 +74,7 @@ fn shoot_bullet(entities: &mut EntitySystem, target_type: WeaponTarget, pos: Vec
         .add(MeshDrawable {
             model: BULLET.as_model_data(),
             rot: Quat::from_axis_angle(Vec3::Z, angle + PI / 2.0),
+            cull_mode: CullMode::None,
         })
         .add(Projectile {
             target_type,
@@ -120,6 +122,7 @@ pub fn shoot_missile(
         .add(MeshDrawable {
             model: MISSILE.as_model_data(),
             rot: Quat::IDENTITY,
+            cull_mode: CullMode::None,
         })
         .add(Projectile {
             target_type,
@@ -28,7 +28,7 @@ use n64::{
     current_time_us,
     gfx::{
         color_combiner_mode::{ASrc, BSrc, CSrc, ColorCombinerMode, DSrc},
-        CommandBuffer, Pipeline,
+        CommandBuffer, CullMode, Pipeline,
     },
     VideoMode,
 };
@@ -91,6 +91,7 @@ pub fn shoot_bullet(
         .add(MeshDrawable {
             model: BULLET.as_model_data(),
             rot: Quat::IDENTITY,
+            cull_mode: CullMode::None,
         })
         .add(Projectile {
             target_type,
@@ -139,6 +140,7 @@ pub fn shoot_missile(
         .add(MeshDrawable {
             model: MISSILE.as_model_data(),
             rot: Quat::IDENTITY,
+            cull_mode: CullMode::None,
         })
         .add(Projectile {
             target_type,
@@ -167,6 +169,7 @@ pub fn shoot_laser(
         .add(MeshDrawable {
             model: LASER.as_model_data(),
             rot: Quat::IDENTITY,
+            cull_mode: CullMode::None,
         })
         .add(Projectile {
             target_type,
@@ -203,6 +206,7 @@ pub fn shoot_flak(
             .add(MeshDrawable {
                 model: BULLET.as_model_data(),
                 rot: Quat::IDENTITY,
+                cull_mode: CullMode::None,
             })
             .add(Projectile {
                 target_type,
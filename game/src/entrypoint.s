@@ -17,6 +17,37 @@
 // Runtime environment pointers
 .set FS_START,              0x8000031C
 
+// COP0 registers
+.set C0_STATUS,             $12
+.set C0_CAUSE,              $13
+.set C0_EPC,                $14
+
+// COP0 status bits
+.set SR_IE,                 0x00000001 // Interrupt enable
+.set SR_EXL,                0x00000002 // Exception level
+.set SR_ERL,                0x00000004 // Error level
+.set SR_IM,                 0x0000ff00 // Interrupt mask for all eight lines
+.set SR_IM2,                0x00000400 // Interrupt mask for the MI
+
+// Exception vectors, written through KSEG1 so the stubs land in RDRAM
+.set VECTOR_TLB_MISS,       0xA0000000
+.set VECTOR_XTLB_MISS,      0xA0000080
+.set VECTOR_GENERAL,        0xA0000180
+.set VECTOR_CACHED_BASE,    0x80000000
+
+// MI interrupt mask, writing the clear bit for every interrupt masks them all
+.set MI_INTR_MASK,          0xA430000C
+.set MI_INTR_MASK_CLEAR,    0x0555
+
+// Registers saved by exception_handler, caller saved GPRs and FPRs plus hi, lo and fcsr.
+// The first 16 bytes are the argument area the o32 ABI reserves for the callee.
+.set EXC_GPR,               16
+.set EXC_HI,                160
+.set EXC_LO,                168
+.set EXC_FCSR,              176
+.set EXC_FPR,               184
+.set EXC_FRAME_SIZE,        272
+
 _start:
     // Initialize stack
     li $t1, OS_MEM_SIZE
@@ -50,6 +81,50 @@ _start:
     li $t1, FS_START
     sw $t0, ($t1)
 
+    // Mask every MI interrupt until a driver registers a handler for it
+    li $t0, MI_INTR_MASK
+    li $t1, MI_INTR_MASK_CLEAR
+    sw $t1, ($t0)
+
+    // Install the exception vectors
+    la $t0, exception_vector
+    lw $t2, 0($t0)
+    lw $t3, 4($t0)
+    lw $t4, 8($t0)
+    lw $t5, 12($t0)
+
+    li $t1, VECTOR_TLB_MISS
+    sw $t2, 0($t1)
+    sw $t3, 4($t1)
+    sw $t4, 8($t1)
+    sw $t5, 12($t1)
+
+    li $t1, VECTOR_XTLB_MISS
+    sw $t2, 0($t1)
+    sw $t3, 4($t1)
+    sw $t4, 8($t1)
+    sw $t5, 12($t1)
+
+    li $t1, VECTOR_GENERAL
+    sw $t2, 0($t1)
+    sw $t3, 4($t1)
+    sw $t4, 8($t1)
+    sw $t5, 12($t1)
+
+    // Drop any stale instruction cache lines for the vectors
+    li $t1, VECTOR_CACHED_BASE
+    cache 0x10, 0x000($t1)
+    cache 0x10, 0x080($t1)
+    cache 0x10, 0x180($t1)
+
+    // Enable only MI interrupts on the CPU side, the handler acknowledges nothing else
+    mfc0 $t0, C0_STATUS
+    li $t1, ~(SR_EXL | SR_ERL | SR_IM)
+    and $t0, $t0, $t1
+    ori $t0, $t0, (SR_IE | SR_IM2)
+    mtc0 $t0, C0_STATUS
+    nop
+
     // Jump to Rust
     jal main
     nop
@@ -59,3 +134,98 @@ _start:
 1:
     j 1b
     nop
+
+// Copied to the exception vectors, jumps to the real handler which does not fit in 0x80 bytes
+exception_vector:
+    lui $k0, %hi(exception_handler)
+    addiu $k0, $k0, %lo(exception_handler)
+    jr $k0
+    nop
+
+exception_handler:
+    .set noat
+    addiu $sp, $sp, -EXC_FRAME_SIZE
+
+    sd $at, (EXC_GPR + 0 * 8)($sp)
+    sd $v0, (EXC_GPR + 1 * 8)($sp)
+    sd $v1, (EXC_GPR + 2 * 8)($sp)
+    sd $a0, (EXC_GPR + 3 * 8)($sp)
+    sd $a1, (EXC_GPR + 4 * 8)($sp)
+    sd $a2, (EXC_GPR + 5 * 8)($sp)
+    sd $a3, (EXC_GPR + 6 * 8)($sp)
+    sd $t0, (EXC_GPR + 7 * 8)($sp)
+    sd $t1, (EXC_GPR + 8 * 8)($sp)
+    sd $t2, (EXC_GPR + 9 * 8)($sp)
+    sd $t3, (EXC_GPR + 10 * 8)($sp)
+    sd $t4, (EXC_GPR + 11 * 8)($sp)
+    sd $t5, (EXC_GPR + 12 * 8)($sp)
+    sd $t6, (EXC_GPR + 13 * 8)($sp)
+    sd $t7, (EXC_GPR + 14 * 8)($sp)
+    sd $t8, (EXC_GPR + 15 * 8)($sp)
+    sd $t9, (EXC_GPR + 16 * 8)($sp)
+    sd $ra, (EXC_GPR + 17 * 8)($sp)
+
+    mfhi $t0
+    sd $t0, EXC_HI($sp)
+    mflo $t0
+    sd $t0, EXC_LO($sp)
+    cfc1 $t0, FPC_CSR
+    sw $t0, EXC_FCSR($sp)
+
+    sdc1 $f0, (EXC_FPR + 0 * 8)($sp)
+    sdc1 $f2, (EXC_FPR + 1 * 8)($sp)
+    sdc1 $f4, (EXC_FPR + 2 * 8)($sp)
+    sdc1 $f6, (EXC_FPR + 3 * 8)($sp)
+    sdc1 $f8, (EXC_FPR + 4 * 8)($sp)
+    sdc1 $f10, (EXC_FPR + 5 * 8)($sp)
+    sdc1 $f12, (EXC_FPR + 6 * 8)($sp)
+    sdc1 $f14, (EXC_FPR + 7 * 8)($sp)
+    sdc1 $f16, (EXC_FPR + 8 * 8)($sp)
+    sdc1 $f18, (EXC_FPR + 9 * 8)($sp)
+
+    // __n64_exception(cause, epc) in n64-sys dispatches to the registered handlers
+    mfc0 $a0, C0_CAUSE
+    mfc0 $a1, C0_EPC
+    jal __n64_exception
+    nop
+
+    ldc1 $f0, (EXC_FPR + 0 * 8)($sp)
+    ldc1 $f2, (EXC_FPR + 1 * 8)($sp)
+    ldc1 $f4, (EXC_FPR + 2 * 8)($sp)
+    ldc1 $f6, (EXC_FPR + 3 * 8)($sp)
+    ldc1 $f8, (EXC_FPR + 4 * 8)($sp)
+    ldc1 $f10, (EXC_FPR + 5 * 8)($sp)
+    ldc1 $f12, (EXC_FPR + 6 * 8)($sp)
+    ldc1 $f14, (EXC_FPR + 7 * 8)($sp)
+    ldc1 $f16, (EXC_FPR + 8 * 8)($sp)
+    ldc1 $f18, (EXC_FPR + 9 * 8)($sp)
+
+    lw $t0, EXC_FCSR($sp)
+    ctc1 $t0, FPC_CSR
+    ld $t0, EXC_LO($sp)
+    mtlo $t0
+    ld $t0, EXC_HI($sp)
+    mthi $t0
+
+    ld $at, (EXC_GPR + 0 * 8)($sp)
+    ld $v0, (EXC_GPR + 1 * 8)($sp)
+    ld $v1, (EXC_GPR + 2 * 8)($sp)
+    ld $a0, (EXC_GPR + 3 * 8)($sp)
+    ld $a1, (EXC_GPR + 4 * 8)($sp)
+    ld $a2, (EXC_GPR + 5 * 8)($sp)
+    ld $a3, (EXC_GPR + 6 * 8)($sp)
+    ld $t0, (EXC_GPR + 7 * 8)($sp)
+    ld $t1, (EXC_GPR + 8 * 8)($sp)
+    ld $t2, (EXC_GPR + 9 * 8)($sp)
+    ld $t3, (EXC_GPR + 10 * 8)($sp)
+    ld $t4, (EXC_GPR + 11 * 8)($sp)
+    ld $t5, (EXC_GPR + 12 * 8)($sp)
+    ld $t6, (EXC_GPR + 13 * 8)($sp)
+    ld $t7, (EXC_GPR + 14 * 8)($sp)
+    ld $t8, (EXC_GPR + 15 * 8)($sp)
+    ld $t9, (EXC_GPR + 16 * 8)($sp)
+    ld $ra, (EXC_GPR + 17 * 8)($sp)
+
+    addiu $sp, $sp, EXC_FRAME_SIZE
+    eret
+    .set at
@@ -0,0 +1,356 @@
+use crate::{
+    camera::Camera,
+    components::{
+        box_drawable, diver_ai, enemy,
+        health::{self, Health},
+        keep_on_screen, mesh_drawable, missile, movable,
+        pickup::{self, spawn_pickup},
+        player::{self, draw_player_weapon, spawn_player, Player},
+        print_position, projectile, remove_when_below, shadow, spawner, sprite_drawable, trap,
+        waypoint_ai,
+        weapon::draw_missile_target,
+    },
+    ecs::{entity::Entity, storage::Storage, world::World},
+    font,
+    map::Map,
+    maps::MAP_1,
+    sound_mixer::SoundMixer,
+};
+use n64::{
+    self, current_time_us,
+    gfx::{CommandBuffer, CommandBufferCache, Pipeline},
+    VideoMode, N64,
+};
+use n64_math::{random_u32, vec2, vec3};
+
+const DEBUG_TRIANGLES: bool = false;
+
+// Everything the main loop keeps between frames, split out of main so tests and benchmarks can drive it
+pub struct GameLoop {
+    video_mode: VideoMode,
+
+    world: World,
+    map: Map,
+    sound_mixer: SoundMixer,
+    camera: Camera,
+    command_buffer_cache: CommandBufferCache,
+
+    player: Entity,
+
+    last_frame_begin_time: i64,
+    swap_time: i64,
+
+    last_colored_rect_count: i32,
+    last_textured_rect_count: i32,
+    last_mesh_count: i32,
+    last_rsp_clock: i32,
+
+    last_step: bool,
+}
+
+impl GameLoop {
+    pub fn new(n64: &N64) -> Self {
+        let video_mode = n64.framebuffer.video_mode();
+
+        let mut world = World::new();
+        let map = Map::load(MAP_1);
+
+        let start_pos = vec2(
+            map.get_start_pos().x / video_mode.width() as f32,
+            map.get_start_pos().y / video_mode.height() as f32 - 1.0,
+        );
+
+        let sound_mixer = SoundMixer::new();
+        let camera = Camera::new(start_pos);
+        let command_buffer_cache = CommandBufferCache::new(video_mode);
+
+        let _test_pickup = spawn_pickup(&mut world.entities, start_pos + vec2(0.5, 0.2));
+
+        let player = spawn_player(&mut world.entities, start_pos);
+
+        map.spawn_enemies(&mut world, &video_mode);
+
+        Self {
+            video_mode,
+
+            world,
+            map,
+            sound_mixer,
+            camera,
+            command_buffer_cache,
+
+            player,
+
+            last_frame_begin_time: current_time_us(),
+            swap_time: 0,
+
+            last_colored_rect_count: 0,
+            last_textured_rect_count: 0,
+            last_mesh_count: 0,
+            last_rsp_clock: 0,
+
+            last_step: false,
+        }
+    }
+
+    // Returns false once the game is over
+    pub fn frame(&mut self, n64: &mut N64) -> bool {
+        n64::frame!();
+        n64::scope!("Frame");
+
+        let frame_begin_time;
+        let dt;
+
+        {
+            frame_begin_time = current_time_us();
+            dt = (frame_begin_time - self.last_frame_begin_time) as f32 / 1e6;
+            self.last_frame_begin_time = frame_begin_time;
+        }
+
+        {
+            n64::scope!("Update");
+
+            n64.controllers.update(&n64.graphics);
+
+            self.camera.update(&n64.controllers, dt, &self.video_mode);
+
+            health::clear_was_damaged(&mut self.world);
+
+            enemy::update(&mut self.world, &mut self.sound_mixer);
+            player::update(
+                &mut self.world,
+                &n64.controllers,
+                &mut self.sound_mixer,
+                &self.camera,
+            );
+
+            diver_ai::update(&mut self.world);
+            waypoint_ai::update(&mut self.world, dt);
+            missile::update(&mut self.world, dt);
+
+            movable::simulate(&mut self.world, dt);
+
+            projectile::update(&mut self.world, &mut self.sound_mixer, &self.camera, dt);
+            trap::update(&mut self.world);
+            pickup::update(&mut self.world, &mut self.sound_mixer, &self.camera);
+            spawner::update(&mut self.world, &self.camera);
+            keep_on_screen::update(&mut self.world, &self.camera);
+            remove_when_below::update(&mut self.world, &self.camera);
+            print_position::print(&mut self.world);
+        }
+
+        {
+            n64::scope!("Audio");
+
+            n64.audio.update(|buffer| {
+                self.sound_mixer.mix(buffer);
+            });
+        }
+
+        let cb = {
+            n64::scope!("Build Command Buffer");
+
+            let mut cb = CommandBuffer::new(
+                n64.framebuffer.vi_buffer_token(),
+                &mut self.command_buffer_cache,
+            );
+
+            cb.clear();
+
+            if !DEBUG_TRIANGLES {
+                self.map.render(&mut cb, self.video_mode, &self.camera);
+
+                shadow::draw(&mut self.world, &mut cb, self.video_mode, &self.camera);
+
+                box_drawable::draw(&mut self.world, &mut cb, self.video_mode, &self.camera);
+                sprite_drawable::draw(&mut self.world, &mut cb, self.video_mode, &self.camera);
+                mesh_drawable::draw(&mut self.world, &mut cb, self.video_mode, &self.camera);
+
+                draw_missile_target(&mut self.world, &mut cb, self.video_mode, &self.camera);
+            }
+
+            if DEBUG_TRIANGLES {
+                let x_limit = 320.0;
+                let y_limit = 240.0;
+
+                let x_off = x_limit * 0.5;
+                let y_off = y_limit * 0.5;
+                let x_scale = x_limit * 0.5;
+                let y_scale = y_limit * 0.5;
+
+                let speed = 0.5; // 0.05
+                let t = speed * (frame_begin_time as f32) / 1e6;
+                let p = 2.0943951;
+                let v0 = vec3(
+                    x_off + x_scale * libm::cosf(t),
+                    y_off + y_scale * libm::sinf(t),
+                    0.0,
+                );
+                let v1 = vec3(
+                    x_off + x_scale * libm::cosf(t + p),
+                    y_off + y_scale * libm::sinf(t + p),
+                    0.0,
+                );
+                let v2 = vec3(
+                    x_off + x_scale * libm::cosf(t - p),
+                    y_off + y_scale * libm::sinf(t - p),
+                    0.0,
+                );
+
+                cb.set_pipeline(&Pipeline::default());
+                cb.add_mesh_indexed(
+                    &[v0.into(), v1.into(), v2.into()],
+                    &[[0.5, 1.0], [0.0, 0.0], [1.0, 0.0]],
+                    &[0xff_00_00_ff, 0x00_ff_00_ff, 0x00_00_ff_ff],
+                    &[[0, 1, 2]],
+                    &[
+                        [1.0, 0.0, 0.0, 0.0],
+                        [0.0, 1.0, 0.0, 0.0],
+                        [0.0, 0.0, 1.0, 0.0],
+                        [0.0, 0.0, 0.0, 1.0],
+                    ],
+                );
+            }
+
+            if !DEBUG_TRIANGLES {
+                n64::scope!("HUD");
+
+                font::draw_number(
+                    &mut cb,
+                    self.world
+                        .components
+                        .get::<(Player,)>()
+                        .lookup(self.player)
+                        .map(|p| p.score)
+                        .unwrap_or(0),
+                    vec2(300.0, 10.0),
+                    0x0000efff,
+                );
+                font::draw_number(
+                    &mut cb,
+                    self.world
+                        .components
+                        .get::<(Health,)>()
+                        .lookup(self.player)
+                        .map(|hc| hc.health)
+                        .unwrap_or(0),
+                    vec2(300.0, 215.0),
+                    0xaf0000ff,
+                );
+
+                #[cfg(target_vendor = "nintendo64")]
+                {
+                    font::draw_number(
+                        &mut cb,
+                        n64::ALLOC_BYTES_USED.load(core::sync::atomic::Ordering::SeqCst),
+                        vec2(100.0, 160.0),
+                        0xff0000ff,
+                    );
+                    font::draw_number(
+                        &mut cb,
+                        n64::ALLOC_BYTES_LEFT.load(core::sync::atomic::Ordering::SeqCst),
+                        vec2(100.0, 180.0),
+                        0xff0000ff,
+                    );
+                    font::draw_number(
+                        &mut cb,
+                        *n64::ALLOC_PAGE_OFFSET.lock() as i32,
+                        vec2(100.0, 200.0),
+                        0xff0000ff,
+                    );
+                }
+
+                {
+                    font::draw_number(&mut cb, (dt * 1e6) as i32, vec2(100.0, 10.0), 0x00af00ff);
+                    font::draw_number(
+                        &mut cb,
+                        (dt * 1e6) as i32 - self.swap_time as i32,
+                        vec2(200.0, 10.0),
+                        0x00af00ff,
+                    );
+                    font::draw_number(
+                        &mut cb,
+                        self.last_colored_rect_count,
+                        vec2(100.0, 30.0),
+                        0x00af00ff,
+                    );
+                    font::draw_number(
+                        &mut cb,
+                        self.last_textured_rect_count,
+                        vec2(200.0, 30.0),
+                        0x00af00ff,
+                    );
+                    font::draw_number(&mut cb, self.last_mesh_count, vec2(300.0, 30.0), 0xaf0000ff);
+
+                    font::draw_number(
+                        &mut cb,
+                        n64.graphics.frame_counter() as _,
+                        vec2(300.0, 50.0),
+                        0xafaf00ff,
+                    );
+
+                    // RSP clock is 62.5Mhz => ticks / 62.5e6 = s, ticks / 62.5e3 = ms, ticks / 62.5 = us
+                    // x/62.5 = 10x/625
+                    let rsp_us = (self.last_rsp_clock * 10) / 625;
+                    font::draw_number(&mut cb, rsp_us, vec2(100.0, 50.0), 0xafaf00ff);
+                }
+
+                draw_player_weapon(&mut self.world, &mut cb, &self.video_mode);
+            }
+
+            cb
+        };
+
+        self.swap_time = {
+            n64::scope!("Swap");
+            n64.graphics.swap_buffers(&mut n64.framebuffer)
+        };
+
+        // Hold R to stream the frames on screen to deploy, which saves them as PNGs
+        #[cfg(target_vendor = "nintendo64")]
+        if n64.controllers.r() {
+            n64::scope!("Capture");
+            n64.graphics.stream_frame(&n64.framebuffer);
+        }
+
+        let (colored_rect_count, textured_rect_count, mesh_count, rsp_clock) = {
+            n64::scope!("Submit Command Buffer");
+            let cb = cb;
+
+            let step = if self.last_step {
+                false
+            } else {
+                n64.controllers.start()
+            };
+
+            self.last_step = n64.controllers.start();
+
+            cb.submit(&mut n64.graphics, step)
+        };
+
+        self.last_colored_rect_count = colored_rect_count;
+        self.last_textured_rect_count = textured_rect_count;
+        self.last_mesh_count = mesh_count;
+        self.last_rsp_clock = rsp_clock;
+
+        {
+            // Cycle the random number generator
+            let now = current_time_us() as u64;
+            for _ in 0..(now & 0xf) {
+                let _ = random_u32();
+            }
+        }
+
+        {
+            n64::scope!("Housekeep");
+            self.world.housekeep();
+        }
+
+        if false {
+            // !health::is_alive(self.world.components.get::<(Health,)>(), self.player) {
+            return false;
+        }
+
+        true
+    }
+}
@@ -13,6 +13,7 @@ pub mod camera;
 pub mod components;
 pub mod ecs;
 pub mod font;
+pub mod game_loop;
 pub mod map;
 pub mod maps;
 pub mod model;
@@ -9,320 +9,27 @@
 
 extern crate alloc;
 
-use game::{
-    camera::Camera,
-    components::{
-        box_drawable, diver_ai, enemy,
-        health::{self, Health},
-        keep_on_screen, mesh_drawable, missile, movable,
-        pickup::{self, spawn_pickup},
-        player::{self, draw_player_weapon, spawn_player, Player},
-        print_position, projectile, remove_when_below, shadow, spawner, sprite_drawable, trap,
-        waypoint_ai,
-        weapon::draw_missile_target,
-    },
-    ecs::{storage::Storage, world::World},
-    font,
-    map::Map,
-    maps::MAP_1,
-    sound_mixer::SoundMixer,
-};
-use n64::{
-    self, current_time_us,
-    gfx::{CommandBuffer, CommandBufferCache, FillPipeline, Pipeline},
-    ipl3font, slow_cpu_clear, VideoMode, N64,
-};
-use n64_math::{random_u32, vec2, vec3, Color};
+use game::game_loop::GameLoop;
+use n64::{self, ipl3font, slow_cpu_clear, VideoMode, N64};
+use n64_math::Color;
 
 const RED: Color = Color::new(0b10000_00011_00011_1);
-const GREEN: Color = Color::new(0b00011_10000_00011_1);
-const BLUE: Color = Color::new(0b0011_00011_10000_1);
-const WHITE: Color = Color::new(0b11111_11111_11111_1);
 
-static DEBUG_PIPELINE: FillPipeline = FillPipeline::default();
-
-const VIDEO_MODE: VideoMode = VideoMode::Pal {
-    width: 320,
-    height: 240,
-};
-
-const DEBUG_TRIANGLES: bool = false;
+const SCREEN_WIDTH: i32 = 320;
+const SCREEN_HEIGHT: i32 = 240;
 
 fn main() {
     n64::init_profiler();
 
-    let mut n64 = N64::new(VIDEO_MODE);
-
-    let mut world = World::new();
-    let map = Map::load(MAP_1);
-
-    let start_pos = vec2(
-        map.get_start_pos().x / VIDEO_MODE.width() as f32,
-        map.get_start_pos().y / VIDEO_MODE.height() as f32 - 1.0,
-    );
-
-    let mut sound_mixer = SoundMixer::new();
-    let mut camera = Camera::new(start_pos);
-    let mut command_buffer_cache = CommandBufferCache::new(VIDEO_MODE);
-
-    let _test_pickup = spawn_pickup(&mut world.entities, start_pos + vec2(0.5, 0.2));
-
-    let player = spawn_player(&mut world.entities, start_pos);
-
-    map.spawn_enemies(&mut world, &VIDEO_MODE);
-
-    let mut frame_begin_time;
-    let mut last_frame_begin_time = current_time_us();
-    let mut swap_time = 0;
-    let mut dt;
-
-    let mut last_colored_rect_count = 0;
-    let mut last_textured_rect_count = 0;
-    let mut last_mesh_count = 0;
-    let mut last_rsp_clock = 0;
-
-    let mut last_step = false;
-
-    loop {
-        n64::frame!();
-        n64::scope!("Frame");
-
-        {
-            frame_begin_time = current_time_us();
-            dt = (frame_begin_time - last_frame_begin_time) as f32 / 1e6;
-            last_frame_begin_time = frame_begin_time;
-        }
-
-        {
-            n64::scope!("Update");
-
-            n64.controllers.update(&n64.graphics);
-
-            camera.update(&n64.controllers, dt, &VIDEO_MODE);
-
-            health::clear_was_damaged(&mut world);
-
-            enemy::update(&mut world, &mut sound_mixer);
-            player::update(&mut world, &n64.controllers, &mut sound_mixer, &camera);
-
-            diver_ai::update(&mut world);
-            waypoint_ai::update(&mut world, dt);
-            missile::update(&mut world, dt);
-
-            movable::simulate(&mut world, dt);
-
-            projectile::update(&mut world, &mut sound_mixer, &camera, dt);
-            trap::update(&mut world);
-            pickup::update(&mut world, &mut sound_mixer, &camera);
-            spawner::update(&mut world, &camera);
-            keep_on_screen::update(&mut world, &camera);
-            remove_when_below::update(&mut world, &camera);
-            print_position::print(&mut world);
-        }
-
-        {
-            n64::scope!("Audio");
-
-            n64.audio.update(|buffer| {
-                sound_mixer.mix(buffer);
-            });
-        }
-
-        let cb = {
-            n64::scope!("Build Command Buffer");
+    let video_mode = VideoMode::detect(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let mut n64 = N64::new(video_mode);
 
-            let mut cb =
-                CommandBuffer::new(n64.framebuffer.vi_buffer_token(), &mut command_buffer_cache);
+    let mut game_loop = GameLoop::new(&n64);
 
-            cb.clear();
+    while game_loop.frame(&mut n64) {}
 
-            if !DEBUG_TRIANGLES {
-                map.render(&mut cb, VIDEO_MODE, &camera);
-
-                shadow::draw(&mut world, &mut cb, VIDEO_MODE, &camera);
-
-                box_drawable::draw(&mut world, &mut cb, VIDEO_MODE, &camera);
-                sprite_drawable::draw(&mut world, &mut cb, VIDEO_MODE, &camera);
-                mesh_drawable::draw(&mut world, &mut cb, VIDEO_MODE, &camera);
-
-                draw_missile_target(&mut world, &mut cb, VIDEO_MODE, &camera);
-            }
-
-            if DEBUG_TRIANGLES {
-                let x_limit = 320.0;
-                let y_limit = 240.0;
-
-                let x_off = x_limit * 0.5;
-                let y_off = y_limit * 0.5;
-                let x_scale = x_limit * 0.5;
-                let y_scale = y_limit * 0.5;
-
-                let speed = 0.5; // 0.05
-                let t = speed * (frame_begin_time as f32) / 1e6;
-                let p = 2.0943951;
-                let v0 = vec3(
-                    x_off + x_scale * libm::cosf(t),
-                    y_off + y_scale * libm::sinf(t),
-                    0.0,
-                );
-                let v1 = vec3(
-                    x_off + x_scale * libm::cosf(t + p),
-                    y_off + y_scale * libm::sinf(t + p),
-                    0.0,
-                );
-                let v2 = vec3(
-                    x_off + x_scale * libm::cosf(t - p),
-                    y_off + y_scale * libm::sinf(t - p),
-                    0.0,
-                );
-
-                cb.set_pipeline(&Pipeline::default());
-                cb.add_mesh_indexed(
-                    &[v0.into(), v1.into(), v2.into()],
-                    &[[0.5, 1.0], [0.0, 0.0], [1.0, 0.0]],
-                    &[0xff_00_00_ff, 0x00_ff_00_ff, 0x00_00_ff_ff],
-                    &[[0, 1, 2]],
-                    &[
-                        [1.0, 0.0, 0.0, 0.0],
-                        [0.0, 1.0, 0.0, 0.0],
-                        [0.0, 0.0, 1.0, 0.0],
-                        [0.0, 0.0, 0.0, 1.0],
-                    ],
-                );
-            }
-
-            if !DEBUG_TRIANGLES {
-                n64::scope!("HUD");
-
-                font::draw_number(
-                    &mut cb,
-                    world
-                        .components
-                        .get::<(Player,)>()
-                        .lookup(player)
-                        .map(|p| p.score)
-                        .unwrap_or(0),
-                    vec2(300.0, 10.0),
-                    0x0000efff,
-                );
-                font::draw_number(
-                    &mut cb,
-                    world
-                        .components
-                        .get::<(Health,)>()
-                        .lookup(player)
-                        .map(|hc| hc.health)
-                        .unwrap_or(0),
-                    vec2(300.0, 215.0),
-                    0xaf0000ff,
-                );
-
-                #[cfg(target_vendor = "nintendo64")]
-                {
-                    font::draw_number(
-                        &mut cb,
-                        n64::ALLOC_BYTES_USED.load(core::sync::atomic::Ordering::SeqCst),
-                        vec2(100.0, 160.0),
-                        0xff0000ff,
-                    );
-                    font::draw_number(
-                        &mut cb,
-                        n64::ALLOC_BYTES_LEFT.load(core::sync::atomic::Ordering::SeqCst),
-                        vec2(100.0, 180.0),
-                        0xff0000ff,
-                    );
-                    font::draw_number(
-                        &mut cb,
-                        *n64::ALLOC_PAGE_OFFSET.lock() as i32,
-                        vec2(100.0, 200.0),
-                        0xff0000ff,
-                    );
-                }
-
-                {
-                    font::draw_number(&mut cb, (dt * 1e6) as i32, vec2(100.0, 10.0), 0x00af00ff);
-                    font::draw_number(
-                        &mut cb,
-                        (dt * 1e6) as i32 - swap_time as i32,
-                        vec2(200.0, 10.0),
-                        0x00af00ff,
-                    );
-                    font::draw_number(
-                        &mut cb,
-                        last_colored_rect_count,
-                        vec2(100.0, 30.0),
-                        0x00af00ff,
-                    );
-                    font::draw_number(
-                        &mut cb,
-                        last_textured_rect_count,
-                        vec2(200.0, 30.0),
-                        0x00af00ff,
-                    );
-                    font::draw_number(&mut cb, last_mesh_count, vec2(300.0, 30.0), 0xaf0000ff);
-
-                    font::draw_number(
-                        &mut cb,
-                        n64.graphics.frame_counter() as _,
-                        vec2(300.0, 50.0),
-                        0xafaf00ff,
-                    );
-
-                    // RSP clock is 62.5Mhz => ticks / 62.5e6 = s, ticks / 62.5e3 = ms, ticks / 62.5 = us
-                    // x/62.5 = 10x/625
-                    let rsp_us = (last_rsp_clock * 10) / 625;
-                    font::draw_number(&mut cb, rsp_us, vec2(100.0, 50.0), 0xafaf00ff);
-                }
-
-                draw_player_weapon(&mut world, &mut cb, &VIDEO_MODE);
-            }
-
-            cb
-        };
-
-        swap_time = {
-            n64::scope!("Swap");
-            n64.graphics.swap_buffers(&mut n64.framebuffer)
-        };
-
-        let (colored_rect_count, textured_rect_count, mesh_count, rsp_clock) = {
-            n64::scope!("Submit Command Buffer");
-            let cb = cb;
-
-            let step = if last_step {
-                false
-            } else {
-                n64.controllers.start()
-            };
-
-            last_step = n64.controllers.start();
-
-            cb.submit(&mut n64.graphics, step)
-        };
-
-        last_colored_rect_count = colored_rect_count;
-        last_textured_rect_count = textured_rect_count;
-        last_mesh_count = mesh_count;
-        last_rsp_clock = rsp_clock;
-
-        {
-            // Cycle the random number generator
-            let now = current_time_us() as u64;
-            for _ in 0..(now & 0xf) {
-                let _ = random_u32();
-            }
-        }
-
-        {
-            n64::scope!("Housekeep");
-            world.housekeep();
-        }
-
-        if false {
-            // !health::is_alive(world.components.get::<(Health,)>(), player) {
-            break;
-        }
-    }
+    // The last frame may still be rendering into the buffer drawn to below
+    n64.graphics.wait_for_gpu();
 
     loop {
         {
@@ -353,8 +60,12 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 
     const GREEN: Color = Color::new(0b00011_10000_00011_1);
 
-    let mut out_tex = n64::gfx::TextureMut::new(VIDEO_MODE.width(), VIDEO_MODE.height(), unsafe {
-        core::slice::from_raw_parts_mut(n64::vi::get_vi_buffer(), VIDEO_MODE.size() as usize)
+    // VideoMode::detect picks the default 16bpp format, so the VI buffer holds one Color per pixel
+    let mut out_tex = n64::gfx::TextureMut::new(SCREEN_WIDTH, SCREEN_HEIGHT, unsafe {
+        core::slice::from_raw_parts_mut(
+            n64::vi::get_vi_buffer(),
+            (SCREEN_WIDTH * SCREEN_HEIGHT) as usize,
+        )
     });
 
     slow_cpu_clear(out_tex.data);
@@ -398,8 +109,11 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 #[cfg(target_vendor = "nintendo64")]
 #[alloc_error_handler]
 fn oom(_: core::alloc::Layout) -> ! {
-    let mut out_tex = n64::gfx::TextureMut::new(VIDEO_MODE.width(), VIDEO_MODE.height(), unsafe {
-        core::slice::from_raw_parts_mut(n64::vi::get_vi_buffer(), VIDEO_MODE.size() as usize)
+    let mut out_tex = n64::gfx::TextureMut::new(SCREEN_WIDTH, SCREEN_HEIGHT, unsafe {
+        core::slice::from_raw_parts_mut(
+            n64::vi::get_vi_buffer(),
+            (SCREEN_WIDTH * SCREEN_HEIGHT) as usize,
+        )
     });
 
     slow_cpu_clear(out_tex.data);
@@ -3,14 +3,16 @@ use crate::{
     components::{enemy::add_enemy_spawner, spawner::SpawnerData},
     ecs::world::World,
 };
+use alloc::vec::Vec;
 use n64::{
     gfx::{
         color_combiner_mode::{ColorCombinerMode, DSrc},
-        CommandBuffer, Pipeline, StaticTexture,
+        CommandBuffer, DisplayList, Pipeline, StaticTexture,
     },
+    rom::{RomAsset, RomData},
     VideoMode,
 };
-use n64_math::Vec2;
+use n64_math::{vec2, Vec2};
 
 #[derive(Copy, Clone)]
 pub struct StaticObject {
@@ -25,7 +27,7 @@ pub struct StaticMapData {
     pub tile_width: i32,
     pub tile_height: i32,
     pub tiles: &'static [&'static StaticTexture],
-    pub layers: &'static [u8],
+    pub layers: RomAsset,
     pub objects: &'static [StaticObject],
 }
 
@@ -35,13 +37,27 @@ static MAP_PIPELINE: Pipeline = Pipeline {
     ..Pipeline::default()
 };
 
+// Tiles are recorded into display lists one screen sized chunk at a time
+struct MapChunk {
+    x: i32,
+    y: i32,
+    display_list: DisplayList,
+}
+
 pub struct Map {
     data: &'static StaticMapData,
+    layers: RomData,
+    // The chunks seen last frame
+    chunks: Vec<MapChunk>,
 }
 
 impl Map {
     pub fn load(data: &'static StaticMapData) -> Self {
-        Self { data }
+        Self {
+            data,
+            layers: data.layers.read(),
+            chunks: Vec::new(),
+        }
     }
 
     pub fn spawn_enemies(&self, world: &mut World, video_mode: &VideoMode) {
@@ -57,40 +73,89 @@ impl Map {
         }
     }
 
-    pub fn render(&self, cb: &mut CommandBuffer, video_mode: VideoMode, camera: &Camera) {
+    pub fn render(&mut self, cb: &mut CommandBuffer, video_mode: VideoMode, camera: &Camera) {
         n64::scope!("map::render");
 
-        let tiles_in_layer = (self.data.width_in_tiles * self.data.height_in_tiles) as usize;
-
-        let tile_scale: Vec2 = Vec2::new(32.0, 32.0);
-
         let camera_pixel_pos = Vec2::new(
             camera.pos.x * video_mode.width() as f32,
             camera.pos.y * video_mode.height() as f32,
         );
 
-        let camera_tile = Vec2::new(
-            camera_pixel_pos.x / self.data.tile_width as f32,
-            camera_pixel_pos.y / self.data.tile_height as f32,
+        let chunk_tiles_x = video_mode.width() / self.data.tile_width;
+        let chunk_tiles_y = video_mode.height() / self.data.tile_height;
+        let chunk_size = vec2(
+            (chunk_tiles_x * self.data.tile_width) as f32,
+            (chunk_tiles_y * self.data.tile_height) as f32,
         );
 
-        let tiles_on_screen_x = (video_mode.width() / self.data.tile_width) + 1;
-        let tiles_on_screen_y = (video_mode.height() / self.data.tile_height) + 2;
+        // Chunks hold whole tiles and can be smaller than the screen, which then overlaps three
+        // rows or columns of them
+        let last_pixel = vec2(
+            (video_mode.width() - 1) as f32,
+            (video_mode.height() - 1) as f32,
+        );
+        let first_chunk = (camera_pixel_pos / chunk_size).floor();
+        let last_chunk = ((camera_pixel_pos + last_pixel) / chunk_size).floor();
+
+        let visible = (first_chunk.y as i32..=last_chunk.y as i32)
+            .flat_map(|y| (first_chunk.x as i32..=last_chunk.x as i32).map(move |x| (x, y)))
+            .collect::<Vec<_>>();
+
+        self.chunks
+            .retain(|chunk| visible.contains(&(chunk.x, chunk.y)));
+
+        for (x, y) in visible {
+            if x < 0
+                || y < 0
+                || x * chunk_tiles_x >= self.data.width_in_tiles
+                || y * chunk_tiles_y >= self.data.height_in_tiles
+            {
+                continue;
+            }
 
-        let first_tile_x = camera_tile.x as i32;
-        let first_tile_y = camera_tile.y as i32;
+            if !self.chunks.iter().any(|chunk| chunk.x == x && chunk.y == y) {
+                let display_list = cb.record_display_list(|cb| {
+                    self.record_chunk(
+                        cb,
+                        x * chunk_tiles_x,
+                        y * chunk_tiles_y,
+                        chunk_tiles_x,
+                        chunk_tiles_y,
+                    )
+                });
+
+                self.chunks.push(MapChunk { x, y, display_list });
+            }
 
-        for layer in self.data.layers.chunks_exact(tiles_in_layer) {
-            for y in first_tile_y..(first_tile_y + tiles_on_screen_y) {
-                if y < 0 || y >= self.data.height_in_tiles {
-                    continue;
-                }
+            let chunk = self
+                .chunks
+                .iter()
+                .find(|chunk| chunk.x == x && chunk.y == y)
+                .unwrap();
 
-                for x in first_tile_x..(first_tile_x + tiles_on_screen_x) {
-                    if x < 0 || x >= self.data.width_in_tiles {
-                        continue;
-                    }
+            cb.add_display_list(
+                &chunk.display_list,
+                vec2(x as f32, y as f32) * chunk_size - camera_pixel_pos,
+            );
+        }
+    }
 
+    // Tiles are placed relative to the corner of the chunk
+    fn record_chunk(
+        &self,
+        cb: &mut CommandBuffer,
+        first_tile_x: i32,
+        first_tile_y: i32,
+        tiles_x: i32,
+        tiles_y: i32,
+    ) {
+        let tiles_in_layer = (self.data.width_in_tiles * self.data.height_in_tiles) as usize;
+
+        let tile_scale: Vec2 = Vec2::new(32.0, 32.0);
+
+        for layer in self.layers.chunks_exact(tiles_in_layer) {
+            for y in first_tile_y..(first_tile_y + tiles_y).min(self.data.height_in_tiles) {
+                for x in first_tile_x..(first_tile_x + tiles_x).min(self.data.width_in_tiles) {
                     let index = x + y * self.data.width_in_tiles;
                     let tile = layer[index as usize];
 
@@ -98,23 +163,18 @@ impl Map {
                         continue;
                     }
 
-                    let pos = Vec2::new(
-                        (x * self.data.tile_width) as f32,
-                        (y * self.data.tile_height) as f32,
+                    let upper_left = Vec2::new(
+                        ((x - first_tile_x) * self.data.tile_width) as f32,
+                        ((y - first_tile_y) * self.data.tile_height) as f32,
                     );
-
-                    let upper_left = pos;
-                    let lower_right = pos + tile_scale;
+                    let lower_right = upper_left + tile_scale;
 
                     cb.set_pipeline(
                         &MAP_PIPELINE
                             .with_texture(Some(self.data.tiles[(tile - 1) as usize].as_texture())),
                     );
 
-                    cb.add_textured_rect(
-                        upper_left - camera_pixel_pos,
-                        lower_right - camera_pixel_pos,
-                    );
+                    cb.add_textured_rect(upper_left, lower_right);
                 }
             }
         }
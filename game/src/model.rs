@@ -1,4 +1,5 @@
 use alloc::borrow::Cow;
+use n64::rom::RomAsset;
 use n64_math::Vec2;
 use zerocopy::LayoutVerified;
 
@@ -12,30 +13,30 @@ pub struct ModelData<'a> {
 }
 
 pub struct StaticModelData {
-    pub verts: &'static [u8],
-    pub uvs: &'static [u8],
-    pub colors: &'static [u8],
-    pub indices: &'static [u8],
+    pub verts: RomAsset,
+    pub uvs: RomAsset,
+    pub colors: RomAsset,
+    pub indices: RomAsset,
     pub size: Vec2,
 }
 
 impl StaticModelData {
-    pub fn as_model_data(&self) -> ModelData {
+    pub fn as_model_data(&self) -> ModelData<'static> {
         #[cfg(target_vendor = "nintendo64")]
         {
-            let verts = LayoutVerified::<_, [[f32; 3]]>::new_slice(self.verts)
+            let verts = LayoutVerified::<_, [[f32; 3]]>::new_slice(self.verts.load())
                 .unwrap()
                 .into_slice();
 
-            let uvs = LayoutVerified::<_, [[f32; 2]]>::new_slice(self.uvs)
+            let uvs = LayoutVerified::<_, [[f32; 2]]>::new_slice(self.uvs.load())
                 .unwrap()
                 .into_slice();
 
-            let colors = LayoutVerified::<_, [u32]>::new_slice(self.colors)
+            let colors = LayoutVerified::<_, [u32]>::new_slice(self.colors.load())
                 .unwrap()
                 .into_slice();
 
-            let indices = LayoutVerified::<_, [[u8; 3]]>::new_slice(self.indices)
+            let indices = LayoutVerified::<_, [[u8; 3]]>::new_slice(self.indices.load())
                 .unwrap()
                 .into_slice();
 
@@ -63,10 +64,10 @@ impl StaticModelData {
                 res
             }
 
-            let verts_in = byteswap_u32_slice(self.verts);
-            let uvs_in = byteswap_u32_slice(self.uvs);
-            let colors_in = byteswap_u32_slice(self.colors);
-            let indices_in = self.indices;
+            let verts_in = byteswap_u32_slice(self.verts.load());
+            let uvs_in = byteswap_u32_slice(self.uvs.load());
+            let colors_in = byteswap_u32_slice(self.colors.load());
+            let indices_in = self.indices.load();
 
             let verts = LayoutVerified::<_, [[f32; 3]]>::new_slice(verts_in.as_slice())
                 .unwrap()
@@ -1,12 +1,13 @@
+use n64::rom::RomAsset;
 use zerocopy::LayoutVerified;
 
 pub struct StaticSoundData {
-    pub data: &'static [u8],
+    pub data: RomAsset,
 }
 
 impl StaticSoundData {
     pub fn as_sound_data(&self) -> SoundData {
-        let samples = LayoutVerified::<_, [i16]>::new_slice(self.data)
+        let samples = LayoutVerified::<_, [i16]>::new_slice(self.data.load())
             .unwrap()
             .into_slice();
 
@@ -0,0 +1,45 @@
+use game::game_loop::GameLoop;
+use n64::{ControllerInput, InputScript, VideoFormat, VideoMode, N64};
+
+const VIDEO_MODE: VideoMode = VideoMode::Pal {
+    width: 320,
+    height: 240,
+    format: VideoFormat::default(),
+};
+
+#[test]
+fn game_loop_runs_headless() {
+    let mut input = InputScript::new();
+    input.hold(10, ControllerInput::default().with_stick(127, 0));
+    input.hold(
+        30,
+        ControllerInput::default()
+            .with_buttons(ControllerInput::Z)
+            .with_stick(0, 127),
+    );
+    input.hold(60, ControllerInput::default());
+
+    let Some(mut n64) = N64::new_headless(VIDEO_MODE, input) else {
+        eprintln!("Skipping game_loop_runs_headless, there is no graphics adapter");
+        return;
+    };
+    n64.audio.start_capture();
+
+    let mut game_loop = GameLoop::new(&n64);
+
+    for _ in 0..90 {
+        assert!(game_loop.frame(&mut n64));
+    }
+
+    assert_eq!(n64.graphics.frame_counter(), 90);
+    let samples = n64.audio.take_captured();
+    assert!(!samples.is_empty());
+    assert_eq!(samples.len() % 90, 0);
+
+    // More than the clear color, the map and the ship are on screen
+    let rgba = n64.graphics.capture_frame(&n64.framebuffer);
+    let mut colors = rgba.chunks_exact(4).collect::<Vec<_>>();
+    colors.sort_unstable();
+    colors.dedup();
+    assert!(colors.len() > 1);
+}
@@ -69,6 +69,19 @@ impl Color {
         [r, g, b, a]
     }
 
+    // Widens to RGBA 8888, replicating the top bits into the new low bits
+    #[inline]
+    pub fn to_rgba32(&self) -> u32 {
+        let expand = |c: u16| ((c << 3) | (c >> 2)) as u32;
+
+        let r = expand(self.value >> 11 & 0b11111);
+        let g = expand(self.value >> 6 & 0b11111);
+        let b = expand(self.value >> 1 & 0b11111);
+        let a = if self.value & 0b1 > 0 { 0xff } else { 0 };
+
+        (r << 24) | (g << 16) | (b << 8) | a
+    }
+
     #[inline]
     pub fn value(&self) -> u16 {
         self.value
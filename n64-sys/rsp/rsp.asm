@@ -20,6 +20,31 @@ constant rdp_command_count_offset = 512
 constant rdp_busy_mask = RDP_CMB//RDP_PLB|RDP_CMB
 constant clear_signals = CLR_SG0|CLR_SG1|CLR_SG2|CLR_SG3|CLR_SG4|CLR_SG5|CLR_SG6|CLR_SG7
 
+// Mesh chunks (command byte 0x10) carry untextured vertices and indices, the RSP transforms the
+// vertices and sets up shaded z buffered triangles. Offsets match n64_types::MeshChunk.
+constant mesh_command = $10
+constant mesh_vertex_count = command_block_start + 1
+constant mesh_triangle_count = command_block_start + 2
+constant mesh_flags = command_block_start + 3
+constant mesh_matrix_int = 8
+constant mesh_matrix_frac = 40
+constant mesh_positions = command_block_start + 72
+constant mesh_colors = command_block_start + 328
+constant mesh_indices = command_block_start + 456
+constant mesh_cull_back = 1
+constant mesh_cull_front = 2
+
+// Transformed vertices, 32 bytes each. Accumulator style hi and lo vectors:
+// hi [0, 0, 0, 0, z, -, x, y], lo [r << 8, g << 8, b << 8, a << 8, z, -, -, -]
+constant mesh_vertex_records = 2064
+
+// Two output buffers the RDP reads over XBUS while the other one is filled
+constant mesh_triangle_size = 112
+constant mesh_output_size = 4 * mesh_triangle_size
+constant mesh_output_a = 3088
+constant mesh_output_b = mesh_output_a + mesh_output_size
+constant mesh_edge_command = $cd // Shaded, z buffered triangle
+
 macro DbgPrint(reg) {
     sw 0, 4(t7) // Write 0 AFTER current value, to mark last print
     sw {reg}, 0(t7)
@@ -348,6 +373,11 @@ if 0 {
     DbgPrint(t5)
     DbgPrint(t5)
 
+    lbu t5, command_block_start(0)
+    li t8, mesh_command
+    beq t5, t8, mesh_chunk
+    nop
+
     // Send to RDP
     li t5, rdp_start_flags // Load rdp start flags
     mtc0 t5, c11           // Prepare rdp for commands.
@@ -399,6 +429,7 @@ wait_rdp_busy:
     li t5, CLR_SG5
     mtc0 t5, c4
 
+next_chunk:
     addiu t3, t3, 1   // Next chunk pointer
     j process_chunk_pointer
     nop
@@ -463,3 +494,400 @@ wait_rdp_busy_end:
     
     break
     nop
+
+macro SwapVertex(record0, y0, record1, y1, tmp) {
+    or {tmp}, {record0}, 0
+    or {record0}, {record1}, 0
+    or {record1}, {tmp}, 0
+    or {tmp}, {y0}, 0
+    or {y0}, {y1}, 0
+    or {y1}, {tmp}, 0
+}
+
+// Vector registers
+// v0: zero
+// v1: constants, [1, 256, 2^(9 - shift)]
+// v2-v5: matrix columns integer part, v6-v9: matrix columns fraction
+// Two vertices are transformed at a time, one in lanes 0-3 and one in lanes 4-7
+// Values with a fraction are kept as a hi and lo vector pair, like the accumulator
+mesh_chunk:
+    vxor v0,v0,v0
+    li t5, 1
+    mtc2 t5,v1[e0]
+    li t5, 256
+    mtc2 t5,v1[e2]
+
+    // Both halves get the same matrix
+    li s0, command_block_start
+    ldv v2[e0],mesh_matrix_int+0(s0)
+    ldv v2[e8],mesh_matrix_int+0(s0)
+    ldv v3[e0],mesh_matrix_int+8(s0)
+    ldv v3[e8],mesh_matrix_int+8(s0)
+    ldv v4[e0],mesh_matrix_int+16(s0)
+    ldv v4[e8],mesh_matrix_int+16(s0)
+    ldv v5[e0],mesh_matrix_int+24(s0)
+    ldv v5[e8],mesh_matrix_int+24(s0)
+    ldv v6[e0],mesh_matrix_frac+0(s0)
+    ldv v6[e8],mesh_matrix_frac+0(s0)
+    ldv v7[e0],mesh_matrix_frac+8(s0)
+    ldv v7[e8],mesh_matrix_frac+8(s0)
+    ldv v8[e0],mesh_matrix_frac+16(s0)
+    ldv v8[e8],mesh_matrix_frac+16(s0)
+    ldv v9[e0],mesh_matrix_frac+24(s0)
+    ldv v9[e8],mesh_matrix_frac+24(s0)
+
+    lbu s1, mesh_vertex_count(0)
+    li s2, mesh_positions
+    li s3, mesh_colors
+    li s4, mesh_vertex_records
+
+mesh_transform_loop:
+    blez s1, mesh_transform_done
+    nop
+
+    ldv v10[e0],0(s2)
+    ldv v10[e8],8(s2)
+    lpv v17[e0],0(s3) // Colors << 8
+
+    // Clip position, s15.16
+    vmudn v31,v6,v10[e4]
+    vmadh v31,v2,v10[e4]
+    vmadn v31,v7,v10[e5]
+    vmadh v31,v3,v10[e5]
+    vmadn v31,v8,v10[e6]
+    vmadh v31,v4,v10[e6]
+    vmadn v31,v9,v10[e7]
+    vmadh v11,v5,v10[e7]
+    vmadn v12,v0,v0[e8]
+
+    // 1 / w, the reciprocal comes out as 1 / 2w
+    vrcph v13[e3],v11[e11]
+    vrcpl v14[e3],v12[e11]
+    vrcph v13[e3],v0[e8]
+    vrcph v13[e7],v11[e15]
+    vrcpl v14[e7],v12[e15]
+    vrcph v13[e7],v0[e8]
+    vaddc v14,v14,v14
+    vadd v13,v13,v13
+
+    // Screen position, the matrix already maps to pixels and z buffer values / 256
+    vmudl v31,v12,v14[e7]
+    vmadm v31,v11,v14[e7]
+    vmadn v31,v12,v13[e7]
+    vmadh v15,v11,v13[e7]
+    vmadn v16,v0,v0[e8]
+
+    sdv v0[e0],0(s4)
+    ssv v15[e4],8(s4)
+    slv v15[e0],12(s4)
+    sdv v17[e0],16(s4)
+    ssv v16[e4],24(s4)
+
+    sdv v0[e0],32(s4)
+    ssv v15[e12],40(s4)
+    slv v15[e8],44(s4)
+    sdv v17[e8],48(s4)
+    ssv v16[e12],56(s4)
+
+    addi s1, s1, -2
+    addi s2, s2, 16
+    addi s3, s3, 8
+    addi s4, s4, 64
+    j mesh_transform_loop
+    nop
+
+mesh_transform_done:
+    lbu s1, mesh_triangle_count(0)
+    lbu s5, mesh_flags(0)
+    li s2, mesh_indices
+    li s6, mesh_output_a
+    or s7, s6, 0
+
+// Scalar registers
+// a0, a1, a2: vertex records sorted by y (high, mid, low)
+// t0, t1, t4: y, k0, k1: x of the high and mid vertex
+// a3: ax, s3: ay, s4: bx, s0: by, deltas from the high vertex to the mid (a) and low (b) vertex
+mesh_triangle_loop:
+    beqz s1, mesh_triangles_done
+    nop
+
+    lbu a0, 0(s2)
+    lbu a1, 1(s2)
+    lbu a2, 2(s2)
+    addi s1, s1, -1
+    addi s2, s2, 3
+
+    sll a0, a0, 5
+    addi a0, a0, mesh_vertex_records
+    sll a1, a1, 5
+    addi a1, a1, mesh_vertex_records
+    sll a2, a2, 5
+    addi a2, a2, mesh_vertex_records
+
+    lh t0, 14(a0)
+    lh t1, 14(a1)
+    lh t4, 14(a2)
+
+    // Sort by y, t9 keeps the parity of the swaps for culling
+    xor t9, t9, t9
+
+    slt t5, t1, t0
+    beqz t5, mesh_sort_1
+    nop
+    SwapVertex(a0, t0, a1, t1, t5)
+    xori t9, t9, 1
+mesh_sort_1:
+    slt t5, t4, t1
+    beqz t5, mesh_sort_2
+    nop
+    SwapVertex(a1, t1, a2, t4, t5)
+    xori t9, t9, 1
+mesh_sort_2:
+    slt t5, t1, t0
+    beqz t5, mesh_sort_3
+    nop
+    SwapVertex(a0, t0, a1, t1, t5)
+    xori t9, t9, 1
+mesh_sort_3:
+
+    lh k0, 12(a0)
+    lh k1, 12(a1)
+    lh t8, 12(a2)
+
+    sub a3, k1, k0
+    sub s3, t1, t0
+    sub s4, t8, k0
+    sub s0, t4, t0
+
+    // v18: [by, -ay, -bx, ax], the attribute gradients are these over the area
+    // v19: [bx], for the area
+    // v20: [by, ay, yl - ym], v21: 2 * [bx, ax, xl - xm], for the edge slopes
+    mtc2 s0,v18[e0]
+    sub t5, 0, s3
+    mtc2 t5,v18[e2]
+    sub t5, 0, s4
+    mtc2 t5,v18[e4]
+    mtc2 a3,v18[e6]
+    mtc2 s4,v19[e0]
+
+    mtc2 s0,v20[e0]
+    mtc2 s3,v20[e2]
+    sub t5, s0, s3
+    mtc2 t5,v20[e4]
+    add t5, s4, s4
+    mtc2 t5,v21[e0]
+    add t5, a3, a3
+    mtc2 t5,v21[e2]
+    sub t5, s4, a3
+    add t5, t5, t5
+    mtc2 t5,v21[e4]
+
+    // Twice the area, ax * by - ay * bx
+    vmudh v31,v18,v18[e11]
+    vmadh v31,v19,v18[e9]
+    vsar v22,v22,v22[e8]
+    vsar v23,v23,v23[e9]
+    mfc2 t5,v22[e0]
+    mfc2 t8,v23[e0]
+    sll t5, t5, 16
+    andi t8, t8, $ffff
+    or t5, t5, t8
+
+    beqz t5, mesh_triangle_loop
+    nop
+
+    // The sorted area is positive for right major triangles, the swaps flip it back to the
+    // winding of the indices
+    slt t8, t5, 0
+    xor t8, t8, t9
+    xori t8, t8, 1
+    addi t8, t8, 1 // 1 for back faces, 2 for front faces
+    and t8, t8, s5
+    bnez t8, mesh_triangle_loop
+    nop
+
+    // Shift the area to 16 bits for the reciprocal, the gradient deltas are shifted back by
+    // 2^(9 - shift) so they come out as s7.24
+    or t8, t5, 0
+    bgez t5, mesh_area_abs
+    nop
+    sub t8, 0, t5
+mesh_area_abs:
+    li gp, 9
+    or sp, t5, 0
+mesh_area_normalize:
+    srl t9, t8, 15
+    beqz t9, mesh_area_normalized
+    nop
+    srl t8, t8, 1
+    sra sp, sp, 1
+    addi gp, gp, -1
+    j mesh_area_normalize
+    nop
+mesh_area_normalized:
+    li t8, 1
+    sllv t8, t8, gp
+    mtc2 t8,v1[e4]
+    mtc2 sp,v22[e0]
+
+    vrcp v24[e0],v22[e8]
+    vrcph v25[e0],v0[e8]
+
+    vmudm v26,v18,v1[e10]
+    vmadn v27,v0,v0[e8]
+
+    // v26, v27: [by, -ay, -bx, ax] / area
+    vmudl v31,v27,v24[e8]
+    vmadm v31,v26,v24[e8]
+    vmadn v31,v27,v25[e8]
+    vmadh v26,v26,v25[e8]
+    vmadn v27,v0,v0[e8]
+
+    // v29, v28: edge slopes, [high, mid, low]. A flat edge gets 2^31 from the reciprocal of 0
+    // and is never walked.
+    vrcp v28[e0],v20[e8]
+    vrcph v29[e0],v0[e8]
+    vrcp v28[e1],v20[e9]
+    vrcph v29[e1],v0[e8]
+    vrcp v28[e2],v20[e10]
+    vrcph v29[e2],v0[e8]
+
+    vmudh v31,v21,v1[e8]
+    vsar v30,v30,v30[e8]
+
+    vmudl v31,v28,v21
+    vmadm v31,v29,v21
+    vmadn v31,v28,v30
+    vmadh v29,v29,v30
+    vmadn v28,v0,v0[e8]
+
+    // Attributes of the vertices, v2 and v3 hold the high vertex
+    lqv v2[e0],0(a0)
+    lqv v3[e0],16(a0)
+    lqv v4[e0],0(a1)
+    lqv v5[e0],16(a1)
+    lqv v6[e0],0(a2)
+    lqv v7[e0],16(a2)
+
+    // v8, v9: mid - high, v10, v11: low - high
+    vsubc v9,v5,v3
+    vsub v8,v4,v2
+    vsubc v11,v7,v3
+    vsub v10,v6,v2
+
+    // v12, v13: d/dx
+    vmudl v31,v9,v27[e8]
+    vmadm v31,v8,v27[e8]
+    vmadn v31,v9,v26[e8]
+    vmadh v31,v8,v26[e8]
+    vmadl v31,v11,v27[e9]
+    vmadm v31,v10,v27[e9]
+    vmadn v31,v11,v26[e9]
+    vmadh v12,v10,v26[e9]
+    vmadn v13,v0,v0[e8]
+
+    // v14, v15: d/dy
+    vmudl v31,v9,v27[e10]
+    vmadm v31,v8,v27[e10]
+    vmadn v31,v9,v26[e10]
+    vmadh v31,v8,v26[e10]
+    vmadl v31,v11,v27[e11]
+    vmadm v31,v10,v27[e11]
+    vmadn v31,v11,v26[e11]
+    vmadh v14,v10,v26[e11]
+    vmadn v15,v0,v0[e8]
+
+    // v16, v17: d/de, along the major edge
+    vmudl v31,v13,v28[e8]
+    vmadm v31,v12,v28[e8]
+    vmadn v31,v13,v29[e8]
+    vmadh v31,v12,v29[e8]
+    vmadn v31,v15,v1[e8]
+    vmadh v16,v14,v1[e8]
+    vmadn v17,v0,v0[e8]
+
+    // v18, v19: values at the high vertex, s15.16
+    vmudn v31,v3,v1[e9]
+    vmadh v18,v2,v1[e9]
+    vmadn v19,v0,v0[e8]
+
+    // Edge coefficients
+    li t8, mesh_edge_command << 1
+    slt t9, 0, t5
+    or t8, t8, t9
+    sll t8, t8, 23
+    sll t4, t4, 2
+    andi t4, t4, $3fff
+    or t8, t8, t4
+    sw t8, 0(s7)
+    sll t1, t1, 2
+    andi t1, t1, $3fff
+    sll t1, t1, 16
+    sll t0, t0, 2
+    andi t0, t0, $3fff
+    or t1, t1, t0
+    sw t1, 4(s7)
+
+    sh k1, 8(s7)
+    sh 0, 10(s7)
+    ssv v29[e4],12(s7)
+    ssv v28[e4],14(s7)
+    sh k0, 16(s7)
+    sh 0, 18(s7)
+    ssv v29[e0],20(s7)
+    ssv v28[e0],22(s7)
+    sh k0, 24(s7)
+    sh 0, 26(s7)
+    ssv v29[e2],28(s7)
+    ssv v28[e2],30(s7)
+
+    // Shade coefficients
+    sdv v18[e0],32(s7)
+    sdv v12[e0],40(s7)
+    sdv v19[e0],48(s7)
+    sdv v13[e0],56(s7)
+    sdv v16[e0],64(s7)
+    sdv v14[e0],72(s7)
+    sdv v17[e0],80(s7)
+    sdv v15[e0],88(s7)
+
+    // Z buffer coefficients
+    ssv v18[e8],96(s7)
+    ssv v19[e8],98(s7)
+    ssv v12[e8],100(s7)
+    ssv v13[e8],102(s7)
+    ssv v16[e8],104(s7)
+    ssv v17[e8],106(s7)
+    ssv v14[e8],108(s7)
+    ssv v15[e8],110(s7)
+
+    addi s7, s7, mesh_triangle_size
+    addi t5, s6, mesh_output_size
+    bne s7, t5, mesh_triangle_loop
+    nop
+    jal mesh_flush
+    nop
+    j mesh_triangle_loop
+    nop
+
+mesh_triangles_done:
+    beq s7, s6, mesh_chunk_done
+    nop
+    jal mesh_flush
+    nop
+mesh_chunk_done:
+    j next_chunk
+    nop
+
+// Hands the filled buffer to the RDP once it is done with the other one, then swaps buffers
+mesh_flush:
+    mfc0 t5, c11
+    andi t5, t5, RDP_CMB|RDP_CMS
+    bnez t5, mesh_flush
+    nop
+    mtc0 s6, c8
+    mtc0 s7, c9
+    xori s6, s6, mesh_output_a ^ mesh_output_b
+    or s7, s6, 0
+    jr ra
+    nop
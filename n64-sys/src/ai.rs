@@ -1,5 +1,6 @@
 use crate::sys::{data_cache_hit_writeback, virtual_to_physical};
 use core::ptr::{read_volatile, write_volatile};
+use n64_types::TvType;
 
 const AI_BASE: usize = 0xA4500000;
 
@@ -17,17 +18,15 @@ const AI_MPAL_DACRATE: usize = 48628316;
 const AI_STATUS_BUSY: usize = 1 << 30;
 const AI_STATUS_FULL: usize = 1 << 31;
 
-const TV_TYPE_LOC: usize = 0x80000300;
-
 const FREQUENCY: usize = 22050;
 
 #[inline]
 pub fn init() {
     unsafe {
-        let clockrate = match read_volatile(TV_TYPE_LOC as *const usize) {
-            0 => AI_PAL_DACRATE,
-            2 => AI_MPAL_DACRATE,
-            _ => AI_NTSC_DACRATE,
+        let clockrate = match TvType::detect() {
+            TvType::Pal => AI_PAL_DACRATE,
+            TvType::Mpal => AI_MPAL_DACRATE,
+            TvType::Ntsc => AI_NTSC_DACRATE,
         };
 
         write_volatile(AI_DACRATE, (2 * clockrate / FREQUENCY) - 1);
@@ -45,6 +44,11 @@ pub fn full() -> bool {
     unsafe { read_volatile(AI_STATUS) & AI_STATUS_FULL > 0 }
 }
 
+#[inline]
+pub(crate) fn clear_interrupt() {
+    unsafe { write_volatile(AI_STATUS, 0) };
+}
+
 #[inline]
 pub fn submit_audio_data_to_dac(buffer: &[i16]) {
     unsafe {
@@ -0,0 +1,97 @@
+use crate::{
+    si::{response_ok, JoybusError, PifBlock, CONTROLLER_PORTS, JOYBUS_CMD_STATUS},
+    sys::current_time_us,
+};
+
+const JOYBUS_CMD_EEPROM_READ: u8 = 0x04;
+const JOYBUS_CMD_EEPROM_WRITE: u8 = 0x05;
+
+const EEPROM_ID_4K: u16 = 0x0080;
+const EEPROM_ID_16K: u16 = 0x00c0;
+
+// The cartridge EEPROM sits on the joybus channel after the controller ports
+const EEPROM_CHANNEL: usize = CONTROLLER_PORTS;
+
+// The EEPROM is busy for up to 15 ms after a block write
+const EEPROM_WRITE_DELAY_US: i64 = 15_000;
+
+pub const EEPROM_BLOCK_SIZE: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EepromType {
+    None,
+    Eeprom4k,
+    Eeprom16k,
+}
+
+impl EepromType {
+    #[inline]
+    pub fn block_count(self) -> usize {
+        match self {
+            EepromType::None => 0,
+            EepromType::Eeprom4k => 64,
+            EepromType::Eeprom16k => 256,
+        }
+    }
+
+    #[inline]
+    pub fn size(self) -> usize {
+        self.block_count() * EEPROM_BLOCK_SIZE
+    }
+}
+
+pub fn detect() -> EepromType {
+    let mut block = PifBlock::new();
+    block.skip_channels(EEPROM_CHANNEL);
+    let offset = block.command(&[JOYBUS_CMD_STATUS], 3);
+
+    let res = block.execute();
+
+    if !response_ok(&res, offset) {
+        return EepromType::None;
+    }
+
+    match u16::from_be_bytes([res[offset + 2], res[offset + 3]]) {
+        EEPROM_ID_4K => EepromType::Eeprom4k,
+        EEPROM_ID_16K => EepromType::Eeprom16k,
+        _ => EepromType::None,
+    }
+}
+
+pub fn read(block_index: u8, data: &mut [u8; EEPROM_BLOCK_SIZE]) -> Result<(), JoybusError> {
+    let mut block = PifBlock::new();
+    block.skip_channels(EEPROM_CHANNEL);
+    let offset = block.command(&[JOYBUS_CMD_EEPROM_READ, block_index], EEPROM_BLOCK_SIZE);
+
+    let res = block.execute();
+
+    if !response_ok(&res, offset) {
+        return Err(JoybusError::NoResponse);
+    }
+
+    data.copy_from_slice(&res[offset + 3..offset + 3 + EEPROM_BLOCK_SIZE]);
+
+    Ok(())
+}
+
+pub fn write(block_index: u8, data: &[u8; EEPROM_BLOCK_SIZE]) -> Result<(), JoybusError> {
+    let mut tx = [0u8; EEPROM_BLOCK_SIZE + 2];
+    tx[0] = JOYBUS_CMD_EEPROM_WRITE;
+    tx[1] = block_index;
+    tx[2..].copy_from_slice(data);
+
+    let mut block = PifBlock::new();
+    block.skip_channels(EEPROM_CHANNEL);
+    let offset = block.command(&tx, 1);
+
+    let res = block.execute();
+
+    if !response_ok(&res, offset) {
+        return Err(JoybusError::NoResponse);
+    }
+
+    let start = current_time_us();
+    while current_time_us() - start < EEPROM_WRITE_DELAY_US {}
+
+    Ok(())
+}
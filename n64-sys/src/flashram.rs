@@ -0,0 +1,100 @@
+use crate::pi;
+
+const FLASHRAM_BASE: usize = 0x0800_0000;
+const FLASHRAM_COMMAND: usize = 0x0801_0000;
+
+const FLASHRAM_CMD_CHIP_ERASE: u32 = 0x3c00_0000;
+const FLASHRAM_CMD_SECTOR_ERASE: u32 = 0x4b00_0000;
+const FLASHRAM_CMD_EXECUTE_ERASE: u32 = 0x7800_0000;
+const FLASHRAM_CMD_PROGRAM_PAGE: u32 = 0xa500_0000;
+const FLASHRAM_CMD_WRITE_BUFFER: u32 = 0xb400_0000;
+const FLASHRAM_CMD_STATUS: u32 = 0xe100_0000;
+const FLASHRAM_CMD_READ_ARRAY: u32 = 0xf000_0000;
+
+const FLASHRAM_STATUS_PROGRAM_BUSY: u32 = 0x01;
+const FLASHRAM_STATUS_ERASE_BUSY: u32 = 0x02;
+const FLASHRAM_STATUS_PROGRAM_OK: u32 = 0x04;
+const FLASHRAM_STATUS_ERASE_OK: u32 = 0x08;
+
+const FLASHRAM_SILICON_ID: u32 = 0x1111_8001;
+
+pub const FLASHRAM_SIZE: usize = 128 * 1024;
+pub const FLASHRAM_PAGE_SIZE: usize = 128;
+pub const FLASHRAM_SECTOR_SIZE: usize = 16 * 1024;
+pub const FLASHRAM_PAGES_PER_SECTOR: usize = FLASHRAM_SECTOR_SIZE / FLASHRAM_PAGE_SIZE;
+
+#[repr(C, align(8))]
+struct SiliconId([u8; 8]);
+
+#[inline]
+pub fn init() {
+    pi::set_dom2_timing(0x05, 0x0c, 0x0f, 0x02);
+}
+
+#[inline]
+fn command(cmd: u32) {
+    unsafe { pi::io_write(FLASHRAM_COMMAND, cmd) };
+}
+
+#[inline]
+pub fn status() -> u32 {
+    command(FLASHRAM_CMD_STATUS);
+    unsafe { pi::io_read(FLASHRAM_BASE) & 0xff }
+}
+
+#[inline]
+pub fn clear_status() {
+    command(FLASHRAM_CMD_STATUS);
+    unsafe { pi::io_write(FLASHRAM_BASE, 0) };
+}
+
+pub fn detect() -> bool {
+    let mut id = SiliconId([0; 8]);
+
+    command(FLASHRAM_CMD_STATUS);
+    unsafe { pi::read_physical(&mut id.0, FLASHRAM_BASE) };
+
+    u32::from_be_bytes([id.0[0], id.0[1], id.0[2], id.0[3]]) == FLASHRAM_SILICON_ID
+}
+
+// In read array mode the flash is addressed in 16 bit words, so the bus offset is halved.
+// Offset and length must be even and the buffer 8 byte aligned.
+pub fn read(offset: usize, dst: &mut [u8]) {
+    command(FLASHRAM_CMD_READ_ARRAY);
+    unsafe { pi::read_physical(dst, FLASHRAM_BASE + offset / 2) };
+}
+
+fn wait_while(busy: u32) -> u32 {
+    loop {
+        let status = status();
+
+        if status & busy == 0 {
+            clear_status();
+            return status;
+        }
+    }
+}
+
+pub fn erase_sector(sector: usize) -> bool {
+    command(FLASHRAM_CMD_SECTOR_ERASE | (sector * FLASHRAM_PAGES_PER_SECTOR) as u32);
+    command(FLASHRAM_CMD_EXECUTE_ERASE);
+
+    wait_while(FLASHRAM_STATUS_ERASE_BUSY) & FLASHRAM_STATUS_ERASE_OK > 0
+}
+
+pub fn erase_chip() -> bool {
+    command(FLASHRAM_CMD_CHIP_ERASE);
+    command(FLASHRAM_CMD_EXECUTE_ERASE);
+
+    wait_while(FLASHRAM_STATUS_ERASE_BUSY) & FLASHRAM_STATUS_ERASE_OK > 0
+}
+
+// Programming can only clear bits, the page has to be erased first.
+// The buffer must be 8 byte aligned.
+pub fn program_page(page: usize, src: &[u8; FLASHRAM_PAGE_SIZE]) -> bool {
+    command(FLASHRAM_CMD_WRITE_BUFFER);
+    unsafe { pi::write_physical(src, FLASHRAM_BASE) };
+    command(FLASHRAM_CMD_PROGRAM_PAGE | page as u32);
+
+    wait_while(FLASHRAM_STATUS_PROGRAM_BUSY) & FLASHRAM_STATUS_PROGRAM_OK > 0
+}
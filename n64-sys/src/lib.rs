@@ -5,9 +5,13 @@
 
 pub mod ai;
 pub mod ed;
+pub mod eeprom;
+pub mod flashram;
+pub mod mi;
 pub mod pi;
 pub mod rdp;
 pub mod rsp;
 pub mod si;
+pub mod sram;
 pub mod sys;
 pub mod vi;
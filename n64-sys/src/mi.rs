@@ -0,0 +1,176 @@
+#![cfg_attr(not(target_vendor = "nintendo64"), allow(unused))]
+
+use crate::{ai, pi, rsp, si, vi};
+use core::ptr::{read_volatile, write_volatile};
+
+#[cfg(target_vendor = "nintendo64")]
+use core::arch::asm;
+
+const MI_BASE: usize = 0xA430_0000;
+
+const MI_MODE: *mut usize = (MI_BASE) as _;
+const MI_VERSION: *const usize = (MI_BASE + 0x04) as _;
+const MI_INTR: *const usize = (MI_BASE + 0x08) as _;
+const MI_INTR_MASK: *mut usize = (MI_BASE + 0x0C) as _;
+
+const MI_MODE_CLEAR_DP_INTR: usize = 0x0800; // MI_MODE write mask: acknowledge the DP interrupt
+
+const CAUSE_EXC_CODE_MASK: u32 = 0x7C; // Cause: exception code (Bit 2..6), zero for interrupts
+const CAUSE_IP2: u32 = 0x400; // Cause: pending interrupt from the MI (Bit 10)
+
+const STATUS_IE: u32 = 0x1; // Status: global interrupt enable (Bit 0)
+
+const INTERRUPT_COUNT: usize = 6;
+
+// Listed in MI_INTR bit order
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    Sp = 0,
+    Si = 1,
+    Ai = 2,
+    Vi = 3,
+    Pi = 4,
+    Dp = 5,
+}
+
+impl Interrupt {
+    const ALL: [Interrupt; INTERRUPT_COUNT] = [
+        Interrupt::Sp,
+        Interrupt::Si,
+        Interrupt::Ai,
+        Interrupt::Vi,
+        Interrupt::Pi,
+        Interrupt::Dp,
+    ];
+
+    #[inline]
+    fn bit(self) -> usize {
+        1 << self as usize
+    }
+
+    // MI_INTR_MASK takes a clear bit followed by a set bit for each interrupt
+    #[inline]
+    fn mask_clear(self) -> usize {
+        1 << (2 * self as usize)
+    }
+
+    #[inline]
+    fn mask_set(self) -> usize {
+        1 << (2 * self as usize + 1)
+    }
+
+    fn acknowledge(self) {
+        match self {
+            Interrupt::Sp => rsp::clear_interrupt(),
+            Interrupt::Si => si::clear_interrupt(),
+            Interrupt::Ai => ai::clear_interrupt(),
+            Interrupt::Vi => vi::clear_interrupt(),
+            Interrupt::Pi => pi::clear_interrupt(),
+            Interrupt::Dp => unsafe { write_volatile(MI_MODE, MI_MODE_CLEAR_DP_INTR) },
+        }
+    }
+}
+
+static mut HANDLERS: [Option<fn()>; INTERRUPT_COUNT] = [None; INTERRUPT_COUNT];
+
+#[inline]
+fn status() -> u32 {
+    #[cfg(target_vendor = "nintendo64")]
+    unsafe {
+        let res;
+        asm!(
+            "mfc0 {}, $12
+            nop",
+            lateout(reg) res,
+        );
+        res
+    }
+
+    #[cfg(not(target_vendor = "nintendo64"))]
+    0
+}
+
+#[inline]
+fn set_status(status: u32) {
+    #[cfg(target_vendor = "nintendo64")]
+    unsafe {
+        asm!(
+            "mtc0 {}, $12
+            nop",
+            in(reg) status,
+        );
+    }
+}
+
+// Runs f with the CPU ignoring interrupts, state shared with a handler must only be touched in here
+#[inline]
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let status = status();
+    set_status(status & !STATUS_IE);
+
+    let res = f();
+
+    set_status(status);
+
+    res
+}
+
+// The handler runs in exception context, keep it short and do not allocate
+pub fn set_handler(interrupt: Interrupt, handler: Option<fn()>) {
+    without_interrupts(|| unsafe {
+        HANDLERS[interrupt as usize] = handler;
+    });
+}
+
+#[inline]
+pub fn enable(interrupt: Interrupt) {
+    unsafe { write_volatile(MI_INTR_MASK, interrupt.mask_set()) };
+}
+
+#[inline]
+pub fn disable(interrupt: Interrupt) {
+    unsafe { write_volatile(MI_INTR_MASK, interrupt.mask_clear()) };
+}
+
+#[inline]
+pub fn enabled(interrupt: Interrupt) -> bool {
+    unsafe { read_volatile(MI_INTR_MASK as *const usize) & interrupt.bit() > 0 }
+}
+
+#[inline]
+pub fn pending(interrupt: Interrupt) -> bool {
+    unsafe { read_volatile(MI_INTR) & interrupt.bit() > 0 }
+}
+
+#[inline]
+pub fn version() -> usize {
+    unsafe { read_volatile(MI_VERSION) }
+}
+
+// Called from the exception vector in entrypoint.s with interrupts disabled
+#[no_mangle]
+extern "C" fn __n64_exception(cause: u32, epc: u32) {
+    if cause & CAUSE_EXC_CODE_MASK != 0 {
+        panic!(
+            "Unhandled exception {} at {:08x}",
+            (cause & CAUSE_EXC_CODE_MASK) >> 2,
+            epc
+        );
+    }
+
+    if cause & CAUSE_IP2 == 0 {
+        return;
+    }
+
+    let active = unsafe { read_volatile(MI_INTR) & read_volatile(MI_INTR_MASK as *const usize) };
+
+    for interrupt in Interrupt::ALL {
+        if active & interrupt.bit() > 0 {
+            interrupt.acknowledge();
+
+            if let Some(handler) = unsafe { HANDLERS[interrupt as usize] } {
+                handler();
+            }
+        }
+    }
+}
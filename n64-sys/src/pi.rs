@@ -1,6 +1,7 @@
 use crate::sys::{
-    data_cache_hit_writeback_invalidate, data_cache_hit_writeback_invalidate_single, uncached_addr,
-    uncached_addr_mut, virtual_to_physical, virtual_to_physical_mut,
+    data_cache_hit_writeback, data_cache_hit_writeback_invalidate,
+    data_cache_hit_writeback_invalidate_single, uncached_addr, uncached_addr_mut,
+    virtual_to_physical, virtual_to_physical_mut,
 };
 use core::{
     ptr::{read_volatile, write_volatile},
@@ -28,6 +29,10 @@ const PI_BSD_DOM2_RLS_REG: *mut usize = (PI_BASE + 0x30) as _;
 const PI_STATUS_DMA_BUSY: usize = 0x0001;
 const PI_STATUS_IO_BUSY: usize = 0x0002;
 
+const PI_STATUS_CLEAR_INTR: usize = 0x0002;
+
+const PI_UNCACHED_BASE: usize = 0xA000_0000;
+
 #[inline]
 fn dma_wait() {
     unsafe { while read_volatile(PI_STATUS) & (PI_STATUS_DMA_BUSY | PI_STATUS_IO_BUSY) > 0 {} }
@@ -48,6 +53,11 @@ pub fn init() {
     }
 }
 
+#[inline]
+pub(crate) fn clear_interrupt() {
+    unsafe { write_volatile(PI_STATUS, PI_STATUS_CLEAR_INTR) };
+}
+
 pub unsafe fn read(dst: *mut u8, len: u32, pi_address: usize) {
     data_cache_hit_writeback_invalidate_single(dst as usize);
 
@@ -72,3 +82,58 @@ pub unsafe fn write(src: *const u8, len: u32, pi_address: usize) {
 
     dma_wait();
 }
+
+pub fn set_dom2_timing(latency: usize, pulse_width: usize, page_size: usize, release: usize) {
+    unsafe {
+        dma_wait();
+        write_volatile(PI_BSD_DOM2_LAT_REG, latency);
+        write_volatile(PI_BSD_DOM2_PWD_REG, pulse_width);
+        write_volatile(PI_BSD_DOM2_PGS_REG, page_size);
+        write_volatile(PI_BSD_DOM2_RLS_REG, release);
+    }
+}
+
+// DMA from a physical PI bus address, unlike `read` this does not assume cartridge ROM.
+// The destination must be 8 byte aligned and the length even.
+pub unsafe fn read_physical(dst: &mut [u8], pi_address: usize) {
+    debug_assert!(dst.as_ptr() as usize % 8 == 0);
+    debug_assert!(dst.len() % 2 == 0);
+
+    data_cache_hit_writeback_invalidate(dst);
+
+    dma_wait();
+    write_volatile(PI_STATUS, 3);
+    write_volatile(PI_RAM_ADDR, virtual_to_physical_mut(dst.as_mut_ptr()));
+    write_volatile(PI_CART_ADDR, pi_address);
+    write_volatile(PI_WRITE_LENGTH, dst.len() - 1);
+
+    dma_wait();
+}
+
+// DMA to a physical PI bus address. The source must be 8 byte aligned and the length even.
+pub unsafe fn write_physical(src: &[u8], pi_address: usize) {
+    debug_assert!(src.as_ptr() as usize % 8 == 0);
+    debug_assert!(src.len() % 2 == 0);
+
+    data_cache_hit_writeback(src);
+
+    dma_wait();
+    write_volatile(PI_STATUS, 3);
+    write_volatile(PI_RAM_ADDR, virtual_to_physical(src.as_ptr()));
+    write_volatile(PI_CART_ADDR, pi_address);
+    write_volatile(PI_READ_LENGTH, src.len() - 1);
+
+    dma_wait();
+}
+
+#[inline]
+pub unsafe fn io_read(pi_address: usize) -> u32 {
+    dma_wait();
+    read_volatile((PI_UNCACHED_BASE | pi_address) as *const u32)
+}
+
+#[inline]
+pub unsafe fn io_write(pi_address: usize, value: u32) {
+    dma_wait();
+    write_volatile((PI_UNCACHED_BASE | pi_address) as *mut u32, value);
+}
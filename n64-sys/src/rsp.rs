@@ -1,9 +1,13 @@
 #![allow(dead_code)]
 
-use crate::sys::{
-    data_cache_hit_writeback, data_cache_hit_writeback_invalidate, virtual_to_physical,
+use crate::{
+    mi::{self, Interrupt},
+    sys::{data_cache_hit_writeback, data_cache_hit_writeback_invalidate, virtual_to_physical},
+};
+use core::{
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicBool, Ordering},
 };
-use core::ptr::{read_volatile, write_volatile};
 
 const RSP_RSP_ADDR: *mut usize = 0xA404_0000_usize as _; // RSP memory address (IMEM/DMEM)
 const RSP_DRAM_ADDR: *mut usize = 0xA404_0004_usize as _; // RSP RDRAM memory address
@@ -60,12 +64,20 @@ const RSP_WSTATUS_SET_SIG6: usize = 0x400000; // RSP_STATUS write mask: set SIG6
 const RSP_WSTATUS_CLEAR_SIG7: usize = 0x800000; // RSP_STATUS write mask: clear SIG7 bit
 const RSP_WSTATUS_SET_SIG7: usize = 0x1000000; // RSP_STATUS write mask: set SIG7 bit
 
+// Set by the SP interrupt raised when the microcode hits its final break
+static BROKE: AtomicBool = AtomicBool::new(false);
+
+fn break_handler() {
+    BROKE.store(true, Ordering::Release);
+}
+
 fn dma_wait() {
     while unsafe { read_volatile(RSP_STATUS) } & (RSP_STATUS_DMA_BUSY | RSP_STATUS_IO_BUSY) > 0 {}
 }
 
 fn start(single_step: bool) {
-    let mut status_cmd = RSP_WSTATUS_CLEAR_HALT | RSP_WSTATUS_CLEAR_BROKE;
+    let mut status_cmd =
+        RSP_WSTATUS_CLEAR_HALT | RSP_WSTATUS_CLEAR_BROKE | RSP_WSTATUS_SET_INTR_BREAK;
 
     if single_step {
         status_cmd |= RSP_WSTATUS_SET_SSTEP;
@@ -76,6 +88,7 @@ fn start(single_step: bool) {
     unsafe {
         write_volatile(RSP_STATUS, RSP_WSTATUS_SET_HALT); // Make sure rsp is halted before pc is set.
         write_volatile(RSP_PC, 0);
+        BROKE.store(false, Ordering::Release);
         write_volatile(RSP_STATUS, status_cmd);
     }
 }
@@ -106,6 +119,14 @@ pub fn clear_halt() {
 
 pub fn init() {
     set_halt();
+
+    mi::set_handler(Interrupt::Sp, Some(break_handler));
+    mi::enable(Interrupt::Sp);
+}
+
+#[inline]
+pub(crate) fn clear_interrupt() {
+    unsafe { write_volatile(RSP_STATUS, RSP_WSTATUS_CLEAR_INTR) };
 }
 
 pub fn run(code: &[u8], data: Option<&[u8]>, single_step: bool) {
@@ -148,18 +169,18 @@ pub fn wait(timeout: u32) -> (bool, usize) {
     let start = crate::sys::current_time_us();
 
     loop {
-        // Wait for the RSP to halt and the DMA engine to be idle.
-        let status = unsafe { read_volatile(RSP_STATUS) };
+        // Wait for the break interrupt and the DMA engine to be idle.
+        if BROKE.load(Ordering::Acquire) {
+            let status = status();
 
-        if (status & RSP_STATUS_HALTED) > 0
-            && (status & (RSP_STATUS_DMA_BUSY | RSP_STATUS_DMA_FULL)) == 0
-        {
-            return (true, status);
+            if (status & (RSP_STATUS_DMA_BUSY | RSP_STATUS_DMA_FULL)) == 0 {
+                return (true, status);
+            }
         }
 
         if crate::sys::current_time_us() > start + timeout as i64 {
             set_halt();
-            return (false, status);
+            return (false, status());
         }
     }
 }
@@ -23,6 +23,194 @@ const SI_STATUS_IO_BUSY: usize = 0x0002;
 
 const PIF_RAM: usize = 0x1fc007c0;
 
+const PIF_CHANNEL_SKIP: u8 = 0x00;
+const PIF_BLOCK_END: u8 = 0xfe;
+const PIF_PLACEHOLDER: u8 = 0xff;
+const PIF_CONTROL_RUN: u8 = 0x01;
+
+const PIF_RX_NO_RESPONSE: u8 = 0x80;
+const PIF_RX_OVERRUN: u8 = 0x40;
+
+pub(crate) const JOYBUS_CMD_STATUS: u8 = 0x00;
+const JOYBUS_CMD_ACCESSORY_READ: u8 = 0x02;
+const JOYBUS_CMD_ACCESSORY_WRITE: u8 = 0x03;
+
+const JOYBUS_STATUS_PAK_INSERTED: u8 = 0x01;
+const JOYBUS_STATUS_PAK_CHANGED: u8 = 0x02;
+const JOYBUS_STATUS_ADDRESS_CRC_ERROR: u8 = 0x04;
+
+pub const CONTROLLER_PORTS: usize = 4;
+pub const ACCESSORY_BLOCK_SIZE: usize = 32;
+
+const ACCESSORY_ADDR_PROBE: u16 = 0x8000;
+const ACCESSORY_ADDR_RUMBLE: u16 = 0xc000;
+
+const ACCESSORY_PROBE_RESET: u8 = 0xfe;
+const ACCESSORY_PROBE_RUMBLE: u8 = 0x80;
+const ACCESSORY_PROBE_TRANSFER: u8 = 0x84;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JoybusError {
+    NoResponse,
+    NoAccessory,
+    DataCrc,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Accessory {
+    None,
+    ControllerPak,
+    RumblePak,
+    TransferPak,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct JoybusStatus {
+    pub connected: bool,
+    pub device_id: u16,
+    pub flags: u8,
+}
+
+impl JoybusStatus {
+    #[inline]
+    pub fn pak_inserted(&self) -> bool {
+        self.connected && self.flags & JOYBUS_STATUS_PAK_INSERTED > 0
+    }
+
+    #[inline]
+    pub fn pak_changed(&self) -> bool {
+        self.connected && self.flags & JOYBUS_STATUS_PAK_CHANGED > 0
+    }
+
+    #[inline]
+    pub fn address_crc_error(&self) -> bool {
+        self.connected && self.flags & JOYBUS_STATUS_ADDRESS_CRC_ERROR > 0
+    }
+}
+
+// Builds the 64 byte command block that the PIF executes. Every command is sent to the
+// next joybus channel in order, so skipped ports need an explicit skip byte.
+pub(crate) struct PifBlock {
+    data: [u8; 64],
+    cursor: usize,
+}
+
+impl PifBlock {
+    #[inline]
+    pub(crate) fn new() -> Self {
+        Self {
+            data: [0; 64],
+            cursor: 0,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn skip_channels(&mut self, count: usize) {
+        for _ in 0..count {
+            self.push(PIF_CHANNEL_SKIP);
+        }
+    }
+
+    // Returns the offset of the rx length byte, the response follows after the tx bytes
+    #[inline]
+    pub(crate) fn command(&mut self, tx: &[u8], rx_len: usize) -> usize {
+        self.push(tx.len() as u8);
+        let offset = self.cursor;
+        self.push(rx_len as u8);
+
+        for byte in tx {
+            self.push(*byte);
+        }
+
+        for _ in 0..rx_len {
+            self.push(PIF_PLACEHOLDER);
+        }
+
+        offset
+    }
+
+    #[inline]
+    fn push(&mut self, byte: u8) {
+        self.data[self.cursor] = byte;
+        self.cursor += 1;
+    }
+
+    #[inline]
+    pub(crate) fn execute(mut self) -> [u8; 64] {
+        self.push(PIF_BLOCK_END);
+        self.data[63] = PIF_CONTROL_RUN;
+
+        let mut inblock = [0u64; 8];
+        let mut outblock = [0u64; 8];
+
+        for (word, bytes) in inblock.iter_mut().zip(self.data.chunks_exact(8)) {
+            *word = u64::from_be_bytes(bytes.try_into().unwrap());
+        }
+
+        dma_pif_block(&inblock, &mut outblock);
+
+        let mut res = [0u8; 64];
+
+        for (bytes, word) in res.chunks_exact_mut(8).zip(outblock.iter()) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+
+        res
+    }
+}
+
+#[inline]
+pub(crate) fn response_ok(block: &[u8; 64], rx_offset: usize) -> bool {
+    block[rx_offset] & (PIF_RX_NO_RESPONSE | PIF_RX_OVERRUN) == 0
+}
+
+// Accessory addresses are 32 byte aligned, the low 5 bits carry a CRC of the upper 11 bits.
+pub fn address_crc(address: u16) -> u16 {
+    const XOR_TABLE: [u16; 16] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x15, 0x1f, 0x0b, 0x16, 0x19, 0x07, 0x0e, 0x1c, 0x0d, 0x1a,
+        0x01,
+    ];
+
+    let address = address & !0x1f;
+    let mut crc = 0;
+
+    for (bit, xor) in XOR_TABLE.iter().enumerate().skip(5) {
+        if (address >> bit) & 0x1 > 0 {
+            crc ^= xor;
+        }
+    }
+
+    address | (crc & 0x1f)
+}
+
+// CRC-8 with polynomial 0x85 over the data block, fed with one extra zero byte.
+pub fn data_crc(data: &[u8; ACCESSORY_BLOCK_SIZE]) -> u8 {
+    let mut crc: u8 = 0;
+
+    for i in 0..=ACCESSORY_BLOCK_SIZE {
+        let byte = data.get(i).copied().unwrap_or(0);
+
+        for bit in (0..8).rev() {
+            let xor = if crc & 0x80 > 0 { 0x85 } else { 0x00 };
+
+            crc <<= 1;
+
+            if byte & (1 << bit) > 0 {
+                crc |= 1;
+            }
+
+            crc ^= xor;
+        }
+    }
+
+    crc
+}
+
+#[inline]
+pub(crate) fn clear_interrupt() {
+    unsafe { write_volatile(SI_STATUS, 0) };
+}
+
 #[inline]
 fn dma_wait() {
     while unsafe { read_volatile(SI_STATUS) } & (SI_STATUS_DMA_BUSY | SI_STATUS_IO_BUSY) > 0 {}
@@ -79,3 +267,145 @@ pub fn read_controllers(outblock: &mut [u64; 8]) {
 
     dma_pif_block(&READ_CON_BLOCK, outblock);
 }
+
+pub fn query_status() -> [JoybusStatus; CONTROLLER_PORTS] {
+    let mut block = PifBlock::new();
+    let mut offsets = [0; CONTROLLER_PORTS];
+
+    for offset in offsets.iter_mut() {
+        *offset = block.command(&[JOYBUS_CMD_STATUS], 3);
+    }
+
+    let res = block.execute();
+    let mut status = [JoybusStatus::default(); CONTROLLER_PORTS];
+
+    for (status, offset) in status.iter_mut().zip(offsets) {
+        if response_ok(&res, offset) {
+            let rx = &res[offset + 2..offset + 5];
+
+            *status = JoybusStatus {
+                connected: true,
+                device_id: u16::from_be_bytes([rx[0], rx[1]]),
+                flags: rx[2],
+            };
+        }
+    }
+
+    status
+}
+
+pub fn accessory_read(
+    port: usize,
+    address: u16,
+    data: &mut [u8; ACCESSORY_BLOCK_SIZE],
+) -> Result<(), JoybusError> {
+    assert!(port < CONTROLLER_PORTS);
+
+    let address = address_crc(address).to_be_bytes();
+
+    let mut block = PifBlock::new();
+    block.skip_channels(port);
+    let offset = block.command(
+        &[JOYBUS_CMD_ACCESSORY_READ, address[0], address[1]],
+        ACCESSORY_BLOCK_SIZE + 1,
+    );
+
+    let res = block.execute();
+
+    if !response_ok(&res, offset) {
+        return Err(JoybusError::NoResponse);
+    }
+
+    let rx = &res[offset + 4..offset + 4 + ACCESSORY_BLOCK_SIZE + 1];
+    data.copy_from_slice(&rx[..ACCESSORY_BLOCK_SIZE]);
+
+    let crc = data_crc(data);
+
+    if rx[ACCESSORY_BLOCK_SIZE] == crc {
+        Ok(())
+    } else if rx[ACCESSORY_BLOCK_SIZE] == !crc {
+        Err(JoybusError::NoAccessory)
+    } else {
+        Err(JoybusError::DataCrc)
+    }
+}
+
+pub fn accessory_write(
+    port: usize,
+    address: u16,
+    data: &[u8; ACCESSORY_BLOCK_SIZE],
+) -> Result<(), JoybusError> {
+    assert!(port < CONTROLLER_PORTS);
+
+    let address = address_crc(address).to_be_bytes();
+
+    let mut tx = [0u8; ACCESSORY_BLOCK_SIZE + 3];
+    tx[0] = JOYBUS_CMD_ACCESSORY_WRITE;
+    tx[1] = address[0];
+    tx[2] = address[1];
+    tx[3..].copy_from_slice(data);
+
+    let mut block = PifBlock::new();
+    block.skip_channels(port);
+    let offset = block.command(&tx, 1);
+
+    let res = block.execute();
+
+    if !response_ok(&res, offset) {
+        return Err(JoybusError::NoResponse);
+    }
+
+    let rx_crc = res[offset + 1 + tx.len()];
+    let crc = data_crc(data);
+
+    if rx_crc == crc {
+        Ok(())
+    } else if rx_crc == !crc {
+        Err(JoybusError::NoAccessory)
+    } else {
+        Err(JoybusError::DataCrc)
+    }
+}
+
+fn accessory_probe(port: usize, value: u8) -> Result<u8, JoybusError> {
+    let mut data = [value; ACCESSORY_BLOCK_SIZE];
+    accessory_write(port, ACCESSORY_ADDR_PROBE, &data)?;
+    accessory_read(port, ACCESSORY_ADDR_PROBE, &mut data)?;
+    Ok(data[0])
+}
+
+pub fn identify_accessory(port: usize) -> Accessory {
+    let status = query_status()[port];
+
+    if !status.pak_inserted() {
+        return Accessory::None;
+    }
+
+    if accessory_probe(port, ACCESSORY_PROBE_RESET).is_err() {
+        return Accessory::None;
+    }
+
+    match accessory_probe(port, ACCESSORY_PROBE_RUMBLE) {
+        Ok(ACCESSORY_PROBE_RUMBLE) => return Accessory::RumblePak,
+        Ok(_) => {}
+        Err(_) => return Accessory::None,
+    }
+
+    match accessory_probe(port, ACCESSORY_PROBE_TRANSFER) {
+        Ok(ACCESSORY_PROBE_TRANSFER) => Accessory::TransferPak,
+        Ok(_) => Accessory::ControllerPak,
+        Err(_) => Accessory::None,
+    }
+}
+
+// Expects the pak to have been identified as a rumble pak, which leaves it initialized
+pub fn set_rumble(port: usize, on: bool) -> Result<(), JoybusError> {
+    let data = [on as u8; ACCESSORY_BLOCK_SIZE];
+    accessory_write(port, ACCESSORY_ADDR_RUMBLE, &data)
+}
+
+#[inline]
+pub fn controller_connected(block: &[u64; 8], port: usize) -> bool {
+    let rx_len = ((block[port] >> 40) & 0xff) as u8;
+    rx_len & (PIF_RX_NO_RESPONSE | PIF_RX_OVERRUN) == 0
+}
@@ -0,0 +1,47 @@
+use crate::pi;
+
+const SRAM_BASE: usize = 0x0800_0000;
+const SRAM_BANK_STRIDE: usize = 0x0004_0000;
+
+pub const SRAM_BANK_SIZE: usize = 32 * 1024;
+
+#[inline]
+pub fn init() {
+    pi::set_dom2_timing(0x05, 0x0c, 0x0d, 0x02);
+}
+
+// 96KB carts expose three 32KB banks, so a transfer is split wherever it crosses a bank
+fn for_each_bank_span(offset: usize, len: usize, mut f: impl FnMut(usize, usize, usize)) {
+    let mut cursor = 0;
+
+    while cursor < len {
+        let address = offset + cursor;
+        let bank = address / SRAM_BANK_SIZE;
+        let bank_offset = address % SRAM_BANK_SIZE;
+        let span = (SRAM_BANK_SIZE - bank_offset).min(len - cursor);
+
+        f(
+            cursor,
+            SRAM_BASE + bank * SRAM_BANK_STRIDE + bank_offset,
+            span,
+        );
+
+        cursor += span;
+    }
+}
+
+// Offset and length must be even and the buffer 8 byte aligned.
+pub fn read(offset: usize, dst: &mut [u8]) {
+    let len = dst.len();
+
+    for_each_bank_span(offset, len, |cursor, pi_address, span| unsafe {
+        pi::read_physical(&mut dst[cursor..cursor + span], pi_address);
+    });
+}
+
+// Offset and length must be even and the buffer 8 byte aligned.
+pub fn write(offset: usize, src: &[u8]) {
+    for_each_bank_span(offset, src.len(), |cursor, pi_address, span| unsafe {
+        pi::write_physical(&src[cursor..cursor + span], pi_address);
+    });
+}
@@ -6,7 +6,7 @@ use core::{arch::asm, mem::size_of};
 
 #[inline]
 pub unsafe fn data_cache_hit_writeback_invalidate<T>(block: &[T]) {
-    let addr = (block.as_ptr() as usize) & 0xffff_fff0;
+    let addr = (block.as_ptr() as usize) & !0xf;
     let len = block.len() * size_of::<T>() + (block.as_ptr() as usize - addr);
     let mut i = 0;
 
@@ -22,7 +22,7 @@ pub unsafe fn data_cache_hit_writeback_invalidate<T>(block: &[T]) {
 
 #[inline]
 pub unsafe fn data_cache_hit_writeback_invalidate_single(addr: usize) {
-    let addr = addr & 0xffff_fff0;
+    let addr = addr & !0xf;
 
     #[cfg(target_vendor = "nintendo64")]
     asm!("cache 0x15, ({})", in(reg) addr);
@@ -30,7 +30,7 @@ pub unsafe fn data_cache_hit_writeback_invalidate_single(addr: usize) {
 
 #[inline]
 pub unsafe fn data_cache_hit_writeback<T>(block: &[T]) {
-    let addr = (block.as_ptr() as usize) & 0xffff_fff0;
+    let addr = (block.as_ptr() as usize) & !0xf;
     let len = block.len() * size_of::<T>() + (block.as_ptr() as usize - addr);
     let mut i = 0;
 
@@ -46,7 +46,7 @@ pub unsafe fn data_cache_hit_writeback<T>(block: &[T]) {
 
 #[inline]
 pub unsafe fn data_cache_hit_writeback_single(addr: usize) {
-    let addr = addr & 0xffff_fff0;
+    let addr = addr & !0xf;
 
     #[cfg(target_vendor = "nintendo64")]
     asm!("cache 0x19, ({})", in(reg) addr);
@@ -54,7 +54,7 @@ pub unsafe fn data_cache_hit_writeback_single(addr: usize) {
 
 #[inline]
 pub unsafe fn data_cache_hit_invalidate<T>(block: &[T]) {
-    let addr = (block.as_ptr() as usize) & 0xffff_fff0;
+    let addr = (block.as_ptr() as usize) & !0xf;
     let len = block.len() * size_of::<T>() + (block.as_ptr() as usize - addr);
     let mut i = 0;
 
@@ -70,7 +70,7 @@ pub unsafe fn data_cache_hit_invalidate<T>(block: &[T]) {
 
 #[inline]
 pub unsafe fn data_cache_hit_invalidate_single(addr: usize) {
-    let addr = addr & 0xffff_fff0;
+    let addr = addr & !0xf;
 
     #[cfg(target_vendor = "nintendo64")]
     asm!("cache 0x11, ({})", in(reg) addr);
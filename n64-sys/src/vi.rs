@@ -1,8 +1,12 @@
 #![allow(dead_code)]
 
-use core::ptr::{read_volatile, write_volatile};
+use crate::mi::{self, Interrupt};
+use core::{
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicU32, Ordering},
+};
 use n64_math::Color;
-use n64_types::VideoMode;
+use n64_types::{BitDepth, VideoFilter, VideoMode};
 
 const VI_STATUS_BPP0: usize = 0x0000; // VI Status/Control: Color Depth Blank (No Data Or Sync) (Bit 0..1)
 const VI_STATUS_BPP16: usize = 0x0002; // VI Status/Control: Color Depth 16BPP R5/G5/B5/A1 (Bit 0..1)
@@ -54,67 +58,203 @@ const VI_V_BURST: *mut usize = (VI_BASE + 0x2C) as _;
 const VI_X_SCALE: *mut usize = (VI_BASE + 0x30) as _;
 const VI_Y_SCALE: *mut usize = (VI_BASE + 0x34) as _;
 
+// The retrace interrupt fires on this half line, right after the vertical blank
+const VI_RETRACE_HALF_LINE: usize = 2;
+
 static mut LAST_BUFFER: Option<*mut Color> = None;
 
+static RETRACE_COUNT: AtomicU32 = AtomicU32::new(0);
+static mut RETRACE_CALLBACK: Option<fn()> = None;
+
+// Only ever written from the interrupt so a plain load and store is enough
+fn retrace_handler() {
+    // Interlaced modes point the VI at the lines of the field that is about to be scanned out
+    if let Some(buffer) = unsafe { LAST_BUFFER } {
+        if unsafe { FIELD_OFFSET } != 0 {
+            unsafe { write_volatile(VI_DRAM_ADDR, buffer as usize + field_offset()) };
+        }
+    }
+
+    RETRACE_COUNT.store(
+        RETRACE_COUNT.load(Ordering::Relaxed).wrapping_add(1),
+        Ordering::Release,
+    );
+
+    if let Some(callback) = unsafe { RETRACE_CALLBACK } {
+        callback();
+    }
+}
+
+// Timing registers, the interlaced sets have one half line less per field so fields alternate
+struct ViTiming {
+    timing: usize,
+    v_sync: usize,
+    h_sync: usize,
+    h_sync_leap: usize,
+    h_video: usize,
+    v_video: usize,
+    v_burst: usize,
+}
+
+const NTSC_TIMING: ViTiming = ViTiming {
+    timing: 0x03E5_2239,
+    v_sync: 0x0000_020D,
+    h_sync: 0x0000_0C15,
+    h_sync_leap: 0x0C15_0C15,
+    h_video: 0x006C_02EC,
+    v_video: 0x0025_01FF,
+    v_burst: 0x000E_0204,
+};
+
+const NTSC_INTERLACED_TIMING: ViTiming = ViTiming {
+    v_sync: 0x0000_020C,
+    v_video: 0x0023_01FD,
+    ..NTSC_TIMING
+};
+
+const MPAL_TIMING: ViTiming = ViTiming {
+    timing: 0x0465_1E39,
+    v_sync: 0x0000_020D,
+    h_sync: 0x0004_0C11,
+    h_sync_leap: 0x0C19_0C1A,
+    h_video: 0x006C_02EC,
+    v_video: 0x0025_01FF,
+    v_burst: 0x000E_0204,
+};
+
+const MPAL_INTERLACED_TIMING: ViTiming = ViTiming {
+    v_sync: 0x0000_020C,
+    v_video: 0x0023_01FD,
+    ..MPAL_TIMING
+};
+
+const PAL_TIMING: ViTiming = ViTiming {
+    timing: 0x0404_233A,
+    v_sync: 0x0000_0271,
+    h_sync: 0x0015_0C69,
+    h_sync_leap: 0x0C6F_0C6E,
+    h_video: 0x0080_0300,
+    v_video: 0x005F_0239,
+    v_burst: 0x0009_026B,
+};
+
+const PAL_INTERLACED_TIMING: ViTiming = ViTiming {
+    v_sync: 0x0000_0270,
+    v_video: 0x005D_0237,
+    ..PAL_TIMING
+};
+
+// Y scale subpixel offset of half a line, so both fields sample their own lines
+const VI_Y_SCALE_FIELD_OFFSET: usize = 0x0200_0000;
+
+// Bytes to skip on the even field of an interlaced mode, zero for progressive modes
+static mut FIELD_OFFSET: usize = 0;
+
+#[inline]
+fn field_offset() -> usize {
+    // Bit 0 of the current half line is set while the odd field is displayed
+    if unsafe { read_volatile(VI_CURRENT) } & 1 == 0 {
+        unsafe { FIELD_OFFSET }
+    } else {
+        0
+    }
+}
+
 #[inline]
 pub fn init(video_mode: VideoMode, fb: &mut [Color]) {
+    let format = video_mode.format();
+
+    let timing = match (video_mode, format.interlaced) {
+        (VideoMode::Ntsc { .. }, false) => &NTSC_TIMING,
+        (VideoMode::Ntsc { .. }, true) => &NTSC_INTERLACED_TIMING,
+        (VideoMode::Pal { .. }, false) => &PAL_TIMING,
+        (VideoMode::Pal { .. }, true) => &PAL_INTERLACED_TIMING,
+        (VideoMode::Mpal { .. }, false) => &MPAL_TIMING,
+        (VideoMode::Mpal { .. }, true) => &MPAL_INTERLACED_TIMING,
+    };
+
+    let status = VI_STATUS_PIXEL_ADV_3
+        | match format.bit_depth {
+            BitDepth::Bpp16 => VI_STATUS_BPP16,
+            BitDepth::Bpp32 => VI_STATUS_BPP32,
+        }
+        | match format.filter {
+            VideoFilter::AntiAliasResample => VI_STATUS_AA_MODE_0,
+            VideoFilter::AntiAliasResampleFetchNeeded => VI_STATUS_AA_MODE_1,
+            VideoFilter::Resample => VI_STATUS_AA_MODE_2,
+            VideoFilter::Replicate => VI_STATUS_AA_MODE_3,
+        }
+        | if format.interlaced {
+            VI_STATUS_INTERLACE
+        } else {
+            0
+        };
+
+    // The scale registers step through the framebuffer per output pixel in 2.10 fixed point,
+    // interlaced modes skip every other line on each field
+    let y_scale = 0x100 * video_mode.height() as usize / 60;
+    let y_scale = if format.interlaced {
+        y_scale | VI_Y_SCALE_FIELD_OFFSET
+    } else {
+        y_scale
+    };
+
     unsafe {
         LAST_BUFFER = Some(fb.as_mut_ptr());
+        FIELD_OFFSET = if format.interlaced {
+            video_mode.stride() as usize
+        } else {
+            0
+        };
+
+        write_volatile(VI_STATUS, status);
+        write_volatile(VI_DRAM_ADDR, fb.as_mut_ptr() as usize + field_offset());
+        write_volatile(VI_H_WIDTH, video_mode.width() as usize);
+        write_volatile(VI_V_INTR, VI_RETRACE_HALF_LINE);
+        write_volatile(VI_TIMING, timing.timing);
+        write_volatile(VI_V_SYNC, timing.v_sync);
+        write_volatile(VI_H_SYNC, timing.h_sync);
+        write_volatile(VI_H_SYNC_LEAP, timing.h_sync_leap);
+        write_volatile(VI_H_VIDEO, timing.h_video);
+        write_volatile(VI_V_VIDEO, timing.v_video);
+        write_volatile(VI_V_BURST, timing.v_burst);
+        write_volatile(VI_X_SCALE, 0x100 * video_mode.width() as usize / 160);
+        write_volatile(VI_Y_SCALE, y_scale);
     }
-    match video_mode {
-        VideoMode::Ntsc { .. } => unsafe {
-            write_volatile(
-                VI_STATUS,
-                VI_STATUS_PIXEL_ADV_3 | VI_STATUS_AA_MODE_2 | VI_STATUS_BPP16,
-            );
-            write_volatile(VI_DRAM_ADDR, fb.as_mut_ptr() as usize);
-            write_volatile(VI_H_WIDTH, video_mode.width() as usize);
-            write_volatile(VI_V_INTR, 2);
-            write_volatile(VI_TIMING, 0x03E5_2239);
-            write_volatile(VI_V_SYNC, 0x0000_020D);
-            write_volatile(VI_H_SYNC, 0x0000_0C15);
-            write_volatile(VI_H_SYNC_LEAP, 0x0C15_0C15);
-            write_volatile(VI_H_VIDEO, 0x006C_02EC);
-            write_volatile(VI_V_VIDEO, 0x0025_01FF);
-            write_volatile(VI_V_BURST, 0x000E_0204);
-            write_volatile(VI_X_SCALE, 0x100 * video_mode.width() as usize / 160);
-            write_volatile(VI_Y_SCALE, 0x100 * video_mode.height() as usize / 60);
-        },
-        VideoMode::Pal { .. } => unsafe {
-            write_volatile(
-                VI_STATUS,
-                VI_STATUS_PIXEL_ADV_3 | VI_STATUS_AA_MODE_2 | VI_STATUS_BPP16,
-            );
-            write_volatile(VI_DRAM_ADDR, fb.as_mut_ptr() as usize);
-            write_volatile(VI_H_WIDTH, video_mode.width() as usize);
-            write_volatile(VI_V_INTR, 0x200);
-            write_volatile(VI_TIMING, 0x0404_233A);
-            write_volatile(VI_V_SYNC, 0x0000_0271);
-            write_volatile(VI_H_SYNC, 0x0015_0C69);
-            write_volatile(VI_H_SYNC_LEAP, 0x0C6F_0C6E);
-            write_volatile(VI_H_VIDEO, 0x0080_0300);
-            write_volatile(VI_V_VIDEO, 0x005F_0239);
-            write_volatile(VI_V_BURST, 0x0009_026B);
-            write_volatile(VI_X_SCALE, 0x100 * video_mode.width() as usize / 160);
-            write_volatile(VI_Y_SCALE, 0x100 * video_mode.height() as usize / 60);
-        },
-    }
+
+    mi::set_handler(Interrupt::Vi, Some(retrace_handler));
+    mi::enable(Interrupt::Vi);
+}
+
+#[inline]
+pub(crate) fn clear_interrupt() {
+    unsafe { write_volatile(VI_CURRENT as *mut usize, 0) };
+}
+
+// Called from the retrace interrupt once per field
+pub fn set_retrace_callback(callback: Option<fn()>) {
+    mi::without_interrupts(|| unsafe {
+        RETRACE_CALLBACK = callback;
+    });
+}
+
+#[inline]
+pub fn retrace_count() -> u32 {
+    RETRACE_COUNT.load(Ordering::Acquire)
 }
 
 #[inline]
 pub fn wait_for_vblank() {
-    loop {
-        let current_halfline = unsafe { read_volatile(VI_CURRENT) };
-        if current_halfline <= 1 {
-            break;
-        }
-    }
+    let count = retrace_count();
+    while retrace_count() == count {}
 }
 
 #[inline]
 pub unsafe fn set_vi_buffer(fb: &mut [Color]) {
-    LAST_BUFFER = Some(fb.as_mut_ptr());
-    write_volatile(VI_DRAM_ADDR, fb.as_mut_ptr() as usize);
+    mi::without_interrupts(|| {
+        LAST_BUFFER = Some(fb.as_mut_ptr());
+        write_volatile(VI_DRAM_ADDR, fb.as_mut_ptr() as usize + field_offset());
+    });
 }
 
 #[inline]
@@ -0,0 +1,22 @@
+use core::mem::size_of;
+use zerocopy::{AsBytes, FromBytes, Unaligned};
+
+use crate::static_assert;
+
+pub const CAPTURE_CHUNK_SIZE: usize = 500;
+
+// One USB transfer worth of a captured frame, the RGBA pixels are split over count chunks
+#[repr(C, packed)]
+#[derive(AsBytes, FromBytes, Unaligned)]
+pub struct CaptureMessageBuffer {
+    pub message_header_buffer: u8,
+    pub padding: u8,
+    pub frame: u16,
+    pub width: u16,
+    pub height: u16,
+    pub index: u16,
+    pub count: u16,
+    pub data: [u8; CAPTURE_CHUNK_SIZE],
+}
+
+static_assert!(size_of::<CaptureMessageBuffer>() == 512);
@@ -1,15 +1,33 @@
 #![no_std]
 
+pub use capture::{CaptureMessageBuffer, CAPTURE_CHUNK_SIZE};
 pub use profiler::{ProfilerMessageBuffer, ScopeData};
-pub use rdp_command::{RdpBlock, RdpCommand};
-pub use video_mode::VideoMode;
+pub use rdp_command::{
+    MeshChunk, RdpBlock, RdpCommand, CHUNK_COMMAND_MESH, CHUNK_COMMAND_RDP, MESH_CHUNK_CULL_BACK,
+    MESH_CHUNK_CULL_FRONT, MESH_CHUNK_MAX_TRIANGLES, MESH_CHUNK_MAX_VERTICES,
+};
+pub use texture_format::{mip_level_size, TextureFormat};
+pub use video_mode::{BitDepth, TvType, VideoFilter, VideoFormat, VideoMode};
 
+mod capture;
 mod profiler;
 mod rdp_command;
+mod texture_format;
 mod video_mode;
 
 pub const MESSAGE_MAGIC_PROFILER: u8 = 0x1c;
 pub const MESSAGE_MAGIC_PRINT: u8 = 0x1d;
+pub const MESSAGE_MAGIC_CAPTURE: u8 = 0x1e;
+
+// Rom filesystem layout: magic, entry count (u32 BE), entry count * (offset, length) (u32 BE),
+// asset data. Offsets are relative to the start of the blob and every asset is 8 byte aligned.
+pub const ROM_FS_MAGIC: [u8; 4] = *b"LKFS";
+pub const ROM_FS_HEADER_SIZE: usize = 8;
+pub const ROM_FS_ENTRY_SIZE: usize = 8;
+pub const ROM_FS_ALIGNMENT: usize = 8;
+
+// Unused bytes in the cartridge header where deploy stores the ROM offset of the filesystem
+pub const ROM_FS_CART_HEADER_OFFSET: usize = 0x18;
 
 #[macro_export]
 macro_rules! static_assert {
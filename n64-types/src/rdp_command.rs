@@ -1,3 +1,6 @@
+use crate::static_assert;
+use core::mem::{align_of, size_of};
+
 #[repr(C, align(8))]
 #[derive(Copy, Clone)]
 pub struct RdpCommand(pub u64);
@@ -16,3 +19,36 @@ impl Default for RdpBlock {
         }
     }
 }
+
+// The top byte of the first word of a block picks how the RSP handles it, RDP blocks have their
+// command count there which always leaves it 0
+pub const CHUNK_COMMAND_RDP: u8 = 0x00;
+pub const CHUNK_COMMAND_MESH: u8 = 0x10;
+
+pub const MESH_CHUNK_MAX_VERTICES: usize = 32;
+pub const MESH_CHUNK_MAX_TRIANGLES: usize = 189;
+
+pub const MESH_CHUNK_CULL_BACK: u8 = 0x1;
+pub const MESH_CHUNK_CULL_FRONT: u8 = 0x2;
+
+// Untextured vertices and indices the RSP transforms and sets up shaded, z buffered triangles
+// for. Takes the place of an RdpBlock, the offsets are hardcoded in rsp.asm.
+#[repr(C, align(8))]
+pub struct MeshChunk {
+    pub command: u8,
+    pub vertex_count: u8,
+    pub triangle_count: u8,
+    pub flags: u8,
+    pub padding: u32,
+    // s15.16 columns, maps the positions to pixels with z buffer values / 256 in z
+    pub matrix_int: [[i16; 4]; 4],
+    pub matrix_frac: [[u16; 4]; 4],
+    // w is always 1
+    pub positions: [[i16; 4]; MESH_CHUNK_MAX_VERTICES],
+    pub colors: [u32; MESH_CHUNK_MAX_VERTICES],
+    pub indices: [[u8; 3]; MESH_CHUNK_MAX_TRIANGLES],
+    pub padding_end: u8,
+}
+
+static_assert!(size_of::<MeshChunk>() == size_of::<RdpBlock>());
+static_assert!(align_of::<MeshChunk>() == align_of::<RdpBlock>());
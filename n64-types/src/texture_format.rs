@@ -0,0 +1,67 @@
+// Texel layouts understood by the RDP, all stored big endian as in RDRAM
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+    Rgba16,
+    Rgba32,
+    // Color indexed, looked up in a TLUT of 16 or 256 RGBA16 entries
+    Ci4,
+    Ci8,
+    I4,
+    I8,
+    // Intensity and alpha, 3/1, 4/4 and 8/8 bits
+    Ia4,
+    Ia8,
+    Ia16,
+}
+
+impl TextureFormat {
+    #[inline]
+    pub const fn bits_per_texel(self) -> usize {
+        match self {
+            TextureFormat::Ci4 | TextureFormat::I4 | TextureFormat::Ia4 => 4,
+            TextureFormat::Ci8 | TextureFormat::I8 | TextureFormat::Ia8 => 8,
+            TextureFormat::Rgba16 | TextureFormat::Ia16 => 16,
+            TextureFormat::Rgba32 => 32,
+        }
+    }
+
+    #[inline]
+    pub const fn palette_size(self) -> usize {
+        match self {
+            TextureFormat::Ci4 => 16,
+            TextureFormat::Ci8 => 256,
+            _ => 0,
+        }
+    }
+
+    #[inline]
+    pub const fn size_in_bytes(self, width: i32, height: i32) -> usize {
+        (width as usize * height as usize * self.bits_per_texel() + 7) / 8
+    }
+
+    // Mip levels follow each other, each starting 8 byte aligned so the RDP can load it
+    pub const fn mip_level_offset(self, width: i32, height: i32, level: u8) -> usize {
+        let mut offset = 0;
+        let mut i = 0;
+
+        while i < level {
+            let size = self.size_in_bytes(mip_level_size(width, i), mip_level_size(height, i));
+            offset += (size + 7) & !7;
+            i += 1;
+        }
+
+        offset
+    }
+}
+
+// Every level halves the size down to a single texel
+#[inline]
+pub const fn mip_level_size(size: i32, level: u8) -> i32 {
+    let size = size >> level;
+
+    if size > 0 {
+        size
+    } else {
+        1
+    }
+}
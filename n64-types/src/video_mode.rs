@@ -1,15 +1,184 @@
+// Written to RDRAM by the IPL3 during boot
+#[cfg(target_vendor = "nintendo64")]
+const TV_TYPE_LOC: usize = 0x8000_0300;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TvType {
+    Pal = 0,
+    Ntsc = 1,
+    Mpal = 2,
+}
+
+impl TvType {
+    // The emulator has no console to ask and runs at NTSC rates
+    #[inline]
+    pub fn detect() -> Self {
+        #[cfg(target_vendor = "nintendo64")]
+        {
+            match unsafe { core::ptr::read_volatile(TV_TYPE_LOC as *const u32) } {
+                0 => TvType::Pal,
+                2 => TvType::Mpal,
+                _ => TvType::Ntsc,
+            }
+        }
+
+        #[cfg(not(target_vendor = "nintendo64"))]
+        TvType::Ntsc
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BitDepth {
+    // RGBA 5551, one Color per pixel
+    Bpp16,
+    // RGBA 8888, two Colors per pixel (RG then BA)
+    Bpp32,
+}
+
+impl BitDepth {
+    #[inline]
+    pub const fn bytes_per_pixel(self) -> i32 {
+        match self {
+            BitDepth::Bpp16 => 2,
+            BitDepth::Bpp32 => 4,
+        }
+    }
+}
+
+// VI anti-aliasing and resampling of the framebuffer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VideoFilter {
+    // Anti-alias and resample, always fetching the extra lines
+    AntiAliasResample,
+    // Anti-alias and resample, fetching the extra lines only when needed
+    AntiAliasResampleFetchNeeded,
+    Resample,
+    // Replicate pixels without any interpolation
+    Replicate,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VideoFormat {
+    pub bit_depth: BitDepth,
+    // Interlaced modes show the even and odd lines of the framebuffer on alternating fields
+    pub interlaced: bool,
+    pub filter: VideoFilter,
+}
+
+impl VideoFormat {
+    pub const fn default() -> Self {
+        Self {
+            bit_depth: BitDepth::Bpp16,
+            interlaced: false,
+            filter: VideoFilter::Resample,
+        }
+    }
+
+    pub fn with_bit_depth(&self, bit_depth: BitDepth) -> Self {
+        Self { bit_depth, ..*self }
+    }
+
+    pub fn with_interlaced(&self, interlaced: bool) -> Self {
+        Self {
+            interlaced,
+            ..*self
+        }
+    }
+
+    pub fn with_filter(&self, filter: VideoFilter) -> Self {
+        Self { filter, ..*self }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum VideoMode {
-    Ntsc { width: i32, height: i32 },
-    Pal { width: i32, height: i32 },
+    Ntsc {
+        width: i32,
+        height: i32,
+        format: VideoFormat,
+    },
+    Pal {
+        width: i32,
+        height: i32,
+        format: VideoFormat,
+    },
+    Mpal {
+        width: i32,
+        height: i32,
+        format: VideoFormat,
+    },
 }
 
 impl VideoMode {
+    // 640x480 32bpp interlaced
+    pub const NTSC_HI_RES: VideoMode = VideoMode::Ntsc {
+        width: 640,
+        height: 480,
+        format: VideoFormat {
+            bit_depth: BitDepth::Bpp32,
+            interlaced: true,
+            filter: VideoFilter::AntiAliasResample,
+        },
+    };
+
+    pub const PAL_HI_RES: VideoMode = VideoMode::Pal {
+        width: 640,
+        height: 480,
+        format: VideoFormat {
+            bit_depth: BitDepth::Bpp32,
+            interlaced: true,
+            filter: VideoFilter::AntiAliasResample,
+        },
+    };
+
+    pub const MPAL_HI_RES: VideoMode = VideoMode::Mpal {
+        width: 640,
+        height: 480,
+        format: VideoFormat {
+            bit_depth: BitDepth::Bpp32,
+            interlaced: true,
+            filter: VideoFilter::AntiAliasResample,
+        },
+    };
+
+    // Picks the video standard of the console the ROM is running on
+    pub fn detect(width: i32, height: i32) -> Self {
+        let format = VideoFormat::default();
+
+        match TvType::detect() {
+            TvType::Pal => VideoMode::Pal {
+                width,
+                height,
+                format,
+            },
+            TvType::Ntsc => VideoMode::Ntsc {
+                width,
+                height,
+                format,
+            },
+            TvType::Mpal => VideoMode::Mpal {
+                width,
+                height,
+                format,
+            },
+        }
+    }
+
+    #[inline]
+    pub fn tv_type(self) -> TvType {
+        match self {
+            VideoMode::Ntsc { .. } => TvType::Ntsc,
+            VideoMode::Pal { .. } => TvType::Pal,
+            VideoMode::Mpal { .. } => TvType::Mpal,
+        }
+    }
+
     #[inline]
     pub fn width(self) -> i32 {
         match self {
             VideoMode::Ntsc { width, .. } => width,
             VideoMode::Pal { width, .. } => width,
+            VideoMode::Mpal { width, .. } => width,
         }
     }
 
@@ -18,11 +187,63 @@ impl VideoMode {
         match self {
             VideoMode::Ntsc { height, .. } => height,
             VideoMode::Pal { height, .. } => height,
+            VideoMode::Mpal { height, .. } => height,
+        }
+    }
+
+    #[inline]
+    pub fn format(self) -> VideoFormat {
+        match self {
+            VideoMode::Ntsc { format, .. } => format,
+            VideoMode::Pal { format, .. } => format,
+            VideoMode::Mpal { format, .. } => format,
+        }
+    }
+
+    #[inline]
+    pub fn bit_depth(self) -> BitDepth {
+        self.format().bit_depth
+    }
+
+    #[inline]
+    pub fn interlaced(self) -> bool {
+        self.format().interlaced
+    }
+
+    #[inline]
+    pub fn filter(self) -> VideoFilter {
+        self.format().filter
+    }
+
+    pub fn with_format(&self, format: VideoFormat) -> Self {
+        match *self {
+            VideoMode::Ntsc { width, height, .. } => VideoMode::Ntsc {
+                width,
+                height,
+                format,
+            },
+            VideoMode::Pal { width, height, .. } => VideoMode::Pal {
+                width,
+                height,
+                format,
+            },
+            VideoMode::Mpal { width, height, .. } => VideoMode::Mpal {
+                width,
+                height,
+                format,
+            },
         }
     }
 
+    // Bytes in one line of the framebuffer
+    #[inline]
+    pub fn stride(self) -> i32 {
+        self.bit_depth().bytes_per_pixel() * self.width()
+    }
+
+    // Bytes in the whole framebuffer, both fields for interlaced modes
     #[inline]
     pub fn size(self) -> i32 {
-        2 * self.width() * self.height()
+        self.stride() * self.height()
     }
 }
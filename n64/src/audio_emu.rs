@@ -185,13 +185,18 @@ impl Buffer {
     }
 }
 
-pub struct Audio {
+struct Stream {
     to_audio_sender: Sender<Buffer>,
     from_audio_receiver: Receiver<Buffer>,
-    buffers: Vec<Buffer>,
     exit_sender: SyncSender<()>,
 }
 
+pub struct Audio {
+    stream: Option<Stream>,
+    buffers: Vec<Buffer>,
+    captured: Option<Vec<i16>>,
+}
+
 impl Audio {
     #[inline]
     pub(crate) fn new() -> Self {
@@ -215,31 +220,79 @@ impl Audio {
         });
 
         Self {
-            to_audio_sender,
-            from_audio_receiver,
+            stream: Some(Stream {
+                to_audio_sender,
+                from_audio_receiver,
+                exit_sender,
+            }),
             buffers,
-            exit_sender,
+            captured: None,
         }
     }
 
+    // Without an output device one buffer is mixed per update and then discarded or captured
     #[inline]
-    pub fn update(&mut self, mut f: impl FnMut(&mut [i16])) {
-        while let Ok(buffer) = self.from_audio_receiver.try_recv() {
-            self.buffers.push(buffer);
+    pub(crate) fn new_headless() -> Self {
+        Self {
+            stream: None,
+            buffers: vec![Buffer::new()],
+            captured: None,
         }
+    }
+
+    #[inline]
+    pub fn update(&mut self, mut f: impl FnMut(&mut [i16])) {
+        match &self.stream {
+            Some(stream) => {
+                while let Ok(buffer) = stream.from_audio_receiver.try_recv() {
+                    self.buffers.push(buffer);
+                }
+
+                for mut buffer in self.buffers.drain(..) {
+                    f(&mut buffer.samples);
 
-        for mut buffer in self.buffers.drain(..) {
-            f(&mut buffer.samples);
-            self.to_audio_sender
-                .send(buffer)
-                .map_err(|_| println!("Failed to send buffer to audio system"))
-                .ok();
+                    if let Some(captured) = &mut self.captured {
+                        captured.extend_from_slice(&buffer.samples);
+                    }
+
+                    stream
+                        .to_audio_sender
+                        .send(buffer)
+                        .map_err(|_| println!("Failed to send buffer to audio system"))
+                        .ok();
+                }
+            }
+            None => {
+                for buffer in &mut self.buffers {
+                    f(&mut buffer.samples);
+
+                    if let Some(captured) = &mut self.captured {
+                        captured.extend_from_slice(&buffer.samples);
+                    }
+                }
+            }
         }
     }
+
+    // Keeps a copy of every mixed sample, interleaved stereo at 22050 Hz
+    #[inline]
+    pub fn start_capture(&mut self) {
+        self.captured.get_or_insert_with(Vec::new);
+    }
+
+    #[inline]
+    pub fn take_captured(&mut self) -> Vec<i16> {
+        self.captured
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
 }
 
 impl Drop for Audio {
     fn drop(&mut self) {
-        self.exit_sender.send(()).unwrap();
+        if let Some(stream) = &self.stream {
+            stream.exit_sender.send(()).unwrap();
+        }
     }
 }
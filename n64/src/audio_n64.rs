@@ -3,17 +3,53 @@
 use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::vec::Vec;
-use n64_sys::ai;
+use n64_sys::{
+    ai,
+    mi::{self, Interrupt},
+};
+use spin::Mutex;
 
 const BUFFER_COUNT: usize = 4;
 const BUFFER_NO_SAMPLES: usize = 2 * 880;
 
-pub struct Audio {
+struct AudioQueue {
     free_buffers: VecDeque<Box<[i16]>>,
     ready_buffers: VecDeque<Box<[i16]>>,
     playing_buffers: VecDeque<Box<[i16]>>,
 }
 
+impl AudioQueue {
+    fn submit(&mut self) {
+        while !ai::full() && !self.ready_buffers.is_empty() && !self.playing_buffers.is_empty() {
+            self.free_buffers
+                .push_back(self.playing_buffers.pop_front().unwrap());
+
+            {
+                let next_buffer = self.ready_buffers.pop_front().unwrap();
+                ai::submit_audio_data_to_dac(&next_buffer);
+                self.playing_buffers.push_back(next_buffer);
+            }
+        }
+    }
+}
+
+// Shared with the AI interrupt, only lock it from the main loop with interrupts disabled.
+// The deques never grow past BUFFER_COUNT so the interrupt does not allocate.
+static QUEUE: Mutex<Option<AudioQueue>> = Mutex::new(None);
+
+// The AI raises an interrupt when it starts playing a buffer and has room for the next one
+fn refill_handler() {
+    if let Some(mut queue) = QUEUE.try_lock() {
+        if let Some(queue) = queue.as_mut() {
+            queue.submit();
+        }
+    }
+}
+
+pub struct Audio {
+    _private: (),
+}
+
 impl Audio {
     #[inline]
     pub(crate) fn new() -> Self {
@@ -35,29 +71,37 @@ impl Audio {
             playing_buffers.push_back(buffer.into_boxed_slice());
         }
 
-        Self {
-            free_buffers,
-            ready_buffers,
-            playing_buffers,
-        }
+        mi::without_interrupts(|| {
+            *QUEUE.lock() = Some(AudioQueue {
+                free_buffers,
+                ready_buffers,
+                playing_buffers,
+            });
+        });
+
+        mi::set_handler(Interrupt::Ai, Some(refill_handler));
+        mi::enable(Interrupt::Ai);
+
+        Self { _private: () }
     }
 
+    // Mixes into every free buffer, the interrupt hands them to the AI as it drains
     #[inline]
     pub fn update(&mut self, mut f: impl FnMut(&mut [i16])) {
-        for mut buffer in self.free_buffers.drain(..) {
+        while let Some(mut buffer) = mi::without_interrupts(|| {
+            QUEUE
+                .lock()
+                .as_mut()
+                .and_then(|queue| queue.free_buffers.pop_front())
+        }) {
             f(&mut buffer);
-            self.ready_buffers.push_back(buffer);
-        }
 
-        while !ai::full() && !self.ready_buffers.is_empty() && !self.playing_buffers.is_empty() {
-            self.free_buffers
-                .push_back(self.playing_buffers.pop_front().unwrap());
-
-            {
-                let next_buffer = self.ready_buffers.pop_front().unwrap();
-                ai::submit_audio_data_to_dac(&next_buffer);
-                self.playing_buffers.push_back(next_buffer);
-            }
+            mi::without_interrupts(|| {
+                if let Some(queue) = QUEUE.lock().as_mut() {
+                    queue.ready_buffers.push_back(buffer);
+                    queue.submit();
+                }
+            });
         }
     }
 }
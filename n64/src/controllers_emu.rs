@@ -1,122 +1,350 @@
 use crate::graphics::Graphics;
+use n64_sys::si::{Accessory, JoybusError, ACCESSORY_BLOCK_SIZE, CONTROLLER_PORTS};
 use std::collections::HashSet;
 use winit::event::VirtualKeyCode;
 
-#[derive(Default)]
+const CONTROLLER_PAK_SIZE: usize = 32 * 1024;
+
+// Keyboard layout of the emulated controller
+const KEY_MAP: &[(VirtualKeyCode, u16)] = &[
+    (VirtualKeyCode::X, ControllerInput::A),
+    (VirtualKeyCode::C, ControllerInput::B),
+    (VirtualKeyCode::Space, ControllerInput::Z),
+    (VirtualKeyCode::Return, ControllerInput::START),
+    (VirtualKeyCode::W, ControllerInput::UP),
+    (VirtualKeyCode::S, ControllerInput::DOWN),
+    (VirtualKeyCode::A, ControllerInput::LEFT),
+    (VirtualKeyCode::D, ControllerInput::RIGHT),
+    (VirtualKeyCode::Q, ControllerInput::L),
+    (VirtualKeyCode::E, ControllerInput::R),
+    (VirtualKeyCode::I, ControllerInput::C_UP),
+    (VirtualKeyCode::K, ControllerInput::C_DOWN),
+    (VirtualKeyCode::J, ControllerInput::C_LEFT),
+    (VirtualKeyCode::L, ControllerInput::C_RIGHT),
+];
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct ControllerInput {
+    pub buttons: u16,
+    pub x: i8,
+    pub y: i8,
+}
+
+impl ControllerInput {
+    pub const A: u16 = 0x8000;
+    pub const B: u16 = 0x4000;
+    pub const Z: u16 = 0x2000;
+    pub const START: u16 = 0x1000;
+    pub const UP: u16 = 0x0800;
+    pub const DOWN: u16 = 0x0400;
+    pub const LEFT: u16 = 0x0200;
+    pub const RIGHT: u16 = 0x0100;
+    pub const L: u16 = 0x0020;
+    pub const R: u16 = 0x0010;
+    pub const C_UP: u16 = 0x0008;
+    pub const C_DOWN: u16 = 0x0004;
+    pub const C_LEFT: u16 = 0x0002;
+    pub const C_RIGHT: u16 = 0x0001;
+
+    #[inline]
+    pub const fn default() -> Self {
+        Self {
+            buttons: 0,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    #[inline]
+    pub fn with_buttons(&self, buttons: u16) -> Self {
+        Self { buttons, ..*self }
+    }
+
+    #[inline]
+    pub fn with_stick(&self, x: i8, y: i8) -> Self {
+        Self { x, y, ..*self }
+    }
+
+    fn from_keys(keys_down: &HashSet<VirtualKeyCode>) -> Self {
+        let axis = |positive, negative| {
+            let mut res = 0;
+
+            if keys_down.contains(&positive) {
+                res += 127;
+            }
+
+            if keys_down.contains(&negative) {
+                res -= 127;
+            }
+
+            res
+        };
+
+        Self {
+            buttons: KEY_MAP
+                .iter()
+                .filter(|(key, _)| keys_down.contains(key))
+                .fold(0, |buttons, (_, button)| buttons | button),
+            x: axis(VirtualKeyCode::Right, VirtualKeyCode::Left),
+            y: axis(VirtualKeyCode::Up, VirtualKeyCode::Down),
+        }
+    }
+
+    // Same layout as the joybus controller state
+    fn to_data(self) -> u32 {
+        (self.buttons as u32) << 16 | (self.x as u8 as u32) << 8 | self.y as u8 as u32
+    }
+}
+
+// Controller input for headless runs, each input is held from its frame until the next one
+#[derive(Clone, Default, Debug)]
+pub struct InputScript {
+    inputs: Vec<(usize, ControllerInput)>,
+}
+
+impl InputScript {
+    #[inline]
+    pub fn new() -> Self {
+        Self { inputs: Vec::new() }
+    }
+
+    pub fn hold(&mut self, frame: usize, input: ControllerInput) {
+        let index = self.inputs.partition_point(|(f, _)| *f <= frame);
+        self.inputs.insert(index, (frame, input));
+    }
+
+    pub fn input(&self, frame: usize) -> ControllerInput {
+        let index = self.inputs.partition_point(|(f, _)| *f <= frame);
+
+        match index {
+            0 => ControllerInput::default(),
+            _ => self.inputs[index - 1].1,
+        }
+    }
+}
+
 pub struct Controllers {
-    data: HashSet<VirtualKeyCode>,
+    data: u32,
+    script: Option<InputScript>,
+    accessories: [Accessory; CONTROLLER_PORTS],
+    rumble: [bool; CONTROLLER_PORTS],
+    paks: [Box<[u8]>; CONTROLLER_PORTS],
+}
+
+impl Default for Controllers {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Controllers {
     #[inline]
     pub fn new() -> Controllers {
+        let mut accessories = [Accessory::None; CONTROLLER_PORTS];
+        accessories[0] = Accessory::ControllerPak;
+
         Controllers {
-            data: HashSet::new(),
+            data: 0,
+            script: None,
+            accessories,
+            rumble: [false; CONTROLLER_PORTS],
+            paks: [(); CONTROLLER_PORTS].map(|_| vec![0; CONTROLLER_PAK_SIZE].into_boxed_slice()),
+        }
+    }
+
+    // Ignores the keyboard and plays back the script instead, keyed by the graphics frame counter
+    #[inline]
+    pub(crate) fn new_scripted(script: InputScript) -> Controllers {
+        Controllers {
+            script: Some(script),
+            ..Self::new()
         }
     }
 
     #[inline]
     pub fn update(&mut self, graphics: &Graphics) {
-        self.data = graphics.keys_down.clone();
+        let input = match &self.script {
+            Some(script) => script.input(graphics.frame_counter()),
+            None => ControllerInput::from_keys(&graphics.keys_down),
+        };
+
+        self.data = input.to_data();
     }
 
     #[inline]
-    pub fn x(&self) -> i8 {
-        let mut res = 0;
+    pub fn connected(&self, port: usize) -> bool {
+        port == 0
+    }
 
-        if self.data.contains(&VirtualKeyCode::Right) {
-            res += 127;
-        }
+    #[inline]
+    pub fn accessory(&self, port: usize) -> Accessory {
+        self.accessories[port]
+    }
 
-        if self.data.contains(&VirtualKeyCode::Left) {
-            res -= 127;
+    // Swaps the pak in a port, the emulated controller pak keeps its contents while removed
+    pub fn insert_accessory(&mut self, port: usize, accessory: Accessory) {
+        self.accessories[port] = accessory;
+        self.rumble[port] = false;
+    }
+
+    #[inline]
+    pub fn rumble(&self, port: usize) -> bool {
+        self.rumble[port]
+    }
+
+    pub fn set_rumble(&mut self, port: usize, on: bool) -> Result<(), JoybusError> {
+        if self.accessories[port] != Accessory::RumblePak {
+            return Err(JoybusError::NoAccessory);
         }
 
-        res
+        self.rumble[port] = on;
+
+        Ok(())
     }
 
-    #[inline]
-    pub fn y(&self) -> i8 {
-        let mut res = 0;
+    pub fn pak_read(
+        &self,
+        port: usize,
+        address: u16,
+        data: &mut [u8; ACCESSORY_BLOCK_SIZE],
+    ) -> Result<(), JoybusError> {
+        if self.accessories[port] != Accessory::ControllerPak {
+            return Err(JoybusError::NoAccessory);
+        }
 
-        if self.data.contains(&VirtualKeyCode::Up) {
-            res += 127;
+        let start = (address as usize) & !(ACCESSORY_BLOCK_SIZE - 1);
+
+        match self.paks[port].get(start..start + ACCESSORY_BLOCK_SIZE) {
+            Some(block) => data.copy_from_slice(block),
+            None => data.fill(0),
         }
 
-        if self.data.contains(&VirtualKeyCode::Down) {
-            res -= 127;
+        Ok(())
+    }
+
+    pub fn pak_write(
+        &mut self,
+        port: usize,
+        address: u16,
+        data: &[u8; ACCESSORY_BLOCK_SIZE],
+    ) -> Result<(), JoybusError> {
+        if self.accessories[port] != Accessory::ControllerPak {
+            return Err(JoybusError::NoAccessory);
         }
 
-        res
+        let start = (address as usize) & !(ACCESSORY_BLOCK_SIZE - 1);
+
+        if let Some(block) = self.paks[port].get_mut(start..start + ACCESSORY_BLOCK_SIZE) {
+            block.copy_from_slice(data);
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn x(&self) -> i8 {
+        ((self.data >> 8) & 0xff) as i8
+    }
+
+    #[inline]
+    pub fn y(&self) -> i8 {
+        (self.data & 0xff) as i8
     }
 
     #[inline]
     pub fn a(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::X)
+        self.button(ControllerInput::A)
     }
 
     #[inline]
     pub fn b(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::C)
+        self.button(ControllerInput::B)
     }
 
     #[inline]
     pub fn z(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::Space)
+        self.button(ControllerInput::Z)
     }
 
     #[inline]
     pub fn start(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::Return)
+        self.button(ControllerInput::START)
     }
 
     #[inline]
     pub fn up(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::W)
+        self.button(ControllerInput::UP)
     }
 
     #[inline]
     pub fn down(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::S)
+        self.button(ControllerInput::DOWN)
     }
 
     #[inline]
     pub fn left(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::A)
+        self.button(ControllerInput::LEFT)
     }
 
     #[inline]
     pub fn right(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::D)
+        self.button(ControllerInput::RIGHT)
     }
 
     #[inline]
     pub fn l(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::Q)
+        self.button(ControllerInput::L)
     }
 
     #[inline]
     pub fn r(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::E)
+        self.button(ControllerInput::R)
     }
 
     #[inline]
     pub fn c_up(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::I)
+        self.button(ControllerInput::C_UP)
     }
 
     #[inline]
     pub fn c_down(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::K)
+        self.button(ControllerInput::C_DOWN)
     }
 
     #[inline]
     pub fn c_left(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::J)
+        self.button(ControllerInput::C_LEFT)
     }
 
     #[inline]
     pub fn c_right(&self) -> bool {
-        self.data.contains(&VirtualKeyCode::L)
+        self.button(ControllerInput::C_RIGHT)
+    }
+
+    #[inline]
+    fn button(&self, button: u16) -> bool {
+        (self.data >> 16) as u16 & button > 0
     }
 }
+
+#[test]
+fn input_script_holds_inputs_until_the_next_one() {
+    let jump = ControllerInput::default().with_buttons(ControllerInput::A);
+    let run = ControllerInput::default().with_stick(127, 0);
+
+    let mut script = InputScript::new();
+    script.hold(10, run);
+    script.hold(5, jump);
+
+    assert_eq!(script.input(0), ControllerInput::default());
+    assert_eq!(script.input(5), jump);
+    assert_eq!(script.input(9), jump);
+    assert_eq!(script.input(10), run);
+    assert_eq!(script.input(1000), run);
+
+    assert_eq!(jump.to_data(), 0x8000_0000);
+    assert_eq!(
+        ControllerInput::default().with_stick(-1, 2).to_data(),
+        0x0000_ff02
+    );
+}
@@ -1,22 +1,117 @@
 #![cfg_attr(not(target_vendor = "nintendo64"), allow(unused))]
 
 use crate::graphics_n64::Graphics;
-use n64_sys::si;
+use n64_sys::si::{self, Accessory, JoybusError, ACCESSORY_BLOCK_SIZE, CONTROLLER_PORTS};
+
+// Querying status and identifying paks costs extra joybus transactions, so only do it periodically
+const STATUS_POLL_INTERVAL: u32 = 30;
 
-#[derive(Default)]
 pub struct Controllers {
     data: [u64; 8],
+    accessories: [Accessory; CONTROLLER_PORTS],
+    rumble: [bool; CONTROLLER_PORTS],
+    frames_until_status_poll: u32,
+}
+
+impl Default for Controllers {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Controllers {
     #[inline]
     pub fn new() -> Controllers {
-        Controllers { data: [0; 8] }
+        Controllers {
+            data: [0; 8],
+            accessories: [Accessory::None; CONTROLLER_PORTS],
+            rumble: [false; CONTROLLER_PORTS],
+            frames_until_status_poll: 0,
+        }
     }
 
     #[inline]
     pub fn update(&mut self, _graphics: &Graphics) {
         si::read_controllers(&mut self.data);
+
+        if self.frames_until_status_poll == 0 {
+            self.poll_accessories();
+            self.frames_until_status_poll = STATUS_POLL_INTERVAL;
+        } else {
+            self.frames_until_status_poll -= 1;
+        }
+    }
+
+    fn poll_accessories(&mut self) {
+        let status = si::query_status();
+
+        for (port, status) in status.iter().enumerate() {
+            let inserted = status.pak_inserted();
+            let known = self.accessories[port] != Accessory::None;
+
+            if inserted != known || status.pak_changed() {
+                self.accessories[port] = if inserted {
+                    si::identify_accessory(port)
+                } else {
+                    Accessory::None
+                };
+                self.rumble[port] = false;
+            }
+        }
+    }
+
+    #[inline]
+    pub fn connected(&self, port: usize) -> bool {
+        si::controller_connected(&self.data, port)
+    }
+
+    #[inline]
+    pub fn accessory(&self, port: usize) -> Accessory {
+        self.accessories[port]
+    }
+
+    #[inline]
+    pub fn rumble(&self, port: usize) -> bool {
+        self.rumble[port]
+    }
+
+    pub fn set_rumble(&mut self, port: usize, on: bool) -> Result<(), JoybusError> {
+        if self.accessories[port] != Accessory::RumblePak {
+            return Err(JoybusError::NoAccessory);
+        }
+
+        if self.rumble[port] != on {
+            si::set_rumble(port, on)?;
+            self.rumble[port] = on;
+        }
+
+        Ok(())
+    }
+
+    pub fn pak_read(
+        &self,
+        port: usize,
+        address: u16,
+        data: &mut [u8; ACCESSORY_BLOCK_SIZE],
+    ) -> Result<(), JoybusError> {
+        if self.accessories[port] != Accessory::ControllerPak {
+            return Err(JoybusError::NoAccessory);
+        }
+
+        si::accessory_read(port, address, data)
+    }
+
+    pub fn pak_write(
+        &mut self,
+        port: usize,
+        address: u16,
+        data: &[u8; ACCESSORY_BLOCK_SIZE],
+    ) -> Result<(), JoybusError> {
+        if self.accessories[port] != Accessory::ControllerPak {
+            return Err(JoybusError::NoAccessory);
+        }
+
+        si::accessory_write(port, address, data)
     }
 
     #[inline]
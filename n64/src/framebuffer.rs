@@ -1,4 +1,4 @@
-use crate::{gfx::TextureMut, VideoMode};
+use crate::{gfx::TextureMut, BitDepth, VideoMode};
 use alloc::{boxed::Box, vec::Vec};
 use core::mem;
 use n64_math::Color;
@@ -18,31 +18,75 @@ impl Framebuffer {
     pub(crate) fn new(video_mode: VideoMode) -> Self {
         Framebuffer {
             video_mode,
-            vi_buffer: ViFramebuffer({
-                let mut buffer = Vec::new();
-                buffer.resize_with(video_mode.size() as usize, || Color::new(0x0001));
-                buffer.into_boxed_slice()
-            }),
-            gpu_buffer: GpuFramebuffer({
-                let mut buffer = Vec::new();
-                buffer.resize_with(video_mode.size() as usize, || Color::new(0x0001));
-                buffer.into_boxed_slice()
-            }),
+            vi_buffer: ViFramebuffer(Self::alloc_buffer(video_mode)),
+            gpu_buffer: GpuFramebuffer(Self::alloc_buffer(video_mode)),
         }
     }
 
+    // Interlaced modes keep both fields in one buffer, the VI picks the lines of each field
+    fn alloc_buffer(video_mode: VideoMode) -> Box<[Color]> {
+        // Opaque black
+        let clear: &[Color] = match video_mode.bit_depth() {
+            BitDepth::Bpp16 => &[Color::new(0x0001)],
+            BitDepth::Bpp32 => &[Color::new(0x0000), Color::new(0x00ff)],
+        };
+
+        clear
+            .iter()
+            .copied()
+            .cycle()
+            .take(video_mode.size() as usize / mem::size_of::<Color>())
+            .collect::<Vec<_>>()
+            .into_boxed_slice()
+    }
+
+    #[inline]
+    pub fn video_mode(&self) -> VideoMode {
+        self.video_mode
+    }
+
     #[inline]
     pub(crate) fn swap(&mut self) {
         mem::swap(&mut self.vi_buffer.0, &mut self.gpu_buffer.0)
     }
 
+    // The VI ignores alpha, so the captured frame is always opaque
+    pub(crate) fn vi_buffer_rgba(&self) -> Vec<u8> {
+        let vi_buffer = &self.vi_buffer.0;
+        let mut rgba =
+            Vec::with_capacity((4 * self.video_mode.width() * self.video_mode.height()) as usize);
+
+        match self.video_mode.bit_depth() {
+            BitDepth::Bpp16 => {
+                for pixel in vi_buffer.iter() {
+                    rgba.extend_from_slice(&(pixel.to_rgba32() | 0xff).to_be_bytes());
+                }
+            }
+            BitDepth::Bpp32 => {
+                for pixel in vi_buffer.chunks_exact(2) {
+                    let [r, g] = pixel[0].value().to_be_bytes();
+                    let [b, _] = pixel[1].value().to_be_bytes();
+                    rgba.extend_from_slice(&[r, g, b, 0xff]);
+                }
+            }
+        }
+
+        rgba
+    }
+
     #[inline]
     pub fn vi_buffer_token(&mut self) -> ViBufferToken {
         ViBufferToken(self.vi_buffer.0.as_mut_ptr())
     }
 
+    // CPU drawing treats the buffer as one Color per pixel
     #[inline]
     pub fn gpu_buffer(&mut self) -> TextureMut {
+        assert!(
+            self.video_mode.bit_depth() == BitDepth::Bpp16,
+            "CPU drawing needs a 16bpp video mode"
+        );
+
         TextureMut {
             width: self.video_mode.width(),
             height: self.video_mode.height(),
@@ -50,3 +94,35 @@ impl Framebuffer {
         }
     }
 }
+
+#[test]
+fn vi_buffer_rgba_is_opaque_in_both_bit_depths() {
+    let format = crate::VideoFormat::default();
+    let mode_16 = VideoMode::Ntsc {
+        width: 4,
+        height: 2,
+        format,
+    };
+    let mode_32 = VideoMode::Ntsc {
+        width: 4,
+        height: 2,
+        format: format.with_bit_depth(BitDepth::Bpp32),
+    };
+
+    let mut fb = Framebuffer::new(mode_16);
+    fb.vi_buffer.0[1] = Color::new(0b11111_00000_10000_0);
+
+    let rgba = fb.vi_buffer_rgba();
+    assert_eq!(rgba.len(), 4 * 4 * 2);
+    assert_eq!(rgba[0..4], [0, 0, 0, 0xff]);
+    assert_eq!(rgba[4..8], [0xff, 0, 0x84, 0xff]);
+
+    let mut fb = Framebuffer::new(mode_32);
+    fb.vi_buffer.0[0] = Color::new(0x1234);
+    fb.vi_buffer.0[1] = Color::new(0x5600);
+
+    let rgba = fb.vi_buffer_rgba();
+    assert_eq!(rgba.len(), 4 * 4 * 2);
+    assert_eq!(rgba[0..4], [0x12, 0x34, 0x56, 0xff]);
+    assert_eq!(rgba[4..8], [0, 0, 0, 0xff]);
+}
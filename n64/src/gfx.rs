@@ -1,9 +1,18 @@
-pub use command_buffer::{CommandBuffer, CommandBufferCache};
-pub use pipeline::{CycleType, FillPipeline, Pipeline, ZMode, ZSrc};
-pub use texture::{StaticTexture, Texture, TextureAlignment, TextureMut};
+pub use command_buffer::{CommandBuffer, CommandBufferCache, DisplayList};
+pub use n64_types::TextureFormat;
+pub use pipeline::{
+    CullMode, CycleType, FillPipeline, FogRange, Pipeline, TextureFilter, TextureLod, TextureWrap,
+    ZMode, ZSrc,
+};
+pub use sprite_batch::{Sprite, SpriteBatch};
+pub use texture::{RenderTarget, StaticTexture, Texture, TextureAlignment, TextureMut};
 
 mod command_buffer_n64;
 
+// Runs what the N64 command buffer builds on the host, for golden image tests of the hardware path
+#[cfg(not(target_vendor = "nintendo64"))]
+pub use command_buffer_n64::soft_rdp;
+
 #[cfg(not(target_vendor = "nintendo64"))]
 mod command_buffer_emu;
 
@@ -16,4 +25,6 @@ use command_buffer_emu as command_buffer;
 pub mod blend_mode;
 pub mod color_combiner_mode;
 mod pipeline;
+mod shapes;
+mod sprite_batch;
 mod texture;
@@ -128,6 +128,18 @@ impl BlendMode {
 }
 
 impl BlendMode {
+    // Blends the combined color towards the fog color by shade alpha in the first cycle, the
+    // second cycle keeps blending with memory
+    pub(crate) fn with_fog(&self) -> Self {
+        Self {
+            p_0: PMCycleOne::FogColor,
+            a_0: ASrc::SteppedAlpha,
+            m_0: PMCycleOne::ColorCombinerRgb,
+            b_0: BSrc::OneMinusA,
+            ..*self
+        }
+    }
+
     pub fn to_command(&self) -> u64 {
         let p_0 = (self.p_0 as u64) << 30;
         let a_0 = (self.a_0 as u64) << 26;
@@ -9,6 +9,8 @@ use strum_macros::FromRepr;
 pub enum ASrc {
     Combined = 0,
     Texel = 1,
+    // The next mip level when texture LOD is enabled
+    Texel1 = 2,
     Primitive = 3,
     Shade = 4,
     Environment = 5,
@@ -29,6 +31,7 @@ pub enum ASrc {
 pub enum BSrc {
     Combined = 0,
     Texel = 1,
+    Texel1 = 2,
     Primitive = 3,
     Shade = 4,
     Environment = 5,
@@ -48,11 +51,13 @@ pub enum BSrc {
 pub enum CSrc {
     Combined = 0,
     Texel = 1,
+    Texel1 = 2,
     Primitive = 3,
     Shade = 4,
     Environment = 5,
     CombinedAlpha = 7,
     TexelAlpha = 8,
+    Texel1Alpha = 9,
     PrimitiveAlpha = 10,
     ShadeAlpha = 11,
     EnvironmentAlpha = 12,
@@ -67,6 +72,7 @@ pub enum CSrc {
 pub enum DSrc {
     Combined = 0,
     Texel = 1,
+    Texel1 = 2,
     Primitive = 3,
     Shade = 4,
     Environment = 5,
@@ -79,6 +85,7 @@ pub enum DSrc {
 pub enum AAlphaSrc {
     CombinedAlpha = 0,
     TexelAlpha = 1,
+    Texel1Alpha = 2,
     PrimitiveAlpha = 3,
     ShadeAlpha = 4,
     EnvironmentAlpha = 5,
@@ -91,6 +98,7 @@ pub enum AAlphaSrc {
 pub enum BAlphaSrc {
     CombinedAlpha = 0,
     TexelAlpha = 1,
+    Texel1Alpha = 2,
     PrimitiveAlpha = 3,
     ShadeAlpha = 4,
     EnvironmentAlpha = 5,
@@ -101,8 +109,10 @@ pub enum BAlphaSrc {
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, FromRepr)]
 pub enum CAlphaSrc {
-    CombinedAlphaInvalid = 0,
+    // There is no combined alpha input on C, 0 selects the LOD fraction
+    LodFraction = 0,
     TexelAlpha = 1,
+    Texel1Alpha = 2,
     PrimitiveAlpha = 3,
     ShadeAlpha = 4,
     EnvironmentAlpha = 5,
@@ -114,6 +124,7 @@ pub enum CAlphaSrc {
 pub enum DAlphaSrc {
     CombinedAlpha = 0,
     TexelAlpha = 1,
+    Texel1Alpha = 2,
     PrimitiveAlpha = 3,
     ShadeAlpha = 4,
     EnvironmentAlpha = 5,
@@ -250,6 +261,49 @@ impl ColorCombinerMode {
             d_alpha_1: d_alpha,
         }
     }
+
+    // Blends between the two closest mip levels in the first cycle and passes the result on,
+    // for pipelines with texture LOD enabled
+    pub const fn mipmapped() -> Self {
+        Self {
+            a_0: ASrc::Texel1,
+            b_0: BSrc::Texel,
+            c_0: CSrc::LodFraction,
+            d_0: DSrc::Texel,
+
+            a_alpha_0: AAlphaSrc::Texel1Alpha,
+            b_alpha_0: BAlphaSrc::TexelAlpha,
+            c_alpha_0: CAlphaSrc::LodFraction,
+            d_alpha_0: DAlphaSrc::TexelAlpha,
+
+            a_1: ASrc::Zero,
+            b_1: BSrc::Zero,
+            c_1: CSrc::Zero,
+            d_1: DSrc::Combined,
+
+            a_alpha_1: AAlphaSrc::Zero,
+            b_alpha_1: BAlphaSrc::Zero,
+            c_alpha_1: CAlphaSrc::Zero,
+            d_alpha_1: DAlphaSrc::CombinedAlpha,
+        }
+    }
+
+    // The first cycle of first and the second cycle of second, which takes what the first one
+    // gave as Combined. For pipelines with CycleType::Two.
+    pub const fn two(first: ColorCombinerMode, second: ColorCombinerMode) -> Self {
+        Self {
+            a_1: second.a_1,
+            b_1: second.b_1,
+            c_1: second.c_1,
+            d_1: second.d_1,
+
+            a_alpha_1: second.a_alpha_1,
+            b_alpha_1: second.b_alpha_1,
+            c_alpha_1: second.c_alpha_1,
+            d_alpha_1: second.d_alpha_1,
+            ..first
+        }
+    }
 }
 
 impl ColorCombinerMode {
@@ -346,6 +400,7 @@ impl ASrc {
         match self {
             ASrc::Combined => AAlphaSrc::CombinedAlpha,
             ASrc::Texel => AAlphaSrc::TexelAlpha,
+            ASrc::Texel1 => AAlphaSrc::Texel1Alpha,
             ASrc::Primitive => AAlphaSrc::PrimitiveAlpha,
             ASrc::Shade => AAlphaSrc::ShadeAlpha,
             ASrc::Environment => AAlphaSrc::EnvironmentAlpha,
@@ -368,6 +423,7 @@ impl BSrc {
         match self {
             BSrc::Combined => BAlphaSrc::CombinedAlpha,
             BSrc::Texel => BAlphaSrc::TexelAlpha,
+            BSrc::Texel1 => BAlphaSrc::Texel1Alpha,
             BSrc::Primitive => BAlphaSrc::PrimitiveAlpha,
             BSrc::Shade => BAlphaSrc::ShadeAlpha,
             BSrc::Environment => BAlphaSrc::EnvironmentAlpha,
@@ -387,17 +443,19 @@ impl BSrc {
 impl CSrc {
     const fn to_symetrical_alpha(self) -> CAlphaSrc {
         match self {
-            CSrc::Combined => CAlphaSrc::CombinedAlphaInvalid,
+            CSrc::Combined => CAlphaSrc::LodFraction,
             CSrc::Texel => CAlphaSrc::TexelAlpha,
+            CSrc::Texel1 => CAlphaSrc::Texel1Alpha,
             CSrc::Primitive => CAlphaSrc::PrimitiveAlpha,
             CSrc::Shade => CAlphaSrc::ShadeAlpha,
             CSrc::Environment => CAlphaSrc::EnvironmentAlpha,
-            CSrc::CombinedAlpha => CAlphaSrc::CombinedAlphaInvalid,
+            CSrc::CombinedAlpha => CAlphaSrc::LodFraction,
             CSrc::TexelAlpha => CAlphaSrc::TexelAlpha,
+            CSrc::Texel1Alpha => CAlphaSrc::Texel1Alpha,
             CSrc::PrimitiveAlpha => CAlphaSrc::PrimitiveAlpha,
             CSrc::ShadeAlpha => CAlphaSrc::ShadeAlpha,
             CSrc::EnvironmentAlpha => CAlphaSrc::EnvironmentAlpha,
-            CSrc::LodFraction => CAlphaSrc::Zero,
+            CSrc::LodFraction => CAlphaSrc::LodFraction,
             CSrc::PrimitiveLodFraction => CAlphaSrc::Zero,
             CSrc::ConvertK5 => CAlphaSrc::Zero,
             CSrc::Zero => CAlphaSrc::Zero,
@@ -410,6 +468,7 @@ impl DSrc {
         match self {
             DSrc::Combined => DAlphaSrc::CombinedAlpha,
             DSrc::Texel => DAlphaSrc::TexelAlpha,
+            DSrc::Texel1 => DAlphaSrc::Texel1Alpha,
             DSrc::Primitive => DAlphaSrc::PrimitiveAlpha,
             DSrc::Shade => DAlphaSrc::ShadeAlpha,
             DSrc::Environment => DAlphaSrc::EnvironmentAlpha,
@@ -1,4 +1,7 @@
-use super::{FillPipeline, Pipeline};
+use super::{
+    color_combiner_mode::{ColorCombinerMode, DSrc},
+    shapes, FillPipeline, Pipeline, RenderTarget, Sprite, SpriteBatch, Texture, TextureWrap,
+};
 use crate::{
     framebuffer::ViBufferToken,
     graphics::QUAD_INDEX_DATA,
@@ -17,14 +20,15 @@ use core::{
     sync::atomic::{AtomicBool, Ordering},
     time::Duration,
 };
-use n64_math::{Color, Vec2};
+use n64_math::{Color, Mat4, Vec2};
 use n64_profiler::scope;
-use n64_types::VideoMode;
+use n64_types::{BitDepth, VideoMode};
 use std::mem;
 use std::num::NonZeroU32;
 use wgpu::util::DeviceExt;
 use zerocopy::{AsBytes, FromBytes};
 
+#[derive(Clone)]
 enum Command {
     ColoredRect {
         upper_left: Vec2,
@@ -45,6 +49,159 @@ enum Command {
         pipeline: Pipeline,
         buffer_index: usize,
     },
+    // Sorted by texture, drawn with one instance per sprite from an atlas of their textures
+    Sprites {
+        sprites: Vec<Sprite>,
+        pipeline: Pipeline,
+        atlas_index: usize,
+    },
+    // Everything up to the next target is drawn into this one, or into the framebuffer if None
+    SetTarget {
+        target: Option<EmuTarget>,
+        clear: bool,
+    },
+}
+
+#[derive(Copy, Clone)]
+struct EmuTarget {
+    data: *mut Color,
+    width: i32,
+    height: i32,
+}
+
+impl EmuTarget {
+    // Render targets are sampled through the same texture caches as everything else
+    fn key(&self) -> usize {
+        self.data as usize
+    }
+}
+
+// Wrap, filter and LOD settings of the pipeline packed for the textured shaders
+fn texture_mode(pipeline: &Pipeline) -> [u32; 4] {
+    let wrap = |wrap: TextureWrap| {
+        wrap.clamp as u32
+            | (wrap.mirror as u32) << 1
+            | (wrap.mask as u32) << 4
+            | (wrap.shift_bits() as u32) << 8
+    };
+
+    // Like on the RDP LOD needs a texture to take the levels from
+    let (lod, mip_levels) = match pipeline.texture {
+        Some(texture) => (pipeline.texture_lod as u32, texture.mip_levels as u32),
+        None => (0, 1),
+    };
+
+    [
+        wrap(pipeline.texture_wrap_s),
+        wrap(pipeline.texture_wrap_t),
+        pipeline.texture_filter as u32,
+        lod | mip_levels << 4
+            | (pipeline.perspective_correction as u32) << 8
+            | (pipeline.is_two_cycle() as u32) << 12,
+    ]
+}
+
+fn rect_uniforms(
+    pipeline: &Pipeline,
+    upper_left: Vec2,
+    lower_right: Vec2,
+    target_size: Vec2,
+) -> TexturedRectUniforms {
+    let size = lower_right - upper_left;
+    let scale = size / target_size;
+    let offset_x = 2.0 * upper_left.x / target_size.x - 1.0 + scale.x;
+    let offset_y = 2.0 * upper_left.y / target_size.y - 1.0 + scale.y;
+
+    let color_combiner_mode = pipeline.effective_combiner_mode().to_command();
+    let blend_mode = pipeline.effective_blend_mode().to_command();
+    let prim_color = pipeline.prim_color.unwrap_or(0);
+    let env_color = pipeline.env_color.unwrap_or(0);
+    let blend_color = pipeline.blend_color.unwrap_or(0);
+    let fog_color = pipeline.fog_color.unwrap_or(0);
+
+    TexturedRectUniforms {
+        offset: [offset_x, offset_y],
+        scale: [scale.x, scale.y],
+        combine_mode: [
+            ((color_combiner_mode >> 32) & u32::MAX as u64) as u32,
+            (color_combiner_mode & u32::MAX as u64) as u32,
+        ],
+        blend_mode: [
+            ((blend_mode >> 32) & u32::MAX as u64) as u32,
+            (blend_mode & u32::MAX as u64) as u32,
+        ],
+        prim_color: [
+            ((prim_color >> 24) & 0xff) as f32 / 255.0,
+            ((prim_color >> 16) & 0xff) as f32 / 255.0,
+            ((prim_color >> 8) & 0xff) as f32 / 255.0,
+            (prim_color & 0xff) as f32 / 255.0,
+        ],
+        env_color: [
+            ((env_color >> 24) & 0xff) as f32 / 255.0,
+            ((env_color >> 16) & 0xff) as f32 / 255.0,
+            ((env_color >> 8) & 0xff) as f32 / 255.0,
+            (env_color & 0xff) as f32 / 255.0,
+        ],
+        blend_color: [
+            ((blend_color >> 24) & 0xff) as f32 / 255.0,
+            ((blend_color >> 16) & 0xff) as f32 / 255.0,
+            ((blend_color >> 8) & 0xff) as f32 / 255.0,
+            (blend_color & 0xff) as f32 / 255.0,
+        ],
+        fog_color: [
+            ((fog_color >> 24) & 0xff) as f32 / 255.0,
+            ((fog_color >> 16) & 0xff) as f32 / 255.0,
+            ((fog_color >> 8) & 0xff) as f32 / 255.0,
+            (fog_color & 0xff) as f32 / 255.0,
+        ],
+        texture_mode: texture_mode(pipeline),
+        rotation: [1.0, 0.0, 0.0, 1.0],
+        uv_offset_and_scale: [0.0, 0.0, 1.0, 1.0],
+        texture_rect: [0; 4],
+    }
+}
+
+// Commands recorded once and copied into command buffers
+pub struct DisplayList {
+    commands: Vec<Command>,
+    colored_rect_count: u32,
+    textured_rect_count: u32,
+    mesh_count: u32,
+}
+
+impl DisplayList {
+    // Points the list at another texture with the same size and format, like the next frame of
+    // an animation
+    pub fn relocate_texture(&mut self, from: &Texture, to: &Texture<'static>) {
+        assert!(
+            from.width == to.width
+                && from.height == to.height
+                && from.format == to.format
+                && from.mip_levels == to.mip_levels,
+            "Display list textures can only be moved to textures with the same layout"
+        );
+
+        for command in &mut self.commands {
+            let pipeline = match command {
+                Command::TexturedRect { pipeline, .. } | Command::Mesh { pipeline, .. } => pipeline,
+                Command::Sprites { sprites, .. } => {
+                    for sprite in sprites {
+                        if sprite.texture.data.as_ptr() == from.data.as_ptr() {
+                            sprite.texture = *to;
+                        }
+                    }
+                    continue;
+                }
+                Command::ColoredRect { .. } | Command::SetTarget { .. } => continue,
+            };
+
+            if let Some(texture) = &mut pipeline.texture {
+                if texture.data.as_ptr() == from.data.as_ptr() {
+                    *texture = *to;
+                }
+            }
+        }
+    }
 }
 
 pub struct CommandBufferCache {
@@ -90,6 +247,9 @@ pub struct CommandBuffer<'a> {
     textured_rect_count: u32,
     mesh_count: u32,
     current_pipeline: Option<EmuPipeline>,
+    recording: bool,
+    // Index of the SetTarget command of the texture being rendered
+    target: Option<usize>,
     cache: &'a mut CommandBufferCache,
 }
 
@@ -103,12 +263,27 @@ impl<'a> CommandBuffer<'a> {
             textured_rect_count: 0,
             mesh_count: 0,
             current_pipeline: None,
+            recording: false,
+            target: None,
             cache,
         }
     }
 
     pub fn clear(&mut self) -> &mut Self {
-        self.clear = true;
+        debug_assert!(
+            !self.recording,
+            "Display lists can not clear the framebuffer"
+        );
+
+        match self.target {
+            Some(index) => {
+                if let Command::SetTarget { clear, .. } = &mut self.cache.commands[index] {
+                    *clear = true;
+                }
+            }
+            None => self.clear = true,
+        }
+
         self
     }
 
@@ -117,6 +292,43 @@ impl<'a> CommandBuffer<'a> {
         self
     }
 
+    // The calls made in f draw into the target instead of the framebuffer, anything added after
+    // this can sample it. The depth of the target is cleared when it is bound.
+    pub fn render_to_texture(
+        &mut self,
+        target: &mut RenderTarget,
+        f: impl FnOnce(&mut Self),
+    ) -> &mut Self {
+        debug_assert!(
+            !self.recording,
+            "Display lists can not change the render target"
+        );
+        debug_assert!(self.target.is_none(), "Render targets can not be nested");
+
+        let target = target.as_texture_mut();
+
+        self.target = Some(self.cache.commands.len());
+        self.cache.commands.push(Command::SetTarget {
+            target: Some(EmuTarget {
+                data: target.data.as_mut_ptr(),
+                width: target.width,
+                height: target.height,
+            }),
+            clear: false,
+        });
+
+        f(self);
+
+        self.cache.commands.push(Command::SetTarget {
+            target: None,
+            clear: false,
+        });
+        self.target = None;
+        self.current_pipeline = None;
+
+        self
+    }
+
     pub fn set_pipeline(&mut self, pipeline: &Pipeline) -> &mut Self {
         self.current_pipeline = Some(EmuPipeline::Pipeline(*pipeline));
         self
@@ -133,6 +345,85 @@ impl<'a> CommandBuffer<'a> {
         self
     }
 
+    // Points, lines and outlines are drawn with the fill color like colored rects
+    pub fn add_point(&mut self, pos: Vec2, size: f32) -> &mut Self {
+        let (upper_left, lower_right) = shapes::point_rect(pos, size);
+        self.add_colored_rect(upper_left, lower_right)
+    }
+
+    pub fn add_line(&mut self, start: Vec2, end: Vec2, width: f32) -> &mut Self {
+        if let Some(quad) = shapes::line_quad(start, end, width, [false, false]) {
+            self.add_fill_quads(&[quad]);
+        }
+
+        self
+    }
+
+    pub fn add_polyline(&mut self, points: &[Vec2], width: f32) -> &mut Self {
+        let last = points.len().saturating_sub(2);
+        let quads = points
+            .windows(2)
+            .enumerate()
+            .filter_map(|(i, segment)| {
+                shapes::line_quad(segment[0], segment[1], width, [i > 0, i < last])
+            })
+            .collect::<Vec<_>>();
+
+        self.add_fill_quads(&quads);
+        self
+    }
+
+    pub fn add_circle(&mut self, center: Vec2, radius: f32, width: f32) -> &mut Self {
+        let quads = shapes::circle_ring(center, radius, width).collect::<Vec<_>>();
+
+        self.add_fill_quads(&quads);
+        self
+    }
+
+    pub fn add_rect_outline(
+        &mut self,
+        upper_left: Vec2,
+        lower_right: Vec2,
+        width: f32,
+    ) -> &mut Self {
+        for (upper_left, lower_right) in shapes::rect_outline(upper_left, lower_right, width) {
+            if lower_right.cmpgt(upper_left).all() {
+                self.add_colored_rect(upper_left, lower_right);
+            }
+        }
+
+        self
+    }
+
+    // Untextured meshes in pixels shaded with the fill color, split so the indices fit in a u8
+    fn add_fill_quads(&mut self, quads: &[[Vec2; 4]]) {
+        let pipeline = self
+            .current_pipeline
+            .expect("No pipeline has been set on the command buffer");
+        debug_assert!(
+            matches!(pipeline, EmuPipeline::FillPipeline(_)),
+            "Lines and shapes are drawn with a fill pipeline"
+        );
+        let color = pipeline.as_fill_pipeline().fill_color.to_rgba32();
+        let pipeline =
+            Pipeline::default().with_combiner_mode(ColorCombinerMode::single(DSrc::Shade));
+
+        for quads in quads.chunks(64) {
+            self.mesh_count += 1;
+            self.cache.commands.push(Command::Mesh {
+                verts: quads.iter().flatten().map(|v| [v.x, v.y, 0.0]).collect(),
+                uvs: vec![[0.0; 2]; 4 * quads.len()],
+                colors: vec![color; 4 * quads.len()],
+                indices: (0..quads.len() as u8)
+                    .flat_map(|i| [0, 1, 2, 0, 2, 3].map(|index| 4 * i + index))
+                    .collect(),
+                transform: Mat4::IDENTITY.to_cols_array_2d(),
+                pipeline,
+                buffer_index: 0,
+            });
+        }
+    }
+
     pub fn add_textured_rect(&mut self, upper_left: Vec2, lower_right: Vec2) -> &mut Self {
         self.textured_rect_count += 1;
         self.cache.commands.push(Command::TexturedRect {
@@ -147,6 +438,26 @@ impl<'a> CommandBuffer<'a> {
         self
     }
 
+    // Draws and empties the batch. Sorted by texture like on the RDP, so sprites with different
+    // textures may not overlap in the order they were added.
+    pub fn add_sprite_batch(&mut self, batch: &mut SpriteBatch) -> &mut Self {
+        if batch.sprites.is_empty() {
+            return self;
+        }
+
+        batch.sort();
+
+        self.textured_rect_count += batch.sprites.len() as u32;
+        self.cache.commands.push(Command::Sprites {
+            sprites: batch.sprites.clone(),
+            pipeline: batch.pipeline,
+            atlas_index: 0,
+        });
+
+        batch.sprites.clear();
+        self
+    }
+
     pub fn add_mesh_indexed(
         &mut self,
         verts: &[[f32; 3]],
@@ -157,22 +468,139 @@ impl<'a> CommandBuffer<'a> {
     ) -> &mut Self {
         self.mesh_count += 1;
 
+        let pipeline = *self
+            .current_pipeline
+            .expect("No pipeline has been set on the command buffer")
+            .as_pipeline();
+
+        // The fog factor goes in the vertex alpha like on the N64
+        let colors = match pipeline.fog_range {
+            Some(fog_range) => {
+                fog_range.shade_colors(verts, colors, Mat4::from_cols_array_2d(transform))
+            }
+            None => colors.to_owned(),
+        };
+
         self.cache.commands.push(Command::Mesh {
             verts: verts.to_owned(),
             uvs: uvs.to_owned(),
-            colors: colors.to_owned(),
+            colors,
             indices: indices.iter().flatten().copied().collect(),
             transform: *transform,
-            pipeline: *self
-                .current_pipeline
-                .expect("No pipeline has been set on the command buffer")
-                .as_pipeline(),
+            pipeline,
             buffer_index: 0,
         });
 
         self
     }
 
+    // The calls made in f are recorded into a display list instead of being drawn
+    pub fn record_display_list(&mut self, f: impl FnOnce(&mut Self)) -> DisplayList {
+        debug_assert!(!self.recording, "Display lists can not be nested");
+
+        let commands = mem::take(&mut self.cache.commands);
+        let pipeline = self.current_pipeline.take();
+        let counts = (
+            mem::take(&mut self.colored_rect_count),
+            mem::take(&mut self.textured_rect_count),
+            mem::take(&mut self.mesh_count),
+        );
+
+        self.recording = true;
+        f(self);
+        self.recording = false;
+
+        let display_list = DisplayList {
+            commands: mem::replace(&mut self.cache.commands, commands),
+            colored_rect_count: self.colored_rect_count,
+            textured_rect_count: self.textured_rect_count,
+            mesh_count: self.mesh_count,
+        };
+
+        self.current_pipeline = pipeline;
+        (
+            self.colored_rect_count,
+            self.textured_rect_count,
+            self.mesh_count,
+        ) = counts;
+
+        display_list
+    }
+
+    // Draws a recorded display list moved by offset pixels, rounded like on the RDP. A pipeline
+    // has to be set again before drawing anything else.
+    pub fn add_display_list(&mut self, display_list: &DisplayList, offset: Vec2) -> &mut Self {
+        self.colored_rect_count += display_list.colored_rect_count;
+        self.textured_rect_count += display_list.textured_rect_count;
+        self.mesh_count += display_list.mesh_count;
+
+        let offset = offset.round();
+
+        self.cache
+            .commands
+            .extend(display_list.commands.iter().cloned().map(|command| {
+                match command {
+                    Command::ColoredRect {
+                        upper_left,
+                        lower_right,
+                        pipeline,
+                    } => Command::ColoredRect {
+                        upper_left: upper_left + offset,
+                        lower_right: lower_right + offset,
+                        pipeline,
+                    },
+                    Command::TexturedRect {
+                        upper_left,
+                        lower_right,
+                        pipeline,
+                    } => Command::TexturedRect {
+                        upper_left: upper_left + offset,
+                        lower_right: lower_right + offset,
+                        pipeline,
+                    },
+                    Command::Mesh {
+                        verts,
+                        uvs,
+                        colors,
+                        indices,
+                        transform,
+                        pipeline,
+                        buffer_index,
+                    } => Command::Mesh {
+                        verts,
+                        uvs,
+                        colors,
+                        indices,
+                        transform: (Mat4::from_translation(offset.extend(0.0))
+                            * Mat4::from_cols_array_2d(&transform))
+                        .to_cols_array_2d(),
+                        pipeline,
+                        buffer_index,
+                    },
+                    Command::Sprites {
+                        mut sprites,
+                        pipeline,
+                        atlas_index,
+                    } => {
+                        for sprite in &mut sprites {
+                            sprite.center += offset;
+                        }
+
+                        Command::Sprites {
+                            sprites,
+                            pipeline,
+                            atlas_index,
+                        }
+                    }
+                    Command::SetTarget { .. } => command,
+                }
+            }));
+
+        self.current_pipeline = None;
+
+        self
+    }
+
     pub fn submit(self, graphics: &mut Graphics, _step: bool) -> (i32, i32, i32, i32) {
         let dst = DstTexture::new(
             &graphics.device,
@@ -195,6 +623,7 @@ impl<'a> CommandBuffer<'a> {
 
             let mut render_pass_vertex_buffers = Vec::new();
             let mut render_pass_index_buffers = Vec::new();
+            let mut sprite_atlases = Vec::new();
 
             {
                 let mut colored_rect_uniforms =
@@ -202,6 +631,7 @@ impl<'a> CommandBuffer<'a> {
                 let mut textured_rect_uniforms =
                     Vec::with_capacity(self.textured_rect_count as usize);
                 let mut mesh_uniforms = Vec::with_capacity(self.mesh_count as usize);
+                let mut target_size = window_size;
 
                 for command in &mut self.cache.commands {
                     match command {
@@ -211,9 +641,9 @@ impl<'a> CommandBuffer<'a> {
                             pipeline,
                         } => {
                             let size = *lower_right - *upper_left;
-                            let scale = size / window_size;
-                            let offset_x = 2.0 * upper_left.x / window_size.x - 1.0 + scale.x;
-                            let offset_y = 2.0 * upper_left.y / window_size.y - 1.0 + scale.y;
+                            let scale = size / target_size;
+                            let offset_x = 2.0 * upper_left.x / target_size.x - 1.0 + scale.x;
+                            let offset_y = 2.0 * upper_left.y / target_size.y - 1.0 + scale.y;
 
                             let color = pipeline.as_fill_pipeline().fill_color;
 
@@ -236,54 +666,64 @@ impl<'a> CommandBuffer<'a> {
                                 &texture,
                             );
 
-                            let size = *lower_right - *upper_left;
-                            let scale = size / window_size;
-                            let offset_x = 2.0 * upper_left.x / window_size.x - 1.0 + scale.x;
-                            let offset_y = 2.0 * upper_left.y / window_size.y - 1.0 + scale.y;
+                            textured_rect_uniforms.push(rect_uniforms(
+                                pipeline,
+                                *upper_left,
+                                *lower_right,
+                                target_size,
+                            ));
+                        }
+                        Command::Sprites {
+                            sprites,
+                            pipeline,
+                            atlas_index,
+                        } => {
+                            for sprite in sprites.iter() {
+                                graphics.textured_rect.upload_texture_data(
+                                    &graphics.device,
+                                    &graphics.queue,
+                                    &sprite.texture,
+                                );
+                            }
 
-                            let color_combiner_mode = pipeline.color_combiner_mode.to_command();
-                            let blend_mode = pipeline.blend_mode.to_command();
-                            let prim_color = pipeline.prim_color.unwrap_or(0);
-                            let env_color = pipeline.env_color.unwrap_or(0);
-                            let blend_color = pipeline.blend_color.unwrap_or(0);
-                            let fog_color = pipeline.fog_color.unwrap_or(0);
+                            let textures = sprites
+                                .iter()
+                                .map(|sprite| sprite.texture)
+                                .collect::<Vec<_>>();
+                            let atlas = graphics
+                                .textured_rect
+                                .create_atlas(&graphics.device, &textures);
 
-                            textured_rect_uniforms.push(TexturedRectUniforms {
-                                offset: [offset_x, offset_y],
-                                scale: [scale.x, scale.y],
-                                combine_mode: [
-                                    ((color_combiner_mode >> 32) & u32::MAX as u64) as u32,
-                                    (color_combiner_mode & u32::MAX as u64) as u32,
-                                ],
-                                blend_mode: [
-                                    ((blend_mode >> 32) & u32::MAX as u64) as u32,
-                                    (blend_mode & u32::MAX as u64) as u32,
-                                ],
-                                prim_color: [
-                                    ((prim_color >> 24) & 0xff) as f32 / 255.0,
-                                    ((prim_color >> 16) & 0xff) as f32 / 255.0,
-                                    ((prim_color >> 8) & 0xff) as f32 / 255.0,
-                                    (prim_color & 0xff) as f32 / 255.0,
-                                ],
-                                env_color: [
-                                    ((env_color >> 24) & 0xff) as f32 / 255.0,
-                                    ((env_color >> 16) & 0xff) as f32 / 255.0,
-                                    ((env_color >> 8) & 0xff) as f32 / 255.0,
-                                    (env_color & 0xff) as f32 / 255.0,
-                                ],
-                                blend_color: [
-                                    ((blend_color >> 24) & 0xff) as f32 / 255.0,
-                                    ((blend_color >> 16) & 0xff) as f32 / 255.0,
-                                    ((blend_color >> 8) & 0xff) as f32 / 255.0,
-                                    (blend_color & 0xff) as f32 / 255.0,
-                                ],
-                                fog_color: [
-                                    ((fog_color >> 24) & 0xff) as f32 / 255.0,
-                                    ((fog_color >> 16) & 0xff) as f32 / 255.0,
-                                    ((fog_color >> 8) & 0xff) as f32 / 255.0,
-                                    (fog_color & 0xff) as f32 / 255.0,
-                                ],
-                            });
+                            for sprite in sprites.iter() {
+                                let mut uniforms = rect_uniforms(
+                                    &pipeline
+                                        .with_texture(Some(sprite.texture))
+                                        .with_prim_color(Some(sprite.tint)),
+                                    sprite.center - 0.5 * sprite.size,
+                                    sprite.center + 0.5 * sprite.size,
+                                    target_size,
+                                );
+
+                                // Rotated in pixels, which are not square in clip space
+                                let (sin, cos) =
+                                    (libm::sinf(sprite.rotation), libm::cosf(sprite.rotation));
+                                let aspect = target_size.x / target_size.y;
+                                uniforms.rotation = [cos, sin * aspect, -sin / aspect, cos];
+
+                                let (uv_start, uv_end) = sprite.uv_corners();
+                                uniforms.uv_offset_and_scale = [
+                                    uv_start.x,
+                                    uv_start.y,
+                                    uv_end.x - uv_start.x,
+                                    uv_end.y - uv_start.y,
+                                ];
+                                uniforms.texture_rect = atlas.rect(&sprite.texture);
+
+                                textured_rect_uniforms.push(uniforms);
+                            }
+
+                            sprite_atlases.push(atlas);
+                            *atlas_index = sprite_atlases.len() - 1;
                         }
                         Command::Mesh {
                             verts,
@@ -358,8 +798,9 @@ impl<'a> CommandBuffer<'a> {
                                 );
                             }
 
-                            let color_combiner_mode = pipeline.color_combiner_mode.to_command();
-                            let blend_mode = pipeline.blend_mode.to_command();
+                            let color_combiner_mode =
+                                pipeline.effective_combiner_mode().to_command();
+                            let blend_mode = pipeline.effective_blend_mode().to_command();
                             let prim_color = pipeline.prim_color.unwrap_or(0);
                             let env_color = pipeline.env_color.unwrap_or(0);
                             let blend_color = pipeline.blend_color.unwrap_or(0);
@@ -367,12 +808,7 @@ impl<'a> CommandBuffer<'a> {
 
                             mesh_uniforms.push(MeshUniforms {
                                 transform: *transform,
-                                screen_size_and_pad: [
-                                    graphics.video_mode.width() as f32,
-                                    graphics.video_mode.height() as f32,
-                                    0.0,
-                                    0.0,
-                                ],
+                                screen_size_and_pad: [target_size.x, target_size.y, 0.0, 0.0],
                                 combine_mode: [
                                     ((color_combiner_mode >> 32) & u32::MAX as u64) as u32,
                                     (color_combiner_mode & u32::MAX as u64) as u32,
@@ -405,8 +841,22 @@ impl<'a> CommandBuffer<'a> {
                                     ((fog_color >> 8) & 0xff) as f32 / 255.0,
                                     (fog_color & 0xff) as f32 / 255.0,
                                 ],
+                                texture_mode: texture_mode(pipeline),
                             });
                         }
+                        Command::SetTarget { target, .. } => {
+                            target_size = match target {
+                                Some(target) => {
+                                    graphics.bind_render_target(
+                                        target.key(),
+                                        target.width,
+                                        target.height,
+                                    );
+                                    Vec2::new(target.width as f32, target.height as f32)
+                                }
+                                None => window_size,
+                            };
+                        }
                     }
                 }
 
@@ -471,152 +921,232 @@ impl<'a> CommandBuffer<'a> {
             }
 
             {
-                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                    label: None,
-                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: &dst.tex_view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: if self.clear {
-                                wgpu::LoadOp::Clear(wgpu::Color {
-                                    r: 0.0,
-                                    g: 0.0,
-                                    b: 0.0,
-                                    a: 1.0,
-                                })
-                            } else {
-                                wgpu::LoadOp::Load
-                            },
-                            store: true,
-                        },
-                    })],
-                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                        view: &dst.depth_view,
-                        depth_ops: Some(wgpu::Operations {
-                            load: if self.clear {
-                                wgpu::LoadOp::Clear(1.0)
-                            } else {
-                                wgpu::LoadOp::Load
-                            },
-                            store: true,
-                        }),
-                        stencil_ops: None,
-                    }),
-                });
+                let mut colored_rect_index = 0;
+                let mut textured_rect_index = 0;
+                let mut mesh_index = 0;
 
-                {
-                    let mut colored_rect_index = 0;
-                    let mut textured_rect_index = 0;
-                    let mut mesh_index = 0;
-
-                    for command in &self.cache.commands {
-                        match command {
-                            Command::ColoredRect { .. } => {
-                                render_pass.set_index_buffer(
-                                    graphics.quad_index_buf.slice(..),
-                                    wgpu::IndexFormat::Uint16,
-                                );
-                                render_pass
-                                    .set_vertex_buffer(0, graphics.quad_vertex_buf.slice(..));
-                                render_pass.set_pipeline(&graphics.colored_rect.pipeline);
-                                render_pass.set_bind_group(
-                                    0,
-                                    &graphics.colored_rect.bind_group,
-                                    &[],
-                                );
-                                render_pass.draw_indexed(
-                                    0..(QUAD_INDEX_DATA.len() as u32),
-                                    0,
-                                    colored_rect_index..(colored_rect_index + 1),
-                                );
-                                colored_rect_index += 1;
-                            }
-                            Command::TexturedRect { pipeline, .. } => {
-                                render_pass.set_index_buffer(
-                                    graphics.quad_index_buf.slice(..),
-                                    wgpu::IndexFormat::Uint16,
-                                );
-                                render_pass
-                                    .set_vertex_buffer(0, graphics.quad_vertex_buf.slice(..));
-                                render_pass.set_pipeline(&graphics.textured_rect.pipeline);
-                                render_pass.set_bind_group(
-                                    0,
-                                    &graphics
-                                        .textured_rect
-                                        .texture_cache
-                                        .get(
-                                            &(pipeline
-                                                .texture
-                                                .expect("Invalid pipeline")
-                                                .data
-                                                .as_ptr()
-                                                as _),
-                                        )
-                                        .unwrap()
-                                        .bind_group,
-                                    &[],
-                                );
-                                render_pass.draw_indexed(
-                                    0..(QUAD_INDEX_DATA.len() as u32),
-                                    0,
-                                    textured_rect_index..(textured_rect_index + 1),
-                                );
-                                textured_rect_index += 1;
-                            }
-                            Command::Mesh {
-                                indices,
-                                pipeline,
-                                buffer_index,
-                                ..
-                            } => {
-                                let tex_key = if let Some(texture) = &pipeline.texture {
-                                    texture.data.as_ptr() as _
-                                } else {
-                                    0
-                                };
-
-                                let pipeline = match (pipeline.z_compare, pipeline.z_update) {
-                                    (true, true) => {
-                                        &graphics.mesh.pipeline_with_depth_compare_and_depth_write
-                                    }
-                                    (true, false) => &graphics.mesh.pipeline_with_depth_compare,
-                                    (false, true) => &graphics.mesh.pipeline_with_depth_write,
-                                    (false, false) => &graphics.mesh.pipeline_with_no_depth,
-                                };
-
-                                render_pass.set_index_buffer(
-                                    render_pass_index_buffers[*buffer_index].slice(..),
-                                    wgpu::IndexFormat::Uint16,
-                                );
-                                render_pass.set_vertex_buffer(
-                                    0,
-                                    render_pass_vertex_buffers[*buffer_index].slice(..),
-                                );
-                                render_pass.set_pipeline(pipeline);
-                                render_pass.set_bind_group(
-                                    0,
-                                    &graphics.mesh.shader_storage_buffer_bind_group,
-                                    &[],
-                                );
-                                render_pass.set_bind_group(
-                                    1,
-                                    &graphics
-                                        .mesh
-                                        .texture_cache
-                                        .get(&tex_key)
-                                        .unwrap()
-                                        .bind_group,
-                                    &[],
-                                );
-                                render_pass.draw_indexed(
-                                    0..(indices.len() as u32),
-                                    0,
-                                    mesh_index..(mesh_index + 1),
-                                );
-                                mesh_index += 1;
+                // One render pass per change of target
+                let mut commands = self.cache.commands.as_slice();
+                let mut target: Option<EmuTarget> = None;
+                let mut clear = self.clear;
+
+                loop {
+                    let end = commands
+                        .iter()
+                        .position(|command| matches!(command, Command::SetTarget { .. }))
+                        .unwrap_or(commands.len());
+
+                    let (tex_view, depth_view) = match target {
+                        Some(target) => {
+                            let render_target = &graphics.render_targets[&target.key()];
+                            (&render_target.tex_view, &render_target.depth_view)
+                        }
+                        None => (&dst.tex_view, &dst.depth_view),
+                    };
+
+                    // Copied in after the passes before, which may have drawn render targets the
+                    // sprites sample
+                    for command in &commands[..end] {
+                        if let Command::Sprites { atlas_index, .. } = command {
+                            sprite_atlases[*atlas_index].copy_textures(&mut encoder, |key| {
+                                match graphics.render_targets.get(&key) {
+                                    Some(render_target) => &render_target.tex,
+                                    None => graphics.textured_rect.texture_cache[&key]
+                                        .tex
+                                        .as_ref()
+                                        .unwrap(),
+                                }
+                            });
+                        }
+                    }
+
+                    {
+                        let mut render_pass =
+                            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                                label: None,
+                                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                                    view: tex_view,
+                                    resolve_target: None,
+                                    ops: wgpu::Operations {
+                                        load: if clear {
+                                            wgpu::LoadOp::Clear(wgpu::Color {
+                                                r: 0.0,
+                                                g: 0.0,
+                                                b: 0.0,
+                                                a: 1.0,
+                                            })
+                                        } else {
+                                            wgpu::LoadOp::Load
+                                        },
+                                        store: true,
+                                    },
+                                })],
+                                depth_stencil_attachment: Some(
+                                    wgpu::RenderPassDepthStencilAttachment {
+                                        view: depth_view,
+                                        depth_ops: Some(wgpu::Operations {
+                                            load: if clear || target.is_some() {
+                                                wgpu::LoadOp::Clear(1.0)
+                                            } else {
+                                                wgpu::LoadOp::Load
+                                            },
+                                            store: true,
+                                        }),
+                                        stencil_ops: None,
+                                    },
+                                ),
+                            });
+
+                        for command in &commands[..end] {
+                            match command {
+                                Command::ColoredRect { .. } => {
+                                    render_pass.set_index_buffer(
+                                        graphics.quad_index_buf.slice(..),
+                                        wgpu::IndexFormat::Uint16,
+                                    );
+                                    render_pass
+                                        .set_vertex_buffer(0, graphics.quad_vertex_buf.slice(..));
+                                    render_pass.set_pipeline(&graphics.colored_rect.pipeline);
+                                    render_pass.set_bind_group(
+                                        0,
+                                        &graphics.colored_rect.bind_group,
+                                        &[],
+                                    );
+                                    render_pass.draw_indexed(
+                                        0..(QUAD_INDEX_DATA.len() as u32),
+                                        0,
+                                        colored_rect_index..(colored_rect_index + 1),
+                                    );
+                                    colored_rect_index += 1;
+                                }
+                                Command::TexturedRect { pipeline, .. } => {
+                                    render_pass.set_index_buffer(
+                                        graphics.quad_index_buf.slice(..),
+                                        wgpu::IndexFormat::Uint16,
+                                    );
+                                    render_pass
+                                        .set_vertex_buffer(0, graphics.quad_vertex_buf.slice(..));
+                                    render_pass.set_pipeline(&graphics.textured_rect.pipeline);
+                                    render_pass.set_bind_group(
+                                        0,
+                                        &graphics
+                                            .textured_rect
+                                            .texture_cache
+                                            .get(
+                                                &(pipeline
+                                                    .texture
+                                                    .expect("Invalid pipeline")
+                                                    .data
+                                                    .as_ptr()
+                                                    as _),
+                                            )
+                                            .unwrap()
+                                            .bind_group,
+                                        &[],
+                                    );
+                                    render_pass.draw_indexed(
+                                        0..(QUAD_INDEX_DATA.len() as u32),
+                                        0,
+                                        textured_rect_index..(textured_rect_index + 1),
+                                    );
+                                    textured_rect_index += 1;
+                                }
+                                Command::Sprites {
+                                    sprites,
+                                    atlas_index,
+                                    ..
+                                } => {
+                                    render_pass.set_index_buffer(
+                                        graphics.quad_index_buf.slice(..),
+                                        wgpu::IndexFormat::Uint16,
+                                    );
+                                    render_pass
+                                        .set_vertex_buffer(0, graphics.quad_vertex_buf.slice(..));
+                                    render_pass.set_pipeline(&graphics.textured_rect.pipeline);
+                                    render_pass.set_bind_group(
+                                        0,
+                                        &sprite_atlases[*atlas_index].bind_group,
+                                        &[],
+                                    );
+                                    render_pass.draw_indexed(
+                                        0..(QUAD_INDEX_DATA.len() as u32),
+                                        0,
+                                        textured_rect_index
+                                            ..(textured_rect_index + sprites.len() as u32),
+                                    );
+                                    textured_rect_index += sprites.len() as u32;
+                                }
+                                Command::Mesh {
+                                    indices,
+                                    pipeline,
+                                    buffer_index,
+                                    ..
+                                } => {
+                                    let tex_key = if let Some(texture) = &pipeline.texture {
+                                        texture.data.as_ptr() as _
+                                    } else {
+                                        0
+                                    };
+
+                                    let pipelines = match (pipeline.z_compare, pipeline.z_update) {
+                                        (true, true) => {
+                                            &graphics
+                                                .mesh
+                                                .pipeline_with_depth_compare_and_depth_write
+                                        }
+                                        (true, false) => &graphics.mesh.pipeline_with_depth_compare,
+                                        (false, true) => &graphics.mesh.pipeline_with_depth_write,
+                                        (false, false) => &graphics.mesh.pipeline_with_no_depth,
+                                    };
+                                    let pipeline = &pipelines[pipeline.cull_mode as usize];
+
+                                    render_pass.set_index_buffer(
+                                        render_pass_index_buffers[*buffer_index].slice(..),
+                                        wgpu::IndexFormat::Uint16,
+                                    );
+                                    render_pass.set_vertex_buffer(
+                                        0,
+                                        render_pass_vertex_buffers[*buffer_index].slice(..),
+                                    );
+                                    render_pass.set_pipeline(pipeline);
+                                    render_pass.set_bind_group(
+                                        0,
+                                        &graphics.mesh.shader_storage_buffer_bind_group,
+                                        &[],
+                                    );
+                                    render_pass.set_bind_group(
+                                        1,
+                                        &graphics
+                                            .mesh
+                                            .texture_cache
+                                            .get(&tex_key)
+                                            .unwrap()
+                                            .bind_group,
+                                        &[],
+                                    );
+                                    render_pass.draw_indexed(
+                                        0..(indices.len() as u32),
+                                        0,
+                                        mesh_index..(mesh_index + 1),
+                                    );
+                                    mesh_index += 1;
+                                }
+                                Command::SetTarget { .. } => unreachable!(),
                             }
                         }
                     }
+
+                    match commands.get(end) {
+                        Some(Command::SetTarget {
+                            target: next_target,
+                            clear: next_clear,
+                        }) => {
+                            target = *next_target;
+                            clear = *next_clear;
+                            commands = &commands[end + 1..];
+                        }
+                        _ => break,
+                    }
                 }
             }
 
@@ -674,16 +1204,59 @@ impl<'a> CommandBuffer<'a> {
                 )
                 .get_mapped_range();
 
-            for (fb_color, mapped_color) in unsafe {
-                slice::from_raw_parts_mut(self.out_tex.0, self.cache.video_mode.size() as usize)
+            let out_tex = unsafe {
+                slice::from_raw_parts_mut(
+                    self.out_tex.0,
+                    self.cache.video_mode.size() as usize / mem::size_of::<Color>(),
+                )
+            };
+
+            match self.cache.video_mode.bit_depth() {
+                BitDepth::Bpp16 => {
+                    for (fb_color, mapped_color) in out_tex
+                        .iter_mut()
+                        .zip(mapped_colored_rect_dst_buffer.chunks(4))
+                    {
+                        *fb_color = Color::from_bytes(mapped_color.assert_into());
+                    }
+                }
+                BitDepth::Bpp32 => {
+                    for (fb_color, mapped_color) in out_tex
+                        .chunks_mut(2)
+                        .zip(mapped_colored_rect_dst_buffer.chunks(4))
+                    {
+                        fb_color[0] =
+                            Color::new(u16::from_be_bytes([mapped_color[0], mapped_color[1]]));
+                        fb_color[1] =
+                            Color::new(u16::from_be_bytes([mapped_color[2], mapped_color[3]]));
+                    }
+                }
             }
-            .iter_mut()
-            .zip(mapped_colored_rect_dst_buffer.chunks(4))
+        }
+
+        // Write the targets back for the CPU, they stay bound for sampling on the GPU
+        for command in &self.cache.commands {
+            if let Command::SetTarget {
+                target: Some(target),
+                ..
+            } = command
             {
-                *fb_color = Color::from_bytes(mapped_color.assert_into());
+                let rgba = graphics.render_targets[&target.key()]
+                    .read_rgba(&graphics.device, &graphics.queue);
+                let data = unsafe {
+                    slice::from_raw_parts_mut(target.data, (target.width * target.height) as usize)
+                };
+
+                for (color, rgba) in data.iter_mut().zip(rgba.chunks(4)) {
+                    *color = Color::from_bytes(rgba.assert_into());
+                }
             }
         }
 
+        // Kept for capture_frame
+        dst.buffer.unmap();
+        graphics.last_frame = Some(dst);
+
         (
             self.colored_rect_count as i32,
             self.textured_rect_count as i32,
@@ -1,31 +1,45 @@
 #![cfg_attr(not(target_vendor = "nintendo64"), allow(unused))]
 
-use super::{FillPipeline, Pipeline};
+use super::{shapes, FillPipeline, Pipeline, RenderTarget, SpriteBatch};
 use crate::{
-    framebuffer::ViBufferToken, graphics_n64::Graphics, ipl3font, slow_cpu_clear, VideoMode,
+    framebuffer::ViBufferToken, graphics_n64::Graphics, ipl3font, slow_cpu_clear, BitDepth,
+    VideoMode,
 };
 use alloc::{boxed::Box, vec::Vec};
-use n64_math::{vec2, Color, Mat4, Vec2, Vec3};
-use n64_sys::rsp;
+use core::mem;
+use n64_math::{vec2, Color, Mat4, Vec2, Vec3, Vec4};
+use n64_sys::{rsp, sys::data_cache_hit_writeback_invalidate};
 use rdp_command_builder::*;
 use rdp_math::{
-    color_to_i32, edge_slope, is_triangle_right_major, shaded_triangle_coeff,
-    slope_y_next_subpixel_intersection, slope_y_prev_scanline_intersection, sorted_triangle,
-    sorted_triangle_indices, triangle_is_too_small, truncate_to_pixel, z_triangle_coeff,
+    clip_outcode, clip_triangle, color_to_i32, edge_slope, is_triangle_right_major, project_vertex,
+    shaded_triangle_coeff, slope_y_next_subpixel_intersection, slope_y_prev_scanline_intersection,
+    sorted_triangle, sorted_triangle_indices, texture_vertex_coords, triangle_is_culled,
+    triangle_is_too_small, truncate_to_pixel, z_triangle_coeff, ClipVertex,
 };
-use rdp_state::RdpState;
+use rdp_state::{RdpState, RENDER_TILE};
+use rsp_mesh::RspMesh;
 
+pub use display_list::DisplayList;
+
+mod display_list;
 mod rdp_command_builder;
 mod rdp_math;
 mod rdp_state;
+mod rsp_mesh;
+
+#[cfg(not(target_vendor = "nintendo64"))]
+pub mod soft_rdp;
 
 // Note: Primitive color, g*DPSetPrimColor( ), primitive depth, g*DPSetPrimDepth( ), and scissor, g*DPSetScissor( ), are attributes that do not require any syncs.
 
 pub struct CommandBufferCache {
     video_mode: VideoMode,
+    // Its blocks are swapped with the ones the RSP reads on submit so the next frame can be built
+    // while the last one renders
     rdp: RdpCommandBuilder,
     depth_buffer: Box<[u16]>,
-    vertex_cache: Box<[(Vec3, i32); 256]>,
+    // Clip space positions
+    vertex_cache: Box<[(Vec4, i32); 256]>,
     vertex_cache_generation: i32,
 }
 
@@ -36,15 +50,15 @@ impl CommandBufferCache {
             rdp: RdpCommandBuilder::new(),
             depth_buffer: {
                 let mut buffer = Vec::new();
-                buffer.resize_with(video_mode.size() as usize, || 0);
+                buffer.resize_with((video_mode.width() * video_mode.height()) as usize, || 0);
                 buffer.into_boxed_slice()
             },
-            vertex_cache: Box::new([(Vec3::ZERO, 0); 256]),
+            vertex_cache: Box::new([(Vec4::ZERO, 0); 256]),
             vertex_cache_generation: 0,
         }
     }
 
-    fn get(&mut self, index: u8, f: impl FnOnce() -> Vec3) -> Vec3 {
+    fn get(&mut self, index: u8, f: impl FnOnce() -> Vec4) -> Vec4 {
         // Transform every vertex to cache first
         // No need for generation
 
@@ -61,12 +75,39 @@ impl CommandBufferCache {
     }
 }
 
+// What the RDP draws into, the VI buffer unless a texture is being rendered
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct ColorImage {
+    address: *mut u16,
+    width: i32,
+    height: i32,
+    bit_depth: BitDepth,
+}
+
+impl ColorImage {
+    #[inline]
+    fn size(&self) -> u8 {
+        match self.bit_depth {
+            BitDepth::Bpp16 => SIZE_OF_PIXEL_16B,
+            BitDepth::Bpp32 => SIZE_OF_PIXEL_32B,
+        }
+    }
+
+    #[inline]
+    fn max(&self) -> Vec2 {
+        vec2((self.width - 1) as f32, (self.height - 1) as f32)
+    }
+}
+
 pub struct CommandBuffer<'a> {
     out_tex: ViBufferToken,
+    color_image: ColorImage,
     colored_rect_count: u32,
     textured_rect_count: u32,
     mesh_count: u32,
     current_state: RdpState,
+    current_pipeline: Option<Pipeline>,
+    recording: bool,
     cache: &'a mut CommandBufferCache,
 }
 
@@ -74,35 +115,47 @@ impl<'a> CommandBuffer<'a> {
     pub fn new(out_tex: ViBufferToken, cache: &'a mut CommandBufferCache) -> Self {
         cache.rdp.clear();
 
+        let color_image = ColorImage {
+            address: out_tex.0 as *mut u16,
+            width: cache.video_mode.width(),
+            height: cache.video_mode.height(),
+            bit_depth: cache.video_mode.bit_depth(),
+        };
+
         cache
             .rdp
             .sync_pipe()
             .set_color_image(
                 FORMAT_RGBA,
-                SIZE_OF_PIXEL_16B,
-                cache.video_mode.width() as u16,
-                out_tex.0 as *mut u16,
+                color_image.size(),
+                color_image.width as u16,
+                color_image.address,
             )
             .set_z_image(cache.depth_buffer.as_mut_ptr())
-            .set_scissor(
-                Vec2::ZERO,
-                vec2(
-                    (cache.video_mode.width() - 1) as f32,
-                    (cache.video_mode.height() - 1) as f32,
-                ),
-            );
+            .set_scissor(Vec2::ZERO, color_image.max());
 
         CommandBuffer {
             out_tex,
+            color_image,
             colored_rect_count: 0,
             textured_rect_count: 0,
             mesh_count: 0,
             current_state: RdpState::default(),
+            current_pipeline: None,
+            recording: false,
             cache,
         }
     }
 
+    // Clears the color and depth of what is drawn into, the framebuffer or a render target
     pub fn clear(&mut self) -> &mut Self {
+        debug_assert!(
+            !self.recording,
+            "Display lists can not clear the framebuffer"
+        );
+
+        let color_image = self.color_image;
+
         rdp_state::apply_fill_pipeline(
             &mut self.cache.rdp,
             &mut self.current_state,
@@ -110,20 +163,26 @@ impl<'a> CommandBuffer<'a> {
                 fill_color: Color::new(0b00000_00000_00000_1),
                 ..FillPipeline::default()
             },
+            color_image.bit_depth,
         );
 
-        self.cache.rdp.fill_rectangle(
-            vec2(0.0, 0.0),
-            vec2(
-                (self.cache.video_mode.width() - 1) as f32,
-                (self.cache.video_mode.height() - 1) as f32,
-            ),
-        );
+        self.cache
+            .rdp
+            .fill_rectangle(vec2(0.0, 0.0), color_image.max());
+
+        self.clear_depth();
+
+        self
+    }
+
+    // The depth buffer is laid out with the width of the color image
+    fn clear_depth(&mut self) {
+        let color_image = self.color_image;
 
         self.cache.rdp.set_color_image(
             FORMAT_RGBA,
             SIZE_OF_PIXEL_16B,
-            self.cache.video_mode.width() as u16,
+            color_image.width as u16,
             self.cache.depth_buffer.as_mut_ptr(),
         );
 
@@ -134,33 +193,99 @@ impl<'a> CommandBuffer<'a> {
                 fill_color: Color::new(0x7fff),
                 ..FillPipeline::default()
             },
+            BitDepth::Bpp16,
         );
 
-        self.cache.rdp.fill_rectangle(
-            vec2(0.0, 0.0),
-            vec2(
-                (self.cache.video_mode.width() - 1) as f32,
-                (self.cache.video_mode.height() - 1) as f32,
-            ),
-        );
+        self.cache
+            .rdp
+            .fill_rectangle(vec2(0.0, 0.0), color_image.max());
 
         self.cache.rdp.set_color_image(
             FORMAT_RGBA,
-            SIZE_OF_PIXEL_16B,
-            self.cache.video_mode.width() as u16,
-            self.out_tex.0 as *mut u16,
+            color_image.size(),
+            color_image.width as u16,
+            color_image.address,
         );
+    }
 
+    pub fn set_fill_pipeline(&mut self, pipeline: &FillPipeline) -> &mut Self {
+        rdp_state::apply_fill_pipeline(
+            &mut self.cache.rdp,
+            &mut self.current_state,
+            pipeline,
+            self.color_image.bit_depth,
+        );
         self
     }
 
-    pub fn set_fill_pipeline(&mut self, pipeline: &FillPipeline) -> &mut Self {
-        rdp_state::apply_fill_pipeline(&mut self.cache.rdp, &mut self.current_state, pipeline);
+    // The calls made in f draw into the target instead of the framebuffer, anything added after
+    // this can sample it. The target shares the depth buffer, which is cleared when the target is
+    // bound, so render targets go before the framebuffer is cleared.
+    pub fn render_to_texture(
+        &mut self,
+        target: &mut RenderTarget,
+        f: impl FnOnce(&mut Self),
+    ) -> &mut Self {
+        debug_assert!(
+            !self.recording,
+            "Display lists can not change the render target"
+        );
+        debug_assert!(
+            self.color_image.address == self.out_tex.0 as *mut u16,
+            "Render targets can not be nested"
+        );
+        assert!(
+            target.width * target.height
+                <= self.cache.video_mode.width() * self.cache.video_mode.height(),
+            "Render targets have to fit in the depth buffer"
+        );
+
+        let target = target.as_texture_mut();
+
+        // Dirty lines written back later would overwrite what the RDP draws
+        unsafe { data_cache_hit_writeback_invalidate(target.data) };
+
+        let framebuffer = self.color_image;
+
+        self.set_color_image(ColorImage {
+            address: target.data.as_mut_ptr() as *mut u16,
+            width: target.width,
+            height: target.height,
+            bit_depth: BitDepth::Bpp16,
+        });
+        self.clear_depth();
+
+        f(self);
+
+        // The target has to be in memory before anything loads it as a texture
+        self.cache.rdp.sync_full();
+        self.set_color_image(framebuffer);
+
+        // TMEM may hold what was in the target before
+        self.current_state = RdpState::default();
+        self.current_pipeline = None;
+
         self
     }
 
+    fn set_color_image(&mut self, color_image: ColorImage) {
+        self.cache
+            .rdp
+            .sync_pipe()
+            .set_color_image(
+                FORMAT_RGBA,
+                color_image.size(),
+                color_image.width as u16,
+                color_image.address,
+            )
+            .set_scissor(Vec2::ZERO, color_image.max());
+
+        self.color_image = color_image;
+    }
+
     pub fn set_pipeline(&mut self, pipeline: &Pipeline) -> &mut Self {
         rdp_state::apply_pipeline(&mut self.cache.rdp, &mut self.current_state, pipeline);
+        self.current_pipeline = Some(*pipeline);
         self
     }
 
@@ -173,149 +298,503 @@ impl<'a> CommandBuffer<'a> {
         self
     }
 
+    // Points, lines and outlines are drawn with the fill color like colored rects
+    pub fn add_point(&mut self, pos: Vec2, size: f32) -> &mut Self {
+        let (upper_left, lower_right) = shapes::point_rect(pos, size);
+        self.add_colored_rect(upper_left, lower_right)
+    }
+
+    // Lines are fill mode triangles
+    pub fn add_line(&mut self, start: Vec2, end: Vec2, width: f32) -> &mut Self {
+        if let Some(quad) = shapes::line_quad(start, end, width, [false, false]) {
+            self.add_fill_quads(&[quad]);
+        }
+
+        self
+    }
+
+    pub fn add_polyline(&mut self, points: &[Vec2], width: f32) -> &mut Self {
+        let last = points.len().saturating_sub(2);
+        let quads = points
+            .windows(2)
+            .enumerate()
+            .filter_map(|(i, segment)| {
+                shapes::line_quad(segment[0], segment[1], width, [i > 0, i < last])
+            })
+            .collect::<Vec<_>>();
+
+        self.add_fill_quads(&quads);
+        self
+    }
+
+    pub fn add_circle(&mut self, center: Vec2, radius: f32, width: f32) -> &mut Self {
+        let quads = shapes::circle_ring(center, radius, width).collect::<Vec<_>>();
+
+        self.add_fill_quads(&quads);
+        self
+    }
+
+    pub fn add_rect_outline(
+        &mut self,
+        upper_left: Vec2,
+        lower_right: Vec2,
+        width: f32,
+    ) -> &mut Self {
+        for (upper_left, lower_right) in shapes::rect_outline(upper_left, lower_right, width) {
+            if lower_right.cmpgt(upper_left).all() {
+                self.add_colored_rect(upper_left, lower_right);
+            }
+        }
+
+        self
+    }
+
+    // Counted as one mesh per 64 quads, the way the emulator draws them
+    fn add_fill_quads(&mut self, quads: &[[Vec2; 4]]) {
+        debug_assert!(
+            self.current_state.is_fill_mode(),
+            "Lines and shapes are drawn with a fill pipeline"
+        );
+
+        self.mesh_count += quads.chunks(64).len() as u32;
+
+        for &[a, b, c, d] in quads {
+            self.add_fill_triangle([a, b, c]);
+            self.add_fill_triangle([a, c, d]);
+        }
+    }
+
+    // Fill mode only steps the edges, there is no shade, texture or depth to interpolate
+    fn add_fill_triangle(&mut self, triangle: [Vec2; 3]) {
+        let [v0, v1, v2] = triangle.map(|v| truncate_to_pixel(v.extend(0.0)));
+
+        if triangle_is_too_small(v0, v1, v2) {
+            return;
+        }
+
+        let (vh, vm, vl) = sorted_triangle(v0, v1, v2);
+        self.edge_coefficients(vh, vm, vl, false, false, false, 0);
+    }
+
     pub fn add_textured_rect(&mut self, upper_left: Vec2, lower_right: Vec2) -> &mut Self {
         self.textured_rect_count += 1;
 
-        self.cache.rdp.texture_rectangle(
-            upper_left,
-            lower_right,
-            0,
-            vec2(0.0, 0.0),
-            vec2(32.0, 32.0),
+        let pipeline = self
+            .current_pipeline
+            .expect("No pipeline has been set on the command buffer");
+        let texture = pipeline
+            .texture
+            .expect("No texture has been set on the pipeline");
+        debug_assert!(
+            !pipeline.perspective_correction,
+            "Texture rects are drawn without perspective correction"
         );
+
+        // The texture is stretched over the whole rect
+        let d_st = vec2(
+            texture.width as f32 / (lower_right.x - upper_left.x),
+            texture.height as f32 / (lower_right.y - upper_left.y),
+        );
+
+        if rdp_state::texture_fits_tmem(&texture) {
+            self.cache.rdp.texture_rectangle(
+                upper_left,
+                lower_right,
+                RENDER_TILE,
+                vec2(0.0, 0.0),
+                d_st,
+            );
+            return self;
+        }
+
+        // Draw the rect in strips of rows that fit TMEM, every strip loads the first row of the
+        // next one as well so filtering across the seam has texels to read
+        let tmem_rows = rdp_state::tmem_rows(&texture);
+        assert!(tmem_rows > 0, "A single texture row does not fit in TMEM");
+        let strip_rows = if tmem_rows > 1 { tmem_rows - 1 } else { 1 };
+
+        let mut row = 0;
+        while row < texture.height {
+            let rows = strip_rows.min(texture.height - row);
+            let loaded_rows = tmem_rows.min(texture.height - row);
+
+            rdp_state::load_texture_strip(
+                &mut self.cache.rdp,
+                &mut self.current_state,
+                &pipeline,
+                row,
+                loaded_rows,
+            );
+
+            self.cache.rdp.texture_rectangle(
+                vec2(upper_left.x, upper_left.y + row as f32 / d_st.y),
+                vec2(lower_right.x, upper_left.y + (row + rows) as f32 / d_st.y),
+                RENDER_TILE,
+                vec2(0.0, row as f32),
+                d_st,
+            );
+
+            row += rows;
+        }
+
+        self
+    }
+
+    // Draws and empties the batch. Sorting by texture loads each one once, so sprites with
+    // different textures may not overlap in the order they were added.
+    pub fn add_sprite_batch(&mut self, batch: &mut SpriteBatch) -> &mut Self {
+        batch.sort();
+
+        for sprite in &batch.sprites {
+            let texture = sprite.texture;
+
+            self.set_pipeline(
+                &batch
+                    .pipeline
+                    .with_texture(Some(texture))
+                    .with_prim_color(Some(sprite.tint)),
+            );
+
+            // Rotated sprites need triangles, the rest are texture rects
+            if sprite.rotation != 0.0 {
+                let corners = sprite.corners();
+
+                self.add_mesh_indexed(
+                    &corners.map(|(position, _)| position.extend(0.0).to_array()),
+                    &corners.map(|(_, uv)| uv.to_array()),
+                    &[0xffff_ffff; 4],
+                    &[[0, 1, 2], [0, 2, 3]],
+                    &Mat4::IDENTITY.to_cols_array_2d(),
+                );
+                continue;
+            }
+
+            let upper_left = sprite.center - 0.5 * sprite.size;
+            let lower_right = sprite.center + 0.5 * sprite.size;
+
+            // Textures too big for TMEM are drawn whole in strips
+            if !rdp_state::texture_fits_tmem(&texture) {
+                debug_assert!(
+                    !sprite.flip_x
+                        && !sprite.flip_y
+                        && sprite.uv_min == Vec2::ZERO
+                        && sprite.uv_max == Vec2::ONE,
+                    "Sprites with textures that do not fit in TMEM can not be flipped or cut out"
+                );
+
+                self.add_textured_rect(upper_left, lower_right);
+                continue;
+            }
+
+            self.textured_rect_count += 1;
+
+            // Flipped axes step backwards from the texel the last pixel would sample unflipped
+            let texture_size = vec2(texture.width as f32, texture.height as f32);
+            let d_st = (sprite.uv_max - sprite.uv_min) * texture_size / sprite.size;
+            let flip = vec2(sprite.flip_x as u8 as f32, sprite.flip_y as u8 as f32);
+
+            self.cache.rdp.texture_rectangle(
+                upper_left,
+                lower_right,
+                RENDER_TILE,
+                sprite.uv_min * texture_size + flip * (sprite.size - Vec2::ONE) * d_st,
+                d_st * (Vec2::ONE - 2.0 * flip),
+            );
+        }
+
+        batch.sprites.clear();
         self
     }
 
     pub fn add_mesh_indexed(
         &mut self,
         verts: &[[f32; 3]],
-        _uvs: &[[f32; 2]],
+        uvs: &[[f32; 2]],
         colors: &[u32],
         indices: &[[u8; 3]],
         transform: &[[f32; 4]; 4],
     ) -> &mut Self {
         self.mesh_count += 1;
 
+        let pipeline = self
+            .current_pipeline
+            .expect("No pipeline has been set on the command buffer");
+
+        // Triangles sample anywhere in the texture so it can not be split like a rect
+        if let Some(texture) = pipeline.texture {
+            debug_assert!(
+                rdp_state::texture_fits_tmem(&texture),
+                "A {}x{} {:?} texture does not fit in TMEM",
+                texture.width,
+                texture.height,
+                texture.format
+            );
+        }
+
         let transform = Mat4::from_cols_array_2d(transform);
 
+        // The RDP interpolates the fog factor as shade alpha
+        let fog_colors;
+        let colors = match pipeline.fog_range {
+            Some(fog_range) => {
+                fog_colors = fog_range.shade_colors(verts, colors, transform);
+                &fog_colors[..]
+            }
+            None => colors,
+        };
+
+        // Untextured meshes that need no clipping are transformed and set up by the RSP. Display
+        // lists keep plain RDP triangles so they can be moved when replayed.
+        if pipeline.texture.is_none() && !self.recording {
+            if let Some(mesh) = RspMesh::new(verts, transform, pipeline.cull_mode) {
+                mesh.add_chunks(&mut self.cache.rdp, verts, colors, indices);
+                return self;
+            }
+        }
+
         self.cache.vertex_cache_generation = self.cache.vertex_cache_generation.wrapping_add(1);
 
         for triangle in indices {
-            let mut v0 = self.cache.get(triangle[0], || {
-                truncate_to_pixel(transform.project_point3(Vec3::from(verts[triangle[0] as usize])))
+            let triangle = triangle.map(|index| ClipVertex {
+                position: self.cache.get(index, || {
+                    transform * Vec3::from(verts[index as usize]).extend(1.0)
+                }),
+                uv: uvs[index as usize].into(),
+                color: colors[index as usize],
             });
-            let mut v1 = self.cache.get(triangle[1], || {
-                truncate_to_pixel(transform.project_point3(Vec3::from(verts[triangle[1] as usize])))
-            });
-            let mut v2 = self.cache.get(triangle[2], || {
-                truncate_to_pixel(transform.project_point3(Vec3::from(verts[triangle[2] as usize])))
-            });
-
-            let x_limit = self.cache.video_mode.width() as f32;
-            let y_limit = self.cache.video_mode.height() as f32;
 
-            v0.x = libm::fmaxf(libm::fminf(v0.x, x_limit), 0.0);
-            v1.x = libm::fmaxf(libm::fminf(v1.x, x_limit), 0.0);
-            v2.x = libm::fmaxf(libm::fminf(v2.x, x_limit), 0.0);
-            v0.y = libm::fmaxf(libm::fminf(v0.y, y_limit), 0.0);
-            v1.y = libm::fmaxf(libm::fminf(v1.y, y_limit), 0.0);
-            v2.y = libm::fmaxf(libm::fminf(v2.y, y_limit), 0.0);
+            let outcodes = triangle.map(|vertex| clip_outcode(vertex.position));
 
-            if triangle_is_too_small(v0, v1, v2) {
+            // Entirely outside one of the planes
+            if outcodes[0] & outcodes[1] & outcodes[2] != 0 {
                 continue;
             }
-            // Vh is the highest point (smallest y value)
-            // Vl is the lowest point (largest y value)
-            let (vh, vm, vl) = sorted_triangle(v0, v1, v2);
-
-            // TODO SEND TRIANGLE
-
-            let (l_int, l_frac) = slope_y_next_subpixel_intersection(vm, vl);
-            let (m_int, m_frac) = slope_y_prev_scanline_intersection(vh, vm);
-            let (h_int, h_frac) = slope_y_prev_scanline_intersection(vh, vl);
-
-            let l_slope = edge_slope(vl, vm);
-            let m_slope = edge_slope(vm, vh);
-            let h_slope = edge_slope(vl, vh);
-
-            let right_major = is_triangle_right_major(vh, vm, vl);
-
-            let is_shaded = true;
-            let is_texured = false;
-            let is_z_buffered = true;
-
-            self.cache.rdp.edge_coefficients(
-                is_shaded,
-                is_texured,
-                is_z_buffered,
-                right_major,
-                0,
-                0,
-                vl.y,
-                vm.y,
-                vh.y,
-                l_int,
-                l_frac,
-                m_int,
-                m_frac,
-                h_int,
-                h_frac,
-                l_slope,
-                m_slope,
-                h_slope,
-            );
-
-            if is_shaded {
-                let (vhi, vmi, vli) = sorted_triangle_indices(v0, v1, v2);
 
-                let color_h = color_to_i32(colors[triangle[vhi as usize] as usize]);
-                let color_m = color_to_i32(colors[triangle[vmi as usize] as usize]);
-                let color_l = color_to_i32(colors[triangle[vli as usize] as usize]);
+            let outcode = outcodes[0] | outcodes[1] | outcodes[2];
 
-                let (r_dx, r_dy, r_de, _r_off) = shaded_triangle_coeff(
-                    vh,
-                    vm,
-                    vl,
-                    color_h[0] as f32,
-                    color_m[0] as f32,
-                    color_l[0] as f32,
-                );
-                let (g_dx, g_dy, g_de, _g_off) = shaded_triangle_coeff(
-                    vh,
-                    vm,
-                    vl,
-                    color_h[1] as f32,
-                    color_m[1] as f32,
-                    color_l[1] as f32,
-                );
-                let (b_dx, b_dy, b_de, _b_off) = shaded_triangle_coeff(
-                    vh,
-                    vm,
-                    vl,
-                    color_h[2] as f32,
-                    color_m[2] as f32,
-                    color_l[2] as f32,
-                );
-                let red = color_h[0] << 16; // r_off;
-                let green = color_h[1] << 16; // g_off;
-                let blue = color_h[2] << 16; // b_off;
-
-                self.cache.rdp.shade_coefficients(
-                    red, green, blue, 0, // Color
-                    r_dx, g_dx, b_dx, 0, // Delta color X
-                    r_de, g_de, b_de, 0, // Delta color Edge
-                    r_dy, g_dy, b_dy, 0, // Delta color y
-                );
+            if outcode == 0 {
+                self.add_triangle(&pipeline, triangle);
+                continue;
             }
 
-            if is_z_buffered {
-                let (z, dx, de, dy) = z_triangle_coeff(vh, vm, vl);
-                self.cache.rdp.z_buffer_coefficients(z, dx, de, dy);
+            let polygon = clip_triangle(triangle, outcode);
+            let vertices = polygon.vertices();
+
+            for i in 2..vertices.len() {
+                self.add_triangle(&pipeline, [vertices[0], vertices[i - 1], vertices[i]]);
             }
         }
         self
     }
 
+    fn add_triangle(&mut self, pipeline: &Pipeline, triangle: [ClipVertex; 3]) {
+        let projected = triangle.map(|vertex| project_vertex(vertex.position));
+        let inv_w = projected.map(|v| v.w);
+        let [v0, v1, v2] = projected.map(|v| truncate_to_pixel(v.truncate()));
+
+        if triangle_is_too_small(v0, v1, v2) || triangle_is_culled(v0, v1, v2, pipeline.cull_mode) {
+            return;
+        }
+        // Vh is the highest point (smallest y value)
+        // Vl is the lowest point (largest y value)
+        let (vh, vm, vl) = sorted_triangle(v0, v1, v2);
+        let (vhi, vmi, vli) = sorted_triangle_indices(v0, v1, v2);
+
+        let is_shaded = true;
+        let is_textured = pipeline.texture.is_some();
+        let is_z_buffered = true;
+
+        // Mip levels after the first are sampled from the tiles that follow the render tile
+        let max_level = pipeline.texture.map_or(0, |texture| texture.mip_levels - 1);
+
+        self.edge_coefficients(vh, vm, vl, is_shaded, is_textured, is_z_buffered, max_level);
+
+        if is_shaded {
+            let color_h = color_to_i32(triangle[vhi as usize].color);
+            let color_m = color_to_i32(triangle[vmi as usize].color);
+            let color_l = color_to_i32(triangle[vli as usize].color);
+
+            let (r_dx, r_dy, r_de, _r_off) = shaded_triangle_coeff(
+                vh,
+                vm,
+                vl,
+                color_h[0] as f32,
+                color_m[0] as f32,
+                color_l[0] as f32,
+            );
+            let (g_dx, g_dy, g_de, _g_off) = shaded_triangle_coeff(
+                vh,
+                vm,
+                vl,
+                color_h[1] as f32,
+                color_m[1] as f32,
+                color_l[1] as f32,
+            );
+            let (b_dx, b_dy, b_de, _b_off) = shaded_triangle_coeff(
+                vh,
+                vm,
+                vl,
+                color_h[2] as f32,
+                color_m[2] as f32,
+                color_l[2] as f32,
+            );
+            let (a_dx, a_dy, a_de, _a_off) = shaded_triangle_coeff(
+                vh,
+                vm,
+                vl,
+                color_h[3] as f32,
+                color_m[3] as f32,
+                color_l[3] as f32,
+            );
+            let red = color_h[0] << 16; // r_off;
+            let green = color_h[1] << 16; // g_off;
+            let blue = color_h[2] << 16; // b_off;
+            let alpha = color_h[3] << 16; // a_off;
+
+            self.cache.rdp.shade_coefficients(
+                red, green, blue, alpha, // Color
+                r_dx, g_dx, b_dx, a_dx, // Delta color X
+                r_de, g_de, b_de, a_de, // Delta color Edge
+                r_dy, g_dy, b_dy, a_dy, // Delta color y
+            );
+        }
+
+        if let Some(texture) = pipeline.texture {
+            let size = vec2(texture.width as f32, texture.height as f32);
+            let st = [vhi, vmi, vli].map(|i| triangle[i as usize].uv * size);
+            let inv_w = [vhi, vmi, vli].map(|i| inv_w[i as usize]);
+
+            let [tex_h, tex_m, tex_l] =
+                texture_vertex_coords(st, inv_w, pipeline.perspective_correction);
+
+            let (s_dx, s_dy, s_de, s) =
+                shaded_triangle_coeff(vh, vm, vl, tex_h.x, tex_m.x, tex_l.x);
+            let (t_dx, t_dy, t_de, t) =
+                shaded_triangle_coeff(vh, vm, vl, tex_h.y, tex_m.y, tex_l.y);
+            let (w_dx, w_dy, w_de, w) =
+                shaded_triangle_coeff(vh, vm, vl, tex_h.z, tex_m.z, tex_l.z);
+
+            self.cache.rdp.texture_coefficients(
+                s, t, w, // Texture
+                s_dx, t_dx, w_dx, // Delta texture X
+                s_de, t_de, w_de, // Delta texture Edge
+                s_dy, t_dy, w_dy, // Delta texture y
+            );
+        }
+
+        if is_z_buffered {
+            let (z, dx, de, dy) = z_triangle_coeff(vh, vm, vl);
+            self.cache.rdp.z_buffer_coefficients(z, dx, de, dy);
+        }
+    }
+
+    // Takes the vertices sorted from top to bottom
+    #[allow(clippy::too_many_arguments)]
+    fn edge_coefficients(
+        &mut self,
+        vh: Vec3,
+        vm: Vec3,
+        vl: Vec3,
+        is_shaded: bool,
+        is_textured: bool,
+        is_z_buffered: bool,
+        max_level: u8,
+    ) {
+        let (l_int, l_frac) = slope_y_next_subpixel_intersection(vm, vl);
+        let (m_int, m_frac) = slope_y_prev_scanline_intersection(vh, vm);
+        let (h_int, h_frac) = slope_y_prev_scanline_intersection(vh, vl);
+
+        let l_slope = edge_slope(vl, vm);
+        let m_slope = edge_slope(vm, vh);
+        let h_slope = edge_slope(vl, vh);
+
+        let right_major = is_triangle_right_major(vh, vm, vl);
+
+        self.cache.rdp.edge_coefficients(
+            is_shaded,
+            is_textured,
+            is_z_buffered,
+            right_major,
+            max_level,
+            RENDER_TILE,
+            vl.y,
+            vm.y,
+            vh.y,
+            l_int,
+            l_frac,
+            m_int,
+            m_frac,
+            h_int,
+            h_frac,
+            l_slope,
+            m_slope,
+            h_slope,
+        );
+    }
+
+    // The calls made in f are recorded into a display list instead of being drawn
+    pub fn record_display_list(&mut self, f: impl FnOnce(&mut Self)) -> DisplayList {
+        debug_assert!(!self.recording, "Display lists can not be nested");
+
+        let mut rdp = RdpCommandBuilder::new();
+        rdp.clear();
+        mem::swap(&mut self.cache.rdp, &mut rdp);
+
+        // The list starts from an unknown state so it sets up everything it uses
+        let state = mem::take(&mut self.current_state);
+        let pipeline = self.current_pipeline.take();
+        let counts = (
+            mem::take(&mut self.colored_rect_count),
+            mem::take(&mut self.textured_rect_count),
+            mem::take(&mut self.mesh_count),
+        );
+
+        self.recording = true;
+        f(self);
+        self.recording = false;
+
+        mem::swap(&mut self.cache.rdp, &mut rdp);
+
+        let mut display_list = DisplayList::new(
+            &rdp.blocks,
+            self.out_tex.0 as *const u16,
+            self.cache.depth_buffer.as_ptr(),
+        );
+        display_list.colored_rect_count = self.colored_rect_count;
+        display_list.textured_rect_count = self.textured_rect_count;
+        display_list.mesh_count = self.mesh_count;
+
+        self.current_state = state;
+        self.current_pipeline = pipeline;
+        (
+            self.colored_rect_count,
+            self.textured_rect_count,
+            self.mesh_count,
+        ) = counts;
+
+        display_list
+    }
+
+    // Draws a recorded display list moved by offset pixels. It leaves the RDP in whatever state
+    // it ended in, so a pipeline has to be set again before drawing anything else.
+    pub fn add_display_list(&mut self, display_list: &DisplayList, offset: Vec2) -> &mut Self {
+        self.colored_rect_count += display_list.colored_rect_count;
+        self.textured_rect_count += display_list.textured_rect_count;
+        self.mesh_count += display_list.mesh_count;
+
+        display_list.replay(
+            &mut self.cache.rdp,
+            self.out_tex.0 as *const u16,
+            self.cache.depth_buffer.as_ptr(),
+            offset,
+        );
+
+        self.current_state = RdpState::default();
+        self.current_pipeline = None;
+
+        self
+    }
+
     pub fn submit(self, graphics: &mut Graphics, step: bool) -> (i32, i32, i32, i32) {
         self.cache.rdp.sync_full();
 
@@ -338,13 +817,18 @@ impl<'a> CommandBuffer<'a> {
                 const GREEN: Color = Color::new(0b00011_10000_00011_1);
                 const RED: Color = Color::new(0b10000_00011_00011_1);
 
+                assert!(
+                    self.cache.video_mode.bit_depth() == BitDepth::Bpp16,
+                    "CPU drawing needs a 16bpp video mode"
+                );
+
                 let mut out_tex = crate::gfx::TextureMut::new(
                     self.cache.video_mode.width(),
                     self.cache.video_mode.height(),
                     unsafe {
                         core::slice::from_raw_parts_mut(
                             self.out_tex.0,
-                            self.cache.video_mode.size() as usize,
+                            self.cache.video_mode.size() as usize / core::mem::size_of::<Color>(),
                         )
                     },
                 );
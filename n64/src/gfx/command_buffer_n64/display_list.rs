@@ -0,0 +1,276 @@
+use super::rdp_command_builder::*;
+use crate::gfx::Texture;
+use alloc::vec::Vec;
+use n64_math::Vec2;
+use n64_sys::sys::virtual_to_physical;
+use n64_types::{RdpBlock, RdpCommand};
+
+// The longest command is a shaded, textured and z buffered triangle
+const MAX_COMMAND_LEN: usize = 22;
+
+const ADDRESS_MASK: u64 = 0xffff_ffff;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Relocation {
+    ColorImage,
+    DepthImage,
+    Texture,
+}
+
+// RDP commands recorded once and copied into command buffers. Framebuffer addresses are filled in
+// when replayed and texture addresses can be moved to another texture of the same layout.
+pub struct DisplayList {
+    commands: Vec<RdpCommand>,
+    // Index of the word holding the address, in order
+    relocations: Vec<(usize, Relocation)>,
+    pub(super) colored_rect_count: u32,
+    pub(super) textured_rect_count: u32,
+    pub(super) mesh_count: u32,
+}
+
+impl DisplayList {
+    pub(super) fn new(
+        blocks: &[RdpBlock],
+        color_image: *const u16,
+        depth_image: *const u16,
+    ) -> Self {
+        let commands = blocks
+            .iter()
+            .flat_map(|block| block.rdp_data[..block.block_len as usize].iter().copied())
+            .collect::<Vec<_>>();
+
+        let color_image = virtual_to_physical(color_image) as u64;
+        let depth_image = virtual_to_physical(depth_image) as u64;
+
+        let mut relocations = Vec::new();
+        let mut index = 0;
+
+        while index < commands.len() {
+            let command = commands[index].0;
+            let address = command & ADDRESS_MASK;
+
+            let relocation = match command >> 56 {
+                COMMAND_SET_COLOR_IMAGE if address == color_image => Some(Relocation::ColorImage),
+                COMMAND_SET_COLOR_IMAGE | COMMAND_SET_Z_IMAGE if address == depth_image => {
+                    Some(Relocation::DepthImage)
+                }
+                COMMAND_SET_TEXTURE_IMAGE => Some(Relocation::Texture),
+                _ => None,
+            };
+
+            if let Some(relocation) = relocation {
+                relocations.push((index, relocation));
+            }
+
+            index += command_len(commands[index]);
+        }
+
+        Self {
+            commands,
+            relocations,
+            colored_rect_count: 0,
+            textured_rect_count: 0,
+            mesh_count: 0,
+        }
+    }
+
+    // Points the list at another texture with the same size and format, like the next frame of
+    // an animation. Palettes and mip levels move with it.
+    pub fn relocate_texture(&mut self, from: &Texture, to: &Texture<'static>) {
+        assert!(
+            from.width == to.width
+                && from.height == to.height
+                && from.format == to.format
+                && from.mip_levels == to.mip_levels,
+            "Display list textures can only be moved to textures with the same layout"
+        );
+
+        let mut ranges = alloc::vec![(from.data.as_ptr(), from.data.len(), to.data.as_ptr())];
+
+        if let (Some(from), Some(to)) = (from.palette, to.palette) {
+            ranges.push((
+                from.as_ptr() as *const u8,
+                2 * from.len(),
+                to.as_ptr() as *const u8,
+            ));
+        }
+
+        for &(index, relocation) in &self.relocations {
+            if relocation != Relocation::Texture {
+                continue;
+            }
+
+            let command = &mut self.commands[index].0;
+            let address = *command & ADDRESS_MASK;
+
+            for &(from, len, to) in &ranges {
+                let start = virtual_to_physical(from) as u64;
+
+                if address >= start && address < start + len as u64 {
+                    let to = virtual_to_physical(to) as u64 + (address - start);
+                    *command = (*command & !ADDRESS_MASK) | to;
+                }
+            }
+        }
+    }
+
+    // Offsets are whole pixels so moved triangles stay on the same subpixel grid
+    pub(super) fn replay(
+        &self,
+        rdp: &mut RdpCommandBuilder,
+        color_image: *const u16,
+        depth_image: *const u16,
+        offset: Vec2,
+    ) {
+        let color_image = virtual_to_physical(color_image) as u64;
+        let depth_image = virtual_to_physical(depth_image) as u64;
+
+        let offset = offset.round();
+        let (dx, dy) = (offset.x as i32, offset.y as i32);
+
+        let mut command = [RdpCommand(0); MAX_COMMAND_LEN];
+        let mut index = 0;
+
+        while index < self.commands.len() {
+            let len = command_len(self.commands[index]);
+            let command = &mut command[..len];
+            command.copy_from_slice(&self.commands[index..index + len]);
+
+            let relocation = self
+                .relocations
+                .binary_search_by_key(&index, |&(index, _)| index)
+                .ok()
+                .map(|relocation| self.relocations[relocation].1);
+
+            index += len;
+
+            let address = match relocation {
+                Some(Relocation::ColorImage) => Some(color_image),
+                Some(Relocation::DepthImage) => Some(depth_image),
+                _ => None,
+            };
+
+            if let Some(address) = address {
+                command[0].0 = (command[0].0 & !ADDRESS_MASK) | address;
+            }
+
+            let visible = match command[0].0 >> 56 {
+                COMMAND_FILL_RECTANGLE | COMMAND_TEXTURE_RECTANGLE => offset_rect(command, dx, dy),
+                op if op & !0x7 == COMMAND_EDGE_COEFFICIENTS => {
+                    offset_triangle(command, dx, dy);
+                    true
+                }
+                _ => true,
+            };
+
+            if visible {
+                rdp.push_command(command);
+            }
+        }
+    }
+}
+
+// Corners are unsigned 10.2, like when building rects the parts left of or above the screen are
+// cut off and textures start that much further in. False when the whole rect is off screen.
+fn offset_rect(command: &mut [RdpCommand], dx: i32, dy: i32) -> bool {
+    let rect = command[0].0;
+    let field = |shift: u32, offset: i32| ((rect >> shift) & 0xfff) as i32 + 4 * offset;
+
+    let r = field(44, dx);
+    let b = field(32, dy);
+    let l = field(12, dx);
+    let t = field(0, dy);
+
+    if r < 0 || b < 0 {
+        return false;
+    }
+
+    command[0].0 = (rect & 0xff00_0000_ff00_0000)
+        | (r.min(0xfff) as u64) << 44
+        | (b.min(0xfff) as u64) << 32
+        | (l.clamp(0, 0xfff) as u64) << 12
+        | t.clamp(0, 0xfff) as u64;
+
+    // S and T are s10.5, their steps per pixel s5.10 and the cut in quarter pixels
+    if let Some(st) = command.get_mut(1) {
+        let cut_s = ((-l).max(0) * (st.0 >> 16) as i16 as i32) >> 7;
+        let cut_t = ((-t).max(0) * st.0 as i16 as i32) >> 7;
+        let s = (st.0 >> 48) as i16 as i32 + cut_s;
+        let t = (st.0 >> 32) as i16 as i32 + cut_t;
+
+        st.0 = (st.0 & 0xffff_ffff) | (s as u16 as u64) << 48 | (t as u16 as u64) << 32;
+    }
+
+    true
+}
+
+// Y is s11.2 in the first word, the edges start at s15.16 x positions in the next three
+fn offset_triangle(command: &mut [RdpCommand], dx: i32, dy: i32) {
+    let edges = command[0].0;
+    let y = |shift: u32| ((((edges >> shift) as i32 + 4 * dy) & 0x3fff) as u64) << shift;
+
+    command[0].0 = (edges & !0x0000_3fff_3fff_3fff) | y(32) | y(16) | y(0);
+
+    for edge in &mut command[1..4] {
+        edge.0 = edge.0.wrapping_add((dx as u64) << 48);
+    }
+}
+
+#[test]
+fn display_list_replay_moves_and_relocates() {
+    let mut color_image = [0u16; 4];
+    let mut depth_image = [0u16; 4];
+    let mut other_image = [0u16; 4];
+
+    let mut rdp = RdpCommandBuilder::new();
+    rdp.clear();
+    rdp.set_color_image(
+        FORMAT_RGBA,
+        SIZE_OF_PIXEL_16B,
+        320,
+        color_image.as_mut_ptr(),
+    )
+    .set_z_image(depth_image.as_mut_ptr())
+    .fill_rectangle(Vec2::new(0.0, 0.0), Vec2::new(8.0, 8.0))
+    .texture_rectangle(
+        Vec2::new(8.0, 8.0),
+        Vec2::new(40.0, 40.0),
+        0,
+        Vec2::ZERO,
+        Vec2::new(1.0, 1.0),
+    );
+
+    let list = DisplayList::new(&rdp.blocks, color_image.as_ptr(), depth_image.as_ptr());
+    assert_eq!(list.commands.len(), 5);
+
+    rdp.clear();
+    list.replay(
+        &mut rdp,
+        other_image.as_ptr(),
+        depth_image.as_ptr(),
+        Vec2::new(-12.0, 2.0),
+    );
+
+    let commands = &rdp.blocks[0].rdp_data[..rdp.blocks[0].block_len as usize];
+
+    // The fill rect is moved off screen, the textured rect is cut at the left edge
+    assert_eq!(commands.len(), 4);
+    assert_eq!(
+        commands[0].0 & ADDRESS_MASK,
+        virtual_to_physical(other_image.as_ptr()) as u64
+    );
+    assert_eq!(
+        commands[1].0 & ADDRESS_MASK,
+        virtual_to_physical(depth_image.as_ptr()) as u64
+    );
+
+    let rect = commands[2].0;
+    assert_eq!((rect >> 44) & 0xfff, 4 * 28);
+    assert_eq!((rect >> 32) & 0xfff, 4 * 42);
+    assert_eq!((rect >> 12) & 0xfff, 0);
+    assert_eq!(rect & 0xfff, 4 * 10);
+
+    // Starts 4 texels in
+    assert_eq!((commands[3].0 >> 48) as i16, 4 << 5);
+    assert_eq!((commands[3].0 >> 32) as i16, 0);
+}
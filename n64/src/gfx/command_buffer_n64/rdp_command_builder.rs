@@ -3,11 +3,13 @@
 
 use core::mem::size_of;
 
-use super::rdp_math::{to_fixpoint_10_2_as_integer, to_fixpoint_s_10_5, to_fixpoint_s_11_2};
+use super::rdp_math::{
+    to_fixpoint_10_2_as_integer, to_fixpoint_s_10_5, to_fixpoint_s_11_2, to_fixpoint_s_5_10,
+};
 use alloc::vec::Vec;
 use n64_math::{Color, Vec2};
 use n64_sys::sys::{virtual_to_physical, virtual_to_physical_mut};
-use n64_types::{static_assert, RdpBlock, RdpCommand};
+use n64_types::{static_assert, MeshChunk, RdpBlock, RdpCommand};
 
 // RDP Command Docs: http://ultra64.ca/files/documentation/silicon-graphics/SGI_RDP_Command_Summary.pdf
 
@@ -110,16 +112,36 @@ pub const COMMAND_SET_FILL_COLOR: u64 = 0xf7;
 pub const COMMAND_FILL_RECTANGLE: u64 = 0xf6;
 pub const COMMAND_SET_TILE: u64 = 0xf5;
 pub const COMMAND_LOAD_TILE: u64 = 0xf4;
+pub const COMMAND_SET_TILE_SIZE: u64 = 0xf2;
+pub const COMMAND_LOAD_TLUT: u64 = 0xf0;
 pub const COMMAND_SET_OTHER_MODE: u64 = 0xef;
+pub const COMMAND_SET_PRIM_DEPTH: u64 = 0xee;
 pub const COMMAND_SET_SCISSOR: u64 = 0xed;
 pub const COMMAND_SYNC_FULL: u64 = 0xe9;
 pub const COMMAND_SYNC_TILE: u64 = 0xe8;
 pub const COMMAND_SYNC_PIPE: u64 = 0xe7;
+pub const COMMAND_SYNC_LOAD: u64 = 0xe6;
 pub const COMMAND_TEXTURE_RECTANGLE: u64 = 0xe4;
 pub const COMMAND_EDGE_COEFFICIENTS: u64 = 0xc8;
 
 pub const COMMAND_TRIANGLE: u64 = 0x01;
 
+// Number of words in the command that starts with this word
+#[inline]
+pub fn command_len(command: RdpCommand) -> usize {
+    let command = command.0 >> 56;
+
+    if command == COMMAND_TEXTURE_RECTANGLE {
+        2
+    } else if command & !0x7 == COMMAND_EDGE_COEFFICIENTS {
+        4 + if command & 0x4 != 0 { 8 } else { 0 }
+            + if command & 0x2 != 0 { 8 } else { 0 }
+            + if command & 0x1 != 0 { 2 } else { 0 }
+    } else {
+        1
+    }
+}
+
 pub struct RdpCommandBuilder {
     pub(crate) blocks: Vec<RdpBlock>,
     index: usize,
@@ -161,6 +183,30 @@ impl RdpCommandBuilder {
         }
     }
 
+    // Words of one command built elsewhere, kept in one block like the commands built here
+    #[inline]
+    pub fn push_command(&mut self, command: &[RdpCommand]) -> &mut RdpCommandBuilder {
+        self.reserve(command.len());
+
+        for word in command {
+            self.push(*word);
+        }
+
+        self
+    }
+
+    // A mesh chunk takes up a whole block, commands after it start a new one
+    #[inline]
+    pub fn mesh_chunk(&mut self) -> &mut MeshChunk {
+        if self.index > 0 {
+            self.blocks.push(RdpBlock::default());
+        }
+        self.index = 127;
+
+        let block = self.blocks.last_mut().unwrap() as *mut RdpBlock;
+        unsafe { &mut *(block as *mut MeshChunk) }
+    }
+
     #[inline]
     pub fn set_color_image(
         &mut self,
@@ -238,12 +284,8 @@ impl RdpCommandBuilder {
     }
 
     #[inline]
-    pub fn set_fill_color(&mut self, color: Color) -> &mut RdpCommandBuilder {
-        self.push(RdpCommand(
-            (COMMAND_SET_FILL_COLOR << 56)
-                | ((color.value() as u64) << 16)
-                | (color.value() as u64),
-        ));
+    pub fn set_fill_color(&mut self, color: u32) -> &mut RdpCommandBuilder {
+        self.push(RdpCommand((COMMAND_SET_FILL_COLOR << 56) | (color as u64)));
         self
     }
 
@@ -283,7 +325,7 @@ impl RdpCommandBuilder {
         &mut self,
         format: u8,
         size: u8,
-        width: u16,
+        line: u16,
         texture_cache_start_address: u16,
         tile_index: u8,
         clamp_t: u8,
@@ -299,7 +341,7 @@ impl RdpCommandBuilder {
             (COMMAND_SET_TILE << 56)
                 | (((format & 0b111) as u64) << 53)
                 | (((size & 0b11) as u64) << 51)
-                | (((width >> 2) as u64) << 41)
+                | (((line & 0x1ff) as u64) << 41)
                 | ((texture_cache_start_address as u64) << 32)
                 | ((tile_index as u64) << 24)
                 | ((clamp_t as u64) << 19)
@@ -323,11 +365,39 @@ impl RdpCommandBuilder {
     ) -> &mut RdpCommandBuilder {
         self.push(RdpCommand(
             (COMMAND_LOAD_TILE << 56)
-                | (to_fixpoint_10_2_as_integer(bottom_right.x) << (32 + 12))
-                | (to_fixpoint_10_2_as_integer(bottom_right.y) << 32)
+                | (to_fixpoint_10_2_as_integer(top_left.x) << (32 + 12))
+                | (to_fixpoint_10_2_as_integer(top_left.y) << 32)
+                | ((tile_index as u64) << 24)
+                | (to_fixpoint_10_2_as_integer(bottom_right.x) << 12)
+                | (to_fixpoint_10_2_as_integer(bottom_right.y)),
+        ));
+        self
+    }
+
+    #[inline]
+    pub fn set_tile_size(
+        &mut self,
+        top_left: Vec2,
+        bottom_right: Vec2,
+        tile_index: u8,
+    ) -> &mut RdpCommandBuilder {
+        self.push(RdpCommand(
+            (COMMAND_SET_TILE_SIZE << 56)
+                | (to_fixpoint_10_2_as_integer(top_left.x) << (32 + 12))
+                | (to_fixpoint_10_2_as_integer(top_left.y) << 32)
                 | ((tile_index as u64) << 24)
-                | (to_fixpoint_10_2_as_integer(top_left.x) << 12)
-                | (to_fixpoint_10_2_as_integer(top_left.y)),
+                | (to_fixpoint_10_2_as_integer(bottom_right.x) << 12)
+                | (to_fixpoint_10_2_as_integer(bottom_right.y)),
+        ));
+        self
+    }
+
+    #[inline]
+    pub fn load_tlut(&mut self, count: u16, tile_index: u8) -> &mut RdpCommandBuilder {
+        self.push(RdpCommand(
+            (COMMAND_LOAD_TLUT << 56)
+                | ((tile_index as u64) << 24)
+                | (to_fixpoint_10_2_as_integer((count - 1) as f32) << 12),
         ));
         self
     }
@@ -365,6 +435,12 @@ impl RdpCommandBuilder {
         self
     }
 
+    #[inline]
+    pub fn sync_load(&mut self) -> &mut RdpCommandBuilder {
+        self.push(RdpCommand(COMMAND_SYNC_LOAD << 56));
+        self
+    }
+
     #[inline]
     pub fn sync_pipe(&mut self) -> &mut RdpCommandBuilder {
         self.push(RdpCommand(COMMAND_SYNC_PIPE << 56));
@@ -378,8 +454,8 @@ impl RdpCommandBuilder {
         texture: bool,
         z_buffer: bool,
         right_major: bool,
-        _level: u8,
-        _tile: u8,
+        level: u8,
+        tile: u8,
         y_low_minor: f32,
         y_mid_minor: f32,
         y_high_major: f32,
@@ -413,6 +489,8 @@ impl RdpCommandBuilder {
         self.push(RdpCommand(
             (command << 56)
                 | if right_major { 0x1u64 << 55 } else { 0u64 }
+                | ((level & 0b111) as u64) << 51
+                | ((tile & 0b111) as u64) << 48
                 | (to_fixpoint_s_11_2(y_low_minor)) << 32
                 | (to_fixpoint_s_11_2(y_mid_minor)) << 16
                 | to_fixpoint_s_11_2(y_high_major),
@@ -513,6 +591,68 @@ impl RdpCommandBuilder {
         self
     }
 
+    // Same layout as the shade coefficients with S, T and W in place of red, green and blue
+    #[inline]
+    pub fn texture_coefficients(
+        &mut self,
+        s: i32,
+        t: i32,
+        w: i32,
+        ds_dx: i32,
+        dt_dx: i32,
+        dw_dx: i32,
+        ds_de: i32,
+        dt_de: i32,
+        dw_de: i32,
+        ds_dy: i32,
+        dt_dy: i32,
+        dw_dy: i32,
+    ) -> &mut RdpCommandBuilder {
+        self.push(RdpCommand(
+            ((s >> 16) as u16 as u64) << 48
+                | ((t >> 16) as u16 as u64) << 32
+                | ((w >> 16) as u16 as u64) << 16,
+        ));
+        self.push(RdpCommand(
+            ((ds_dx >> 16) as u16 as u64) << 48
+                | ((dt_dx >> 16) as u16 as u64) << 32
+                | ((dw_dx >> 16) as u16 as u64) << 16,
+        ));
+        self.push(RdpCommand(
+            ((s & 0x0000ffff) as u16 as u64) << 48
+                | ((t & 0x0000ffff) as u16 as u64) << 32
+                | ((w & 0x0000ffff) as u16 as u64) << 16,
+        ));
+        self.push(RdpCommand(
+            ((ds_dx & 0x0000ffff) as u16 as u64) << 48
+                | ((dt_dx & 0x0000ffff) as u16 as u64) << 32
+                | ((dw_dx & 0x0000ffff) as u16 as u64) << 16,
+        ));
+
+        self.push(RdpCommand(
+            ((ds_de >> 16) as u16 as u64) << 48
+                | ((dt_de >> 16) as u16 as u64) << 32
+                | ((dw_de >> 16) as u16 as u64) << 16,
+        ));
+        self.push(RdpCommand(
+            ((ds_dy >> 16) as u16 as u64) << 48
+                | ((dt_dy >> 16) as u16 as u64) << 32
+                | ((dw_dy >> 16) as u16 as u64) << 16,
+        ));
+        self.push(RdpCommand(
+            ((ds_de & 0x0000ffff) as u16 as u64) << 48
+                | ((dt_de & 0x0000ffff) as u16 as u64) << 32
+                | ((dw_de & 0x0000ffff) as u16 as u64) << 16,
+        ));
+        self.push(RdpCommand(
+            ((ds_dy & 0x0000ffff) as u16 as u64) << 48
+                | ((dt_dy & 0x0000ffff) as u16 as u64) << 32
+                | ((dw_dy & 0x0000ffff) as u16 as u64) << 16,
+        ));
+
+        self
+    }
+
     #[inline]
     pub fn z_buffer_coefficients(
         &mut self,
@@ -534,7 +674,7 @@ impl RdpCommandBuilder {
         bottom_right: Vec2,
         tile_index: u8,
         st_top_left: Vec2,
-        d_xy_d_st: Vec2,
+        d_st_d_xy: Vec2,
     ) -> &mut RdpCommandBuilder {
         self.reserve(2);
 
@@ -552,12 +692,12 @@ impl RdpCommandBuilder {
         let mut st_t = st_top_left.y;
 
         if l < 0.0 {
-            st_l -= l;
+            st_l -= l * d_st_d_xy.x;
             l = 0.0;
         }
 
         if t < 0.0 {
-            st_t -= t;
+            st_t -= t * d_st_d_xy.y;
             t = 0.0;
         }
 
@@ -573,8 +713,8 @@ impl RdpCommandBuilder {
         self.push(RdpCommand(
             (to_fixpoint_s_10_5(st_l) << 48)
                 | (to_fixpoint_s_10_5(st_t) << 32)
-                | (to_fixpoint_s_10_5(d_xy_d_st.x) << 16)
-                | to_fixpoint_s_10_5(d_xy_d_st.y),
+                | (to_fixpoint_s_5_10(d_st_d_xy.x) << 16)
+                | to_fixpoint_s_5_10(d_st_d_xy.y),
         ));
         self
     }
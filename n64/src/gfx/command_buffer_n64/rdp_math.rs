@@ -1,4 +1,5 @@
-use n64_math::{vec3, Vec3};
+use crate::gfx::CullMode;
+use n64_math::{vec3, Vec2, Vec3, Vec4};
 
 pub fn to_fixpoint_10_2_as_integer(val: f32) -> u64 {
     (((val as i16) * (1 << 2)) & 0xffc) as u64
@@ -18,7 +19,11 @@ pub fn to_fixpoint_s_11_2(val: f32) -> u64 {
 }
 
 pub fn to_fixpoint_s_10_5(val: f32) -> u64 {
-    ((val * (1 << 5) as f32) as i16) as u64
+    ((val * (1 << 5) as f32) as i16) as u16 as u64
+}
+
+pub fn to_fixpoint_s_5_10(val: f32) -> u64 {
+    ((val * (1 << 10) as f32) as i16) as u16 as u64
 }
 
 pub fn fixed_16_16_to_f32(fixed_point: i32) -> f32 {
@@ -35,23 +40,11 @@ pub fn fixed_16_16_to_f32(fixed_point: i32) -> f32 {
     }
 }
 
-pub fn float_to_unsigned_int_frac(val: f32) -> (u16, u16) {
-    if 0.0 >= val {
-        return (u16::MAX, u16::MAX);
-    }
-
-    let integer_part = libm::floorf(val);
-
-    if (u16::MAX as f32) < integer_part {
-        return (u16::MAX, u16::MAX);
-    }
-
-    let fractal_part = val - integer_part;
+// Integer and fraction of a s15.16 value, vertices in the guard band left of the screen are negative
+pub fn float_to_int_frac(val: f32) -> (u16, u16) {
+    let fixed = f32_to_fixed_16_16(val);
 
-    (
-        integer_part as u16,
-        libm::floorf(fractal_part * ((1 << 16) as f32)) as u16,
-    )
+    ((fixed >> 16) as u16, fixed as u16)
 }
 
 pub fn f32_to_fixed_16_16(val: f32) -> i32 {
@@ -93,12 +86,12 @@ pub fn slope_x_from_y(p0: Vec3, p1: Vec3, y: f32) -> (u16, u16) {
 
     // ZERO DIVISION check
     if 1.0 > libm::fabsf(p1.y - p0.y) {
-        return float_to_unsigned_int_frac(p0.x);
+        return float_to_int_frac(p0.x);
     }
 
     let x = p0.x + (y - p0.y) * (p1.x - p0.x) / (p1.y - p0.y);
 
-    float_to_unsigned_int_frac(x)
+    float_to_int_frac(x)
 }
 
 // X coordinate of the intersection of the edge from p0 to p1 and the sub-scanline at (or higher than) p0.y
@@ -176,6 +169,19 @@ pub fn sorted_triangle_indices(v0: Vec3, v1: Vec3, v2: Vec3) -> (u8, u8, u8) {
     }
 }
 
+// Twice the area of the triangle, positive when it is a front face
+pub fn triangle_signed_area(v0: Vec3, v1: Vec3, v2: Vec3) -> f32 {
+    (v1.x - v0.x) * (v2.y - v0.y) - (v2.x - v0.x) * (v1.y - v0.y)
+}
+
+pub fn triangle_is_culled(v0: Vec3, v1: Vec3, v2: Vec3, cull_mode: CullMode) -> bool {
+    match cull_mode {
+        CullMode::None => false,
+        CullMode::Back => triangle_signed_area(v0, v1, v2) < 0.0,
+        CullMode::Front => triangle_signed_area(v0, v1, v2) > 0.0,
+    }
+}
+
 pub fn triangle_is_too_small(v0: Vec3, v1: Vec3, v2: Vec3) -> bool {
     // Check area == 0
     (v0.x - v1.x) * (v2.y - v1.y) == (v0.y - v1.y) * (v2.x - v1.x)
@@ -207,11 +213,12 @@ pub fn shaded_triangle_coeff(
     (dcdx, dcdy, dcde, color)
 }
 
-pub fn color_to_i32(color: u32) -> [i32; 3] {
+pub fn color_to_i32(color: u32) -> [i32; 4] {
     [
         ((color >> 24) & 0xff) as i32,
         ((color >> 16) & 0xff) as i32,
         ((color >> 8) & 0xff) as i32,
+        (color & 0xff) as i32,
     ]
 }
 
@@ -232,6 +239,138 @@ pub fn z_triangle_coeff(vh: Vec3, vm: Vec3, vl: Vec3) -> (i32, i32, i32, i32) {
     (val, dx, de, dy)
 }
 
+// Screen position with 1/w in w, which textures need to be interpolated with perspective
+pub fn project_vertex(clip: Vec4) -> Vec4 {
+    let inv_w = 1.0 / clip.w;
+
+    (clip.truncate() * inv_w).extend(inv_w)
+}
+
+// Triangles are clipped to the near and far planes like the GPU does for the emulator. Sideways
+// they only need to stay inside the range the edge coefficients can hold, the scissor cuts them
+// to the screen.
+pub const GUARD_BAND: f32 = 1023.0;
+
+// Inside where the dot product with the clip space position is positive
+const CLIP_PLANES: [[f32; 4]; 6] = [
+    [0.0, 0.0, 1.0, 1.0],
+    [0.0, 0.0, -1.0, 1.0],
+    [1.0, 0.0, 0.0, GUARD_BAND],
+    [-1.0, 0.0, 0.0, GUARD_BAND],
+    [0.0, 1.0, 0.0, GUARD_BAND],
+    [0.0, -1.0, 0.0, GUARD_BAND],
+];
+
+const MAX_CLIPPED_VERTICES: usize = 3 + CLIP_PLANES.len();
+
+#[derive(Copy, Clone, Default)]
+pub struct ClipVertex {
+    pub position: Vec4,
+    pub uv: Vec2,
+    pub color: u32,
+}
+
+impl ClipVertex {
+    fn lerp(self, other: ClipVertex, t: f32) -> ClipVertex {
+        let channel = |shift: u32| {
+            let a = ((self.color >> shift) & 0xff) as f32;
+            let b = ((other.color >> shift) & 0xff) as f32;
+            ((a + (b - a) * t + 0.5) as u32 & 0xff) << shift
+        };
+
+        ClipVertex {
+            position: self.position.lerp(other.position, t),
+            uv: self.uv.lerp(other.uv, t),
+            color: channel(24) | channel(16) | channel(8) | channel(0),
+        }
+    }
+}
+
+// One bit for every plane the position is outside of
+pub fn clip_outcode(position: Vec4) -> u8 {
+    CLIP_PLANES
+        .iter()
+        .enumerate()
+        .fold(0, |outcode, (i, plane)| {
+            outcode | ((Vec4::from(*plane).dot(position) < 0.0) as u8) << i
+        })
+}
+
+// Clipping a convex polygon to a plane adds at most one vertex
+pub struct ClippedPolygon {
+    vertices: [ClipVertex; MAX_CLIPPED_VERTICES],
+    len: usize,
+}
+
+impl ClippedPolygon {
+    #[inline]
+    pub fn vertices(&self) -> &[ClipVertex] {
+        &self.vertices[..self.len]
+    }
+}
+
+// Sutherland-Hodgman against the planes in `outcode`, the result is convex and drawn as a fan
+pub fn clip_triangle(triangle: [ClipVertex; 3], outcode: u8) -> ClippedPolygon {
+    let mut polygon = ClippedPolygon {
+        vertices: [ClipVertex::default(); MAX_CLIPPED_VERTICES],
+        len: 3,
+    };
+    polygon.vertices[..3].copy_from_slice(&triangle);
+
+    for (i, plane) in CLIP_PLANES.iter().enumerate() {
+        if outcode & (1 << i) == 0 {
+            continue;
+        }
+
+        let plane = Vec4::from(*plane);
+        let input = polygon.vertices;
+        let input = &input[..polygon.len];
+        polygon.len = 0;
+
+        for (j, &current) in input.iter().enumerate() {
+            let next = input[(j + 1) % input.len()];
+            let current_distance = plane.dot(current.position);
+            let next_distance = plane.dot(next.position);
+
+            if current_distance >= 0.0 {
+                polygon.vertices[polygon.len] = current;
+                polygon.len += 1;
+            }
+
+            if (current_distance >= 0.0) != (next_distance >= 0.0) {
+                let t = current_distance / (current_distance - next_distance);
+                polygon.vertices[polygon.len] = current.lerp(next, t);
+                polygon.len += 1;
+            }
+        }
+
+        if polygon.len < 3 {
+            polygon.len = 0;
+            break;
+        }
+    }
+
+    polygon
+}
+
+// S, T and W of each vertex as the RDP interpolates them, S and T in s10.5 texels. With
+// perspective correction S and T are divided by w and W is 1/w scaled so the closest vertex
+// gets the full range.
+pub fn texture_vertex_coords(st: [Vec2; 3], inv_w: [f32; 3], perspective: bool) -> [Vec3; 3] {
+    let w_range = 0x7fff as f32;
+
+    if !perspective {
+        return st.map(|st| vec3(32.0 * st.x, 32.0 * st.y, w_range));
+    }
+
+    let max_inv_w = libm::fmaxf(libm::fmaxf(inv_w[0], inv_w[1]), inv_w[2]);
+
+    [0, 1, 2].map(|i| {
+        let w = inv_w[i] / max_inv_w;
+        vec3(32.0 * st[i].x * w, 32.0 * st[i].y * w, w_range * w)
+    })
+}
+
 pub fn truncate_to_pixel(val: Vec3) -> Vec3 {
     vec3(libm::floorf(val.x), libm::floorf(val.y), val.z)
 }
@@ -245,3 +384,58 @@ pub fn safe_cast_i32(val: f32) -> i32 {
         val as i32
     }
 }
+
+#[test]
+fn clip_triangle_to_near_plane() {
+    let vertex = |z: f32, color: u32| ClipVertex {
+        position: Vec4::new(0.0, 0.0, z, 1.0),
+        uv: Vec2::new(z, 0.0),
+        color,
+    };
+
+    // One vertex behind the near plane turns the triangle into a quad
+    let triangle = [vertex(0.0, 0), vertex(-3.0, 0xff), vertex(0.5, 0)];
+    let outcode = triangle
+        .iter()
+        .fold(0, |outcode, vertex| outcode | clip_outcode(vertex.position));
+    assert_eq!(outcode, 1);
+
+    let polygon = clip_triangle(triangle, outcode);
+    let vertices = polygon.vertices();
+    assert_eq!(vertices.len(), 4);
+    assert!(vertices.iter().all(|vertex| vertex.position.z >= -1.0));
+    assert_eq!(vertices[1].uv.x, -1.0);
+    assert_eq!(vertices[1].color, 0x55);
+}
+
+#[test]
+fn texture_coords_are_divided_by_w() {
+    let st = [
+        Vec2::new(1.0, 2.0),
+        Vec2::new(4.0, 0.0),
+        Vec2::new(8.0, 8.0),
+    ];
+
+    let affine = texture_vertex_coords(st, [1.0, 0.5, 0.25], false);
+    assert_eq!(affine[1], vec3(128.0, 0.0, 0x7fff as f32));
+
+    // The closest vertex keeps the whole W range and S / W gives back the texel
+    let perspective = texture_vertex_coords(st, [1.0, 0.5, 0.25], true);
+    assert_eq!(perspective[0], vec3(32.0, 64.0, 0x7fff as f32));
+    assert_eq!(perspective[2].z, 0.25 * 0x7fff as f32);
+    assert_eq!(perspective[2].x / perspective[2].z * 0x7fff as f32, 256.0);
+}
+
+#[test]
+fn back_faces_have_negative_area() {
+    let (v0, v1, v2) = (
+        vec3(0.0, 0.0, 0.0),
+        vec3(4.0, 0.0, 0.0),
+        vec3(0.0, 4.0, 0.0),
+    );
+
+    assert!(!triangle_is_culled(v0, v1, v2, CullMode::Back));
+    assert!(triangle_is_culled(v0, v2, v1, CullMode::Back));
+    assert!(triangle_is_culled(v0, v1, v2, CullMode::Front));
+    assert!(!triangle_is_culled(v0, v2, v1, CullMode::None));
+}
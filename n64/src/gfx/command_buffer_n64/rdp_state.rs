@@ -1,18 +1,46 @@
 use super::rdp_command_builder::*;
-use crate::gfx::{CycleType, FillPipeline, Pipeline, ZMode, ZSrc};
+use crate::{
+    gfx::{
+        FillPipeline, Pipeline, Texture, TextureFilter, TextureFormat, TextureLod, TextureWrap,
+        ZMode, ZSrc,
+    },
+    BitDepth,
+};
 use n64_math::{vec2, Color};
 
+// None until set, so the first use emits the command whatever the RDP was left with
 #[derive(Copy, Clone, Default)]
 pub struct RdpState {
-    other_modes: u64,
-    color_combiner_mode: u64,
-    fill_color: Color,
-    prim_color: u32,
-    env_color: u32,
-    blend_color: u32,
+    other_modes: Option<u64>,
+    color_combiner_mode: Option<u64>,
+    fill_color: Option<u32>,
+    prim_color: Option<u32>,
+    env_color: Option<u32>,
+    blend_color: Option<u32>,
+    fog_color: Option<u32>,
     texture: usize,
+    palette: usize,
+    wrap_s: TextureWrap,
+    wrap_t: TextureWrap,
 }
 
+impl RdpState {
+    // Only known after a pipeline has been applied
+    pub fn is_fill_mode(&self) -> bool {
+        self.other_modes
+            .map(|other_modes| other_modes & OTHER_MODE_CYCLE_TYPE_FILL)
+            == Some(OTHER_MODE_CYCLE_TYPE_FILL)
+    }
+}
+
+// TMEM is addressed in 64 bit words, a TLUT lives in the upper half
+const TMEM_WORDS: usize = 512;
+const TMEM_TLUT_ADDRESS: u16 = 256;
+
+// Loads go through their own tile so the render tile keeps the layout the texels are sampled with
+const LOAD_TILE: u8 = 7;
+pub(crate) const RENDER_TILE: u8 = 0;
+
 fn apply_sync_if_first_change(rdp: &mut RdpCommandBuilder, emitted_sync: &mut bool) {
     if !*emitted_sync {
         rdp.sync_pipe();
@@ -24,6 +52,7 @@ pub fn apply_fill_pipeline(
     rdp: &mut RdpCommandBuilder,
     state: &mut RdpState,
     pipeline: &FillPipeline,
+    bit_depth: BitDepth,
 ) {
     let mut emitted_sync = false;
 
@@ -34,30 +63,37 @@ pub fn apply_fill_pipeline(
             other_modes |= OTHER_MODE_FORCE_BLEND;
         }
 
-        if other_modes != state.other_modes {
+        if Some(other_modes) != state.other_modes {
             apply_sync_if_first_change(rdp, &mut emitted_sync);
             rdp.set_other_modes(other_modes);
-            state.other_modes = other_modes;
+            state.other_modes = Some(other_modes);
         }
     }
 
     {
         let color_combiner_mode = pipeline.color_combiner_mode.to_command();
 
-        if color_combiner_mode != state.color_combiner_mode {
+        if Some(color_combiner_mode) != state.color_combiner_mode {
             apply_sync_if_first_change(rdp, &mut emitted_sync);
             rdp.set_combine_mode(color_combiner_mode);
-            state.color_combiner_mode = color_combiner_mode;
+            state.color_combiner_mode = Some(color_combiner_mode);
         }
     }
 
     {
-        let fill_color = pipeline.fill_color;
+        // 16bpp images take the color twice, once for each pixel in a 32 bit word
+        let fill_color = match bit_depth {
+            BitDepth::Bpp16 => {
+                let value = pipeline.fill_color.value() as u32;
+                (value << 16) | value
+            }
+            BitDepth::Bpp32 => pipeline.fill_color.to_rgba32(),
+        };
 
-        if fill_color != state.fill_color {
+        if Some(fill_color) != state.fill_color {
             apply_sync_if_first_change(rdp, &mut emitted_sync);
             rdp.set_fill_color(fill_color);
-            state.fill_color = fill_color;
+            state.fill_color = Some(fill_color);
         }
     }
 }
@@ -66,15 +102,34 @@ pub fn apply_pipeline(rdp: &mut RdpCommandBuilder, state: &mut RdpState, pipelin
     let mut emitted_sync = false;
 
     {
-        let mut other_modes =
-            OTHER_MODE_CYCLE_TYPE_1_CYCLE | OTHER_MODE_SAMPLE_TYPE | OTHER_MODE_BI_LERP_0;
+        let mut other_modes = OTHER_MODE_CYCLE_TYPE_1_CYCLE | OTHER_MODE_BI_LERP_0;
+
+        other_modes |= match pipeline.texture_filter {
+            TextureFilter::Point => 0,
+            TextureFilter::Bilinear => OTHER_MODE_SAMPLE_TYPE,
+            TextureFilter::Average => OTHER_MODE_SAMPLE_TYPE | OTHER_MODE_MID_TEXEL,
+        };
+
+        other_modes |= pipeline.effective_blend_mode().to_command();
 
-        other_modes |= pipeline.blend_mode.to_command();
+        let lod = pipeline.texture.is_some() && pipeline.texture_lod != TextureLod::Off;
 
-        if pipeline.cycle_type == CycleType::Two {
+        if pipeline.is_two_cycle() {
             other_modes |= OTHER_MODE_CYCLE_TYPE_2_CYCLE;
         }
 
+        if lod {
+            other_modes |= OTHER_MODE_TEX_LOD_EN | OTHER_MODE_BI_LERP_1;
+
+            if pipeline.texture_lod == TextureLod::Sharpen {
+                other_modes |= OTHER_MODE_SHARPEN_TEX_EN;
+            }
+        }
+
+        if pipeline.texture.is_some() && pipeline.perspective_correction {
+            other_modes |= OTHER_MODE_PERSP_TEX_EN;
+        }
+
         other_modes |= match pipeline.z_mode {
             ZMode::Opaque => OTHER_MODE_Z_MODE_OPAQUE,
             ZMode::Interpenetrating => OTHER_MODE_Z_MODE_INTERPENETRATING,
@@ -101,81 +156,341 @@ pub fn apply_pipeline(rdp: &mut RdpCommandBuilder, state: &mut RdpState, pipelin
             other_modes |= OTHER_MODE_IMAGE_READ_EN;
         }
 
-        if pipeline.texture.is_some() {
+        if let Some(texture) = pipeline.texture {
             other_modes |= OTHER_MODE_IMAGE_READ_EN;
+
+            if texture.format.palette_size() > 0 {
+                other_modes |= OTHER_MODE_EN_TLUT;
+            }
         }
 
-        if other_modes != state.other_modes {
+        if Some(other_modes) != state.other_modes {
             apply_sync_if_first_change(rdp, &mut emitted_sync);
             rdp.set_other_modes(other_modes);
-            state.other_modes = other_modes;
+            state.other_modes = Some(other_modes);
         }
     }
 
     {
-        let color_combiner_mode = pipeline.color_combiner_mode.to_command();
+        let color_combiner_mode = pipeline.effective_combiner_mode().to_command();
 
-        if color_combiner_mode != state.color_combiner_mode {
+        if Some(color_combiner_mode) != state.color_combiner_mode {
             apply_sync_if_first_change(rdp, &mut emitted_sync);
             rdp.set_combine_mode(color_combiner_mode);
-            state.color_combiner_mode = color_combiner_mode;
+            state.color_combiner_mode = Some(color_combiner_mode);
         }
     }
 
     if let Some(blend_color) = pipeline.blend_color {
-        if blend_color != state.blend_color {
+        if Some(blend_color) != state.blend_color {
             apply_sync_if_first_change(rdp, &mut emitted_sync);
             rdp.set_blend_color(blend_color);
-            state.blend_color = blend_color;
+            state.blend_color = Some(blend_color);
+        }
+    }
+
+    if let Some(fog_color) = pipeline.fog_color {
+        if Some(fog_color) != state.fog_color {
+            apply_sync_if_first_change(rdp, &mut emitted_sync);
+            rdp.set_fog_color(fog_color);
+            state.fog_color = Some(fog_color);
         }
     }
 
     if let Some(prim_color) = pipeline.prim_color {
-        if prim_color != state.prim_color {
+        if Some(prim_color) != state.prim_color {
             apply_sync_if_first_change(rdp, &mut emitted_sync);
             rdp.set_prim_color(prim_color);
-            state.prim_color = prim_color;
+            state.prim_color = Some(prim_color);
         }
     }
 
     if let Some(env_color) = pipeline.env_color {
-        if env_color != state.env_color {
+        if Some(env_color) != state.env_color {
             apply_sync_if_first_change(rdp, &mut emitted_sync);
             rdp.set_env_color(env_color);
-            state.env_color = env_color;
+            state.env_color = Some(env_color);
         }
     }
 
     if let Some(texture) = pipeline.texture {
-        if state.texture != texture.data.as_ptr() as usize {
-            rdp.sync_tile()
-                .set_texture_image(
-                    FORMAT_RGBA,
-                    SIZE_OF_PIXEL_16B,
-                    texture.width as u16,
-                    texture.data.as_ptr() as *const u16,
-                )
-                .set_tile(
-                    FORMAT_RGBA,
-                    SIZE_OF_PIXEL_16B,
-                    texture.width as u16,
-                    0,
-                    0,
-                    0,
-                    0,
-                    0,
-                    0,
-                    0,
-                    0,
-                    0,
-                    0,
-                )
-                .load_tile(
-                    vec2((texture.width) as f32, (texture.height) as f32),
-                    vec2(0.0, 0.0),
-                    0,
-                );
+        let palette = texture
+            .palette
+            .map_or(0, |palette| palette.as_ptr() as usize);
+
+        let wrap_s = pipeline.texture_wrap_s;
+        let wrap_t = pipeline.texture_wrap_t;
+
+        // Textures too big for TMEM are loaded in strips while drawing
+        if texture_fits_tmem(&texture)
+            && (state.texture != texture.data.as_ptr() as usize || state.palette != palette)
+        {
+            load_texture(rdp, &texture, wrap_s, wrap_t);
             state.texture = texture.data.as_ptr() as usize;
+            state.palette = palette;
+            state.wrap_s = wrap_s;
+            state.wrap_t = wrap_t;
+        } else if state.texture == texture.data.as_ptr() as usize
+            && (state.wrap_s != wrap_s || state.wrap_t != wrap_t)
+        {
+            rdp.sync_tile();
+
+            let mut tmem_address = 0;
+            for level in 0..texture.mip_levels {
+                let level_texture = texture.mip_level(level);
+                set_render_tile(
+                    rdp,
+                    &level_texture,
+                    tmem_address,
+                    RENDER_TILE + level,
+                    wrap_s.for_mip_level(level),
+                    wrap_t.for_mip_level(level),
+                );
+                tmem_address += tmem_size(&level_texture) as u16;
+            }
+            state.wrap_s = wrap_s;
+            state.wrap_t = wrap_t;
         }
     }
 }
+
+fn texture_format(format: TextureFormat) -> (u8, u8) {
+    match format {
+        TextureFormat::Rgba16 => (FORMAT_RGBA, SIZE_OF_PIXEL_16B),
+        TextureFormat::Rgba32 => (FORMAT_RGBA, SIZE_OF_PIXEL_32B),
+        TextureFormat::Ci4 => (FORMAT_COLOR_INDX, SIZE_OF_PIXEL_4B),
+        TextureFormat::Ci8 => (FORMAT_COLOR_INDX, SIZE_OF_PIXEL_8B),
+        TextureFormat::I4 => (FORMAT_I, SIZE_OF_PIXEL_4B),
+        TextureFormat::I8 => (FORMAT_I, SIZE_OF_PIXEL_8B),
+        TextureFormat::Ia4 => (FORMAT_IA, SIZE_OF_PIXEL_4B),
+        TextureFormat::Ia8 => (FORMAT_IA, SIZE_OF_PIXEL_8B),
+        TextureFormat::Ia16 => (FORMAT_IA, SIZE_OF_PIXEL_16B),
+    }
+}
+
+// Length of a texture row in TMEM in 64 bit words. RGBA32 is split over both halves of TMEM
+// with 16 bits of every texel in each.
+fn tmem_line(texture: &Texture) -> u16 {
+    let bits_per_texel = match texture.format {
+        TextureFormat::Rgba32 => 16,
+        format => format.bits_per_texel(),
+    };
+
+    ((texture.width as usize * bits_per_texel + 63) / 64) as u16
+}
+
+// Color indexed textures share TMEM with their TLUT and RGBA32 needs both halves
+fn tmem_words(format: TextureFormat) -> usize {
+    if format.palette_size() > 0 || format == TextureFormat::Rgba32 {
+        TMEM_WORDS / 2
+    } else {
+        TMEM_WORDS
+    }
+}
+
+// Texture rows that can be loaded at once
+pub fn tmem_rows(texture: &Texture) -> i32 {
+    (tmem_words(texture.format) / tmem_line(texture) as usize) as i32
+}
+
+// 64 bit words taken by all mip levels
+fn tmem_size(texture: &Texture) -> usize {
+    (0..texture.mip_levels)
+        .map(|level| {
+            let level_texture = texture.mip_level(level);
+            tmem_line(&level_texture) as usize * level_texture.height as usize
+        })
+        .sum()
+}
+
+#[inline]
+pub fn texture_fits_tmem(texture: &Texture) -> bool {
+    tmem_size(texture) <= tmem_words(texture.format)
+}
+
+// Loads part of a texture that does not fit TMEM, whatever was loaded before is gone
+pub fn load_texture_strip(
+    rdp: &mut RdpCommandBuilder,
+    state: &mut RdpState,
+    pipeline: &Pipeline,
+    first_row: i32,
+    row_count: i32,
+) {
+    let texture = pipeline
+        .texture
+        .expect("No texture has been set on the pipeline");
+
+    // Mip levels are sampled all over the texture, they can not be split
+    assert!(texture.mip_levels == 1);
+
+    rdp.sync_tile();
+    load_palette(rdp, &texture);
+    load_texture_rows(
+        rdp,
+        &texture,
+        0,
+        RENDER_TILE,
+        pipeline.texture_wrap_s,
+        pipeline.texture_wrap_t,
+        first_row,
+        row_count,
+    );
+    state.texture = 0;
+    state.palette = 0;
+}
+
+// Mip levels are stored one after the other, each with a render tile of its own counting up
+// from RENDER_TILE as the RDP expects with LOD enabled
+fn load_texture(
+    rdp: &mut RdpCommandBuilder,
+    texture: &Texture,
+    wrap_s: TextureWrap,
+    wrap_t: TextureWrap,
+) {
+    rdp.sync_tile();
+    load_palette(rdp, texture);
+
+    let mut tmem_address = 0;
+    for level in 0..texture.mip_levels {
+        let level_texture = texture.mip_level(level);
+        load_texture_rows(
+            rdp,
+            &level_texture,
+            tmem_address,
+            RENDER_TILE + level,
+            wrap_s.for_mip_level(level),
+            wrap_t.for_mip_level(level),
+            0,
+            level_texture.height,
+        );
+        tmem_address += tmem_size(&level_texture) as u16;
+    }
+}
+
+fn load_palette(rdp: &mut RdpCommandBuilder, texture: &Texture) {
+    if texture.format.palette_size() == 0 {
+        return;
+    }
+
+    let palette = texture
+        .palette
+        .expect("Color indexed textures need a palette");
+
+    rdp.sync_load()
+        .set_texture_image(
+            FORMAT_RGBA,
+            SIZE_OF_PIXEL_16B,
+            1,
+            palette.as_ptr() as *const u16,
+        )
+        .set_tile(
+            0,
+            0,
+            0,
+            TMEM_TLUT_ADDRESS,
+            LOAD_TILE,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        )
+        .load_tlut(texture.format.palette_size() as u16, LOAD_TILE);
+}
+
+fn set_render_tile(
+    rdp: &mut RdpCommandBuilder,
+    texture: &Texture,
+    tmem_address: u16,
+    tile: u8,
+    wrap_s: TextureWrap,
+    wrap_t: TextureWrap,
+) {
+    let (format, size) = texture_format(texture.format);
+
+    rdp.set_tile(
+        format,
+        size,
+        tmem_line(texture),
+        tmem_address,
+        tile,
+        wrap_t.clamp as u8,
+        wrap_t.mirror as u8,
+        wrap_t.mask,
+        wrap_t.shift_bits(),
+        wrap_s.clamp as u8,
+        wrap_s.mirror as u8,
+        wrap_s.mask,
+        wrap_s.shift_bits(),
+    );
+}
+
+// Loads row_count rows starting at first_row, the render tile keeps addressing them with their
+// coordinates in the whole texture
+#[allow(clippy::too_many_arguments)]
+fn load_texture_rows(
+    rdp: &mut RdpCommandBuilder,
+    texture: &Texture,
+    tmem_address: u16,
+    tile: u8,
+    wrap_s: TextureWrap,
+    wrap_t: TextureWrap,
+    first_row: i32,
+    row_count: i32,
+) {
+    let (format, size) = texture_format(texture.format);
+    let line = tmem_line(texture);
+
+    assert!(row_count <= tmem_rows(texture));
+
+    // LOAD_TILE can not load 4 bit texels, they are loaded as 8 bit texels of half the width
+    let (load_size, load_width) = if size == SIZE_OF_PIXEL_4B {
+        assert!(texture.width % 2 == 0);
+        (SIZE_OF_PIXEL_8B, texture.width / 2)
+    } else {
+        (size, texture.width)
+    };
+
+    rdp.sync_load()
+        .set_texture_image(
+            format,
+            load_size,
+            load_width as u16,
+            texture.data.as_ptr() as *const u16,
+        )
+        .set_tile(
+            format,
+            load_size,
+            line,
+            tmem_address,
+            LOAD_TILE,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        )
+        .load_tile(
+            vec2(0.0, first_row as f32),
+            vec2((load_width - 1) as f32, (first_row + row_count - 1) as f32),
+            LOAD_TILE,
+        )
+        .sync_tile();
+
+    set_render_tile(rdp, texture, tmem_address, tile, wrap_s, wrap_t);
+
+    rdp.set_tile_size(
+        vec2(0.0, first_row as f32),
+        vec2(
+            (texture.width - 1) as f32,
+            (first_row + row_count - 1) as f32,
+        ),
+        tile,
+    );
+}
@@ -0,0 +1,282 @@
+use super::{
+    rdp_command_builder::RdpCommandBuilder,
+    rdp_math::{clip_outcode, f32_to_fixed_16_16, z_buff_val_transform},
+};
+use crate::gfx::CullMode;
+use n64_math::{vec3, Mat4, Vec3};
+use n64_types::{
+    MeshChunk, CHUNK_COMMAND_MESH, MESH_CHUNK_CULL_BACK, MESH_CHUNK_CULL_FRONT,
+    MESH_CHUNK_MAX_TRIANGLES, MESH_CHUNK_MAX_VERTICES,
+};
+
+// Positions are sent as integers relative to the center of the mesh, the matrix only has 16 bits
+// of fraction so larger positions would lose more precision in it than they gain
+const POSITION_RANGE: f32 = 2047.0;
+
+// The RSP reciprocal of w loses precision for large w, the matrix is scaled so w is at most 1
+// and the vertices furthest away can not have a w much smaller than that
+const MAX_W_RATIO: f32 = 16.0;
+
+// The RSP keeps z / 256 so z can be interpolated as s15.16 next to the colors
+const Z_SCALE: f32 = 1.0 / 256.0;
+
+pub struct RspMesh {
+    matrix_int: [[i16; 4]; 4],
+    matrix_frac: [[u16; 4]; 4],
+    center: Vec3,
+    scale: f32,
+    flags: u8,
+}
+
+impl RspMesh {
+    // None when any part of the mesh needs clipping, checked with the corners of its bounding box
+    pub fn new(verts: &[[f32; 3]], transform: Mat4, cull_mode: CullMode) -> Option<Self> {
+        if verts.is_empty() {
+            return None;
+        }
+
+        let (min, max) = verts.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), vert| (min.min(Vec3::from(*vert)), max.max(Vec3::from(*vert))),
+        );
+
+        let mut w_min = f32::MAX;
+        let mut w_max = 0.0f32;
+
+        for corner in 0..8 {
+            let position = vec3(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            );
+            let clip = transform * position.extend(1.0);
+
+            if clip_outcode(clip) != 0 || clip.w <= 0.0 {
+                return None;
+            }
+
+            w_min = w_min.min(clip.w);
+            w_max = w_max.max(clip.w);
+        }
+
+        if w_min * MAX_W_RATIO < w_max {
+            return None;
+        }
+
+        let center = 0.5 * (min + max);
+        let extent = (0.5 * (max - min)).max_element();
+        let scale = if extent > 0.0 {
+            POSITION_RANGE / extent
+        } else {
+            1.0
+        };
+
+        let z_scale = Mat4::from_scale(vec3(1.0, 1.0, z_buff_val_transform(1.0) * Z_SCALE));
+        let matrix = z_scale
+            * transform
+            * Mat4::from_translation(center)
+            * Mat4::from_scale(Vec3::splat(1.0 / scale))
+            * (1.0 / w_max);
+
+        let mut matrix_int = [[0; 4]; 4];
+        let mut matrix_frac = [[0; 4]; 4];
+
+        for (column, value) in matrix.to_cols_array().iter().enumerate() {
+            if value.abs() >= i16::MAX as f32 {
+                return None;
+            }
+
+            let fixed = f32_to_fixed_16_16(*value);
+            matrix_int[column / 4][column % 4] = (fixed >> 16) as i16;
+            matrix_frac[column / 4][column % 4] = fixed as u16;
+        }
+
+        Some(Self {
+            matrix_int,
+            matrix_frac,
+            center,
+            scale,
+            flags: match cull_mode {
+                CullMode::None => 0,
+                CullMode::Back => MESH_CHUNK_CULL_BACK,
+                CullMode::Front => MESH_CHUNK_CULL_FRONT,
+            },
+        })
+    }
+
+    #[inline]
+    fn position(&self, vert: [f32; 3]) -> [i16; 4] {
+        let position = ((Vec3::from(vert) - self.center) * self.scale).round();
+
+        [position.x as i16, position.y as i16, position.z as i16, 1]
+    }
+
+    fn start_chunk<'a>(&self, rdp: &'a mut RdpCommandBuilder) -> &'a mut MeshChunk {
+        let chunk = rdp.mesh_chunk();
+
+        chunk.command = CHUNK_COMMAND_MESH;
+        chunk.flags = self.flags;
+        chunk.matrix_int = self.matrix_int;
+        chunk.matrix_frac = self.matrix_frac;
+
+        chunk
+    }
+
+    // Triangles go in the current chunk until their vertices no longer fit, vertices shared
+    // with earlier triangles in the chunk are only sent once
+    pub fn add_chunks(
+        &self,
+        rdp: &mut RdpCommandBuilder,
+        verts: &[[f32; 3]],
+        colors: &[u32],
+        indices: &[[u8; 3]],
+    ) {
+        if indices.is_empty() {
+            return;
+        }
+
+        let mut chunk = self.start_chunk(rdp);
+        let mut chunk_index = [u8::MAX; 256];
+
+        for triangle in indices {
+            let new_vertices = triangle
+                .iter()
+                .enumerate()
+                .filter(|&(i, index)| {
+                    chunk_index[*index as usize] == u8::MAX && !triangle[..i].contains(index)
+                })
+                .count();
+
+            if chunk.vertex_count as usize + new_vertices > MESH_CHUNK_MAX_VERTICES
+                || chunk.triangle_count as usize == MESH_CHUNK_MAX_TRIANGLES
+            {
+                chunk = self.start_chunk(rdp);
+                chunk_index = [u8::MAX; 256];
+            }
+
+            let mut chunk_triangle = [0; 3];
+
+            for (corner, index) in triangle.iter().enumerate() {
+                let index = *index as usize;
+
+                if chunk_index[index] == u8::MAX {
+                    let vertex = chunk.vertex_count as usize;
+                    chunk.positions[vertex] = self.position(verts[index]);
+                    chunk.colors[vertex] = colors[index];
+                    chunk.vertex_count += 1;
+                    chunk_index[index] = vertex as u8;
+                }
+
+                chunk_triangle[corner] = chunk_index[index];
+            }
+
+            chunk.indices[chunk.triangle_count as usize] = chunk_triangle;
+            chunk.triangle_count += 1;
+        }
+    }
+}
+
+#[test]
+fn mesh_chunk_matrix_maps_to_pixels() {
+    use n64_math::Vec4;
+
+    let transform =
+        Mat4::from_translation(vec3(160.0, 120.0, 0.0)) * Mat4::from_scale(vec3(100.0, 100.0, 0.5));
+
+    // A strip of 40 triangles needs more vertices than one chunk holds
+    let verts = (0..42)
+        .map(|i| [(i / 2) as f32 * 0.05 - 0.5, (i % 2) as f32, 0.5])
+        .collect::<alloc::vec::Vec<_>>();
+    let colors = [0xff0000ff; 42];
+    let indices = (0..40u8)
+        .map(|i| [i, i + 1, i + 2])
+        .collect::<alloc::vec::Vec<_>>();
+
+    let mesh = RspMesh::new(&verts, transform, CullMode::Back).unwrap();
+
+    let mut rdp = RdpCommandBuilder::new();
+    rdp.clear();
+    mesh.add_chunks(&mut rdp, &verts, &colors, &indices);
+
+    let chunks = rdp
+        .blocks
+        .iter()
+        .map(|block| unsafe { &*(block as *const n64_types::RdpBlock as *const MeshChunk) })
+        .collect::<alloc::vec::Vec<_>>();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].vertex_count as usize, MESH_CHUNK_MAX_VERTICES);
+    assert_eq!(
+        chunks
+            .iter()
+            .map(|c| c.triangle_count as usize)
+            .sum::<usize>(),
+        40
+    );
+    assert_eq!(chunks[0].flags, MESH_CHUNK_CULL_BACK);
+
+    // Run the first vertex through the fixed point matrix like the RSP does
+    let chunk = chunks[0];
+    let position = chunk.positions[chunk.indices[0][0] as usize];
+    let clip = (0..4).fold(Vec4::ZERO, |clip, column| {
+        let column_value = Vec4::from([0, 1, 2, 3].map(|row| {
+            chunk.matrix_int[column][row] as f32 + chunk.matrix_frac[column][row] as f32 / 65536.0
+        }));
+        clip + column_value * position[column] as f32
+    });
+
+    let screen = clip.truncate() / clip.w;
+    assert!((screen.x - 110.0).abs() < 0.1);
+    assert!((screen.y - 120.0).abs() < 0.1);
+    assert!((screen.z - z_buff_val_transform(0.25) * Z_SCALE).abs() < 0.1);
+
+    // Behind the camera needs clipping
+    let behind = Mat4::from_translation(vec3(0.0, 0.0, -10.0)) * transform;
+    assert!(RspMesh::new(&verts, behind, CullMode::None).is_none());
+}
+
+#[test]
+fn mesh_chunk_carries_the_fog_factor_in_vertex_alpha() {
+    use super::{CommandBuffer, CommandBufferCache};
+    use crate::{
+        framebuffer::ViBufferToken,
+        gfx::{FogRange, Pipeline},
+        VideoFormat, VideoMode,
+    };
+    use n64_math::Color;
+
+    // Vertex z goes to w and the positions are premultiplied by it
+    let perspective = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+        [0.0, 0.0, 0.5, 0.0],
+    ];
+
+    let video_mode = VideoMode::Ntsc {
+        width: 32,
+        height: 16,
+        format: VideoFormat::default(),
+    };
+    let mut framebuffer = alloc::vec![Color::new(0); 32 * 16];
+    let mut cache = CommandBufferCache::new(video_mode);
+
+    let mut cb = CommandBuffer::new(ViBufferToken(framebuffer.as_mut_ptr()), &mut cache);
+    cb.set_pipeline(&Pipeline::default().with_fog_range(Some(FogRange::new(1.0, 3.0))))
+        .add_mesh_indexed(
+            &[[2.0, 2.0, 1.0], [30.0, 6.0, 3.0], [4.0, 24.0, 2.0]],
+            &[[0.0, 0.0]; 3],
+            &[0xff00_00ff; 3],
+            &[[0, 1, 2]],
+            &perspective,
+        );
+
+    let chunk = cache
+        .rdp
+        .blocks
+        .iter()
+        .map(|block| unsafe { &*(block as *const n64_types::RdpBlock as *const MeshChunk) })
+        .find(|chunk| chunk.command == CHUNK_COMMAND_MESH)
+        .expect("The mesh should go to the RSP");
+
+    assert_eq!(chunk.colors[..3], [0xff00_0000, 0xff00_00ff, 0xff00_0080]);
+}
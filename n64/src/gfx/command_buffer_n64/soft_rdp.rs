@@ -0,0 +1,1374 @@
+use super::rdp_command_builder::*;
+use alloc::vec::Vec;
+use n64_sys::sys::virtual_to_physical;
+use n64_types::{RdpBlock, RdpCommand};
+
+#[cfg(any(test, feature = "golden-images"))]
+use std::{fs::File, io, io::BufWriter, path::Path};
+
+// Runs the RDP commands built for the N64 on the host so the hardware path can be checked against
+// golden images. Close to the RDP but not exact: coverage is sampled at pixel centers without
+// antialiasing, the z buffer holds plain 16 bit depth, there is no dithering or noise and LOD is
+// not computed so triangles sample their first tile.
+//
+// Other crates get to it as n64::gfx::soft_rdp on the host. render draws with the N64 command
+// buffer and returns the image, which assert_golden compares with a PNG.
+
+// The command buffer used on the N64, n64::gfx::CommandBuffer is the wgpu one on the host
+pub use super::CommandBuffer;
+
+const TMEM_SIZE: usize = 4096;
+// RGBA32 texels keep red and green in the lower half of TMEM, blue and alpha in the upper
+const TMEM_HIGH_HALF: usize = TMEM_SIZE / 2;
+// Where the texture unit looks up TLUT entries, one 16 bit entry after the other
+const TMEM_TLUT: usize = 0x800;
+
+const CYCLE_TYPE_MASK: u64 = 0x30_0000_0000_0000;
+
+#[derive(Copy, Clone, Default)]
+struct Image {
+    format: u8,
+    size: u8,
+    width: usize,
+    address: usize,
+}
+
+#[derive(Copy, Clone, Default)]
+struct Tile {
+    format: u8,
+    size: u8,
+    // In 64 bit words
+    line: usize,
+    tmem: usize,
+    palette: u8,
+    clamp_t: bool,
+    mirror_t: bool,
+    mask_t: u8,
+    shift_t: u8,
+    clamp_s: bool,
+    mirror_s: bool,
+    mask_s: u8,
+    shift_s: u8,
+    // Unsigned 10.2
+    sl: i32,
+    tl: i32,
+    sh: i32,
+    th: i32,
+}
+
+// Shade, texture and z coefficients of a triangle, as s15.16 lanes in the order the RDP takes them
+#[derive(Copy, Clone, Default)]
+struct Coefficients {
+    value: [i32; 4],
+    dx: [i32; 4],
+    de: [i32; 4],
+}
+
+impl Coefficients {
+    // Integer parts of the start value and x step in the first two words and their fractions in
+    // the next two, then the same for the edge and y steps
+    fn new(words: &[RdpCommand]) -> Self {
+        let lanes = |int: RdpCommand, frac: RdpCommand| {
+            [0, 1, 2, 3].map(|lane| {
+                let shift = 48 - 16 * lane;
+                (((int.0 >> shift) as u16 as u32) << 16 | (frac.0 >> shift) as u16 as u32) as i32
+            })
+        };
+
+        Self {
+            value: lanes(words[0], words[2]),
+            dx: lanes(words[1], words[3]),
+            de: lanes(words[4], words[6]),
+        }
+    }
+
+    fn z(words: &[RdpCommand]) -> Self {
+        Self {
+            value: [(words[0].0 >> 32) as i32, 0, 0, 0],
+            dx: [words[0].0 as i32, 0, 0, 0],
+            de: [(words[1].0 >> 32) as i32, 0, 0, 0],
+        }
+    }
+
+    // Lines below the first scanline and s15.16 x right of the major edge
+    fn at(&self, lines: i32, x: i64) -> [i32; 4] {
+        [0, 1, 2, 3].map(|lane| {
+            (self.value[lane] as i64
+                + self.de[lane] as i64 * lines as i64
+                + ((self.dx[lane] as i64 * x) >> 16)) as i32
+        })
+    }
+}
+
+// Per pixel inputs to the combiner, channels are 0 to 255
+#[derive(Copy, Clone, Default)]
+struct Inputs {
+    combined: [i32; 4],
+    texel0: [i32; 4],
+    texel1: [i32; 4],
+    shade: [i32; 4],
+}
+
+pub struct SoftRdp {
+    // Copies of the mapped memory by physical address
+    memory: Vec<(usize, Vec<u8>)>,
+    tmem: [u8; TMEM_SIZE],
+    tiles: [Tile; 8],
+    color_image: Image,
+    z_image: usize,
+    texture_image: Image,
+    other_modes: u64,
+    combine: u64,
+    fill_color: u32,
+    prim_color: u32,
+    prim_lod_fraction: i32,
+    env_color: u32,
+    blend_color: u32,
+    fog_color: u32,
+    prim_z: i32,
+    // Top left inclusive and bottom right exclusive in unsigned 10.2
+    scissor: [i32; 4],
+}
+
+impl SoftRdp {
+    pub fn new() -> Self {
+        Self {
+            memory: Vec::new(),
+            tmem: [0; TMEM_SIZE],
+            tiles: [Tile::default(); 8],
+            color_image: Image::default(),
+            z_image: 0,
+            texture_image: Image::default(),
+            other_modes: 0,
+            combine: 0,
+            fill_color: 0,
+            prim_color: 0,
+            prim_lod_fraction: 0,
+            env_color: 0,
+            blend_color: 0,
+            fog_color: 0,
+            prim_z: 0,
+            scissor: [0; 4],
+        }
+    }
+
+    // Copies memory the commands point at, like images and textures. It is found by the address
+    // the command builder gave the RDP for it.
+    pub fn map(&mut self, memory: &[u8]) {
+        let start = virtual_to_physical(memory.as_ptr());
+        let end = start + memory.len();
+
+        assert!(
+            self.memory
+                .iter()
+                .all(|(other, data)| end <= *other || start >= other + data.len()),
+            "Mapped memory overlaps"
+        );
+
+        self.memory.push((start, memory.to_vec()));
+    }
+
+    // Mesh chunks are set up by the RSP, on the host their first byte ends up in the command count
+    pub fn run(&mut self, blocks: &[RdpBlock]) {
+        for block in blocks {
+            assert!(
+                block.block_len <= block.rdp_data.len() as u64,
+                "Mesh chunks need the RSP"
+            );
+
+            self.run_commands(&block.rdp_data[..block.block_len as usize]);
+        }
+    }
+
+    pub fn run_commands(&mut self, commands: &[RdpCommand]) {
+        let mut index = 0;
+
+        while index < commands.len() {
+            let len = command_len(commands[index]);
+            self.command(&commands[index..index + len]);
+            index += len;
+        }
+    }
+
+    // The color image the commands ended with, the VI ignores coverage so it comes out opaque
+    pub fn color_image_rgba8(&self, height: usize) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.color_image.width * height * 4);
+
+        for y in 0..height as i32 {
+            for x in 0..self.color_image.width as i32 {
+                let [r, g, b, _] = self.read_pixel(x, y);
+                rgba.extend_from_slice(&[r as u8, g as u8, b as u8, 0xff]);
+            }
+        }
+
+        rgba
+    }
+
+    fn command(&mut self, command: &[RdpCommand]) {
+        let word = command[0].0;
+
+        match word >> 56 {
+            COMMAND_SET_COLOR_IMAGE => self.color_image = image(word),
+            COMMAND_SET_Z_IMAGE => self.z_image = (word & 0xffff_ffff) as usize,
+            COMMAND_SET_TEXTURE_IMAGE => self.texture_image = image(word),
+            COMMAND_SET_COMBINE_MODE => self.combine = word & 0x00ff_ffff_ffff_ffff,
+            COMMAND_SET_ENV_COLOR => self.env_color = word as u32,
+            COMMAND_SET_PRIM_COLOR => {
+                self.prim_color = word as u32;
+                self.prim_lod_fraction = ((word >> 32) & 0xff) as i32;
+            }
+            COMMAND_SET_BLEND_COLOR => self.blend_color = word as u32,
+            COMMAND_SET_FOG_COLOR => self.fog_color = word as u32,
+            COMMAND_SET_FILL_COLOR => self.fill_color = word as u32,
+            COMMAND_SET_PRIM_DEPTH => self.prim_z = ((word >> 16) & 0xffff) as i32,
+            COMMAND_FILL_RECTANGLE => self.fill_rectangle(word),
+            COMMAND_SET_TILE => self.set_tile(word),
+            COMMAND_LOAD_TILE => self.load_tile(word),
+            COMMAND_SET_TILE_SIZE => {
+                let tile = &mut self.tiles[((word >> 24) & 0x7) as usize];
+                tile.sl = ((word >> 44) & 0xfff) as i32;
+                tile.tl = ((word >> 32) & 0xfff) as i32;
+                tile.sh = ((word >> 12) & 0xfff) as i32;
+                tile.th = (word & 0xfff) as i32;
+            }
+            COMMAND_LOAD_TLUT => self.load_tlut(word),
+            COMMAND_SET_OTHER_MODE => self.other_modes = word & 0x00ff_ffff_ffff_ffff,
+            COMMAND_SET_SCISSOR => {
+                self.scissor = [
+                    ((word >> 44) & 0xfff) as i32,
+                    ((word >> 32) & 0xfff) as i32,
+                    ((word >> 12) & 0xfff) as i32,
+                    (word & 0xfff) as i32,
+                ];
+            }
+            COMMAND_SYNC_FULL | COMMAND_SYNC_TILE | COMMAND_SYNC_PIPE | COMMAND_SYNC_LOAD => {}
+            COMMAND_TEXTURE_RECTANGLE => self.texture_rectangle(word, command[1].0),
+            op if op & !0x7 == COMMAND_EDGE_COEFFICIENTS => self.triangle(command),
+            op => panic!("Unsupported RDP command {:#04x}", op),
+        }
+    }
+
+    fn set_tile(&mut self, word: u64) {
+        let tile = &mut self.tiles[((word >> 24) & 0x7) as usize];
+
+        tile.format = ((word >> 53) & 0x7) as u8;
+        tile.size = ((word >> 51) & 0x3) as u8;
+        tile.line = ((word >> 41) & 0x1ff) as usize;
+        tile.tmem = ((word >> 32) & 0x1ff) as usize;
+        tile.palette = ((word >> 20) & 0xf) as u8;
+        tile.clamp_t = (word >> 19) & 0x1 != 0;
+        tile.mirror_t = (word >> 18) & 0x1 != 0;
+        tile.mask_t = ((word >> 14) & 0xf) as u8;
+        tile.shift_t = ((word >> 10) & 0xf) as u8;
+        tile.clamp_s = (word >> 9) & 0x1 != 0;
+        tile.mirror_s = (word >> 8) & 0x1 != 0;
+        tile.mask_s = ((word >> 4) & 0xf) as u8;
+        tile.shift_s = (word & 0xf) as u8;
+    }
+
+    // Copies whole rows of texels, the texture image width apart in memory and the tile line
+    // apart in TMEM
+    fn load_tile(&mut self, word: u64) {
+        let image = self.texture_image;
+        let tile = self.tiles[((word >> 24) & 0x7) as usize];
+
+        let sl = ((word >> 44) & 0xfff) as usize >> 2;
+        let tl = ((word >> 32) & 0xfff) as usize >> 2;
+        let sh = ((word >> 12) & 0xfff) as usize >> 2;
+        let th = (word & 0xfff) as usize >> 2;
+
+        let texel_size = match image.size {
+            SIZE_OF_PIXEL_8B => 1,
+            SIZE_OF_PIXEL_16B => 2,
+            SIZE_OF_PIXEL_32B => 4,
+            _ => panic!("4 bit textures are loaded as 8 bit textures of half the width"),
+        };
+
+        for t in tl..=th {
+            let row = tile.tmem * 8 + (t - tl) * tile.line * 8;
+
+            for s in sl..=sh {
+                let mut texel = [0; 4];
+                self.read(
+                    image.address + (t * image.width + s) * texel_size,
+                    &mut texel[..texel_size],
+                );
+
+                if texel_size == 4 {
+                    let offset = row + (s - sl) * 2;
+                    self.write_tmem(offset, &texel[..2]);
+                    self.write_tmem(offset + TMEM_HIGH_HALF, &texel[2..]);
+                } else {
+                    self.write_tmem(row + (s - sl) * texel_size, &texel[..texel_size]);
+                }
+            }
+        }
+    }
+
+    fn load_tlut(&mut self, word: u64) {
+        let tile = self.tiles[((word >> 24) & 0x7) as usize];
+
+        let first = ((word >> 44) & 0xfff) as usize >> 2;
+        let last = ((word >> 12) & 0xfff) as usize >> 2;
+
+        for entry in first..=last {
+            let mut color = [0; 2];
+            self.read(self.texture_image.address + entry * 2, &mut color);
+            self.write_tmem(tile.tmem * 8 + (entry - first) * 2, &color);
+        }
+    }
+
+    fn write_tmem(&mut self, offset: usize, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            self.tmem[(offset + i) % TMEM_SIZE] = *byte;
+        }
+    }
+
+    // Fill and copy mode draw the bottom and right edges too
+    fn fill_rectangle(&mut self, word: u64) {
+        let r = ((word >> 44) & 0xfff) as i32;
+        let b = ((word >> 32) & 0xfff) as i32;
+        let l = ((word >> 12) & 0xfff) as i32;
+        let t = (word & 0xfff) as i32;
+
+        if self.other_modes & CYCLE_TYPE_MASK == OTHER_MODE_CYCLE_TYPE_FILL {
+            for y in self.rows(t >> 2, (b >> 2) + 1) {
+                for x in self.columns(l >> 2, (r >> 2) + 1) {
+                    self.fill_pixel(x, y);
+                }
+            }
+            return;
+        }
+
+        let mut inputs = Inputs::default();
+
+        for y in self.rows((t + 1) >> 2, (b + 1) >> 2) {
+            for x in self.columns((l + 1) >> 2, (r + 1) >> 2) {
+                self.shade_pixel(x, y, &mut inputs, None);
+            }
+        }
+    }
+
+    // S and T are s10.5 at the top left corner, their steps per pixel s5.10
+    fn texture_rectangle(&mut self, word: u64, st: u64) {
+        let r = ((word >> 44) & 0xfff) as i32;
+        let b = ((word >> 32) & 0xfff) as i32;
+        let tile = ((word >> 24) & 0x7) as usize;
+        let l = ((word >> 12) & 0xfff) as i32;
+        let t = (word & 0xfff) as i32;
+
+        let s = (st >> 48) as i16 as i32;
+        let t_start = (st >> 32) as i16 as i32;
+        let mut ds_dx = (st >> 16) as i16 as i32;
+        let dt_dy = st as i16 as i32;
+
+        let copy = self.other_modes & CYCLE_TYPE_MASK == OTHER_MODE_CYCLE_TYPE_COPY;
+
+        // Copy mode steps four texels at a time
+        let (rows, columns) = if copy {
+            ds_dx /= 4;
+            (
+                self.rows(t >> 2, (b >> 2) + 1),
+                self.columns(l >> 2, (r >> 2) + 1),
+            )
+        } else {
+            (
+                self.rows((t + 1) >> 2, (b + 1) >> 2),
+                self.columns((l + 1) >> 2, (r + 1) >> 2),
+            )
+        };
+
+        let mut inputs = Inputs::default();
+
+        for y in rows {
+            let t = t_start + ((dt_dy * (4 * y - t)) >> 7);
+
+            for x in columns.clone() {
+                let s = s + ((ds_dx * (4 * x - l)) >> 7);
+
+                if copy {
+                    let texel = self.sample(tile, s, t);
+
+                    if self.other_modes & OTHER_MODE_ALPHA_COMPARE_EN == 0 || texel[3] != 0 {
+                        self.write_pixel(x, y, texel);
+                    }
+                } else {
+                    self.sample_texels(tile, s, t, &mut inputs);
+                    self.shade_pixel(x, y, &mut inputs, None);
+                }
+            }
+        }
+    }
+
+    // Y is s11.2 and the edges s15.16. XH and XM start at the scanline YH is on and XL at YM, the
+    // major edge H goes from top to bottom.
+    fn triangle(&mut self, command: &[RdpCommand]) {
+        let word = command[0].0;
+        let op = word >> 56;
+
+        // Spans go right from the major edge when set
+        let left_major = word & (1 << 55) != 0;
+        let tile = ((word >> 48) & 0x7) as usize;
+
+        let y = |shift: u32| ((word >> shift) as i32) << 18 >> 18;
+        let (yl, ym, yh) = (y(32), y(16), y(0));
+
+        let edge = |word: RdpCommand| ((word.0 >> 32) as i32 as i64, word.0 as i32 as i64);
+        let (xl, dxl_dy) = edge(command[1]);
+        let (xh, dxh_dy) = edge(command[2]);
+        let (xm, dxm_dy) = edge(command[3]);
+
+        let mut words = &command[4..];
+        let mut take = |count: usize| {
+            let taken = &words[..count];
+            words = &words[count..];
+            taken
+        };
+
+        let shade = (op & 0x4 != 0).then(|| Coefficients::new(take(8)));
+        let texture = (op & 0x2 != 0).then(|| Coefficients::new(take(8)));
+        let z = (op & 0x1 != 0).then(|| Coefficients::z(take(2)));
+
+        let first_line = yh >> 2;
+        let mut inputs = Inputs::default();
+
+        // Fill mode triangles only step the edges
+        let fill = self.other_modes & CYCLE_TYPE_MASK == OTHER_MODE_CYCLE_TYPE_FILL;
+
+        for y in self.rows(first_line, (yl + 3) >> 2) {
+            // Pixels with their center inside the span on any of the four sub scanlines
+            let mut left = i32::MAX;
+            let mut right = i32::MIN;
+
+            for sub_line in 4 * y..4 * y + 4 {
+                if sub_line < yh || sub_line >= yl {
+                    continue;
+                }
+
+                let steps = (sub_line - 4 * first_line) as i64;
+                let major = xh + dxh_dy * steps / 4;
+                let minor = if sub_line < ym {
+                    xm + dxm_dy * steps / 4
+                } else {
+                    xl + dxl_dy * (sub_line - ym) as i64 / 4
+                };
+
+                let (start, end) = if left_major {
+                    (major, minor)
+                } else {
+                    (minor, major)
+                };
+
+                // The edges cross on a thin tip
+                if start > end {
+                    continue;
+                }
+
+                left = left.min(((start + 0x7fff) >> 16) as i32);
+                right = right.max(((end + 0x7fff) >> 16) as i32);
+            }
+
+            let lines = y - first_line;
+            let major = xh + dxh_dy * lines as i64;
+
+            for x in self.columns(left, right) {
+                if fill {
+                    self.fill_pixel(x, y);
+                    continue;
+                }
+
+                let offset = ((x as i64) << 16) - major;
+
+                inputs.shade = shade.map_or([0; 4], |shade| {
+                    shade.at(lines, offset).map(|c| (c >> 16).clamp(0, 0xff))
+                });
+
+                if let Some(texture) = texture {
+                    let [s, t, w, _] = texture.at(lines, offset);
+
+                    let (s, t) = if self.other_modes & OTHER_MODE_PERSP_TEX_EN != 0 {
+                        let w = (w as i64).max(1);
+                        (
+                            (s as i64 * 0x7fff / w) as i32,
+                            (t as i64 * 0x7fff / w) as i32,
+                        )
+                    } else {
+                        (s >> 16, t >> 16)
+                    };
+
+                    self.sample_texels(tile, s, t, &mut inputs);
+                }
+
+                let z = z.map(|z| (z.at(lines, offset)[0] >> 16).clamp(0, 0xffff));
+                self.shade_pixel(x, y, &mut inputs, z);
+            }
+        }
+    }
+
+    fn rows(&self, top: i32, bottom: i32) -> core::ops::Range<i32> {
+        top.max(self.scissor[1] >> 2)..bottom.min(self.scissor[3] >> 2)
+    }
+
+    fn columns(&self, left: i32, right: i32) -> core::ops::Range<i32> {
+        left.max(self.scissor[0] >> 2)
+            ..right
+                .min(self.scissor[2] >> 2)
+                .min(self.color_image.width as i32)
+    }
+
+    // 16 bit images take the upper half of the fill color on even pixels and the lower half on odd
+    fn fill_pixel(&mut self, x: i32, y: i32) {
+        let address = self.pixel_address(x, y);
+
+        match self.color_image.size {
+            SIZE_OF_PIXEL_16B => {
+                let color = if x % 2 == 0 {
+                    self.fill_color >> 16
+                } else {
+                    self.fill_color
+                };
+                self.write(address, &(color as u16).to_be_bytes());
+            }
+            SIZE_OF_PIXEL_32B => self.write(address, &self.fill_color.to_be_bytes()),
+            size => panic!("Unsupported color image size {}", size),
+        }
+    }
+
+    // Combines, tests and blends a pixel of a rect or triangle. Z is None for rects.
+    fn shade_pixel(&mut self, x: i32, y: i32, inputs: &mut Inputs, z: Option<i32>) {
+        let two_cycle = self.other_modes & CYCLE_TYPE_MASK == OTHER_MODE_CYCLE_TYPE_2_CYCLE;
+
+        // One cycle mode takes the second cycle of the combiner and the first of the blender
+        let combined = if two_cycle {
+            inputs.combined = self.combine_cycle(0, inputs);
+            self.combine_cycle(1, inputs)
+        } else {
+            self.combine_cycle(1, inputs)
+        };
+
+        if self.other_modes & OTHER_MODE_ALPHA_COMPARE_EN != 0
+            && combined[3] < (self.blend_color & 0xff) as i32
+        {
+            return;
+        }
+
+        let z = if self.other_modes & OTHER_MODE_Z_SOURCE_SEL != 0 {
+            Some(self.prim_z)
+        } else {
+            z
+        };
+
+        let z_address = self.z_image + 2 * (y as usize * self.color_image.width + x as usize);
+
+        if let Some(z) = z {
+            if self.other_modes & OTHER_MODE_Z_COMPARE_EN != 0 {
+                let mut memory_z = [0; 2];
+                self.read(z_address, &mut memory_z);
+
+                if z > u16::from_be_bytes(memory_z) as i32 {
+                    return;
+                }
+            }
+        }
+
+        let memory = self.read_pixel(x, y);
+        let mut color = self.blend_cycle(0, combined, [0; 3], memory, inputs.shade[3]);
+
+        if two_cycle {
+            color = self.blend_cycle(1, combined, color, memory, inputs.shade[3]);
+        }
+
+        self.write_pixel(x, y, [color[0], color[1], color[2], combined[3]]);
+
+        if let Some(z) = z {
+            if self.other_modes & OTHER_MODE_Z_UPDATE_EN != 0 {
+                self.write(z_address, &(z as u16).to_be_bytes());
+            }
+        }
+    }
+
+    // (a - b) * c + d for color and alpha
+    fn combine_cycle(&self, cycle: usize, inputs: &Inputs) -> [i32; 4] {
+        let mode = self.combine;
+        let field = |shift: u32, mask: u64| (mode >> shift) & mask;
+
+        let (a, b, c, d, a_alpha, b_alpha, c_alpha, d_alpha) = if cycle == 0 {
+            (
+                field(52, 0xf),
+                field(28, 0xf),
+                field(47, 0x1f),
+                field(15, 0x7),
+                field(44, 0x7),
+                field(12, 0x7),
+                field(41, 0x7),
+                field(9, 0x7),
+            )
+        } else {
+            (
+                field(37, 0xf),
+                field(24, 0xf),
+                field(32, 0x1f),
+                field(6, 0x7),
+                field(21, 0x7),
+                field(3, 0x7),
+                field(18, 0x7),
+                field(0, 0x7),
+            )
+        };
+
+        let prim = color_channels(self.prim_color);
+        let env = color_channels(self.env_color);
+
+        let source = |index: u64| match index {
+            0 => Some(inputs.combined),
+            1 => Some(inputs.texel0),
+            2 => Some(inputs.texel1),
+            3 => Some(prim),
+            4 => Some(inputs.shade),
+            5 => Some(env),
+            _ => None,
+        };
+        let one = [0xff; 4];
+
+        let a = source(a).unwrap_or(if a == 6 { one } else { [0; 4] });
+        let b = source(b).unwrap_or([0; 4]);
+        let c = source(c).unwrap_or_else(|| match c {
+            7..=12 => [source(c - 7).unwrap()[3]; 4],
+            14 => [self.prim_lod_fraction; 4],
+            _ => [0; 4],
+        });
+        let d = source(d).unwrap_or(if d == 6 { one } else { [0; 4] });
+
+        let alpha = |index: u64| match index {
+            6 => 0xff,
+            7 => 0,
+            index => source(index).unwrap()[3],
+        };
+
+        let c_alpha = match c_alpha {
+            0 => 0,
+            6 => self.prim_lod_fraction,
+            7 => 0,
+            index => source(index).unwrap()[3],
+        };
+
+        let combine = |a: i32, b: i32, c: i32, d: i32| (((a - b) * c + 0x80) >> 8) + d;
+
+        [
+            combine(a[0], b[0], c[0], d[0]).clamp(0, 0xff),
+            combine(a[1], b[1], c[1], d[1]).clamp(0, 0xff),
+            combine(a[2], b[2], c[2], d[2]).clamp(0, 0xff),
+            combine(alpha(a_alpha), alpha(b_alpha), c_alpha, alpha(d_alpha)).clamp(0, 0xff),
+        ]
+    }
+
+    // p * a + m * b, the last cycle only blends when forced
+    fn blend_cycle(
+        &self,
+        cycle: usize,
+        combined: [i32; 4],
+        first: [i32; 3],
+        memory: [i32; 4],
+        shade_alpha: i32,
+    ) -> [i32; 3] {
+        let modes = self.other_modes;
+        let shift = if cycle == 0 { 2 } else { 0 };
+        let field = |base: u32| ((modes >> (base + shift)) & 0x3) as usize;
+
+        let (p, a, m, b) = (field(28), field(24), field(20), field(16));
+
+        let fog = color_channels(self.fog_color);
+        let blend = color_channels(self.blend_color);
+
+        let color = |select: usize| match select {
+            0 if cycle == 0 => [combined[0], combined[1], combined[2]],
+            0 => first,
+            1 => [memory[0], memory[1], memory[2]],
+            2 => [blend[0], blend[1], blend[2]],
+            _ => [fog[0], fog[1], fog[2]],
+        };
+
+        let a = match a {
+            0 => combined[3],
+            1 => fog[3],
+            2 => shade_alpha,
+            _ => 0,
+        };
+
+        let b = match b {
+            0 => 0xff - a,
+            1 => memory[3],
+            2 => 0xff,
+            _ => 0,
+        };
+
+        let p = color(p);
+
+        // The first of two cycles always blends, like fog does
+        let last_cycle = cycle == 1 || modes & CYCLE_TYPE_MASK != OTHER_MODE_CYCLE_TYPE_2_CYCLE;
+
+        if last_cycle && modes & OTHER_MODE_FORCE_BLEND == 0 {
+            return p;
+        }
+
+        let m = color(m);
+
+        [0, 1, 2].map(|i| ((p[i] * a + m[i] * b) / 0xff).clamp(0, 0xff))
+    }
+
+    fn sample_texels(&self, tile: usize, s: i32, t: i32, inputs: &mut Inputs) {
+        inputs.texel0 = self.sample(tile, s, t);
+
+        if self.other_modes & CYCLE_TYPE_MASK == OTHER_MODE_CYCLE_TYPE_2_CYCLE {
+            inputs.texel1 = self.sample((tile + 1) % 8, s, t);
+        }
+    }
+
+    // S and T are s10.5, bilinear filtering weighs the four texels around them. Copy mode always
+    // takes the nearest texel.
+    fn sample(&self, tile: usize, s: i32, t: i32) -> [i32; 4] {
+        let desc = &self.tiles[tile];
+
+        let s = shift_coordinate(s, desc.shift_s) - (desc.sl << 3);
+        let t = shift_coordinate(t, desc.shift_t) - (desc.tl << 3);
+
+        if self.other_modes & OTHER_MODE_SAMPLE_TYPE == 0
+            || self.other_modes & CYCLE_TYPE_MASK == OTHER_MODE_CYCLE_TYPE_COPY
+        {
+            return self.texel(tile, s >> 5, t >> 5);
+        }
+
+        let (s0, t0) = (s >> 5, t >> 5);
+        let (fs, ft) = (s & 0x1f, t & 0x1f);
+
+        let texels = [
+            self.texel(tile, s0, t0),
+            self.texel(tile, s0 + 1, t0),
+            self.texel(tile, s0, t0 + 1),
+            self.texel(tile, s0 + 1, t0 + 1),
+        ];
+        let weights = [
+            (32 - fs) * (32 - ft),
+            fs * (32 - ft),
+            (32 - fs) * ft,
+            fs * ft,
+        ];
+
+        [0, 1, 2, 3].map(|channel| {
+            let sum = texels
+                .iter()
+                .zip(weights)
+                .map(|(texel, weight)| texel[channel] * weight)
+                .sum::<i32>();
+            (sum + 512) >> 10
+        })
+    }
+
+    // A texel at whole coordinates relative to the tile, wrapped as its clamp, mirror and mask say
+    fn texel(&self, tile: usize, s: i32, t: i32) -> [i32; 4] {
+        let desc = &self.tiles[tile];
+
+        let s = wrap_coordinate(
+            s,
+            desc.clamp_s,
+            desc.mirror_s,
+            desc.mask_s,
+            (desc.sh - desc.sl) >> 2,
+        ) as usize;
+        let t = wrap_coordinate(
+            t,
+            desc.clamp_t,
+            desc.mirror_t,
+            desc.mask_t,
+            (desc.th - desc.tl) >> 2,
+        ) as usize;
+
+        let row = desc.tmem * 8 + t * desc.line * 8;
+        let tmem = |offset: usize| self.tmem[offset % TMEM_SIZE] as i32;
+
+        let index = match desc.size {
+            SIZE_OF_PIXEL_4B => {
+                let byte = tmem(row + s / 2);
+                if s & 0x1 == 0 {
+                    byte >> 4
+                } else {
+                    byte & 0xf
+                }
+            }
+            SIZE_OF_PIXEL_8B => tmem(row + s),
+            SIZE_OF_PIXEL_16B => {
+                return match desc.format {
+                    FORMAT_IA => {
+                        let i = tmem(row + 2 * s);
+                        [i, i, i, tmem(row + 2 * s + 1)]
+                    }
+                    _ => rgba16_channels(tmem(row + 2 * s) << 8 | tmem(row + 2 * s + 1)),
+                };
+            }
+            _ => {
+                let offset = row + 2 * s;
+                return [
+                    tmem(offset),
+                    tmem(offset + 1),
+                    tmem(offset + TMEM_HIGH_HALF),
+                    tmem(offset + TMEM_HIGH_HALF + 1),
+                ];
+            }
+        };
+
+        if self.other_modes & OTHER_MODE_EN_TLUT != 0 {
+            let entry = if desc.size == SIZE_OF_PIXEL_4B {
+                (desc.palette as usize) << 4 | index as usize
+            } else {
+                index as usize
+            };
+
+            let offset = TMEM_TLUT + 2 * entry;
+            let value = tmem(offset) << 8 | tmem(offset + 1);
+
+            return if self.other_modes & OTHER_MODE_TLUT_TYPE != 0 {
+                let i = value >> 8;
+                [i, i, i, value & 0xff]
+            } else {
+                rgba16_channels(value)
+            };
+        }
+
+        match (desc.format, desc.size) {
+            (FORMAT_IA, SIZE_OF_PIXEL_4B) => {
+                let i = index >> 1;
+                let i = i << 5 | i << 2 | i >> 1;
+                [i, i, i, if index & 0x1 != 0 { 0xff } else { 0 }]
+            }
+            (FORMAT_IA, _) => {
+                let i = (index >> 4) * 0x11;
+                [i, i, i, (index & 0xf) * 0x11]
+            }
+            (_, SIZE_OF_PIXEL_4B) => [index * 0x11; 4],
+            _ => [index; 4],
+        }
+    }
+
+    fn pixel_address(&self, x: i32, y: i32) -> usize {
+        let bytes = if self.color_image.size == SIZE_OF_PIXEL_32B {
+            4
+        } else {
+            2
+        };
+
+        self.color_image.address + bytes * (y as usize * self.color_image.width + x as usize)
+    }
+
+    fn read_pixel(&self, x: i32, y: i32) -> [i32; 4] {
+        let address = self.pixel_address(x, y);
+
+        if self.color_image.size == SIZE_OF_PIXEL_32B {
+            let mut color = [0; 4];
+            self.read(address, &mut color);
+            color.map(|c| c as i32)
+        } else {
+            let mut color = [0; 2];
+            self.read(address, &mut color);
+            rgba16_channels(u16::from_be_bytes(color) as i32)
+        }
+    }
+
+    // Pixels are written with full coverage
+    fn write_pixel(&mut self, x: i32, y: i32, color: [i32; 4]) {
+        let address = self.pixel_address(x, y);
+
+        if self.color_image.size == SIZE_OF_PIXEL_32B {
+            self.write(
+                address,
+                &[color[0] as u8, color[1] as u8, color[2] as u8, 0xff],
+            );
+        } else {
+            let value = (color[0] >> 3) << 11 | (color[1] >> 3) << 6 | (color[2] >> 3) << 1 | 0x1;
+            self.write(address, &(value as u16).to_be_bytes());
+        }
+    }
+
+    fn region(&self, address: usize, len: usize) -> (usize, usize) {
+        self.memory
+            .iter()
+            .position(|(start, data)| address >= *start && address + len <= start + data.len())
+            .map(|region| (region, address - self.memory[region].0))
+            .unwrap_or_else(|| panic!("RDP access to unmapped memory at {:#010x}", address))
+    }
+
+    fn read(&self, address: usize, data: &mut [u8]) {
+        let (region, offset) = self.region(address, data.len());
+        data.copy_from_slice(&self.memory[region].1[offset..offset + data.len()]);
+    }
+
+    fn write(&mut self, address: usize, data: &[u8]) {
+        let (region, offset) = self.region(address, data.len());
+        self.memory[region].1[offset..offset + data.len()].copy_from_slice(data);
+    }
+}
+
+impl Default for SoftRdp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn image(word: u64) -> Image {
+    Image {
+        format: ((word >> 53) & 0x7) as u8,
+        size: ((word >> 51) & 0x3) as u8,
+        width: ((word >> 32) & 0x3ff) as usize + 1,
+        address: (word & 0xffff_ffff) as usize,
+    }
+}
+
+fn color_channels(color: u32) -> [i32; 4] {
+    color.to_be_bytes().map(|c| c as i32)
+}
+
+fn rgba16_channels(value: i32) -> [i32; 4] {
+    let expand = |c: i32| c << 3 | c >> 2;
+
+    [
+        expand((value >> 11) & 0x1f),
+        expand((value >> 6) & 0x1f),
+        expand((value >> 1) & 0x1f),
+        if value & 0x1 != 0 { 0xff } else { 0 },
+    ]
+}
+
+// Shifts 1 to 10 divide, 11 to 15 multiply by 2^(16 - shift)
+fn shift_coordinate(c: i32, shift: u8) -> i32 {
+    match shift {
+        0..=10 => c >> shift,
+        _ => c << (16 - shift),
+    }
+}
+
+fn wrap_coordinate(c: i32, clamp: bool, mirror: bool, mask: u8, max: i32) -> i32 {
+    let c = if clamp || mask == 0 {
+        c.clamp(0, max)
+    } else {
+        c
+    };
+
+    if mask == 0 {
+        return c;
+    }
+
+    let c = if mirror && (c >> mask) & 0x1 != 0 {
+        !c
+    } else {
+        c
+    };
+
+    c & ((1 << mask) - 1)
+}
+
+#[cfg(any(test, feature = "golden-images"))]
+pub fn write_png(path: &Path, width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder.write_header()?.write_image_data(rgba)?;
+
+    Ok(())
+}
+
+#[cfg(any(test, feature = "golden-images"))]
+// Size and RGBA8 pixels of a PNG written by write_png
+pub fn read_png(path: &Path) -> io::Result<(u32, u32, Vec<u8>)> {
+    let mut reader = png::Decoder::new(File::open(path)?).read_info()?;
+    let mut rgba = alloc::vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut rgba)?;
+
+    if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Expected an 8 bit RGBA image",
+        ));
+    }
+
+    rgba.truncate(info.buffer_size());
+
+    Ok((info.width, info.height, rgba))
+}
+
+// Draws with a command buffer into a 16 bit framebuffer, runs the commands and returns the RGBA8
+// image. Textures and render targets sampled by f have to be listed in textures.
+pub fn render(
+    width: i32,
+    height: i32,
+    textures: &[&[u8]],
+    f: impl FnOnce(&mut CommandBuffer),
+) -> Vec<u8> {
+    use crate::{framebuffer::ViBufferToken, BitDepth, VideoFormat, VideoMode};
+    use n64_math::Color;
+    use zerocopy::AsBytes;
+
+    let video_mode = VideoMode::Ntsc {
+        width,
+        height,
+        format: VideoFormat::default().with_bit_depth(BitDepth::Bpp16),
+    };
+
+    let mut framebuffer = alloc::vec![Color::new(0); (width * height) as usize];
+    let mut cache = super::CommandBufferCache::new(video_mode);
+
+    let mut cb = CommandBuffer::new(ViBufferToken(framebuffer.as_mut_ptr()), &mut cache);
+    cb.clear();
+    f(&mut cb);
+
+    let mut rdp = SoftRdp::new();
+    rdp.map(framebuffer.as_bytes());
+    rdp.map(cache.depth_buffer.as_bytes());
+    for texture in textures {
+        rdp.map(texture);
+    }
+    rdp.run(&cache.rdp.blocks);
+
+    rdp.color_image_rgba8(height as usize)
+}
+
+// A pixel of an image width pixels wide, as returned by render
+pub fn pixel(rgba: &[u8], width: i32, x: i32, y: i32) -> [u8; 4] {
+    let offset = 4 * (y * width + x) as usize;
+    rgba[offset..offset + 4].try_into().unwrap()
+}
+
+#[cfg(any(test, feature = "golden-images"))]
+// Compares with a PNG in golden_dir, UPDATE_GOLDEN=1 writes the image there instead
+pub fn assert_golden(golden_dir: &Path, name: &str, width: u32, height: u32, rgba: &[u8]) {
+    let path = golden_dir.join(name);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write_png(&path, width, height, rgba).unwrap();
+        return;
+    }
+
+    let golden = read_png(&path)
+        .unwrap_or_else(|error| panic!("Can not read {}: {}", path.display(), error));
+
+    if golden != (width, height, rgba.to_vec()) {
+        let actual = std::env::temp_dir().join(name);
+        write_png(&actual, width, height, rgba).unwrap();
+        panic!("{} differs from {}", actual.display(), path.display());
+    }
+}
+
+// 8x8 RGBA16 checkerboard of opaque white and blue squares of 2x2 texels
+fn checker_texture() -> crate::gfx::Texture<'static> {
+    use crate::gfx::{Texture, TextureFormat};
+
+    static DATA: [u8; 128] = {
+        let mut data = [0; 128];
+        let mut i = 0;
+        while i < 64 {
+            let color: u16 = if (i % 8 / 2 + i / 16) & 0x1 == 0 {
+                0xffff
+            } else {
+                0x003f
+            };
+            data[2 * i] = (color >> 8) as u8;
+            data[2 * i + 1] = color as u8;
+            i += 1;
+        }
+        data
+    };
+
+    Texture::from_bytes(8, 8, TextureFormat::Rgba16, &DATA)
+}
+
+#[test]
+fn soft_rdp_rects_match_golden() {
+    use crate::gfx::{
+        color_combiner_mode::{ColorCombinerMode, DSrc},
+        FillPipeline, Pipeline, TextureFilter,
+    };
+    use n64_math::{vec2, Color};
+
+    let texture = checker_texture();
+
+    let rgba = render(32, 24, &[texture.data], |cb| {
+        cb.set_fill_pipeline(
+            &FillPipeline::default().with_fill_color(Color::new(0b11111_00000_00000_1)),
+        )
+        .add_colored_rect(vec2(2.0, 2.0), vec2(12.0, 10.0));
+
+        // Stretched twice as wide
+        cb.set_pipeline(
+            &Pipeline::default()
+                .with_combiner_mode(ColorCombinerMode::single(DSrc::Texel))
+                .with_texture(Some(texture))
+                .with_texture_filter(TextureFilter::Point),
+        )
+        .add_textured_rect(vec2(14.0, 2.0), vec2(30.0, 10.0));
+
+        // Half transparent green over both
+        cb.set_pipeline(
+            &Pipeline::default()
+                .with_combiner_mode(ColorCombinerMode::single(DSrc::Primitive))
+                .with_prim_color(Some(0x00ff_0080))
+                .with_blend(true),
+        )
+        .add_colored_rect(vec2(8.0, 8.0), vec2(20.0, 20.0));
+    });
+
+    assert_eq!(pixel(&rgba, 32, 0, 0), [0, 0, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 32, 2, 2), [0xff, 0, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 32, 11, 7), [0xff, 0, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 32, 12, 7), [0, 0, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 32, 14, 2), [0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(pixel(&rgba, 32, 18, 2), [0, 0, 0xff, 0xff]);
+    assert_eq!(pixel(&rgba, 32, 10, 15), [0, 0x84, 0, 0xff]);
+
+    assert_golden(
+        Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden")),
+        "soft_rdp_rects.png",
+        32,
+        24,
+        &rgba,
+    );
+}
+
+#[test]
+fn soft_rdp_render_to_texture_is_sampled_later() {
+    use crate::gfx::{
+        color_combiner_mode::{ColorCombinerMode, DSrc},
+        FillPipeline, Pipeline, RenderTarget, TextureFilter,
+    };
+    use n64_math::{vec2, Color};
+
+    let mut target = RenderTarget::new(8, 8);
+    let target_data = unsafe { target.as_texture() }.data;
+
+    let rgba = render(24, 16, &[target_data], |cb| {
+        cb.render_to_texture(&mut target, |cb| {
+            cb.clear();
+            cb.set_fill_pipeline(
+                &FillPipeline::default().with_fill_color(Color::new(0b11111_00000_00000_1)),
+            )
+            .add_colored_rect(vec2(0.0, 0.0), vec2(4.0, 8.0));
+            cb.set_fill_pipeline(
+                &FillPipeline::default().with_fill_color(Color::new(0b00000_11111_00000_1)),
+            )
+            .add_colored_rect(vec2(4.0, 0.0), vec2(8.0, 8.0));
+        });
+
+        // Stretched twice as wide
+        cb.set_pipeline(
+            &Pipeline::default()
+                .with_combiner_mode(ColorCombinerMode::single(DSrc::Texel))
+                .with_texture(Some(unsafe { target.as_texture() }))
+                .with_texture_filter(TextureFilter::Point),
+        )
+        .add_textured_rect(vec2(8.0, 4.0), vec2(24.0, 12.0));
+    });
+
+    // The target drew nothing into the framebuffer
+    assert_eq!(pixel(&rgba, 24, 2, 2), [0, 0, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 6, 6), [0, 0, 0, 0xff]);
+
+    assert_eq!(pixel(&rgba, 24, 8, 4), [0xff, 0, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 15, 10), [0xff, 0, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 16, 4), [0, 0xff, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 21, 10), [0, 0xff, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 16, 13), [0, 0, 0, 0xff]);
+}
+
+#[test]
+fn soft_rdp_render_targets_do_not_see_the_frame_depth() {
+    use crate::gfx::{
+        color_combiner_mode::{ColorCombinerMode, DSrc},
+        Pipeline, RenderTarget, TextureFilter,
+    };
+    use n64_math::{vec2, Vec2};
+
+    let identity = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+    let quad = |cb: &mut CommandBuffer, size: f32, z: f32, color: u32| {
+        let list = cb.record_display_list(|cb| {
+            cb.set_pipeline(&Pipeline::default().with_z_compare(true).with_z_update(true))
+                .add_mesh_indexed(
+                    &[
+                        [0.0, 0.0, z],
+                        [size, 0.0, z],
+                        [size, size, z],
+                        [0.0, size, z],
+                    ],
+                    &[[0.0, 0.0]; 4],
+                    &[color; 4],
+                    &[[0, 1, 2], [0, 2, 3]],
+                    &identity,
+                );
+        });
+        cb.add_display_list(&list, Vec2::ZERO);
+    };
+
+    let mut target = RenderTarget::new(8, 8);
+    let target_data = unsafe { target.as_texture() }.data;
+
+    let rgba = render(24, 16, &[target_data], |cb| {
+        // Leaves depth in front of what the target draws
+        quad(cb, 24.0, 0.1, 0x0000_ffff);
+
+        cb.render_to_texture(&mut target, |cb| quad(cb, 8.0, 0.8, 0xff00_00ff));
+
+        cb.set_pipeline(
+            &Pipeline::default()
+                .with_combiner_mode(ColorCombinerMode::single(DSrc::Texel))
+                .with_texture(Some(unsafe { target.as_texture() }))
+                .with_texture_filter(TextureFilter::Point),
+        )
+        .add_textured_rect(vec2(16.0, 8.0), vec2(24.0, 16.0));
+    });
+
+    assert_eq!(pixel(&rgba, 24, 2, 12), [0, 0, 0xff, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 18, 10), [0xff, 0, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 22, 14), [0xff, 0, 0, 0xff]);
+}
+
+#[test]
+fn soft_rdp_triangles_match_golden() {
+    use crate::gfx::{
+        color_combiner_mode::{ColorCombinerMode, DSrc},
+        Pipeline, TextureFilter,
+    };
+
+    let texture = checker_texture();
+    let identity = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+
+    let rgba = render(48, 32, &[texture.data], |cb| {
+        let shaded = Pipeline::default().with_z_compare(true).with_z_update(true);
+
+        // Display lists keep the triangles on the CPU, untextured meshes would go to the RSP
+        let list = cb.record_display_list(|cb| {
+            cb.set_pipeline(&shaded).add_mesh_indexed(
+                &[[2.0, 2.0, 0.5], [30.0, 4.0, 0.5], [8.0, 28.0, 0.5]],
+                &[[0.0, 0.0]; 3],
+                &[0xff00_00ff, 0x00ff_00ff, 0x0000_ffff],
+                &[[0, 1, 2]],
+                &identity,
+            );
+
+            // Goes through the first triangle, only the part in front of it is drawn
+            cb.add_mesh_indexed(
+                &[[4.0, 14.0, 0.2], [26.0, 10.0, 0.8], [26.0, 20.0, 0.8]],
+                &[[0.0, 0.0]; 3],
+                &[0xffff_ffff; 3],
+                &[[0, 1, 2]],
+                &identity,
+            );
+        });
+        cb.add_display_list(&list, n64_math::Vec2::ZERO);
+
+        cb.set_pipeline(
+            &Pipeline::default()
+                .with_combiner_mode(ColorCombinerMode::single(DSrc::Texel))
+                .with_texture(Some(texture))
+                .with_texture_filter(TextureFilter::Point),
+        )
+        .add_mesh_indexed(
+            &[[30.0, 6.0, 0.5], [46.0, 6.0, 0.5], [46.0, 30.0, 0.5]],
+            &[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]],
+            &[0xffff_ffff; 3],
+            &[[0, 1, 2]],
+            &identity,
+        );
+    });
+
+    // Near the red corner, in front of the white triangle and behind it
+    assert!(pixel(&rgba, 48, 4, 4)[0] > 0xc0);
+    assert_eq!(pixel(&rgba, 48, 6, 14), [0xff, 0xff, 0xff, 0xff]);
+    assert_ne!(pixel(&rgba, 48, 16, 14), [0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(pixel(&rgba, 48, 45, 7), [0, 0, 0xff, 0xff]);
+
+    assert_golden(
+        Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden")),
+        "soft_rdp_triangles.png",
+        48,
+        32,
+        &rgba,
+    );
+}
+
+#[test]
+fn soft_rdp_fog_fades_with_w() {
+    use crate::gfx::{
+        color_combiner_mode::{
+            AAlphaSrc, ASrc, BAlphaSrc, BSrc, CAlphaSrc, CSrc, ColorCombinerMode, DAlphaSrc, DSrc,
+        },
+        FogRange, Pipeline,
+    };
+
+    // Vertex z goes to w and the positions are premultiplied by it
+    let perspective = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+        [0.0, 0.0, 0.5, 0.0],
+    ];
+
+    let rgba = render(32, 16, &[], |cb| {
+        let list = cb.record_display_list(|cb| {
+            cb.set_pipeline(
+                &Pipeline::default()
+                    .with_combiner_mode(ColorCombinerMode::one(
+                        ASrc::Zero,
+                        BSrc::Zero,
+                        CSrc::Zero,
+                        DSrc::Shade,
+                        AAlphaSrc::Zero,
+                        BAlphaSrc::Zero,
+                        CAlphaSrc::Zero,
+                        DAlphaSrc::One,
+                    ))
+                    .with_fog_color(Some(0x0000_ffff))
+                    .with_fog_range(Some(FogRange::new(1.0, 3.0))),
+            )
+            .add_mesh_indexed(
+                &[
+                    [2.0, 2.0, 1.0],
+                    [90.0, 6.0, 3.0],
+                    [90.0, 42.0, 3.0],
+                    [2.0, 14.0, 1.0],
+                ],
+                &[[0.0, 0.0]; 4],
+                &[0xffff_ffff; 4],
+                &[[0, 1, 2], [0, 2, 3]],
+                &perspective,
+            );
+        });
+        cb.add_display_list(&list, n64_math::Vec2::ZERO);
+    });
+
+    // White in front of the range and the fog color behind it
+    assert!(pixel(&rgba, 32, 3, 8)[0] > 0xe0);
+    assert!(pixel(&rgba, 32, 28, 8)[0] < 0x20 && pixel(&rgba, 32, 28, 8)[2] > 0xe0);
+    assert!(pixel(&rgba, 32, 10, 8)[0] > pixel(&rgba, 32, 20, 8)[0]);
+}
+
+#[test]
+fn soft_rdp_matches_the_conformance_table() {
+    use crate::graphics_emu::conformance::{
+        assert_conformance, conformance_texture, draw_conformance_cases, CONFORMANCE_WIDTH,
+    };
+
+    let texture = conformance_texture();
+
+    let rgba = render(CONFORMANCE_WIDTH, 8, &[texture.data], |cb| {
+        draw_conformance_cases!(cb, texture);
+    });
+
+    assert_conformance(&rgba, 8);
+}
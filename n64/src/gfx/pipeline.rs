@@ -1,5 +1,10 @@
-use super::{blend_mode::BlendMode, color_combiner_mode::ColorCombinerMode, Texture};
-use n64_math::Color;
+use super::{
+    blend_mode::BlendMode,
+    color_combiner_mode::{ColorCombinerMode, DSrc},
+    Texture,
+};
+use alloc::vec::Vec;
+use n64_math::{Color, Mat4, Vec3};
 
 #[derive(Copy, Clone)]
 pub struct FillPipeline {
@@ -59,18 +64,170 @@ pub enum CycleType {
     Two,
 }
 
+// Front faces have a positive signed area in screen space, the winding wgpu::FrontFace::Ccw
+// picks in the emulator
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TextureFilter {
+    Point,
+    Bilinear,
+    // Averages the 2x2 texels around the sample point, for textures drawn at half size
+    Average,
+}
+
+// Texture level of detail, picks mip levels from the texel to pixel ratio. Needs a mipmapped
+// texture and runs the pipeline in two cycle mode, see ColorCombinerMode::mipmapped.
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TextureLod {
+    Off,
+    Mipmap,
+    // Extrapolates away from level 1 when magnified to keep level 0 sharp
+    Sharpen,
+}
+
+// How texture coordinates are wrapped on one axis of the tile
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct TextureWrap {
+    // Clamps to the edges of the texture, always done when mask is 0
+    pub clamp: bool,
+    // Flips every other repeat, needs a mask
+    pub mirror: bool,
+    // Coordinates repeat every 2^mask texels, 0 disables repeating
+    pub mask: u8,
+    // Coordinates are divided by 2^shift before wrapping, negative shifts multiply
+    pub shift: i8,
+}
+
+impl TextureWrap {
+    pub const fn default() -> Self {
+        Self {
+            clamp: false,
+            mirror: false,
+            mask: 0,
+            shift: 0,
+        }
+    }
+
+    pub fn with_clamp(&self, clamp: bool) -> Self {
+        let mut res = *self;
+        res.clamp = clamp;
+        res
+    }
+
+    pub fn with_mirror(&self, mirror: bool) -> Self {
+        let mut res = *self;
+        res.mirror = mirror;
+        res
+    }
+
+    pub fn with_mask(&self, mask: u8) -> Self {
+        assert!(mask <= 10);
+        let mut res = *self;
+        res.mask = mask;
+        res
+    }
+
+    pub fn with_shift(&self, shift: i8) -> Self {
+        assert!((-5..=10).contains(&shift));
+        let mut res = *self;
+        res.shift = shift;
+        res
+    }
+
+    // Each mip level has half the texels, so coordinates are shifted once more and repeat one
+    // bit earlier
+    pub(crate) fn for_mip_level(&self, level: u8) -> Self {
+        Self {
+            mask: self.mask.saturating_sub(level),
+            shift: (self.shift + level as i8).min(10),
+            ..*self
+        }
+    }
+
+    // The shift as the RDP takes it, 11 to 15 shift left by 5 to 1
+    pub(crate) fn shift_bits(&self) -> u8 {
+        if self.shift < 0 {
+            (16 + self.shift) as u8
+        } else {
+            self.shift as u8
+        }
+    }
+}
+
+impl Default for TextureWrap {
+    fn default() -> Self {
+        Self::default()
+    }
+}
+
+// Clip space w over which meshes fade into the fog color, the view depth with a perspective
+// transform. The fog factor replaces the vertex alpha, so combiners used with fog should not take
+// their alpha from shade.
+#[derive(Copy, Clone, PartialEq)]
+pub struct FogRange {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl FogRange {
+    pub fn new(start: f32, end: f32) -> Self {
+        assert!(start < end);
+        Self { start, end }
+    }
+
+    // 0 in front of start to 0xff behind end
+    pub(crate) fn alpha(&self, w: f32) -> u32 {
+        let fog = ((w - self.start) / (self.end - self.start)).clamp(0.0, 1.0);
+        (fog * 255.0 + 0.5) as u32
+    }
+
+    // Vertex colors with the fog factor of each vertex in their alpha
+    pub(crate) fn shade_colors(
+        &self,
+        verts: &[[f32; 3]],
+        colors: &[u32],
+        transform: Mat4,
+    ) -> Vec<u32> {
+        verts
+            .iter()
+            .zip(colors)
+            .map(|(vert, color)| {
+                let w = (transform * Vec3::from(*vert).extend(1.0)).w;
+                (color & 0xffff_ff00) | self.alpha(w)
+            })
+            .collect()
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Pipeline {
     pub cycle_type: CycleType,
     pub color_combiner_mode: ColorCombinerMode,
     pub blend_mode: BlendMode,
+    pub cull_mode: CullMode,
 
     pub texture: Option<Texture<'static>>,
+    pub texture_filter: TextureFilter,
+    pub texture_lod: TextureLod,
+    pub texture_wrap_s: TextureWrap,
+    pub texture_wrap_t: TextureWrap,
+    // Interpolates texture coordinates of triangles with 1/w, texture rects must have it off
+    pub perspective_correction: bool,
 
     pub prim_color: Option<u32>,
     pub env_color: Option<u32>,
     pub blend_color: Option<u32>,
     pub fog_color: Option<u32>,
+    pub fog_range: Option<FogRange>,
 
     pub blend: bool,
     pub z_mode: ZMode,
@@ -85,11 +242,18 @@ impl Pipeline {
             cycle_type: CycleType::One,
             color_combiner_mode: ColorCombinerMode::default(),
             blend_mode: BlendMode::default(),
+            cull_mode: CullMode::None,
             prim_color: None,
             env_color: None,
             blend_color: None,
             fog_color: None,
+            fog_range: None,
             texture: None,
+            texture_filter: TextureFilter::Bilinear,
+            texture_lod: TextureLod::Off,
+            texture_wrap_s: TextureWrap::default(),
+            texture_wrap_t: TextureWrap::default(),
+            perspective_correction: false,
             blend: false,
             z_mode: ZMode::Opaque,
             z_src: ZSrc::Pixel,
@@ -116,12 +280,48 @@ impl Pipeline {
         res
     }
 
+    pub fn with_cull_mode(&self, cull_mode: CullMode) -> Self {
+        let mut res = *self;
+        res.cull_mode = cull_mode;
+        res
+    }
+
     pub fn with_texture(&self, texture: Option<Texture<'static>>) -> Self {
         let mut res = *self;
         res.texture = texture;
         res
     }
 
+    pub fn with_texture_filter(&self, texture_filter: TextureFilter) -> Self {
+        let mut res = *self;
+        res.texture_filter = texture_filter;
+        res
+    }
+
+    pub fn with_texture_lod(&self, texture_lod: TextureLod) -> Self {
+        let mut res = *self;
+        res.texture_lod = texture_lod;
+        res
+    }
+
+    pub fn with_texture_wrap_s(&self, texture_wrap_s: TextureWrap) -> Self {
+        let mut res = *self;
+        res.texture_wrap_s = texture_wrap_s;
+        res
+    }
+
+    pub fn with_texture_wrap_t(&self, texture_wrap_t: TextureWrap) -> Self {
+        let mut res = *self;
+        res.texture_wrap_t = texture_wrap_t;
+        res
+    }
+
+    pub fn with_perspective_correction(&self, perspective_correction: bool) -> Self {
+        let mut res = *self;
+        res.perspective_correction = perspective_correction;
+        res
+    }
+
     pub fn with_prim_color(&self, prim_color: Option<u32>) -> Self {
         let mut res = *self;
         res.prim_color = prim_color;
@@ -146,6 +346,12 @@ impl Pipeline {
         res
     }
 
+    pub fn with_fog_range(&self, fog_range: Option<FogRange>) -> Self {
+        let mut res = *self;
+        res.fog_range = fog_range;
+        res
+    }
+
     pub fn with_blend(&self, blend: bool) -> Self {
         let mut res = *self;
         res.blend = blend;
@@ -169,6 +375,37 @@ impl Pipeline {
         res.z_compare = z_compare;
         res
     }
+
+    // Texture LOD samples the next mip level in the second cycle and fog takes the first cycle
+    // of the blender
+    pub(crate) fn is_two_cycle(&self) -> bool {
+        self.cycle_type == CycleType::Two || self.has_lod() || self.fog_range.is_some()
+    }
+
+    // When only fog needs two cycles the second combiner cycle passes the first one through,
+    // instead of repeating a formula that would read texels from a tile that is never set up
+    pub(crate) fn effective_combiner_mode(&self) -> ColorCombinerMode {
+        if self.fog_range.is_some() && self.cycle_type == CycleType::One && !self.has_lod() {
+            ColorCombinerMode::two(
+                self.color_combiner_mode,
+                ColorCombinerMode::single(DSrc::Combined),
+            )
+        } else {
+            self.color_combiner_mode
+        }
+    }
+
+    fn has_lod(&self) -> bool {
+        self.texture.is_some() && self.texture_lod != TextureLod::Off
+    }
+
+    // What is blended with memory moves to the second cycle when fog is on
+    pub(crate) fn effective_blend_mode(&self) -> BlendMode {
+        match self.fog_range {
+            Some(_) => self.blend_mode.with_fog(),
+            None => self.blend_mode,
+        }
+    }
 }
 
 impl Default for Pipeline {
@@ -176,3 +413,19 @@ impl Default for Pipeline {
         Self::default()
     }
 }
+
+#[test]
+fn fog_passes_the_one_cycle_combiner_through_the_second_cycle() {
+    let pipeline = Pipeline::default()
+        .with_combiner_mode(ColorCombinerMode::single(DSrc::Texel))
+        .with_fog_range(Some(FogRange::new(1.0, 2.0)));
+    let mode = pipeline.effective_combiner_mode();
+
+    assert!(pipeline.is_two_cycle());
+    assert!(matches!(mode.d_0, DSrc::Texel));
+    assert!(matches!(mode.d_1, DSrc::Combined));
+
+    // Pipelines asking for two cycles keep their second cycle
+    let two = pipeline.with_cycle_type(CycleType::Two);
+    assert!(matches!(two.effective_combiner_mode().d_1, DSrc::Texel));
+}
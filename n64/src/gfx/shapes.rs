@@ -0,0 +1,154 @@
+// Outlines and thick lines split into fill rects and triangles, shared by both command buffers
+use core::f32::consts::TAU;
+use n64_math::{vec2, Vec2};
+
+// Corners of a line width pixels thick, going around. Caps extend the ends by half the width so
+// the segments of a polyline overlap at the joints.
+pub(crate) fn line_quad(start: Vec2, end: Vec2, width: f32, caps: [bool; 2]) -> Option<[Vec2; 4]> {
+    let direction = (end - start).normalize_or_zero();
+
+    if direction == Vec2::ZERO || width <= 0.0 {
+        return None;
+    }
+
+    let half_width = 0.5 * width;
+    let side = direction.perp() * half_width;
+    let start = start - direction * if caps[0] { half_width } else { 0.0 };
+    let end = end + direction * if caps[1] { half_width } else { 0.0 };
+
+    Some([start - side, end - side, end + side, start + side])
+}
+
+// Segment count that keeps the outline within about half a pixel of the circle
+pub(crate) fn circle_segments(radius: f32) -> usize {
+    (libm::ceilf(TAU * libm::sqrtf(radius.max(0.0))) as usize).clamp(8, 64)
+}
+
+// Corners of the ring segments, inner and outer point for every angle
+pub(crate) fn circle_ring(
+    center: Vec2,
+    radius: f32,
+    width: f32,
+) -> impl Iterator<Item = [Vec2; 4]> {
+    let segments = circle_segments(radius);
+    let outer = radius + 0.5 * width;
+    let inner = (radius - 0.5 * width).max(0.0);
+
+    let point = move |segment: usize| {
+        let angle = TAU * segment as f32 / segments as f32;
+        vec2(libm::cosf(angle), libm::sinf(angle))
+    };
+
+    (0..segments).map(move |segment| {
+        let (a, b) = (point(segment), point(segment + 1));
+        [
+            center + a * inner,
+            center + a * outer,
+            center + b * outer,
+            center + b * inner,
+        ]
+    })
+}
+
+// A square of whole pixels around pos, at least one pixel big
+pub(crate) fn point_rect(pos: Vec2, size: f32) -> (Vec2, Vec2) {
+    let size = size.round().max(1.0);
+    let upper_left = (pos - Vec2::splat(0.5 * size)).round();
+
+    (upper_left, upper_left + Vec2::splat(size))
+}
+
+// Top and bottom span the whole width, the sides fit in between
+pub(crate) fn rect_outline(upper_left: Vec2, lower_right: Vec2, width: f32) -> [(Vec2, Vec2); 4] {
+    let size = lower_right - upper_left;
+    let width = width.min(0.5 * size.x).min(0.5 * size.y).max(0.0);
+
+    [
+        (upper_left, vec2(lower_right.x, upper_left.y + width)),
+        (vec2(upper_left.x, lower_right.y - width), lower_right),
+        (
+            vec2(upper_left.x, upper_left.y + width),
+            vec2(upper_left.x + width, lower_right.y - width),
+        ),
+        (
+            vec2(lower_right.x - width, upper_left.y + width),
+            vec2(lower_right.x, lower_right.y - width),
+        ),
+    ]
+}
+
+#[test]
+fn rect_outline_covers_the_border_once() {
+    let rects = rect_outline(vec2(2.0, 3.0), vec2(12.0, 9.0), 2.0);
+
+    for y in 0..12 {
+        for x in 0..16 {
+            let p = vec2(x as f32 + 0.5, y as f32 + 0.5);
+            let hits = rects
+                .iter()
+                .filter(|(min, max)| p.cmpge(*min).all() && p.cmplt(*max).all())
+                .count();
+            let border = (2..12).contains(&x)
+                && (3..9).contains(&y)
+                && !((4..10).contains(&x) && (5..7).contains(&y));
+
+            assert_eq!(hits, border as usize, "{} {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn points_cover_whole_pixels() {
+    assert_eq!(
+        point_rect(vec2(3.0, 3.0), 1.0),
+        (vec2(3.0, 3.0), vec2(4.0, 4.0))
+    );
+    assert_eq!(
+        point_rect(vec2(3.0, 3.0), 2.0),
+        (vec2(2.0, 2.0), vec2(4.0, 4.0))
+    );
+    assert_eq!(
+        point_rect(vec2(3.2, 2.9), 0.1),
+        (vec2(3.0, 2.0), vec2(4.0, 3.0))
+    );
+}
+
+#[test]
+fn shapes_use_the_fill_color() {
+    use super::{
+        soft_rdp::{pixel, render},
+        FillPipeline,
+    };
+    use n64_math::Color;
+
+    let rgba = render(32, 32, &[], |cb| {
+        cb.set_fill_pipeline(&FillPipeline::default().with_fill_color(Color::new(0xffff)))
+            .add_rect_outline(vec2(2.0, 2.0), vec2(14.0, 10.0), 2.0)
+            .add_line(vec2(16.0, 4.0), vec2(30.0, 4.0), 2.0)
+            .add_polyline(&[vec2(2.0, 20.0), vec2(10.0, 20.0), vec2(10.0, 28.0)], 2.0)
+            .add_circle(vec2(22.0, 22.0), 6.0, 2.0)
+            .add_point(vec2(30.0, 30.0), 2.0);
+    });
+
+    let lit = |x, y| pixel(&rgba, 32, x, y)[0] == 0xff;
+
+    // Outline without the inside
+    assert!(lit(2, 2) && lit(13, 9) && lit(3, 5) && lit(12, 5));
+    assert!(!lit(5, 5) && !lit(11, 7) && !lit(14, 10));
+
+    // Two pixels thick around y 4
+    assert!(lit(16, 3) && lit(29, 4));
+    assert!(!lit(20, 2) && !lit(20, 5) && !lit(30, 4));
+
+    // The joint of the polyline is filled
+    assert!(lit(2, 19) && lit(9, 20) && lit(10, 19) && lit(10, 20) && lit(10, 27));
+    assert!(!lit(8, 24) && !lit(12, 20));
+
+    // A ring around the center
+    assert!(lit(22, 16) && lit(28, 22) && lit(16, 22) && lit(22, 27));
+    assert!(!lit(22, 22) && !lit(20, 20) && !lit(22, 13));
+
+    // Two by two pixels
+    assert!(lit(29, 29) && lit(30, 30));
+    assert!(!lit(28, 29) && !lit(31, 30));
+}
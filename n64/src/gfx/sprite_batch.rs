@@ -0,0 +1,198 @@
+use super::{Pipeline, Texture};
+use alloc::vec::Vec;
+use n64_math::{vec2, Vec2};
+
+#[derive(Copy, Clone)]
+pub struct Sprite {
+    pub texture: Texture<'static>,
+    // In pixels
+    pub center: Vec2,
+    pub size: Vec2,
+    // Radians, clockwise on screen
+    pub rotation: f32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    // Prim color of the sprite, the pipeline of the batch decides how it is combined
+    pub tint: u32,
+    // Part of the texture that is drawn, like a frame of an atlas
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+}
+
+impl Sprite {
+    pub const fn new(texture: Texture<'static>, center: Vec2, size: Vec2) -> Self {
+        Self {
+            texture,
+            center,
+            size,
+            rotation: 0.0,
+            flip_x: false,
+            flip_y: false,
+            tint: 0xffff_ffff,
+            uv_min: Vec2::ZERO,
+            uv_max: Vec2::ONE,
+        }
+    }
+
+    pub fn with_rotation(&self, rotation: f32) -> Self {
+        let mut res = *self;
+        res.rotation = rotation;
+        res
+    }
+
+    pub fn with_flip(&self, flip_x: bool, flip_y: bool) -> Self {
+        let mut res = *self;
+        res.flip_x = flip_x;
+        res.flip_y = flip_y;
+        res
+    }
+
+    pub fn with_tint(&self, tint: u32) -> Self {
+        let mut res = *self;
+        res.tint = tint;
+        res
+    }
+
+    pub fn with_uv_rect(&self, uv_min: Vec2, uv_max: Vec2) -> Self {
+        let mut res = *self;
+        res.uv_min = uv_min;
+        res.uv_max = uv_max;
+        res
+    }
+
+    // UVs at the upper left and lower right corner before rotating
+    pub(crate) fn uv_corners(&self) -> (Vec2, Vec2) {
+        let (mut start, mut end) = (self.uv_min, self.uv_max);
+
+        if self.flip_x {
+            (start.x, end.x) = (end.x, start.x);
+        }
+
+        if self.flip_y {
+            (start.y, end.y) = (end.y, start.y);
+        }
+
+        (start, end)
+    }
+
+    // Clockwise from the upper left, with their UVs
+    pub(crate) fn corners(&self) -> [(Vec2, Vec2); 4] {
+        let (sin, cos) = (libm::sinf(self.rotation), libm::cosf(self.rotation));
+        let (uv_start, uv_end) = self.uv_corners();
+        let half_size = 0.5 * self.size;
+
+        [
+            (vec2(-1.0, -1.0), uv_start),
+            (vec2(1.0, -1.0), vec2(uv_end.x, uv_start.y)),
+            (vec2(1.0, 1.0), uv_end),
+            (vec2(-1.0, 1.0), vec2(uv_start.x, uv_end.y)),
+        ]
+        .map(|(corner, uv)| {
+            let p = corner * half_size;
+            (
+                self.center + vec2(cos * p.x - sin * p.y, sin * p.x + cos * p.y),
+                uv,
+            )
+        })
+    }
+}
+
+// Sprites drawn with one pipeline, the texture and prim color are set per sprite
+pub struct SpriteBatch {
+    pub(crate) pipeline: Pipeline,
+    pub(crate) sprites: Vec<Sprite>,
+}
+
+impl SpriteBatch {
+    pub fn new(pipeline: Pipeline) -> Self {
+        Self {
+            pipeline,
+            sprites: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, sprite: Sprite) -> &mut Self {
+        self.sprites.push(sprite);
+        self
+    }
+
+    // Sprites of a texture stay in the order they were added
+    pub(crate) fn sort(&mut self) {
+        self.sprites
+            .sort_by_key(|sprite| sprite.texture.data.as_ptr() as usize);
+    }
+}
+
+#[test]
+fn sprite_corners_rotate_clockwise_and_flip_uvs() {
+    static DATA: [n64_math::Color; 4] = [n64_math::Color::new(0); 4];
+    let texture = Texture::new(2, 2, &DATA);
+
+    let sprite = Sprite::new(texture, vec2(10.0, 10.0), vec2(4.0, 2.0))
+        .with_rotation(core::f32::consts::FRAC_PI_2)
+        .with_flip(true, false)
+        .with_uv_rect(vec2(0.25, 0.0), vec2(0.75, 0.5));
+
+    let corners = sprite.corners();
+
+    // The upper left corner turns to the upper right
+    assert!(corners[0].0.abs_diff_eq(vec2(11.0, 8.0), 1e-5));
+    assert!(corners[2].0.abs_diff_eq(vec2(9.0, 12.0), 1e-5));
+    assert_eq!(corners[0].1, vec2(0.75, 0.0));
+    assert_eq!(corners[2].1, vec2(0.25, 0.5));
+}
+
+#[test]
+fn sprite_batch_flips_and_cuts_out() {
+    use super::{
+        color_combiner_mode::{ColorCombinerMode, DSrc},
+        soft_rdp::{pixel, render},
+        TextureFilter, TextureFormat,
+    };
+
+    // Red, green, blue and white columns
+    static DATA: [u8; 16] = [
+        0xf8, 0x01, 0x07, 0xc1, 0x00, 0x3f, 0xff, 0xff, 0xf8, 0x01, 0x07, 0xc1, 0x00, 0x3f, 0xff,
+        0xff,
+    ];
+    let texture = Texture::from_bytes(4, 2, TextureFormat::Rgba16, &DATA);
+
+    let mut batch = SpriteBatch::new(
+        Pipeline::default()
+            .with_combiner_mode(ColorCombinerMode::single(DSrc::Texel))
+            .with_texture_filter(TextureFilter::Point),
+    );
+
+    batch
+        .add(Sprite::new(texture, vec2(4.0, 2.0), vec2(4.0, 2.0)).with_flip(true, false))
+        .add(
+            Sprite::new(texture, vec2(10.0, 2.0), vec2(2.0, 2.0))
+                .with_uv_rect(vec2(0.5, 0.0), vec2(1.0, 1.0)),
+        )
+        .add(
+            Sprite::new(texture, vec2(20.0, 8.0), vec2(4.0, 2.0))
+                .with_rotation(core::f32::consts::FRAC_PI_2),
+        );
+
+    let rgba = render(24, 12, &[texture.data], |cb| {
+        cb.add_sprite_batch(&mut batch);
+    });
+
+    assert!(batch.sprites.is_empty());
+
+    assert_eq!(pixel(&rgba, 24, 2, 1), [0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 3, 1), [0, 0, 0xff, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 4, 2), [0, 0xff, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 5, 2), [0xff, 0, 0, 0xff]);
+
+    assert_eq!(pixel(&rgba, 24, 9, 1), [0, 0, 0xff, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 10, 2), [0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 11, 2), [0, 0, 0, 0xff]);
+
+    // Turned upright, two pixels wide and four high
+    assert_ne!(pixel(&rgba, 24, 19, 6), [0, 0, 0, 0xff]);
+    assert_ne!(pixel(&rgba, 24, 20, 9), [0, 0, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 18, 8), [0, 0, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 21, 8), [0, 0, 0, 0xff]);
+    assert_eq!(pixel(&rgba, 24, 20, 5), [0, 0, 0, 0xff]);
+}
@@ -1,5 +1,9 @@
+use crate::rom::RomAsset;
+use alloc::{boxed::Box, vec};
+use core::{ptr::NonNull, slice};
 use n64_math::Color;
-use zerocopy::LayoutVerified;
+use n64_types::{mip_level_size, TextureFormat};
+use zerocopy::{AsBytes, LayoutVerified};
 
 #[repr(align(8))]
 pub struct TextureAlignment;
@@ -8,16 +12,72 @@ pub struct TextureAlignment;
 pub struct Texture<'a> {
     pub width: i32,
     pub height: i32,
-    pub data: &'a [Color],
+    pub format: TextureFormat,
+    pub data: &'a [u8],
+    pub palette: Option<&'a [Color]>,
+    // 1 for textures without mipmaps
+    pub mip_levels: u8,
 }
 
 impl<'a> Texture<'a> {
+    // Levels use render tiles 0 to 6, tile 7 is kept for loads
+    pub const MAX_MIP_LEVELS: u8 = 7;
+
     #[inline]
     pub fn new(width: i32, height: i32, data: &'a [Color]) -> Self {
+        Self::from_bytes(width, height, TextureFormat::Rgba16, data.as_bytes())
+    }
+
+    #[inline]
+    pub fn from_bytes(width: i32, height: i32, format: TextureFormat, data: &'a [u8]) -> Self {
+        assert!(data.len() >= format.size_in_bytes(width, height));
+
         Self {
             width,
             height,
+            format,
             data,
+            palette: None,
+            mip_levels: 1,
+        }
+    }
+
+    // The data has to hold the whole chain, laid out as described by mip_level_offset
+    pub fn with_mip_levels(&self, mip_levels: u8) -> Self {
+        assert!(mip_levels > 0 && mip_levels <= Self::MAX_MIP_LEVELS);
+        assert!(
+            self.data.len()
+                >= self
+                    .format
+                    .mip_level_offset(self.width, self.height, mip_levels)
+        );
+
+        Self {
+            mip_levels,
+            ..*self
+        }
+    }
+
+    pub fn mip_level(&self, level: u8) -> Texture<'a> {
+        assert!(level < self.mip_levels);
+
+        let offset = self.format.mip_level_offset(self.width, self.height, level);
+
+        Self {
+            width: mip_level_size(self.width, level),
+            height: mip_level_size(self.height, level),
+            data: &self.data[offset..],
+            mip_levels: 1,
+            ..*self
+        }
+    }
+
+    pub fn with_palette(&self, palette: &'a [Color]) -> Self {
+        assert!(palette.len() >= self.format.palette_size());
+
+        Self {
+            palette: Some(palette),
+            ..*self
         }
     }
 }
@@ -40,42 +100,129 @@ impl<'a> TextureMut<'a> {
 
     #[inline]
     pub fn into_texture(self) -> Texture<'a> {
-        Texture {
-            width: self.width,
-            height: self.height,
-            data: self.data,
+        Texture::new(self.width, self.height, self.data)
+    }
+}
+
+// Color images have to start 64 byte aligned, which also covers the 8 bytes texture loads need
+#[repr(C, align(64))]
+#[derive(Copy, Clone)]
+struct RenderTargetBlock([Color; 32]);
+
+// Memory the RDP draws into and later samples. Pipelines hold on to 'static textures, so the
+// buffer is allocated once and never freed, create targets up front and reuse them every frame.
+pub struct RenderTarget {
+    pub width: i32,
+    pub height: i32,
+    data: NonNull<Color>,
+    len: usize,
+}
+
+impl RenderTarget {
+    pub fn new(width: i32, height: i32) -> Self {
+        let len = (width * height) as usize;
+        let blocks = vec![RenderTargetBlock([Color::new(0x0001); 32]); (len + 31) / 32];
+        let blocks = Box::leak(blocks.into_boxed_slice());
+
+        Self {
+            width,
+            height,
+            data: NonNull::new(blocks.as_mut_ptr() as *mut Color).unwrap(),
+            len,
         }
     }
+
+    #[inline]
+    pub fn as_texture_mut(&mut self) -> TextureMut<'_> {
+        let data = unsafe { slice::from_raw_parts_mut(self.data.as_ptr(), self.len) };
+
+        TextureMut::new(self.width, self.height, data)
+    }
+
+    /// Samples what was rendered into the target.
+    ///
+    /// # Safety
+    ///
+    /// The texture aliases the target. It must not be read, on the CPU or by a command buffer in
+    /// flight, while the target is drawn into again through `as_texture_mut` or
+    /// `render_to_texture`.
+    #[inline]
+    pub unsafe fn as_texture(&self) -> Texture<'static> {
+        let data = slice::from_raw_parts(self.data.as_ptr() as *const Color, self.len);
+
+        Texture::new(self.width, self.height, data)
+    }
 }
 
 #[derive(Copy, Clone)]
 pub struct StaticTexture {
     pub width: i32,
     pub height: i32,
-    // Should be 8 byte aligned
-    pub data: &'static [u8],
+    pub format: TextureFormat,
+    pub asset: RomAsset,
+    pub palette: Option<RomAsset>,
+    pub mip_levels: u8,
 }
 
 impl StaticTexture {
     #[inline]
-    pub const fn from_static(width: i32, height: i32, data: &'static [u8]) -> Self {
+    pub const fn from_rom(width: i32, height: i32, asset: RomAsset) -> Self {
         Self {
             width,
             height,
-            data,
+            format: TextureFormat::Rgba16,
+            asset,
+            palette: None,
+            mip_levels: 1,
         }
     }
 
+    // Const so generated statics can describe other formats
+    #[inline]
+    pub const fn with_format(self, format: TextureFormat) -> Self {
+        Self { format, ..self }
+    }
+
+    #[inline]
+    pub const fn with_palette(self, palette: RomAsset) -> Self {
+        Self {
+            palette: Some(palette),
+            ..self
+        }
+    }
+
+    #[inline]
+    pub const fn with_mip_levels(self, mip_levels: u8) -> Self {
+        Self { mip_levels, ..self }
+    }
+
+    // Streams the texture in from the rom the first time it is used
     #[inline]
     pub fn as_texture(self) -> Texture<'static> {
-        let data = LayoutVerified::<_, [Color]>::new_slice_unaligned(self.data)
-            .unwrap()
-            .into_slice();
+        let texture = Texture::from_bytes(self.width, self.height, self.format, self.asset.load())
+            .with_mip_levels(self.mip_levels);
 
-        Texture {
-            width: self.width,
-            height: self.height,
-            data,
+        if let Some(palette) = self.palette {
+            texture.with_palette(
+                LayoutVerified::<_, [Color]>::new_slice_unaligned(palette.load())
+                    .unwrap()
+                    .into_slice(),
+            )
+        } else {
+            texture
         }
     }
 }
+
+#[test]
+fn mip_levels_are_8_byte_aligned() {
+    // 8x4 CI4 takes 16 bytes, 4x2 takes 4 padded to 8, 2x1 takes 1
+    assert_eq!(TextureFormat::Ci4.mip_level_offset(8, 4, 1), 16);
+    assert_eq!(TextureFormat::Ci4.mip_level_offset(8, 4, 2), 24);
+    assert_eq!(TextureFormat::Ci4.mip_level_offset(8, 4, 3), 32);
+
+    let data = [0u8; 32];
+    let texture = Texture::from_bytes(8, 4, TextureFormat::Ci4, &data).with_mip_levels(3);
+    let level = texture.mip_level(2);
+    assert_eq!((level.width, level.height, level.data.len()), (2, 1, 8));
+}
@@ -1,8 +1,9 @@
-use crate::{current_time_us, framebuffer::Framebuffer, VideoMode};
+use crate::{current_time_us, framebuffer::Framebuffer, BitDepth, VideoMode};
 use colored_rect::ColoredRect;
 use copy_tex::CopyTex;
+use dst_texture::DstTexture;
 use mesh::Mesh;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::num::NonZeroU32;
 use std::process::exit;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -21,14 +22,19 @@ use winit::{
 use zerocopy::{AsBytes, FromBytes};
 
 pub(crate) mod colored_rect;
+#[cfg(test)]
+pub(crate) mod conformance;
 pub(crate) mod copy_tex;
 pub(crate) mod dst_texture;
 pub(crate) mod mesh;
+pub(crate) mod texture_decode;
 pub(crate) mod textured_rect;
 
 mod shader;
 
-const SCALE: i32 = 2;
+// Size of the picture the VI puts out, lower resolution modes are scaled up to fill it
+const OUTPUT_WIDTH: i32 = 640;
+const OUTPUT_HEIGHT: i32 = 480;
 
 #[repr(C)]
 #[derive(Clone, Copy, AsBytes, FromBytes)]
@@ -62,19 +68,29 @@ thread_local! {
     static EVENT_LOOP: Mutex<EventLoop<()>> = Mutex::new(EventLoop::new());
 }
 
+pub(crate) enum Output {
+    Window {
+        window: Window,
+        surface: wgpu::Surface,
+        surface_config: wgpu::SurfaceConfiguration,
+    },
+    // Headless runs present into a texture of the same size as the window would have
+    Offscreen {
+        tex: wgpu::Texture,
+    },
+}
+
 pub struct Graphics {
     pub(crate) video_mode: VideoMode,
     pub(crate) keys_down: HashSet<VirtualKeyCode>,
 
-    _window: Window,
     _instance: wgpu::Instance,
     _adapter: wgpu::Adapter,
 
     pub(crate) device: Arc<wgpu::Device>,
     pub(crate) queue: wgpu::Queue,
 
-    pub(crate) surface: wgpu::Surface,
-    pub(crate) surface_config: wgpu::SurfaceConfiguration,
+    pub(crate) output: Output,
 
     pub(crate) quad_vertex_buf: wgpu::Buffer,
     pub(crate) quad_index_buf: wgpu::Buffer,
@@ -84,27 +100,68 @@ pub struct Graphics {
     pub(crate) textured_rect: TexturedRect,
     pub(crate) mesh: Mesh,
 
+    // Target of the last submitted command buffer
+    pub(crate) last_frame: Option<DstTexture>,
+    // Offscreen textures of render targets, by address of the texture data
+    pub(crate) render_targets: HashMap<usize, DstTexture>,
+
     pub(crate) device_poll_thread_run: Arc<AtomicBool>,
     pub(crate) device_poll_thread: Option<thread::JoinHandle<()>>,
 
     frame_counter: usize,
 }
 
+// Falls back to a software adapter, like lavapipe or WARP, when there is no GPU
+fn request_device(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface>,
+) -> Option<(wgpu::Adapter, Arc<wgpu::Device>, wgpu::Queue)> {
+    futures_executor::block_on(async {
+        let mut adapter = None;
+
+        for force_fallback_adapter in [false, true] {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    force_fallback_adapter,
+                    compatible_surface,
+                })
+                .await;
+
+            if adapter.is_some() {
+                break;
+            }
+        }
+
+        let adapter = adapter?;
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::default(),
+                    limits: wgpu::Limits::default(),
+                },
+                None,
+            )
+            .await
+            .ok()?;
+
+        Some((adapter, Arc::new(device), queue))
+    })
+}
+
 impl Graphics {
     pub(crate) fn new(video_mode: VideoMode, _framebuffer: &mut Framebuffer) -> Self {
         let window = {
             let mut builder = winit::window::WindowBuilder::new();
             builder = builder.with_title("N64");
-            builder = builder.with_inner_size(winit::dpi::LogicalSize::new(
-                SCALE * video_mode.width(),
-                SCALE * video_mode.height(),
-            ));
+            builder =
+                builder.with_inner_size(winit::dpi::LogicalSize::new(OUTPUT_WIDTH, OUTPUT_HEIGHT));
             builder = builder.with_visible(false);
             EVENT_LOOP.with(|event_loop| builder.build(&event_loop.lock().unwrap()).unwrap())
         };
 
-        let keys_down = HashSet::new();
-
         let instance = wgpu::Instance::new(InstanceDescriptor {
             backends: wgpu::Backends::PRIMARY,
             ..Default::default()
@@ -116,29 +173,8 @@ impl Graphics {
             (size, surface)
         };
 
-        let (adapter, device, queue) = futures_executor::block_on(async {
-            let adapter = instance
-                .request_adapter(&wgpu::RequestAdapterOptions {
-                    power_preference: wgpu::PowerPreference::HighPerformance,
-                    force_fallback_adapter: false,
-                    compatible_surface: Some(&surface),
-                })
-                .await
-                .unwrap();
-
-            let (device, queue) = adapter
-                .request_device(
-                    &wgpu::DeviceDescriptor {
-                        label: None,
-                        features: wgpu::Features::default(),
-                        limits: wgpu::Limits::default(),
-                    },
-                    None,
-                )
-                .await
-                .unwrap();
-            (adapter, Arc::new(device), queue)
-        });
+        let (adapter, device, queue) =
+            request_device(&instance, Some(&surface)).expect("No graphics adapter available");
 
         let surface_format = surface.get_capabilities(&adapter).formats[0];
 
@@ -154,6 +190,78 @@ impl Graphics {
 
         surface.configure(&device, &surface_config);
 
+        let graphics = Self::with_output(
+            video_mode,
+            instance,
+            adapter,
+            device,
+            queue,
+            surface_format,
+            Output::Window {
+                window,
+                surface,
+                surface_config,
+            },
+        );
+
+        if let Output::Window { window, .. } = &graphics.output {
+            window.set_visible(true);
+        }
+
+        graphics
+    }
+
+    // Renders into an offscreen texture, no window is created and no events are polled. None
+    // without a Vulkan, Metal or DX12 adapter, the GL backend can not offset the instance index
+    // the uniforms are read with.
+    pub(crate) fn new_headless(
+        video_mode: VideoMode,
+        _framebuffer: &mut Framebuffer,
+    ) -> Option<Self> {
+        let instance = wgpu::Instance::new(InstanceDescriptor {
+            backends: wgpu::Backends::PRIMARY,
+            ..Default::default()
+        });
+
+        let (adapter, device, queue) = request_device(&instance, None)?;
+
+        let tex = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: OUTPUT_WIDTH as u32,
+                height: OUTPUT_HEIGHT as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: dst_texture::TEXUTRE_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[dst_texture::TEXUTRE_FORMAT],
+        });
+
+        Some(Self::with_output(
+            video_mode,
+            instance,
+            adapter,
+            device,
+            queue,
+            dst_texture::TEXUTRE_FORMAT,
+            Output::Offscreen { tex },
+        ))
+    }
+
+    fn with_output(
+        video_mode: VideoMode,
+        instance: wgpu::Instance,
+        adapter: wgpu::Adapter,
+        device: Arc<wgpu::Device>,
+        queue: wgpu::Queue,
+        output_format: wgpu::TextureFormat,
+        output: Output,
+    ) -> Self {
+        let keys_down = HashSet::new();
+
         let quad_vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
             contents: QUAD_VERTEX_DATA.as_bytes(),
@@ -166,7 +274,7 @@ impl Graphics {
             usage: wgpu::BufferUsages::INDEX,
         });
 
-        let copy_tex = CopyTex::new(&device, surface_format, video_mode);
+        let copy_tex = CopyTex::new(&device, output_format, video_mode);
         let colored_rect = ColoredRect::new(
             &device,
             dst_texture::TEXUTRE_FORMAT,
@@ -184,8 +292,6 @@ impl Graphics {
             dst_texture::DEPTH_FORMAT,
         );
 
-        window.set_visible(true);
-
         let device_poll_thread_run = Arc::new(AtomicBool::new(true));
         let device_poll_thread = {
             let run = device_poll_thread_run.clone();
@@ -202,15 +308,13 @@ impl Graphics {
             video_mode,
             keys_down,
 
-            _window: window,
             _instance: instance,
             _adapter: adapter,
 
             device,
             queue,
 
-            surface,
-            surface_config,
+            output,
 
             quad_vertex_buf,
             quad_index_buf,
@@ -220,6 +324,9 @@ impl Graphics {
             textured_rect,
             mesh,
 
+            last_frame: None,
+            render_targets: HashMap::new(),
+
             device_poll_thread_run,
             device_poll_thread,
 
@@ -228,6 +335,10 @@ impl Graphics {
     }
 
     pub(crate) fn poll_events(&mut self, framebuffer: &mut Framebuffer) {
+        if let Output::Offscreen { .. } = self.output {
+            return;
+        }
+
         EVENT_LOOP.with(|event_loop| {
             event_loop
                 .lock()
@@ -239,9 +350,16 @@ impl Graphics {
                             event: WindowEvent::Resized(size),
                             ..
                         } => {
-                            self.surface_config.width = size.width;
-                            self.surface_config.height = size.height;
-                            self.surface.configure(&self.device, &self.surface_config);
+                            if let Output::Window {
+                                surface,
+                                surface_config,
+                                ..
+                            } = &mut self.output
+                            {
+                                surface_config.width = size.width;
+                                surface_config.height = size.height;
+                                surface.configure(&self.device, surface_config);
+                            }
                         }
                         event::Event::WindowEvent { event, .. } => match event {
                             WindowEvent::KeyboardInput {
@@ -292,19 +410,44 @@ impl Graphics {
     pub(crate) fn render_cpu_buffer(&mut self, framebuffer: &mut Framebuffer) -> i64 {
         let fb = framebuffer.gpu_buffer();
 
-        for (pixel, data) in fb.data.iter().zip(self.copy_tex.src_buffer.chunks_mut(4)) {
-            let rgba = pixel.to_rgba();
+        match self.video_mode.bit_depth() {
+            BitDepth::Bpp16 => {
+                for (pixel, data) in fb.data.iter().zip(self.copy_tex.src_buffer.chunks_mut(4)) {
+                    let rgba = pixel.to_rgba();
 
-            data[0] = (rgba[0] * 255.0) as u8;
-            data[1] = (rgba[1] * 255.0) as u8;
-            data[2] = (rgba[2] * 255.0) as u8;
-            data[3] = (rgba[3] * 255.0) as u8;
+                    data[0] = (rgba[0] * 255.0) as u8;
+                    data[1] = (rgba[1] * 255.0) as u8;
+                    data[2] = (rgba[2] * 255.0) as u8;
+                    data[3] = (rgba[3] * 255.0) as u8;
+                }
+            }
+            BitDepth::Bpp32 => {
+                for (pixel, data) in fb
+                    .data
+                    .chunks(2)
+                    .zip(self.copy_tex.src_buffer.chunks_mut(4))
+                {
+                    data[0..2].copy_from_slice(&pixel[0].value().to_be_bytes());
+                    data[2..4].copy_from_slice(&pixel[1].value().to_be_bytes());
+                }
+            }
         }
 
-        let frame = self
-            .surface
-            .get_current_texture()
-            .expect("Timeout when acquiring next swap chain texture");
+        let (frame, view) = match &self.output {
+            Output::Window { surface, .. } => {
+                let frame = surface
+                    .get_current_texture()
+                    .expect("Timeout when acquiring next swap chain texture");
+                let view = frame
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(frame), view)
+            }
+            Output::Offscreen { tex } => (
+                None,
+                tex.create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+        };
 
         let temp_buf = self
             .device
@@ -338,10 +481,6 @@ impl Graphics {
             );
 
             {
-                let view = frame
-                    .texture
-                    .create_view(&wgpu::TextureViewDescriptor::default());
-
                 let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: None,
                     color_attachments: &[Some(wgpu::RenderPassColorAttachment {
@@ -373,12 +512,17 @@ impl Graphics {
         self.queue.submit(Some(render_command_buf));
 
         let swap_start = current_time_us();
-        frame.present();
+        if let Some(frame) = frame {
+            frame.present();
+        }
         let swap_end = current_time_us();
 
         swap_end - swap_start
     }
 
+    // Submit already waits for the frame to be copied back to the framebuffer
+    pub fn wait_for_gpu(&mut self) {}
+
     pub fn swap_buffers(&mut self, framebuffer: &mut Framebuffer) -> i64 {
         self.poll_events(framebuffer);
         let swap_time = self.render_cpu_buffer(framebuffer);
@@ -389,6 +533,41 @@ impl Graphics {
         swap_time
     }
 
+    // The last submitted frame as RGBA 8888, or the CPU framebuffer if nothing was submitted yet
+    pub fn capture_frame(&mut self, framebuffer: &Framebuffer) -> Vec<u8> {
+        match &self.last_frame {
+            Some(dst) => {
+                let mut rgba = dst.read_rgba(&self.device, &self.queue);
+
+                for pixel in rgba.chunks_exact_mut(4) {
+                    pixel[3] = 0xff;
+                }
+
+                rgba
+            }
+            None => framebuffer.vi_buffer_rgba(),
+        }
+    }
+
+    // Makes sure the render target exists and is what gets sampled for its texture data
+    pub(crate) fn bind_render_target(&mut self, key: usize, width: i32, height: i32) {
+        if let Some(render_target) = self.render_targets.get(&key) {
+            if render_target.tex_extent.width == width as u32
+                && render_target.tex_extent.height == height as u32
+            {
+                return;
+            }
+        }
+
+        let render_target = DstTexture::new(&self.device, width, height);
+
+        self.textured_rect
+            .bind_texture_view(&self.device, key, &render_target.tex_view);
+        self.mesh
+            .bind_texture_view(&self.device, key, &render_target.tex_view);
+        self.render_targets.insert(key, render_target);
+    }
+
     pub fn frame_counter(&self) -> usize {
         self.frame_counter
     }
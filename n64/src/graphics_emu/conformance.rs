@@ -0,0 +1,362 @@
+use crate::gfx::{
+    color_combiner_mode::{
+        AAlphaSrc, ASrc, BAlphaSrc, BSrc, CAlphaSrc, CSrc, ColorCombinerMode, DAlphaSrc, DSrc,
+    },
+    soft_rdp::pixel,
+    Pipeline,
+};
+use alloc::vec::Vec;
+
+// A combiner or blender setup and the color it gives on a quad with SHADE vertices and a texture
+// of TEXEL. Both backends are held to these, the emulator within the 5 bits the framebuffer keeps.
+// Noise is left out, the soft RDP has none, and is checked on the emulator alone.
+pub(crate) struct ConformanceCase {
+    pub(crate) name: &'static str,
+    pub(crate) pipeline: fn() -> Pipeline,
+    pub(crate) rgb: [u8; 3],
+}
+
+// RGBA16 and the 8 bit color it expands to
+const TEXEL: u16 = 0x8231;
+const TEXEL_RGB: [u8; 3] = [0x84, 0x42, 0xc6];
+pub(crate) const SHADE: u32 = 0x8040_c080;
+const PRIM: u32 = 0x20c0_60ff;
+const ENV: u32 = 0xe020_4040;
+const BLEND: u32 = 0x00ff_00ff;
+const FOG: u32 = 0x0000_ff80;
+
+// Opaque, so only the blender decides what is mixed with memory
+fn opaque(a: ASrc, b: BSrc, c: CSrc, d: DSrc) -> ColorCombinerMode {
+    ColorCombinerMode::one(
+        a,
+        b,
+        c,
+        d,
+        AAlphaSrc::Zero,
+        BAlphaSrc::Zero,
+        CAlphaSrc::Zero,
+        DAlphaSrc::One,
+    )
+}
+
+fn conformance_pipeline(mode: ColorCombinerMode) -> Pipeline {
+    Pipeline::default()
+        .with_combiner_mode(mode)
+        .with_prim_color(Some(PRIM))
+        .with_env_color(Some(ENV))
+        .with_blend_color(Some(BLEND))
+        .with_fog_color(Some(FOG))
+}
+
+pub(crate) const CONFORMANCE_CASES: &[ConformanceCase] = {
+    use crate::gfx::{
+        blend_mode::{self, BlendMode, PMCycleOne},
+        CycleType, FogRange,
+    };
+
+    &[
+        ConformanceCase {
+            name: "shade",
+            pipeline: || {
+                conformance_pipeline(opaque(ASrc::Zero, BSrc::Zero, CSrc::Zero, DSrc::Shade))
+            },
+            rgb: [0x80, 0x40, 0xc0],
+        },
+        ConformanceCase {
+            name: "texel",
+            pipeline: || {
+                conformance_pipeline(opaque(ASrc::Zero, BSrc::Zero, CSrc::Zero, DSrc::Texel))
+            },
+            rgb: TEXEL_RGB,
+        },
+        ConformanceCase {
+            name: "texel times shade",
+            pipeline: || {
+                conformance_pipeline(opaque(ASrc::Texel, BSrc::Zero, CSrc::Shade, DSrc::Zero))
+            },
+            rgb: [66, 17, 149],
+        },
+        ConformanceCase {
+            name: "prim to env by shade alpha",
+            pipeline: || {
+                conformance_pipeline(opaque(
+                    ASrc::Primitive,
+                    BSrc::Environment,
+                    CSrc::ShadeAlpha,
+                    DSrc::Environment,
+                ))
+            },
+            rgb: [128, 112, 80],
+        },
+        ConformanceCase {
+            name: "one minus texel",
+            pipeline: || {
+                conformance_pipeline(opaque(
+                    ASrc::One,
+                    BSrc::Texel,
+                    CSrc::PrimitiveAlpha,
+                    DSrc::Zero,
+                ))
+            },
+            rgb: [123, 189, 57],
+        },
+        ConformanceCase {
+            name: "prim times env alpha",
+            pipeline: || {
+                conformance_pipeline(opaque(
+                    ASrc::Primitive,
+                    BSrc::Zero,
+                    CSrc::EnvironmentAlpha,
+                    DSrc::Zero,
+                ))
+            },
+            rgb: [8, 48, 24],
+        },
+        ConformanceCase {
+            name: "LOD fraction is 0 without LOD",
+            pipeline: || {
+                conformance_pipeline(opaque(
+                    ASrc::Primitive,
+                    BSrc::Environment,
+                    CSrc::LodFraction,
+                    DSrc::Environment,
+                ))
+            },
+            rgb: [0xe0, 0x20, 0x40],
+        },
+        ConformanceCase {
+            name: "combined times prim",
+            pipeline: || {
+                conformance_pipeline(ColorCombinerMode::two(
+                    opaque(ASrc::Texel, BSrc::Zero, CSrc::Shade, DSrc::Zero),
+                    opaque(ASrc::Combined, BSrc::Zero, CSrc::Primitive, DSrc::Zero),
+                ))
+                .with_cycle_type(CycleType::Two)
+            },
+            rgb: [8, 13, 56],
+        },
+        ConformanceCase {
+            name: "combined times combined alpha",
+            pipeline: || {
+                conformance_pipeline(ColorCombinerMode::two(
+                    ColorCombinerMode::one(
+                        ASrc::Zero,
+                        BSrc::Zero,
+                        CSrc::Zero,
+                        DSrc::Texel,
+                        AAlphaSrc::Zero,
+                        BAlphaSrc::Zero,
+                        CAlphaSrc::Zero,
+                        DAlphaSrc::ShadeAlpha,
+                    ),
+                    opaque(ASrc::Combined, BSrc::Zero, CSrc::CombinedAlpha, DSrc::Zero),
+                ))
+                .with_cycle_type(CycleType::Two)
+            },
+            rgb: [66, 33, 99],
+        },
+        ConformanceCase {
+            name: "fog halfway",
+            pipeline: || {
+                conformance_pipeline(opaque(ASrc::Zero, BSrc::Zero, CSrc::Zero, DSrc::Shade))
+                    .with_fog_range(Some(FogRange::new(0.0, 2.0)))
+            },
+            rgb: [64, 32, 224],
+        },
+        ConformanceCase {
+            name: "blend color over the first cycle",
+            pipeline: || {
+                conformance_pipeline(opaque(ASrc::Zero, BSrc::Zero, CSrc::Zero, DSrc::Shade))
+                    .with_blend_mode(BlendMode::one(
+                        PMCycleOne::BlendColor,
+                        blend_mode::ASrc::FogAlpha,
+                        PMCycleOne::ColorCombinerRgb,
+                        blend_mode::BSrc::OneMinusA,
+                    ))
+                    .with_cycle_type(CycleType::Two)
+                    .with_blend(true)
+            },
+            rgb: [32, 208, 48],
+        },
+        ConformanceCase {
+            name: "blend color over memory",
+            pipeline: || {
+                conformance_pipeline(opaque(ASrc::Zero, BSrc::Zero, CSrc::Zero, DSrc::Shade))
+                    .with_blend_mode(BlendMode::one(
+                        PMCycleOne::BlendColor,
+                        blend_mode::ASrc::FogAlpha,
+                        PMCycleOne::Memory,
+                        blend_mode::BSrc::OneMinusA,
+                    ))
+                    .with_blend(true)
+            },
+            rgb: [0, 128, 0],
+        },
+    ]
+};
+
+// Width of the framebuffer the cases are drawn in next to each other, 8 pixels apart
+pub(crate) const CONFORMANCE_WIDTH: i32 = 128;
+
+pub(crate) fn conformance_texture() -> crate::gfx::Texture<'static> {
+    use crate::gfx::{Texture, TextureFormat};
+
+    let data = [TEXEL; 16]
+        .iter()
+        .flat_map(|texel| texel.to_be_bytes())
+        .collect::<Vec<_>>();
+
+    Texture::from_bytes(4, 4, TextureFormat::Rgba16, data.leak())
+}
+
+// Sets the pipeline of each case and draws its quad with add_mesh
+macro_rules! draw_conformance_cases {
+    ($cb:expr, $texture:expr) => {
+        for (i, case) in $crate::graphics_emu::conformance::CONFORMANCE_CASES
+            .iter()
+            .enumerate()
+        {
+            let x = 8.0 * i as f32;
+
+            $cb.set_pipeline(
+                &(case.pipeline)()
+                    .with_texture(Some($texture))
+                    .with_texture_filter($crate::gfx::TextureFilter::Point),
+            )
+            .add_mesh_indexed(
+                &[
+                    [x, 0.0, 0.5],
+                    [x + 8.0, 0.0, 0.5],
+                    [x + 8.0, 8.0, 0.5],
+                    [x, 8.0, 0.5],
+                ],
+                &[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+                &[$crate::graphics_emu::conformance::SHADE; 4],
+                &[[0, 1, 2], [0, 2, 3]],
+                &[
+                    [1.0, 0.0, 0.0, 0.0],
+                    [0.0, 1.0, 0.0, 0.0],
+                    [0.0, 0.0, 1.0, 0.0],
+                    [0.0, 0.0, 0.0, 1.0],
+                ],
+            );
+        }
+    };
+}
+
+pub(crate) use draw_conformance_cases;
+
+// A pixel of every case within tolerance of its color, away from the diagonal the two triangles
+// of the quad share
+pub(crate) fn assert_conformance(rgba: &[u8], tolerance: i32) {
+    for (i, case) in CONFORMANCE_CASES.iter().enumerate() {
+        let pixel = &pixel(rgba, CONFORMANCE_WIDTH, 8 * i as i32 + 5, 2)[..3];
+
+        assert!(
+            pixel
+                .iter()
+                .zip(case.rgb)
+                .all(|(actual, expected)| (*actual as i32 - expected as i32).abs() <= tolerance),
+            "{}: {:?} is not {:?}",
+            case.name,
+            pixel,
+            case.rgb
+        );
+    }
+}
+
+// Skipped without a Vulkan, Metal or DX12 adapter, GL can not offset the instance index the
+// meshes take their uniforms with
+#[test]
+fn emu_matches_the_conformance_table() {
+    use crate::{
+        gfx::{CommandBuffer, CommandBufferCache},
+        BitDepth, InputScript, VideoFormat, VideoMode, N64,
+    };
+
+    let video_mode = VideoMode::Ntsc {
+        width: CONFORMANCE_WIDTH,
+        height: 8,
+        format: VideoFormat::default().with_bit_depth(BitDepth::Bpp16),
+    };
+    let texture = conformance_texture();
+
+    let Some(mut n64) = N64::new_headless(video_mode, InputScript::new()) else {
+        eprintln!("Skipping emu_matches_the_conformance_table, there is no graphics adapter");
+        return;
+    };
+    let mut cache = CommandBufferCache::new(video_mode);
+    let mut cb = CommandBuffer::new(n64.framebuffer.vi_buffer_token(), &mut cache);
+    cb.clear();
+    draw_conformance_cases!(cb, texture);
+    cb.submit(&mut n64.graphics, false);
+
+    // Both are compared with the table so the emulator is also within 5 bits of the soft RDP
+    assert_conformance(&n64.graphics.capture_frame(&n64.framebuffer), 8);
+}
+
+#[test]
+fn emu_noise_is_gray_and_varies_per_pixel() {
+    use crate::{
+        gfx::{CommandBuffer, CommandBufferCache},
+        BitDepth, InputScript, VideoFormat, VideoMode, N64,
+    };
+
+    let video_mode = VideoMode::Ntsc {
+        width: CONFORMANCE_WIDTH,
+        height: 8,
+        format: VideoFormat::default().with_bit_depth(BitDepth::Bpp16),
+    };
+
+    let Some(mut n64) = N64::new_headless(video_mode, InputScript::new()) else {
+        eprintln!("Skipping emu_noise_is_gray_and_varies_per_pixel, there is no graphics adapter");
+        return;
+    };
+    let mut cache = CommandBufferCache::new(video_mode);
+    let mut cb = CommandBuffer::new(n64.framebuffer.vi_buffer_token(), &mut cache);
+    cb.clear();
+
+    // Noise times the opaque primitive alpha
+    let width = CONFORMANCE_WIDTH as f32;
+    cb.set_pipeline(&conformance_pipeline(opaque(
+        ASrc::Noise,
+        BSrc::Zero,
+        CSrc::PrimitiveAlpha,
+        DSrc::Zero,
+    )))
+    .add_mesh_indexed(
+        &[
+            [0.0, 0.0, 0.5],
+            [width, 0.0, 0.5],
+            [width, 8.0, 0.5],
+            [0.0, 8.0, 0.5],
+        ],
+        &[[0.0, 0.0]; 4],
+        &[SHADE; 4],
+        &[[0, 1, 2], [0, 2, 3]],
+        &[
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    );
+    cb.submit(&mut n64.graphics, false);
+
+    let rgba = n64.graphics.capture_frame(&n64.framebuffer);
+    let mut levels = rgba
+        .chunks_exact(4)
+        .map(|pixel| {
+            assert!(
+                pixel[0] == pixel[1] && pixel[1] == pixel[2],
+                "{pixel:?} is not gray"
+            );
+            pixel[0]
+        })
+        .collect::<Vec<_>>();
+    levels.sort_unstable();
+    levels.dedup();
+
+    // Most of the 32 levels the framebuffer keeps show up
+    assert!(levels.len() > 16, "{levels:?}");
+}
@@ -2,7 +2,7 @@ use wgpu::SamplerBindingType;
 
 use crate::{
     graphics_emu::{shader, Vertex},
-    VideoMode,
+    VideoFilter, VideoMode,
 };
 use std::mem;
 
@@ -46,13 +46,20 @@ impl CopyTex {
         });
         let src_tex_view = src_tex.create_view(&Default::default());
 
+        // The VI resamples the framebuffer to the output size unless it replicates pixels.
+        // Interlaced modes are shown with both fields at once.
+        let filter = match video_mode.filter() {
+            VideoFilter::Replicate => wgpu::FilterMode::Nearest,
+            _ => wgpu::FilterMode::Linear,
+        };
+
         let src_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: None,
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
+            mag_filter: filter,
+            min_filter: filter,
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
@@ -1,3 +1,9 @@
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
 pub(crate) static TEXUTRE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
 pub(crate) static DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
@@ -34,7 +40,8 @@ impl DstTexture {
             format: TEXUTRE_FORMAT,
             usage: wgpu::TextureUsages::COPY_DST
                 | wgpu::TextureUsages::COPY_SRC
-                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[TEXUTRE_FORMAT],
         });
         let tex_view = tex.create_view(&Default::default());
@@ -60,4 +67,49 @@ impl DstTexture {
             depth_view,
         }
     }
+    // Relies on the device poll thread to finish the mapping
+    pub(crate) fn read_rgba(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<u8> {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.tex,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * self.tex_extent.width),
+                    rows_per_image: NonZeroU32::new(self.tex_extent.height),
+                },
+            },
+            self.tex_extent,
+        );
+
+        queue.submit(Some(encoder.finish()));
+
+        let mapped = Arc::new(AtomicBool::new(false));
+        let slice = self.buffer.slice(..);
+
+        slice.map_async(wgpu::MapMode::Read, {
+            let mapped = mapped.clone();
+            move |mapped_slice| {
+                assert!(mapped_slice.is_ok());
+                mapped.store(true, Ordering::SeqCst)
+            }
+        });
+
+        while !mapped.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let rgba = slice.get_mapped_range().to_vec();
+        self.buffer.unmap();
+
+        rgba
+    }
 }
@@ -1,6 +1,9 @@
 #![allow(clippy::inconsistent_digit_grouping)]
 
-use crate::{gfx::Texture, graphics_emu::shader};
+use crate::{
+    gfx::{CullMode, Texture},
+    graphics_emu::{shader, texture_decode::decode_texture},
+};
 use n64_math::Color;
 use std::{collections::HashMap, mem, num::NonZeroU32};
 use zerocopy::{AsBytes, FromBytes};
@@ -18,6 +21,21 @@ pub(crate) struct MeshUniforms {
     pub env_color: [f32; 4],
     pub blend_color: [f32; 4],
     pub fog_color: [f32; 4],
+    pub texture_mode: [u32; 4],
+}
+
+fn create_cull_mode_pipelines(
+    device: &wgpu::Device,
+    pipeline_desc: &mut wgpu::RenderPipelineDescriptor,
+) -> [wgpu::RenderPipeline; 3] {
+    [CullMode::None, CullMode::Back, CullMode::Front].map(|cull_mode| {
+        pipeline_desc.primitive.cull_mode = match cull_mode {
+            CullMode::None => None,
+            CullMode::Back => Some(wgpu::Face::Back),
+            CullMode::Front => Some(wgpu::Face::Front),
+        };
+        device.create_render_pipeline(pipeline_desc)
+    })
 }
 
 pub(crate) struct UploadedTexture {
@@ -27,10 +45,11 @@ pub(crate) struct UploadedTexture {
 pub(crate) struct Mesh {
     pub _uniforms_bind_group_layout: wgpu::BindGroupLayout,
     pub tex_bind_group_layout: wgpu::BindGroupLayout,
-    pub pipeline_with_depth_compare_and_depth_write: wgpu::RenderPipeline,
-    pub pipeline_with_depth_compare: wgpu::RenderPipeline,
-    pub pipeline_with_depth_write: wgpu::RenderPipeline,
-    pub pipeline_with_no_depth: wgpu::RenderPipeline,
+    // Indexed by CullMode
+    pub pipeline_with_depth_compare_and_depth_write: [wgpu::RenderPipeline; 3],
+    pub pipeline_with_depth_compare: [wgpu::RenderPipeline; 3],
+    pub pipeline_with_depth_write: [wgpu::RenderPipeline; 3],
+    pub pipeline_with_no_depth: [wgpu::RenderPipeline; 3],
     pub shader_storage_buffer: wgpu::Buffer,
     pub shader_storage_buffer_bind_group: wgpu::BindGroup,
     pub tex_sampler: wgpu::Sampler,
@@ -145,6 +164,7 @@ impl Mesh {
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
                 cull_mode: None,
+                // Clips to the near and far planes, the N64 backend clips triangles the same way
                 unclipped_depth: false,
                 polygon_mode: wgpu::PolygonMode::Fill,
                 conservative: false,
@@ -170,7 +190,7 @@ impl Mesh {
         };
 
         let pipeline_with_depth_compare_and_depth_write =
-            device.create_render_pipeline(&pipeline_desc);
+            create_cull_mode_pipelines(device, &mut pipeline_desc);
 
         pipeline_desc.depth_stencil = Some(wgpu::DepthStencilState {
             format: depth_format,
@@ -179,7 +199,7 @@ impl Mesh {
             stencil: wgpu::StencilState::default(),
             bias: wgpu::DepthBiasState::default(),
         });
-        let pipeline_with_depth_compare = device.create_render_pipeline(&pipeline_desc);
+        let pipeline_with_depth_compare = create_cull_mode_pipelines(device, &mut pipeline_desc);
 
         pipeline_desc.depth_stencil = Some(wgpu::DepthStencilState {
             format: depth_format,
@@ -188,7 +208,7 @@ impl Mesh {
             stencil: wgpu::StencilState::default(),
             bias: wgpu::DepthBiasState::default(),
         });
-        let pipeline_with_depth_write = device.create_render_pipeline(&pipeline_desc);
+        let pipeline_with_depth_write = create_cull_mode_pipelines(device, &mut pipeline_desc);
 
         pipeline_desc.depth_stencil = Some(wgpu::DepthStencilState {
             format: depth_format,
@@ -197,7 +217,7 @@ impl Mesh {
             stencil: wgpu::StencilState::default(),
             bias: wgpu::DepthBiasState::default(),
         });
-        let pipeline_with_no_depth = device.create_render_pipeline(&pipeline_desc);
+        let pipeline_with_no_depth = create_cull_mode_pipelines(device, &mut pipeline_desc);
 
         let shader_storage_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
@@ -252,11 +272,7 @@ impl Mesh {
             #[allow(clippy::unusual_byte_groupings)]
             let data = [Color::new(0b11111_11111_11111_1)];
 
-            let texture = Texture {
-                width: 1,
-                height: 1,
-                data: &data,
-            };
+            let texture = Texture::new(1, 1, &data);
 
             mesh.upload_texture_data_internal(device, queue, 0, &texture);
         }
@@ -285,7 +301,7 @@ impl Mesh {
         let tex = device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size: tex_extent,
-            mip_level_count: 1,
+            mip_level_count: texture.mip_levels as u32,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: tex_format,
@@ -293,50 +309,33 @@ impl Mesh {
             view_formats: &[tex_format],
         });
         let tex_view = tex.create_view(&Default::default());
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &self.tex_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&tex_view),
+        let bind_group = self.create_texture_bind_group(device, &tex_view);
+
+        for level in 0..texture.mip_levels {
+            let level_texture = texture.mip_level(level);
+            let buffer = decode_texture(&level_texture);
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &tex,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
                 },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.tex_sampler),
+                &buffer,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * level_texture.width as u32),
+                    rows_per_image: NonZeroU32::new(level_texture.height as u32),
                 },
-            ],
-            label: None,
-        });
-
-        let mut buffer = Vec::new();
-        buffer.resize_with(4 * texture.data.len(), Default::default);
-
-        for (pixel, data) in texture.data.iter().zip(buffer.chunks_exact_mut(4_usize)) {
-            let rgba = pixel.be_to_le().to_rgba();
-
-            data[0] = (rgba[0] * 255.0) as u8;
-            data[1] = (rgba[1] * 255.0) as u8;
-            data[2] = (rgba[2] * 255.0) as u8;
-            data[3] = (rgba[3] * 255.0) as u8;
+                wgpu::Extent3d {
+                    width: level_texture.width as u32,
+                    height: level_texture.height as u32,
+                    depth_or_array_layers: 1,
+                },
+            );
         }
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &tex,
-                mip_level: 0,
-                origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
-                aspect: wgpu::TextureAspect::All,
-            },
-            &buffer,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: NonZeroU32::new(4 * texture.width as u32),
-                rows_per_image: NonZeroU32::new(texture.height as u32),
-            },
-            tex_extent,
-        );
-
         self.texture_cache
             .insert(key, UploadedTexture { bind_group });
     }
@@ -349,4 +348,37 @@ impl Mesh {
     ) {
         self.upload_texture_data_internal(device, queue, texture.data.as_ptr() as _, texture)
     }
+
+    // Samples the view for the texture data at key, replacing anything uploaded for it
+    pub(crate) fn bind_texture_view(
+        &mut self,
+        device: &wgpu::Device,
+        key: usize,
+        tex_view: &wgpu::TextureView,
+    ) {
+        let bind_group = self.create_texture_bind_group(device, tex_view);
+        self.texture_cache
+            .insert(key, UploadedTexture { bind_group });
+    }
+
+    fn create_texture_bind_group(
+        &self,
+        device: &wgpu::Device,
+        tex_view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.tex_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(tex_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.tex_sampler),
+                },
+            ],
+            label: None,
+        })
+    }
 }
@@ -9,228 +9,309 @@ layout(location = 4) in flat vec4 v_prim_color;
 layout(location = 5) in flat vec4 v_env_color;
 layout(location = 6) in flat vec4 v_blend_color;
 layout(location = 7) in flat vec4 v_fog_color;
+layout(location = 8) in flat uvec4 v_texture_mode;
+layout(location = 9) in noperspective vec2 v_tex_coord_affine;
 
 layout(location = 0) out vec4 o_color;
 
 layout(set = 1, binding = 0) uniform texture2D t_tex;
 layout(set = 1, binding = 1) uniform sampler s_tex;
 
-vec4 color_combiner() {
-    vec4 shade_color = v_color;
-    vec4 texel_color = texture(sampler2D(t_tex, s_tex), v_tex_coord);
-    vec4 prim_color = v_prim_color;
-    vec4 env_color = v_env_color;
+// Wraps a texel coordinate on one axis the way the RDP tile does, mip levels repeat one bit earlier
+int wrap_coord(int coord, int size, uint mode, int level) {
+    int mask = int((mode >> 4u) & 0xfu);
 
-    uvec2 color_combiner_mode = v_color_combiner_mode;
+    if (mask != 0) {
+        mask = max(mask - level, 0);
+    }
 
-    vec4 a = vec4(0.0);
-    vec4 b = vec4(0.0);
-    vec4 c = vec4(0.0);
-    vec4 d = vec4(0.0);
+    if ((mode & 1u) != 0u || mask == 0) {
+        coord = clamp(coord, 0, size - 1);
+    }
 
-    switch ((color_combiner_mode.x >> (52 - 32)) & 0xf) {
-        case 1:
-            a.rgb = texel_color.rgb;
-            break;
-        case 3:
-            a.rgb = prim_color.rgb;
-            break;
-        case 4:
-            a.rgb = shade_color.rgb;
-            break;
-        case 5:
-            a.rgb = env_color.rgb;
-            break;
-        case 7:
-            // Implement noise
-            a.rgb = vec3(1.0);
-            break;
-        case 6:
-            a.rgb = vec3(1.0);
-            break;
-        case 8:
-            a.rgb = vec3(0.0);
-            break;
-        default:
-            break;
+    if (mask != 0) {
+        if ((mode & 2u) != 0u && ((coord >> mask) & 1) != 0) {
+            coord = ~coord;
+        }
+        coord &= (1 << mask) - 1;
     }
 
-    switch ((color_combiner_mode.y >> 28) & 0xf) {
-        case 1:
-            b.rgb = texel_color.rgb;
-            break;
-        case 3:
-            b.rgb = prim_color.rgb;
-            break;
-        case 4:
-            b.rgb = shade_color.rgb;
-            break;
-        case 5:
-            b.rgb = env_color.rgb;
-            break;
-        case 8:
-            b.rgb = vec3(0.0);
-            break;
-        default:
-            break;
+    return min(coord, size - 1);
+}
+
+// Shifts of 11 to 15 multiply by 2^(16 - shift), the rest divide by 2^shift
+float shift_coord(float coord, uint mode) {
+    uint shift = (mode >> 8u) & 0xfu;
+
+    if (shift > 10u) {
+        return coord * float(1 << int(16u - shift));
     }
 
-    switch ((color_combiner_mode.x >> (47 - 32)) & 0x1f) {
-        case 1:
-            c.rgb = texel_color.rgb;
-            break;
-        case 3:
-            c.rgb = prim_color.rgb;
-            break;
-        case 4:
-            c.rgb = shade_color.rgb;
-            break;
-        case 5:
-            c.rgb = env_color.rgb;
-            break;
-        case 8:
-            c.rgb = vec3(texel_color.a);
-            break;
-        case 10:
-            c.rgb = vec3(prim_color.a);
-            break;
-        case 11:
-            c.rgb = vec3(shade_color.a);
-            break;
-        case 12:
-            c.rgb = vec3(env_color.a);
-            break;
-        case 16:
-            c.rgb = vec3(0.0);
-            break;
-        default:
-            break;
+    return coord / float(1 << int(shift));
+}
+
+vec4 fetch_texel(ivec2 st, ivec2 size, int level) {
+    return texelFetch(
+        sampler2D(t_tex, s_tex),
+        ivec2(
+            wrap_coord(st.x, size.x, v_texture_mode.x, level),
+            wrap_coord(st.y, size.y, v_texture_mode.y, level)),
+        level);
+}
+
+// st is in texels of level 0
+vec4 sample_level(vec2 st, int level) {
+    ivec2 size = textureSize(sampler2D(t_tex, s_tex), level);
+    st /= float(1 << level);
+
+    // Point
+    if (v_texture_mode.z == 0u) {
+        return fetch_texel(ivec2(floor(st)), size, level);
     }
 
-    switch ((color_combiner_mode.y >> 15) & 0x7) {
-        case 1:
-            d.rgb = texel_color.rgb;
-            break;
-        case 3:
-            d.rgb = prim_color.rgb;
-            break;
-        case 4:
-            d.rgb = shade_color.rgb;
-            break;
-        case 5:
-            d.rgb = env_color.rgb;
-            break;
-        case 6:
-            d.rgb = vec3(1.0);
-            break;
-        case 7:
-            d.rgb = vec3(0.0);
-            break;
-        default:
-            break;
+    vec2 base = floor(st - 0.5);
+    ivec2 i = ivec2(base);
+    vec4 t00 = fetch_texel(i, size, level);
+    vec4 t10 = fetch_texel(i + ivec2(1, 0), size, level);
+    vec4 t01 = fetch_texel(i + ivec2(0, 1), size, level);
+    vec4 t11 = fetch_texel(i + ivec2(1, 1), size, level);
+
+    // Average
+    if (v_texture_mode.z == 2u) {
+        return 0.25 * (t00 + t10 + t01 + t11);
     }
 
-    switch ((color_combiner_mode.x >> (44 - 32)) & 0x7) {
-        case 1:
-            a.a = texel_color.a;
-            break;
-        case 3:
-            a.a = prim_color.a;
-            break;
-        case 4:
-            a.a = shade_color.a;
-            break;
-        case 5:
-            a.a = env_color.a;
-            break;
-        case 6:
-            a.a = 1.0;
-            break;
-        case 7:
-            a.a = 0.0;
-            break;
-        default:
-            break;
+    // Bilinear
+    vec2 f = st - 0.5 - base;
+    return mix(mix(t00, t10, f.x), mix(t01, t11, f.x), f.y);
+}
+
+// Samples the mip level picked by the texel to pixel ratio and the next one like the two cycles
+// of the RDP do with LOD enabled
+vec4 sample_texture(vec2 tex_coord, out vec4 texel1, out float lod_fraction) {
+    ivec2 size = textureSize(sampler2D(t_tex, s_tex), 0);
+    vec2 st = vec2(
+        shift_coord(tex_coord.x * float(size.x), v_texture_mode.x),
+        shift_coord(tex_coord.y * float(size.y), v_texture_mode.y));
+
+    // The RDP takes the largest step of either coordinate
+    vec2 st_dx = abs(dFdx(st));
+    vec2 st_dy = abs(dFdy(st));
+    float lod = max(max(st_dx.x, st_dx.y), max(st_dy.x, st_dy.y));
+
+    uint lod_mode = v_texture_mode.w & 0xfu;
+    int levels = int((v_texture_mode.w >> 4u) & 0xfu);
+
+    int level = 0;
+    lod_fraction = 0.0;
+
+    if (lod_mode != 0u) {
+        if (lod < 1.0) {
+            // Sharpen extrapolates away from level 1 when magnified
+            if (lod_mode == 2u) {
+                lod_fraction = lod - 1.0;
+            }
+        } else {
+            level = min(int(floor(log2(lod))), levels - 1);
+            lod_fraction = min(lod / float(1 << level) - 1.0, 1.0);
+        }
     }
 
-    switch ((color_combiner_mode.y >> 12) & 0x7) {
+    vec4 texel0 = sample_level(st, level);
+    texel1 = sample_level(st, min(level + 1, levels - 1));
+
+    return texel0;
+}
+
+// Per pixel inputs of the combiner, combined is what the first cycle gave
+struct CombinerInputs {
+    vec4 combined;
+    vec4 texel0;
+    vec4 texel1;
+    vec4 shade;
+    float lod_fraction;
+    float noise;
+};
+
+// 0 to 5 select the same input on A, B, C and D, 6 is 1 on A and D
+vec4 combiner_input(uint select, CombinerInputs inputs) {
+    switch (select) {
+        case 0:
+            return inputs.combined;
         case 1:
-            b.a = texel_color.a;
-            break;
+            return inputs.texel0;
+        case 2:
+            return inputs.texel1;
         case 3:
-            b.a = prim_color.a;
-            break;
+            return v_prim_color;
         case 4:
-            b.a = shade_color.a;
-            break;
+            return inputs.shade;
         case 5:
-            b.a = env_color.a;
-            break;
+            return v_env_color;
         case 6:
-            b.a = 1.0;
-            break;
-        case 7:
-            b.a = 0.0;
-            break;
+            return vec4(1.0);
         default:
-            break;
+            return vec4(0.0);
     }
+}
 
-    switch ((color_combiner_mode.x >> (41 - 32)) & 0x7) {
-        case 1:
-            c.a = texel_color.a;
-            break;
+// (a - b) * c + d of one cycle, the fields of the first cycle are above the ones of the second.
+// Key center, key scale and K4 and K5 are 0 and so is the primitive LOD fraction, which the prim
+// color is always set with.
+vec4 combine_cycle(uint cycle, CombinerInputs inputs) {
+    uvec2 mode = v_color_combiner_mode;
+    uint a_select = (mode.x >> 5) & 0xfu;
+    uint b_select = (mode.y >> 24) & 0xfu;
+    uint c_select = mode.x & 0x1fu;
+    uint d_select = (mode.y >> 6) & 0x7u;
+    uint a_alpha_select = (mode.y >> 21) & 0x7u;
+    uint b_alpha_select = (mode.y >> 3) & 0x7u;
+    uint c_alpha_select = (mode.y >> 18) & 0x7u;
+    uint d_alpha_select = mode.y & 0x7u;
+
+    if (cycle == 0u) {
+        a_select = (mode.x >> (52 - 32)) & 0xfu;
+        b_select = (mode.y >> 28) & 0xfu;
+        c_select = (mode.x >> (47 - 32)) & 0x1fu;
+        d_select = (mode.y >> 15) & 0x7u;
+        a_alpha_select = (mode.x >> (44 - 32)) & 0x7u;
+        b_alpha_select = (mode.y >> 12) & 0x7u;
+        c_alpha_select = (mode.x >> (41 - 32)) & 0x7u;
+        d_alpha_select = (mode.y >> 9) & 0x7u;
+    }
+
+    vec3 a = a_select == 7u ? vec3(inputs.noise) : combiner_input(a_select, inputs).rgb;
+    vec3 b = b_select < 6u ? combiner_input(b_select, inputs).rgb : vec3(0.0);
+    vec3 d = combiner_input(d_select, inputs).rgb;
+    vec3 c = vec3(0.0);
+
+    if (c_select < 6u) {
+        c = combiner_input(c_select, inputs).rgb;
+    } else if (c_select >= 7u && c_select <= 12u) {
+        c = vec3(combiner_input(c_select - 7u, inputs).a);
+    } else if (c_select == 13u) {
+        c = vec3(inputs.lod_fraction);
+    }
+
+    float c_alpha = 0.0;
+
+    if (c_alpha_select == 0u) {
+        c_alpha = inputs.lod_fraction;
+    } else if (c_alpha_select < 6u) {
+        c_alpha = combiner_input(c_alpha_select, inputs).a;
+    }
+
+    float a_alpha = combiner_input(a_alpha_select, inputs).a;
+    float b_alpha = combiner_input(b_alpha_select, inputs).a;
+    float d_alpha = combiner_input(d_alpha_select, inputs).a;
+
+    return clamp(
+        vec4((a - b) * c + d, (a_alpha - b_alpha) * c_alpha + d_alpha),
+        0.0,
+        1.0);
+}
+
+// Select 0 is the combined color in the first cycle and what the first cycle gave in the second
+vec3 blender_color(uint select, vec3 first) {
+    switch (select) {
+        case 0:
+            return first;
+        case 2:
+            return v_blend_color.rgb;
         case 3:
-            c.a = prim_color.a;
-            break;
-        case 4:
-            c.a = shade_color.a;
-            break;
-        case 5:
-            c.a = env_color.a;
-            break;
-        case 7:
-            c.a = 0.0;
-            break;
+            return v_fog_color.rgb;
         default:
-            break;
+            return vec3(0.0);
     }
+}
 
-    switch ((color_combiner_mode.y >> 9) & 0x7) {
+float blender_a(uint select, vec4 combined) {
+    switch (select) {
+        case 0:
+            return combined.a;
         case 1:
-            d.a = texel_color.a;
-            break;
-        case 3:
-            d.a = prim_color.a;
-            break;
-        case 4:
-            d.a = shade_color.a;
-            break;
-        case 5:
-            d.a = env_color.a;
-            break;
-        case 6:
-            d.a = 1.0;
-            break;
-        case 7:
-            d.a = 0.0;
-            break;
+            return v_fog_color.a;
+        case 2:
+            return v_color.a;
         default:
-            break;
+            return 0.0;
     }
+}
 
-    return vec4(
-        (a.rgb - b.rgb) * c.rgb + d.rgb,
-        (a.a - b.a) * c.a + d.a);
+float blender_b(uint select, float a) {
+    switch (select) {
+        case 0:
+            return 1.0 - a;
+        case 2:
+            return 1.0;
+        default:
+            return 0.0;
+    }
 }
 
-vec4 blender(vec4 input) {
-    //vec4 memory = texelFetch(sampler2D(t_output, s_output), ivec2(gl_FragCoord.xy), 0);
-    vec4 blend_color = v_blend_color;
-    vec4 fog_color = v_fog_color;
-    return vec4(0.0);
+// p * a + m * b, the fields of the first cycle are above the ones of the second. Memory is only
+// read by the blend state of the pipeline after the shader, which takes p * a + memory * (1 - a)
+// from the output, so the last cycle gives that whenever m is memory. Without memory the output
+// has alpha 1 so it passes the blend state unchanged.
+vec4 blender(vec4 combined) {
+    uint mode = v_blend_mode.y;
+    bool two_cycle = (v_texture_mode.w & 0x1000u) != 0u;
+    vec3 first = combined.rgb;
+
+    // The first of two cycles always blends, one that reads memory passes the color on
+    if (two_cycle) {
+        uint p = (mode >> 30) & 0x3u;
+        uint a = (mode >> 26) & 0x3u;
+        uint m = (mode >> 22) & 0x3u;
+        uint b = (mode >> 18) & 0x3u;
+
+        if (p != 1u && m != 1u) {
+            float a_value = blender_a(a, combined);
+            first = blender_color(p, first) * a_value
+                + blender_color(m, first) * blender_b(b, a_value);
+        }
+    }
+
+    // One cycle mode blends with the fields of the first cycle
+    uint shift = two_cycle ? 0u : 2u;
+    uint p = (mode >> (28u + shift)) & 0x3u;
+    uint a = (mode >> (24u + shift)) & 0x3u;
+    uint m = (mode >> (20u + shift)) & 0x3u;
+    uint b = (mode >> (16u + shift)) & 0x3u;
+    float a_value = blender_a(a, combined);
+
+    if (m == 1u) {
+        return vec4(blender_color(p, first), a_value);
+    }
+
+    if (p == 1u) {
+        return vec4(first, combined.a);
+    }
+
+    return vec4(
+        blender_color(p, first) * a_value + blender_color(m, first) * blender_b(b, a_value),
+        1.0);
 }
 
 void main() {
-    o_color = color_combiner();
+    CombinerInputs inputs;
+    vec4 texel1;
+    float lod_fraction;
+    // Without perspective correction the RDP interpolates texture coordinates linearly on screen
+    bool perspective = (v_texture_mode.w & 0x100u) != 0u;
+    vec2 tex_coord = perspective ? v_tex_coord : v_tex_coord_affine;
+    inputs.texel0 = sample_texture(tex_coord, texel1, lod_fraction);
+    inputs.texel1 = texel1;
+    inputs.lod_fraction = lod_fraction;
+    inputs.shade = v_color;
+    inputs.combined = vec4(0.0);
+    // The RDP takes a new random value for every pixel
+    inputs.noise = fract(sin(dot(gl_FragCoord.xy, vec2(12.9898, 78.233))) * 43758.5453);
+
+    // One cycle mode only runs the second cycle of the combiner
+    if ((v_texture_mode.w & 0x1000u) != 0u) {
+        inputs.combined = combine_cycle(0u, inputs);
+    }
+
+    o_color = blender(combine_cycle(1u, inputs));
 }
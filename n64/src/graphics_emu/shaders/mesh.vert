@@ -12,6 +12,8 @@ layout(location = 4) out flat vec4 v_prim_color;
 layout(location = 5) out flat vec4 v_env_color;
 layout(location = 6) out flat vec4 v_blend_color;
 layout(location = 7) out flat vec4 v_fog_color;
+layout(location = 8) out flat uvec4 v_texture_mode;
+layout(location = 9) out noperspective vec2 v_tex_coord_affine;
 
 struct Uniforms {
     mat4 u_transform;
@@ -22,6 +24,7 @@ struct Uniforms {
     vec4 u_env_color;
     vec4 u_blend_color;
     vec4 u_fog_color;
+    uvec4 u_texture_mode;
 };
 
 layout(std430, set = 0, binding = 0) readonly buffer Locals {
@@ -30,6 +33,7 @@ layout(std430, set = 0, binding = 0) readonly buffer Locals {
 
 void main() {
     v_tex_coord = a_tex_coord;
+    v_tex_coord_affine = a_tex_coord;
     v_color = a_color;
     v_color_combiner_mode = uniforms[gl_InstanceIndex].u_color_combiner_mode;
     v_blend_mode = uniforms[gl_InstanceIndex].u_blend_mode;
@@ -37,14 +41,16 @@ void main() {
     v_env_color = uniforms[gl_InstanceIndex].u_env_color;
     v_blend_color = uniforms[gl_InstanceIndex].u_blend_color;
     v_fog_color = uniforms[gl_InstanceIndex].u_fog_color;
+    v_texture_mode = uniforms[gl_InstanceIndex].u_texture_mode;
 
     vec4 position = uniforms[gl_InstanceIndex].u_transform * vec4(a_pos, 1.0);
-    position.xyz /= position.w;
-    position.w = 1.0;
+    // Keep w so the GPU can interpolate perspective correct texture coordinates
+    float w = position.w;
+    position.xyz /= w;
     gl_Position =
-        vec4(
+        w * vec4(
             -1.0 + 2.0 * position.x / uniforms[gl_InstanceIndex].u_screen_size_and_pad.x,
             -1.0 + 2.0 * position.y / uniforms[gl_InstanceIndex].u_screen_size_and_pad.y,
             0.5*(position.z + 1.0), // Opengl [-1, 1] -> Vulkan [0, 1]
-            position.w);
+            1.0);
 }
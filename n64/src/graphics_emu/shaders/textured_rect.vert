@@ -11,6 +11,8 @@ layout(location = 4) out flat vec4 v_prim_color;
 layout(location = 5) out flat vec4 v_env_color;
 layout(location = 6) out flat vec4 v_blend_color;
 layout(location = 7) out flat vec4 v_fog_color;
+layout(location = 8) out flat uvec4 v_texture_mode;
+layout(location = 9) out flat uvec4 v_texture_rect;
 
 struct Uniforms {
     vec4 u_offset_and_scale;
@@ -20,6 +22,10 @@ struct Uniforms {
     vec4 u_env_color;
     vec4 u_blend_color;
     vec4 u_fog_color;
+    uvec4 u_texture_mode;
+    vec4 u_rotation;
+    vec4 u_uv_offset_and_scale;
+    uvec4 u_texture_rect;
 };
 
 layout(std430, set = 0, binding = 0) readonly buffer Locals {
@@ -27,7 +33,8 @@ layout(std430, set = 0, binding = 0) readonly buffer Locals {
 };
 
 void main() {
-    v_tex_coord = a_tex_coord;
+    vec4 uv_offset_and_scale = uniforms[gl_InstanceIndex].u_uv_offset_and_scale;
+    v_tex_coord = uv_offset_and_scale.xy + uv_offset_and_scale.zw * a_tex_coord;
     v_color = vec4(0.0);
     v_color_combiner_mode = uniforms[gl_InstanceIndex].u_color_combiner_mode;
     v_blend_mode = uniforms[gl_InstanceIndex].u_blend_mode;
@@ -35,9 +42,13 @@ void main() {
     v_env_color = uniforms[gl_InstanceIndex].u_env_color;
     v_blend_color = uniforms[gl_InstanceIndex].u_blend_color;
     v_fog_color = uniforms[gl_InstanceIndex].u_fog_color;
+    v_texture_mode = uniforms[gl_InstanceIndex].u_texture_mode;
+    v_texture_rect = uniforms[gl_InstanceIndex].u_texture_rect;
 
     vec2 offset = uniforms[gl_InstanceIndex].u_offset_and_scale.xy;
     vec2 scale = uniforms[gl_InstanceIndex].u_offset_and_scale.zw;
 
-    gl_Position = vec4(vec3(scale*a_pos.xy + offset, a_pos.z), 1.0);
+    vec4 rotation = uniforms[gl_InstanceIndex].u_rotation;
+
+    gl_Position = vec4(vec3(mat2(rotation.xy, rotation.zw)*(scale*a_pos.xy) + offset, a_pos.z), 1.0);
 }
@@ -0,0 +1,108 @@
+use crate::gfx::{Texture, TextureFormat};
+use n64_math::Color;
+
+#[inline]
+fn rgba16_to_rgba8(color: Color) -> [u8; 4] {
+    let rgba = color.to_rgba();
+
+    [
+        (rgba[0] * 255.0) as u8,
+        (rgba[1] * 255.0) as u8,
+        (rgba[2] * 255.0) as u8,
+        (rgba[3] * 255.0) as u8,
+    ]
+}
+
+// Replicates the top bits of a narrow channel into the low bits, like the RDP does
+#[inline]
+fn expand(value: u8, bits: u32) -> u8 {
+    let value = (value as u32) << (8 - bits);
+    (value | (value >> bits) | (value >> (2 * bits))) as u8
+}
+
+#[inline]
+fn texel(texture: &Texture, index: usize) -> u8 {
+    match texture.format.bits_per_texel() {
+        4 => {
+            let byte = texture.data[index / 2];
+            if index % 2 == 0 {
+                byte >> 4
+            } else {
+                byte & 0xf
+            }
+        }
+        _ => texture.data[index],
+    }
+}
+
+// Decodes the big endian texels of any format to RGBA 8888, the same way the RDP expands them
+pub(crate) fn decode_texture(texture: &Texture) -> Vec<u8> {
+    let texel_count = (texture.width * texture.height) as usize;
+    let mut buffer = Vec::with_capacity(4 * texel_count);
+
+    for index in 0..texel_count {
+        let rgba = match texture.format {
+            TextureFormat::Rgba16 => rgba16_to_rgba8(Color::new(u16::from_be_bytes([
+                texture.data[2 * index],
+                texture.data[2 * index + 1],
+            ]))),
+            TextureFormat::Rgba32 => [
+                texture.data[4 * index],
+                texture.data[4 * index + 1],
+                texture.data[4 * index + 2],
+                texture.data[4 * index + 3],
+            ],
+            TextureFormat::Ci4 | TextureFormat::Ci8 => {
+                let palette = texture
+                    .palette
+                    .expect("Color indexed textures need a palette");
+
+                rgba16_to_rgba8(palette[texel(texture, index) as usize].be_to_le())
+            }
+            TextureFormat::I4 | TextureFormat::I8 => {
+                let i = expand(
+                    texel(texture, index),
+                    texture.format.bits_per_texel() as u32,
+                );
+                [i, i, i, i]
+            }
+            TextureFormat::Ia4 => {
+                let ia = texel(texture, index);
+                let i = expand(ia >> 1, 3);
+                let a = if ia & 1 > 0 { 0xff } else { 0 };
+                [i, i, i, a]
+            }
+            TextureFormat::Ia8 => {
+                let ia = texel(texture, index);
+                let i = expand(ia >> 4, 4);
+                [i, i, i, expand(ia & 0xf, 4)]
+            }
+            TextureFormat::Ia16 => {
+                let i = texture.data[2 * index];
+                [i, i, i, texture.data[2 * index + 1]]
+            }
+        };
+
+        buffer.extend_from_slice(&rgba);
+    }
+
+    buffer
+}
+
+#[test]
+fn decode_4_bit_texels() {
+    let mut palette = [Color::new(0x0001).be_to_le(); 16];
+    palette[1] = Color::new(0xffff);
+
+    let ci4 = Texture::from_bytes(2, 1, TextureFormat::Ci4, &[0x10]).with_palette(&palette);
+    assert_eq!(decode_texture(&ci4), [255, 255, 255, 255, 0, 0, 0, 255]);
+
+    let i4 = Texture::from_bytes(2, 1, TextureFormat::I4, &[0xf8]);
+    assert_eq!(
+        decode_texture(&i4),
+        [255, 255, 255, 255, 136, 136, 136, 136]
+    );
+
+    let ia4 = Texture::from_bytes(2, 1, TextureFormat::Ia4, &[0xe0]);
+    assert_eq!(decode_texture(&ia4), [255, 255, 255, 0, 0, 0, 0, 0]);
+}
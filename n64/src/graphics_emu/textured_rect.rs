@@ -1,6 +1,6 @@
 use crate::{
     gfx::Texture,
-    graphics_emu::{shader, Vertex},
+    graphics_emu::{shader, texture_decode::decode_texture, Vertex},
 };
 use std::{collections::HashMap, mem, num::NonZeroU32};
 use wgpu::SamplerBindingType;
@@ -8,6 +8,9 @@ use zerocopy::{AsBytes, FromBytes};
 
 pub const MAX_TEXTURED_RECTS: u64 = 4096;
 
+// Sprite atlases are packed in shelves at least this wide
+const ATLAS_WIDTH: u32 = 1024;
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, AsBytes, FromBytes)]
 pub(crate) struct TexturedRectUniforms {
@@ -19,12 +22,76 @@ pub(crate) struct TexturedRectUniforms {
     pub env_color: [f32; 4],
     pub blend_color: [f32; 4],
     pub fog_color: [f32; 4],
+    pub texture_mode: [u32; 4],
+    // Columns of a 2x2 matrix turning the rect around its center, in clip space
+    pub rotation: [f32; 4],
+    pub uv_offset_and_scale: [f32; 4],
+    // Texels of level 0 the texture takes in the bound one, all of it if the size is zero
+    pub texture_rect: [u32; 4],
 }
 
 pub(crate) struct UploadedTexture {
+    // None for render targets, which Graphics owns
+    pub tex: Option<wgpu::Texture>,
     pub bind_group: wgpu::BindGroup,
 }
 
+// The textures of a sprite batch packed into one so the batch is a single draw
+pub(crate) struct SpriteAtlas {
+    pub tex: wgpu::Texture,
+    pub bind_group: wgpu::BindGroup,
+    // Texture data and where it goes, at level 0
+    pub rects: Vec<(usize, [u32; 4])>,
+}
+
+impl SpriteAtlas {
+    pub(crate) fn rect(&self, texture: &Texture) -> [u32; 4] {
+        self.rects
+            .iter()
+            .find(|(key, _)| *key == texture.data.as_ptr() as usize)
+            .expect("Texture is not in the atlas")
+            .1
+    }
+
+    // Copies every level of the textures in, source looks up the uploaded texture or render
+    // target holding the texture data
+    pub(crate) fn copy_textures<'a>(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        source: impl Fn(usize) -> &'a wgpu::Texture,
+    ) {
+        for (key, rect) in &self.rects {
+            let src = source(*key);
+
+            for level in 0..src.mip_level_count().min(self.tex.mip_level_count()) {
+                encoder.copy_texture_to_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: src,
+                        mip_level: level,
+                        origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::ImageCopyTexture {
+                        texture: &self.tex,
+                        mip_level: level,
+                        origin: wgpu::Origin3d {
+                            x: rect[0] >> level,
+                            y: rect[1] >> level,
+                            z: 0,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::Extent3d {
+                        width: (src.width() >> level).max(1),
+                        height: (src.height() >> level).max(1),
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+    }
+}
+
 pub(crate) struct TexturedRect {
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub pipeline: wgpu::RenderPipeline,
@@ -206,17 +273,136 @@ impl TexturedRect {
         let tex_descriptor = wgpu::TextureDescriptor {
             label: None,
             size: tex_extent,
-            mip_level_count: 1,
+            mip_level_count: texture.mip_levels as u32,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: tex_format,
-            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage: wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[tex_format],
         };
         let tex = device.create_texture(&tex_descriptor);
         let tex_view = tex.create_view(&Default::default());
+        let bind_group = self.create_bind_group(device, &tex_view);
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        for level in 0..texture.mip_levels {
+            let level_texture = texture.mip_level(level);
+            let buffer = decode_texture(&level_texture);
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &tex,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &buffer,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * level_texture.width as u32),
+                    rows_per_image: NonZeroU32::new(level_texture.height as u32),
+                },
+                wgpu::Extent3d {
+                    width: level_texture.width as u32,
+                    height: level_texture.height as u32,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        self.texture_cache.insert(
+            texture.data.as_ptr() as _,
+            UploadedTexture {
+                tex: Some(tex),
+                bind_group,
+            },
+        );
+    }
+
+    // Packs the textures in shelves. Offsets are aligned to the size of the smallest mip level
+    // so every level of a texture starts at its offset shifted by the level.
+    pub(crate) fn create_atlas(&self, device: &wgpu::Device, textures: &[Texture]) -> SpriteAtlas {
+        let mip_levels = textures
+            .iter()
+            .map(|texture| texture.mip_levels as u32)
+            .max()
+            .unwrap_or(1);
+        let alignment = 1 << (mip_levels - 1);
+        let align = |size: i32| (size as u32 + alignment - 1) & !(alignment - 1);
+
+        let width = textures
+            .iter()
+            .map(|texture| align(texture.width))
+            .fold(ATLAS_WIDTH, u32::max);
+
+        let mut rects: Vec<(usize, [u32; 4])> = Vec::new();
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+
+        for texture in textures {
+            let key = texture.data.as_ptr() as usize;
+            if rects.iter().any(|(packed, _)| *packed == key) {
+                continue;
+            }
+
+            if x + align(texture.width) > width {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+
+            rects.push((key, [x, y, texture.width as u32, texture.height as u32]));
+            x += align(texture.width);
+            shelf_height = shelf_height.max(align(texture.height));
+        }
+
+        let tex_format = wgpu::TextureFormat::Rgba8Unorm;
+        let tex = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height: y + shelf_height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: mip_levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: tex_format,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[tex_format],
+        });
+        let bind_group = self.create_bind_group(device, &tex.create_view(&Default::default()));
+
+        SpriteAtlas {
+            tex,
+            bind_group,
+            rects,
+        }
+    }
+
+    // Samples the view for the texture data at key, replacing anything uploaded for it
+    pub(crate) fn bind_texture_view(
+        &mut self,
+        device: &wgpu::Device,
+        key: usize,
+        tex_view: &wgpu::TextureView,
+    ) {
+        let bind_group = self.create_bind_group(device, tex_view);
+        self.texture_cache.insert(
+            key,
+            UploadedTexture {
+                tex: None,
+                bind_group,
+            },
+        );
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        tex_view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &self.bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
@@ -227,7 +413,7 @@ impl TexturedRect {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&tex_view),
+                    resource: wgpu::BindingResource::TextureView(tex_view),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
@@ -235,37 +421,6 @@ impl TexturedRect {
                 },
             ],
             label: None,
-        });
-
-        let mut buffer = Vec::new();
-        buffer.resize_with(4 * texture.data.len(), Default::default);
-
-        for (pixel, data) in texture.data.iter().zip(buffer.chunks_exact_mut(4_usize)) {
-            let rgba = pixel.be_to_le().to_rgba();
-
-            data[0] = (rgba[0] * 255.0) as u8;
-            data[1] = (rgba[1] * 255.0) as u8;
-            data[2] = (rgba[2] * 255.0) as u8;
-            data[3] = (rgba[3] * 255.0) as u8;
-        }
-
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &tex,
-                mip_level: 0,
-                origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
-                aspect: wgpu::TextureAspect::All,
-            },
-            &buffer,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: NonZeroU32::new(4 * texture.width as u32),
-                rows_per_image: NonZeroU32::new(texture.height as u32),
-            },
-            tex_extent,
-        );
-
-        self.texture_cache
-            .insert(texture.data.as_ptr() as _, UploadedTexture { bind_group });
+        })
     }
 }
@@ -6,11 +6,14 @@ use alloc::{string::String, vec::Vec};
 use core::{ops::DerefMut, slice};
 use n64_macros::debugln;
 use n64_sys::{
-    rdp, rsp,
-    sys::{data_cache_hit_invalidate, data_cache_hit_writeback, virtual_to_physical},
+    ed, rdp, rsp,
+    sys::{
+        data_cache_hit_invalidate, data_cache_hit_writeback, data_cache_hit_writeback_invalidate,
+        virtual_to_physical,
+    },
     vi,
 };
-use n64_types::RdpBlock;
+use n64_types::{CaptureMessageBuffer, RdpBlock, CAPTURE_CHUNK_SIZE, MESSAGE_MAGIC_CAPTURE};
 use zerocopy::AsBytes;
 
 pub static CODE: &[u8] = include_bytes_align_as!(u64, "../../n64-sys/rsp/rsp.bin");
@@ -37,9 +40,24 @@ struct RspRes {
     h: u32,
 }
 
+#[repr(C, align(16))]
+struct CaptureMessage {
+    b: CaptureMessageBuffer,
+}
+
+// A submitted frame the RSP and RDP are still working on
+struct InFlight {
+    res_index: usize,
+    frame_counter: usize,
+}
+
 pub struct Graphics {
+    // Read by the RSP while the command buffer cache fills the other block list
     gpu_commands: Vec<RdpBlock>,
-    gpu_res: RspRes,
+    // The job in flight writes one while the results of the last finished job are read from the other
+    gpu_res: [RspRes; 2],
+    gpu_res_index: usize,
+    in_flight: Option<InFlight>,
     pub buffer_started: bool,
     pub code: Vec<String>,
     pub pc: usize,
@@ -61,7 +79,9 @@ impl Graphics {
 
         Self {
             gpu_commands: Vec::with_capacity(32),
-            gpu_res: RspRes::default(),
+            gpu_res: Default::default(),
+            gpu_res_index: 0,
+            in_flight: None,
             buffer_started: false,
             code,
             pc: 0,
@@ -74,13 +94,14 @@ impl Graphics {
         &self.code
     }
 
+    // The frame submitted last renders into the gpu buffer, it is only shown once it is finished
     #[inline]
     pub fn swap_buffers(&mut self, framebuffer: &mut Framebuffer) -> i64 {
-        //rsp::wait(5_000_000);
+        let swap_start = current_time_us();
+        self.wait_for_gpu();
 
         framebuffer.swap();
 
-        let swap_start = current_time_us();
         vi::wait_for_vblank();
         let swap_end = current_time_us();
         unsafe { vi::set_vi_buffer(&mut framebuffer.vi_buffer.0) };
@@ -95,6 +116,39 @@ impl Graphics {
         self.frame_counter
     }
 
+    // The frame on screen as RGBA 8888, it is finished so only the cache has to be made coherent
+    pub fn capture_frame(&mut self, framebuffer: &Framebuffer) -> Vec<u8> {
+        unsafe { data_cache_hit_writeback_invalidate(&framebuffer.vi_buffer.0) };
+        framebuffer.vi_buffer_rgba()
+    }
+
+    // Sends the frame on screen over the EverDrive USB, deploy puts the chunks back together as a PNG
+    pub fn stream_frame(&mut self, framebuffer: &Framebuffer) {
+        let rgba = self.capture_frame(framebuffer);
+        let chunks = rgba.chunks(CAPTURE_CHUNK_SIZE);
+
+        let mut msg = CaptureMessage {
+            b: CaptureMessageBuffer {
+                message_header_buffer: MESSAGE_MAGIC_CAPTURE,
+                padding: 0,
+                frame: self.frame_counter as u16,
+                width: framebuffer.video_mode().width() as u16,
+                height: framebuffer.video_mode().height() as u16,
+                index: 0,
+                count: chunks.len() as u16,
+                data: [0; CAPTURE_CHUNK_SIZE],
+            },
+        };
+
+        for (index, chunk) in chunks.enumerate() {
+            msg.b.index = index as u16;
+            msg.b.data[..chunk.len()].copy_from_slice(chunk);
+            msg.b.data[chunk.len()..].fill(0);
+
+            assert!(ed::usb_write(msg.b.as_bytes()));
+        }
+    }
+
     #[inline]
     pub fn rsp_step(&mut self, step: bool) -> (usize, usize, [u8; 4096]) {
         if step {
@@ -170,14 +224,48 @@ impl Graphics {
         }
     }
 
+    // Fence for the frame in flight, the gpu buffer and the block list it used are free after this
+    pub fn wait_for_gpu(&mut self) {
+        let in_flight = match self.in_flight.take() {
+            Some(in_flight) => in_flight,
+            None => return,
+        };
+
+        let (wait_ok, rsp_status) = {
+            n64_profiler::scope!("Rsp Wait");
+            rsp::wait(5_000_000)
+        };
+
+        if !wait_ok {
+            debugln!(
+                "RSP TIMEOUT! {:032b} pc {:08x}, fc {}",
+                rsp_status,
+                rsp::pc(),
+                in_flight.frame_counter,
+            );
+            self.rsp_timeout_panic(in_flight.res_index);
+        }
+
+        unsafe {
+            data_cache_hit_invalidate(slice::from_raw_parts::<u64>(
+                &self.gpu_res[in_flight.res_index] as *const _ as *const u64,
+                4,
+            ));
+        }
+    }
+
+    // Returns without waiting for the RSP, the next rsp_start or swap_buffers waits for it
     #[inline]
     pub fn rsp_start(&mut self, commands: &mut Vec<RdpBlock>, single_step: bool) {
+        self.wait_for_gpu();
+
         core::mem::swap(&mut self.gpu_commands, commands);
 
-        // TODO(JoNil): Rsp Res needs to be double buffered when the gpu is pipelined
+        let res_index = self.gpu_res_index ^ 1;
+
         let mut rsp_dmem = RspDmem {
             pointer_count: self.gpu_commands.len() as u32,
-            rsp_res_ptr: virtual_to_physical(&self.gpu_res as *const RspRes) as u32,
+            rsp_res_ptr: virtual_to_physical(&self.gpu_res[res_index] as *const RspRes) as u32,
             chunk_pointer: [0; 255],
             padding: 0,
         };
@@ -192,57 +280,53 @@ impl Graphics {
             rsp_dmem.chunk_pointer[index] = virtual_to_physical(chunk as *const RdpBlock) as u32;
         }
 
-        let mut should_panic = false;
-
         rsp::run(CODE, Some(rsp_dmem.as_bytes()), single_step);
+
+        self.gpu_res_index = res_index;
+
         if !single_step {
-            let (wait_ok, rsp_status) = {
-                n64_profiler::scope!("Rsp Wait");
-                rsp::wait(5_000_000)
-            };
-            if !wait_ok {
+            self.in_flight = Some(InFlight {
+                res_index,
+                frame_counter: self.frame_counter,
+            });
+        }
+    }
+
+    fn rsp_timeout_panic(&mut self, res_index: usize) -> ! {
+        for (block_index, block) in self.gpu_commands.iter().enumerate() {
+            debugln!("BLOCK {}: {}", block_index, block.block_len);
+            for (i, command) in block.rdp_data.iter().enumerate() {
                 debugln!(
-                    "RSP TIMEOUT! {:032b} pc {:08x}, fc {}",
-                    rsp_status,
-                    rsp::pc(),
-                    self.frame_counter,
+                    "ADDR {:<8} : {:064b} : {:016x} : {:20}",
+                    i,
+                    command.0,
+                    command.0,
+                    command.0,
                 );
-                should_panic = true;
             }
         }
+        self.rsp_dump_mem();
 
-        if should_panic {
-            for (block_index, block) in self.gpu_commands.iter().enumerate() {
-                debugln!("BLOCK {}: {}", block_index, block.block_len);
-                for (i, command) in block.rdp_data.iter().enumerate() {
-                    debugln!(
-                        "ADDR {:<8} : {:064b} : {:016x} : {:20}",
-                        i,
-                        command.0,
-                        command.0,
-                        command.0,
-                    );
-                }
-            }
-            self.rsp_dump_mem();
-
-            self.rsp_single_step_print();
+        self.rsp_single_step_print();
 
-            unsafe {
-                data_cache_hit_invalidate(slice::from_raw_parts::<u64>(
-                    &self.gpu_res as *const _ as *const u64,
-                    4,
-                ));
-            }
+        unsafe {
+            data_cache_hit_invalidate(slice::from_raw_parts::<u64>(
+                &self.gpu_res[res_index] as *const _ as *const u64,
+                4,
+            ));
+        }
 
-            debugln!("RSP RES {:#?}", self.gpu_res);
+        debugln!("RSP RES {:#?}", self.gpu_res[res_index]);
 
-            panic!("RSP TIMEOUT PANIC");
-        }
+        panic!("RSP TIMEOUT PANIC");
     }
 
+    // From the last finished frame, the one in flight has not written its results yet
     pub fn rdp_clock_count(&self) -> u32 {
-        self.gpu_res.a
+        match self.in_flight {
+            Some(ref in_flight) => self.gpu_res[in_flight.res_index ^ 1].a,
+            None => self.gpu_res[self.gpu_res_index].a,
+        }
     }
 }
 
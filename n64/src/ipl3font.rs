@@ -10,6 +10,7 @@ const UNKNOWN: usize = 48;
 const GLYPH_SIZE: usize = 23;
 const GLYPH_ADDR: usize = 0xB000_0B70;
 
+// The texture holds one RGBA16 Color per pixel, 32bpp framebuffers can not be drawn into
 pub fn draw_str(out_tex: &mut TextureMut, mut x: i32, mut y: i32, color: Color, string: &[u8]) {
     let start_x = x;
 
@@ -6,6 +6,10 @@ pub use audio::Audio;
 pub use controllers::Controllers;
 pub use framebuffer::Framebuffer;
 pub use graphics::Graphics;
+pub use save::{Save, SaveError, SaveMemory};
+
+#[cfg(not(target_vendor = "nintendo64"))]
+pub use controllers_emu::{ControllerInput, InputScript};
 
 pub use n64_macros::*;
 pub use n64_profiler::*;
@@ -17,6 +21,8 @@ pub use n64_alloc::*;
 
 pub mod gfx;
 pub mod ipl3font;
+pub mod rom;
+pub mod save;
 pub mod utils;
 
 mod framebuffer;
@@ -24,6 +30,8 @@ mod framebuffer;
 mod audio_n64;
 mod controllers_n64;
 mod graphics_n64;
+mod rom_n64;
+mod save_n64;
 
 #[cfg(not(target_vendor = "nintendo64"))]
 pub mod audio_emu;
@@ -31,6 +39,10 @@ pub mod audio_emu;
 pub mod controllers_emu;
 #[cfg(not(target_vendor = "nintendo64"))]
 pub mod graphics_emu;
+#[cfg(not(target_vendor = "nintendo64"))]
+mod rom_emu;
+#[cfg(not(target_vendor = "nintendo64"))]
+mod save_emu;
 
 #[cfg(target_vendor = "nintendo64")]
 use audio_n64 as audio;
@@ -74,6 +86,24 @@ impl N64 {
             controllers,
         }
     }
+
+    // No window, sound device or profiler server, for running the game in tests and benchmarks.
+    // None when there is no Vulkan, Metal or DX12 adapter, not even a software one.
+    #[cfg(not(target_vendor = "nintendo64"))]
+    #[inline]
+    pub fn new_headless(video_mode: VideoMode, input: InputScript) -> Option<N64> {
+        let audio = Audio::new_headless();
+        let mut framebuffer = Framebuffer::new(video_mode);
+        let graphics = Graphics::new_headless(video_mode, &mut framebuffer)?;
+        let controllers = Controllers::new_scripted(input);
+
+        Some(N64 {
+            audio,
+            framebuffer,
+            graphics,
+            controllers,
+        })
+    }
 }
 
 #[cfg(target_vendor = "nintendo64")]
@@ -104,6 +134,7 @@ pub use inner::*;
 #[cfg(not(target_vendor = "nintendo64"))]
 pub use inner::*;
 
+// Clears a 16bpp framebuffer to opaque black
 #[inline]
 pub fn slow_cpu_clear(fb: &mut [n64_math::Color]) {
     #[allow(clippy::cast_ptr_alignment)]
@@ -0,0 +1,183 @@
+use alloc::vec::Vec;
+use core::{ops::Deref, slice};
+use n64_types::{ROM_FS_ALIGNMENT, ROM_FS_ENTRY_SIZE, ROM_FS_HEADER_SIZE, ROM_FS_MAGIC};
+use spin::Mutex;
+use zerocopy::AsBytes;
+
+#[cfg(target_vendor = "nintendo64")]
+use crate::rom_n64::RomStorage;
+
+#[cfg(not(target_vendor = "nintendo64"))]
+use crate::rom_emu::RomStorage;
+
+struct RomFs {
+    storage: RomStorage,
+    entries: Vec<(usize, usize)>,
+    // Assets handed out by RomAsset::load, until RomAsset::unload frees them
+    loaded: Vec<Option<Vec<u64>>>,
+}
+
+impl RomFs {
+    fn new(mut storage: RomStorage) -> Self {
+        let mut header = [0u64; ROM_FS_HEADER_SIZE / 8];
+        storage.read(0, header.as_bytes_mut());
+        let header = header.as_bytes();
+
+        assert!(header[0..4] == ROM_FS_MAGIC, "Bad rom filesystem magic");
+
+        let count = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+
+        let mut table = aligned_buffer(count * ROM_FS_ENTRY_SIZE);
+        storage.read(ROM_FS_HEADER_SIZE, table.as_bytes_mut());
+
+        let entries = table.as_bytes()[..count * ROM_FS_ENTRY_SIZE]
+            .chunks_exact(ROM_FS_ENTRY_SIZE)
+            .map(|entry| {
+                (
+                    u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]) as usize,
+                    u32::from_be_bytes([entry[4], entry[5], entry[6], entry[7]]) as usize,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            storage,
+            entries,
+            loaded: alloc::vec![None; count],
+        }
+    }
+
+    fn read(&mut self, index: usize) -> RomData {
+        let (offset, len) = self.entries[index];
+
+        let mut buffer = aligned_buffer(len);
+        self.storage.read(offset, buffer.as_bytes_mut());
+
+        RomData { buffer, len }
+    }
+
+    fn load(&mut self, index: usize) -> &'static [u8] {
+        if self.loaded[index].is_none() {
+            self.loaded[index] = Some(self.read(index).buffer);
+        }
+
+        let len = self.entries[index].1;
+        let buffer = self.loaded[index].as_ref().unwrap();
+
+        // The heap allocation stays put until unload, which the caller promises to only call
+        // once nothing uses the data
+        unsafe { slice::from_raw_parts(buffer.as_ptr() as *const u8, len) }
+    }
+
+    fn unload(&mut self, index: usize) {
+        self.loaded[index] = None;
+    }
+}
+
+static ROM_FS: Mutex<Option<RomFs>> = Mutex::new(None);
+
+fn with_rom_fs<R>(f: impl FnOnce(&mut RomFs) -> R) -> R {
+    let mut rom_fs = ROM_FS.lock();
+    f(rom_fs.get_or_insert_with(|| RomFs::new(RomStorage::open())))
+}
+
+// PI DMA needs 8 byte aligned destinations and even lengths
+fn aligned_buffer(len: usize) -> Vec<u64> {
+    alloc::vec![0u64; (len + ROM_FS_ALIGNMENT - 1) / ROM_FS_ALIGNMENT]
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RomAsset {
+    index: u32,
+}
+
+impl RomAsset {
+    #[inline]
+    pub const fn new(index: u32) -> Self {
+        Self { index }
+    }
+
+    #[inline]
+    pub fn index(self) -> u32 {
+        self.index
+    }
+
+    #[inline]
+    pub fn len(self) -> usize {
+        with_rom_fs(|rom_fs| rom_fs.entries[self.index as usize].1)
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
+    // Loads the asset on first use, the data stays in RDRAM until it is unloaded
+    #[inline]
+    pub fn load(self) -> &'static [u8] {
+        with_rom_fs(|rom_fs| rom_fs.load(self.index as usize))
+    }
+
+    /// Frees the data loaded by `load`, the next `load` reads it from the rom again.
+    ///
+    /// # Safety
+    ///
+    /// Nothing returned by `load` for this asset, or built from it like textures and sounds, may
+    /// be used after this.
+    #[inline]
+    pub unsafe fn unload(self) {
+        with_rom_fs(|rom_fs| rom_fs.unload(self.index as usize))
+    }
+
+    // Reads a copy owned by the caller, which is freed when dropped. For assets only needed for
+    // a while, like the current map.
+    #[inline]
+    pub fn read(self) -> RomData {
+        with_rom_fs(|rom_fs| rom_fs.read(self.index as usize))
+    }
+}
+
+pub struct RomData {
+    buffer: Vec<u64>,
+    len: usize,
+}
+
+impl Deref for RomData {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        &self.buffer.as_bytes()[..self.len]
+    }
+}
+
+#[test]
+fn rom_fs_reads_assets_from_the_table() {
+    // Three entries after the header, [1, 2, 3], [4; 13] padded to 16 bytes and an empty asset
+    let mut blob = Vec::new();
+    blob.extend_from_slice(&ROM_FS_MAGIC);
+    blob.extend_from_slice(&3u32.to_be_bytes());
+    for (offset, len) in [(32u32, 3u32), (40, 13), (56, 0)] {
+        blob.extend_from_slice(&offset.to_be_bytes());
+        blob.extend_from_slice(&len.to_be_bytes());
+    }
+    blob.extend_from_slice(&[1, 2, 3, 0, 0, 0, 0, 0]);
+    blob.extend_from_slice(&[4; 13]);
+    blob.resize(56, 0);
+
+    let mut rom_fs = RomFs::new(RomStorage::from_bytes(blob));
+
+    assert_eq!(rom_fs.entries, [(32, 3), (40, 13), (56, 0)]);
+    assert!(rom_fs
+        .entries
+        .iter()
+        .all(|(offset, _)| offset % ROM_FS_ALIGNMENT == 0));
+
+    assert_eq!(rom_fs.load(0), [1, 2, 3]);
+    assert_eq!(*rom_fs.read(1), [4; 13]);
+    assert_eq!(*rom_fs.read(2), []);
+
+    rom_fs.unload(0);
+    assert!(rom_fs.loaded.iter().all(Option::is_none));
+    assert_eq!(rom_fs.load(0), [1, 2, 3]);
+}
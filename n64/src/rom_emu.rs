@@ -0,0 +1,42 @@
+use std::{env, fs, path::PathBuf};
+
+const ROM_FS_FILE_NAME: &str = "rom_fs.bin";
+
+pub(crate) struct RomStorage {
+    data: Vec<u8>,
+}
+
+impl RomStorage {
+    // The pipeline writes the blob next to the game executable, tests and benches live one
+    // directory further down in deps
+    pub(crate) fn open() -> Self {
+        let exe = env::current_exe().unwrap();
+
+        let path = exe
+            .ancestors()
+            .skip(1)
+            .take(2)
+            .map(|dir| dir.join(ROM_FS_FILE_NAME))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| PathBuf::from(ROM_FS_FILE_NAME));
+
+        let data = fs::read(&path)
+            .map_err(|e| format!("Unable to read rom filesystem {}: {}", path.display(), e))
+            .unwrap();
+
+        Self { data }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_bytes(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+
+    #[inline]
+    pub(crate) fn read(&mut self, offset: usize, data: &mut [u8]) {
+        // The last asset is rounded up to the DMA alignment and may run past the end of the file
+        let available = &self.data[offset.min(self.data.len())..];
+        let len = data.len().min(available.len());
+        data[..len].copy_from_slice(&available[..len]);
+    }
+}
@@ -0,0 +1,27 @@
+#![cfg_attr(not(target_vendor = "nintendo64"), allow(unused))]
+
+use n64_sys::pi;
+use n64_types::ROM_FS_CART_HEADER_OFFSET;
+
+const CART_ROM_BASE: usize = 0x1000_0000;
+
+pub(crate) struct RomStorage {
+    base: usize,
+}
+
+impl RomStorage {
+    pub(crate) fn open() -> Self {
+        let offset = unsafe { pi::io_read(CART_ROM_BASE + ROM_FS_CART_HEADER_OFFSET) } as usize;
+
+        assert!(offset != 0, "No rom filesystem appended to the cartridge");
+
+        Self {
+            base: CART_ROM_BASE + offset,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn read(&mut self, offset: usize, data: &mut [u8]) {
+        unsafe { pi::read_physical(data, self.base + offset) };
+    }
+}
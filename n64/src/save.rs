@@ -0,0 +1,219 @@
+use alloc::vec::Vec;
+use n64_sys::si::JoybusError;
+
+#[cfg(all(
+    target_vendor = "nintendo64",
+    not(any(
+        feature = "save-sram",
+        feature = "save-sram-96k",
+        feature = "save-flashram"
+    ))
+))]
+pub type SaveBackend = crate::save_n64::Eeprom;
+
+#[cfg(all(
+    target_vendor = "nintendo64",
+    any(feature = "save-sram", feature = "save-sram-96k"),
+    not(feature = "save-flashram")
+))]
+pub type SaveBackend = crate::save_n64::Sram;
+
+#[cfg(all(target_vendor = "nintendo64", feature = "save-flashram"))]
+pub type SaveBackend = crate::save_n64::FlashRam;
+
+#[cfg(not(target_vendor = "nintendo64"))]
+pub type SaveBackend = crate::save_emu::FileSave;
+
+const SLOT_MAGIC: [u8; 4] = *b"LOKA";
+const SLOT_HEADER_SIZE: usize = 8;
+
+// Slots are kept a multiple of the EEPROM block size so no slot shares a block with another,
+// which also keeps them aligned for PI DMA
+const SLOT_ALIGNMENT: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SaveError {
+    // The cartridge has no save chip of the selected type
+    NoSaveMemory,
+    // The payload is larger than slot_capacity
+    TooLarge,
+    Eeprom(JoybusError),
+    // A FlashRAM sector erase or page program did not report success
+    FlashRam,
+    // The save file next to the executable could not be written
+    File,
+}
+
+impl From<JoybusError> for SaveError {
+    #[inline]
+    fn from(error: JoybusError) -> Self {
+        SaveError::Eeprom(error)
+    }
+}
+
+pub trait SaveMemory {
+    fn size(&self) -> usize;
+    fn read(&mut self, offset: usize, data: &mut [u8]) -> Result<(), SaveError>;
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), SaveError>;
+}
+
+// Slot layout: magic (4 bytes), payload length (u16 BE), fletcher-16 of the payload (u16 BE), payload
+pub struct Save<M: SaveMemory = SaveBackend> {
+    memory: M,
+    slot_count: usize,
+    slot_size: usize,
+}
+
+impl Save {
+    #[inline]
+    pub fn new(slot_count: usize) -> Self {
+        Self::with_memory(SaveBackend::new(), slot_count)
+    }
+}
+
+impl<M: SaveMemory> Save<M> {
+    pub fn with_memory(memory: M, slot_count: usize) -> Self {
+        assert!(slot_count > 0);
+
+        let slot_size = (memory.size() / slot_count) & !(SLOT_ALIGNMENT - 1);
+
+        Self {
+            memory,
+            slot_count,
+            slot_size,
+        }
+    }
+
+    #[inline]
+    pub fn slot_count(&self) -> usize {
+        self.slot_count
+    }
+
+    #[inline]
+    pub fn slot_capacity(&self) -> usize {
+        self.slot_size.saturating_sub(SLOT_HEADER_SIZE)
+    }
+
+    // Returns the payload length, or None if the slot is empty or fails its checksum
+    pub fn load(&mut self, slot: usize, data: &mut [u8]) -> Result<Option<usize>, SaveError> {
+        assert!(slot < self.slot_count);
+
+        if self.slot_capacity() == 0 {
+            return Ok(None);
+        }
+
+        let offset = slot * self.slot_size;
+
+        let mut header = [0u8; SLOT_HEADER_SIZE];
+        self.memory.read(offset, &mut header)?;
+
+        if header[0..4] != SLOT_MAGIC {
+            return Ok(None);
+        }
+
+        let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let checksum = u16::from_be_bytes([header[6], header[7]]);
+
+        if len > self.slot_capacity() || len > data.len() {
+            return Ok(None);
+        }
+
+        self.memory
+            .read(offset + SLOT_HEADER_SIZE, &mut data[..len])?;
+
+        if fletcher16(&data[..len]) != checksum {
+            return Ok(None);
+        }
+
+        Ok(Some(len))
+    }
+
+    pub fn store(&mut self, slot: usize, data: &[u8]) -> Result<(), SaveError> {
+        assert!(slot < self.slot_count);
+
+        if self.slot_capacity() == 0 {
+            return Err(SaveError::NoSaveMemory);
+        }
+
+        if data.len() > self.slot_capacity() {
+            return Err(SaveError::TooLarge);
+        }
+
+        let mut buffer = Vec::with_capacity(SLOT_HEADER_SIZE + data.len());
+        buffer.extend_from_slice(&SLOT_MAGIC);
+        buffer.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        buffer.extend_from_slice(&fletcher16(data).to_be_bytes());
+        buffer.extend_from_slice(data);
+
+        self.memory.write(slot * self.slot_size, &buffer)
+    }
+
+    pub fn erase(&mut self, slot: usize) -> Result<(), SaveError> {
+        assert!(slot < self.slot_count);
+
+        if self.slot_capacity() == 0 {
+            return Ok(());
+        }
+
+        self.memory
+            .write(slot * self.slot_size, &[0u8; SLOT_HEADER_SIZE])
+    }
+}
+
+fn fletcher16(data: &[u8]) -> u16 {
+    let mut sum_1: u16 = 0;
+    let mut sum_2: u16 = 0;
+
+    for byte in data {
+        sum_1 = (sum_1 + *byte as u16) % 255;
+        sum_2 = (sum_2 + sum_1) % 255;
+    }
+
+    (sum_2 << 8) | sum_1
+}
+
+#[test]
+fn save_slot_roundtrip() {
+    struct MemorySave(Vec<u8>);
+
+    impl SaveMemory for MemorySave {
+        fn size(&self) -> usize {
+            self.0.len()
+        }
+
+        fn read(&mut self, offset: usize, data: &mut [u8]) -> Result<(), SaveError> {
+            data.copy_from_slice(&self.0[offset..offset + data.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), SaveError> {
+            self.0[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    let mut save = Save::with_memory(MemorySave(alloc::vec![0; 512]), 4);
+    let mut data = [0u8; 120];
+
+    assert_eq!(save.slot_capacity(), 120);
+    assert_eq!(save.load(1, &mut data), Ok(None));
+
+    assert_eq!(save.store(1, b"high score"), Ok(()));
+    assert_eq!(save.load(1, &mut data), Ok(Some(10)));
+    assert_eq!(&data[..10], b"high score");
+    assert_eq!(save.load(0, &mut data), Ok(None));
+
+    save.memory.0[128 + SLOT_HEADER_SIZE] ^= 0xff;
+    assert_eq!(save.load(1, &mut data), Ok(None));
+
+    assert_eq!(save.store(1, &[0; 121]), Err(SaveError::TooLarge));
+    assert_eq!(save.store(1, b"settings"), Ok(()));
+    assert_eq!(save.erase(1), Ok(()));
+    assert_eq!(save.load(1, &mut data), Ok(None));
+
+    // A cartridge without a save chip
+    let mut save = Save::with_memory(MemorySave(Vec::new()), 4);
+    assert_eq!(save.load(1, &mut data), Ok(None));
+    assert_eq!(save.store(1, b"high score"), Err(SaveError::NoSaveMemory));
+    assert_eq!(save.erase(1), Ok(()));
+}
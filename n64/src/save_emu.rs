@@ -0,0 +1,70 @@
+use crate::save::{SaveError, SaveMemory};
+use n64_sys::{eeprom::EepromType, flashram::FLASHRAM_SIZE, sram::SRAM_BANK_SIZE};
+use std::{env, fs, path::PathBuf};
+
+pub struct FileSave {
+    path: Option<PathBuf>,
+    data: Vec<u8>,
+}
+
+impl FileSave {
+    // Matches the size of the save memory selected for the N64 build
+    pub fn new() -> Self {
+        Self::with_size(if cfg!(feature = "save-flashram") {
+            FLASHRAM_SIZE
+        } else if cfg!(feature = "save-sram-96k") {
+            3 * SRAM_BANK_SIZE
+        } else if cfg!(feature = "save-sram") {
+            SRAM_BANK_SIZE
+        } else {
+            EepromType::Eeprom4k.size()
+        })
+    }
+
+    pub fn with_size(size: usize) -> Self {
+        let path = env::current_exe().ok().map(|exe| exe.with_extension("sav"));
+
+        let mut data = path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .unwrap_or_default();
+
+        data.resize(size, 0);
+
+        Self { path, data }
+    }
+
+    fn flush(&self) -> Result<(), SaveError> {
+        if let Some(path) = &self.path {
+            fs::write(path, &self.data).map_err(|e| {
+                println!("Failed to write save file {}: {}", path.display(), e);
+                SaveError::File
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for FileSave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveMemory for FileSave {
+    #[inline]
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn read(&mut self, offset: usize, data: &mut [u8]) -> Result<(), SaveError> {
+        data.copy_from_slice(&self.data[offset..offset + data.len()]);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), SaveError> {
+        self.data[offset..offset + data.len()].copy_from_slice(data);
+        self.flush()
+    }
+}
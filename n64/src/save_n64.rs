@@ -0,0 +1,252 @@
+#![cfg_attr(not(target_vendor = "nintendo64"), allow(unused))]
+
+use crate::save::{SaveError, SaveMemory};
+use alloc::vec::Vec;
+use n64_sys::{
+    eeprom::{self, EepromType, EEPROM_BLOCK_SIZE},
+    flashram::{self, FLASHRAM_PAGE_SIZE, FLASHRAM_SECTOR_SIZE, FLASHRAM_SIZE},
+    sram::{self, SRAM_BANK_SIZE},
+};
+use zerocopy::AsBytes;
+
+pub struct Eeprom {
+    eeprom_type: EepromType,
+}
+
+impl Eeprom {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            eeprom_type: eeprom::detect(),
+        }
+    }
+
+    #[inline]
+    pub fn eeprom_type(&self) -> EepromType {
+        self.eeprom_type
+    }
+}
+
+impl Default for Eeprom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveMemory for Eeprom {
+    #[inline]
+    fn size(&self) -> usize {
+        self.eeprom_type.size()
+    }
+
+    fn read(&mut self, offset: usize, data: &mut [u8]) -> Result<(), SaveError> {
+        assert!(offset + data.len() <= self.size());
+
+        let mut block = [0u8; EEPROM_BLOCK_SIZE];
+        let mut cursor = 0;
+
+        while cursor < data.len() {
+            let address = offset + cursor;
+            let block_offset = address % EEPROM_BLOCK_SIZE;
+            let len = (EEPROM_BLOCK_SIZE - block_offset).min(data.len() - cursor);
+
+            eeprom::read((address / EEPROM_BLOCK_SIZE) as u8, &mut block)?;
+
+            data[cursor..cursor + len].copy_from_slice(&block[block_offset..block_offset + len]);
+            cursor += len;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), SaveError> {
+        assert!(offset + data.len() <= self.size());
+
+        let mut block = [0u8; EEPROM_BLOCK_SIZE];
+        let mut cursor = 0;
+
+        while cursor < data.len() {
+            let address = offset + cursor;
+            let block_index = (address / EEPROM_BLOCK_SIZE) as u8;
+            let block_offset = address % EEPROM_BLOCK_SIZE;
+            let len = (EEPROM_BLOCK_SIZE - block_offset).min(data.len() - cursor);
+
+            // Partial blocks need a read-modify-write since the EEPROM only writes whole blocks
+            if len < EEPROM_BLOCK_SIZE {
+                eeprom::read(block_index, &mut block)?;
+            }
+
+            block[block_offset..block_offset + len].copy_from_slice(&data[cursor..cursor + len]);
+            eeprom::write(block_index, &block)?;
+
+            cursor += len;
+        }
+
+        Ok(())
+    }
+}
+
+// PI DMA needs an 8 byte aligned RDRAM buffer and a 2 byte aligned bus address and length
+fn aligned_span(offset: usize, len: usize) -> (usize, usize, Vec<u64>) {
+    let start = offset & !1;
+    let end = (offset + len + 1) & !1;
+    let buffer = alloc::vec![0; (end - start + 7) / 8];
+
+    (start, end - start, buffer)
+}
+
+pub struct Sram {
+    size: usize,
+}
+
+impl Sram {
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_banks(if cfg!(feature = "save-sram-96k") {
+            3
+        } else {
+            1
+        })
+    }
+
+    #[inline]
+    pub fn with_banks(banks: usize) -> Self {
+        sram::init();
+
+        Self {
+            size: banks * SRAM_BANK_SIZE,
+        }
+    }
+}
+
+impl Default for Sram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveMemory for Sram {
+    #[inline]
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn read(&mut self, offset: usize, data: &mut [u8]) -> Result<(), SaveError> {
+        assert!(offset + data.len() <= self.size());
+
+        let (start, len, mut buffer) = aligned_span(offset, data.len());
+        let bytes = &mut buffer.as_bytes_mut()[..len];
+
+        sram::read(start, bytes);
+
+        data.copy_from_slice(&bytes[offset - start..offset - start + data.len()]);
+
+        Ok(())
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), SaveError> {
+        assert!(offset + data.len() <= self.size());
+
+        let (start, len, mut buffer) = aligned_span(offset, data.len());
+        let bytes = &mut buffer.as_bytes_mut()[..len];
+
+        if len != data.len() {
+            sram::read(start, bytes);
+        }
+
+        bytes[offset - start..offset - start + data.len()].copy_from_slice(data);
+
+        sram::write(start, bytes);
+
+        Ok(())
+    }
+}
+
+pub struct FlashRam {
+    present: bool,
+}
+
+impl FlashRam {
+    #[inline]
+    pub fn new() -> Self {
+        flashram::init();
+
+        Self {
+            present: flashram::detect(),
+        }
+    }
+}
+
+impl Default for FlashRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveMemory for FlashRam {
+    #[inline]
+    fn size(&self) -> usize {
+        if self.present {
+            FLASHRAM_SIZE
+        } else {
+            0
+        }
+    }
+
+    fn read(&mut self, offset: usize, data: &mut [u8]) -> Result<(), SaveError> {
+        assert!(offset + data.len() <= self.size());
+
+        let (start, len, mut buffer) = aligned_span(offset, data.len());
+        let bytes = &mut buffer.as_bytes_mut()[..len];
+
+        flashram::read(start, bytes);
+
+        data.copy_from_slice(&bytes[offset - start..offset - start + data.len()]);
+
+        Ok(())
+    }
+
+    // Flash can only be erased a sector at a time, so every touched sector is read back,
+    // patched, erased and reprogrammed page by page.
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), SaveError> {
+        assert!(offset + data.len() <= self.size());
+
+        let mut sector_buffer = alloc::vec![0u64; FLASHRAM_SECTOR_SIZE / 8];
+        let sector_bytes = sector_buffer.as_bytes_mut();
+
+        let first_sector = offset / FLASHRAM_SECTOR_SIZE;
+        let last_sector = (offset + data.len() - 1) / FLASHRAM_SECTOR_SIZE;
+
+        for sector in first_sector..=last_sector {
+            let sector_start = sector * FLASHRAM_SECTOR_SIZE;
+
+            flashram::read(sector_start, sector_bytes);
+
+            let patch_start = offset.max(sector_start);
+            let patch_end = (offset + data.len()).min(sector_start + FLASHRAM_SECTOR_SIZE);
+
+            sector_bytes[patch_start - sector_start..patch_end - sector_start]
+                .copy_from_slice(&data[patch_start - offset..patch_end - offset]);
+
+            if !flashram::erase_sector(sector) {
+                return Err(SaveError::FlashRam);
+            }
+
+            for (index, page) in sector_bytes.chunks_exact(FLASHRAM_PAGE_SIZE).enumerate() {
+                // Erased flash reads back as all ones, no need to program those pages
+                if page.iter().all(|b| *b == 0xff) {
+                    continue;
+                }
+
+                if !flashram::program_page(
+                    sector_start / FLASHRAM_PAGE_SIZE + index,
+                    page.try_into().unwrap(),
+                ) {
+                    return Err(SaveError::FlashRam);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}